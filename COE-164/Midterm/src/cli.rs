@@ -0,0 +1,1173 @@
+use core::fmt;
+use std::error;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Read, Write};
+
+use crate::flac::decoder::analyze::{AnalysisFormat, AnalyzerOptions};
+use crate::flac::decoder::stream::FlacDecoder;
+use crate::flac::decoder::DecoderError;
+use crate::flac::encoder::frame::BlockingStrategy;
+use crate::flac::encoder::stream::{FlacEncoderOptions, FlacStreamEncoder, SeekSpacing};
+use crate::flac::encoder::report::EncodeReport;
+use crate::flac::encoder::EncoderError;
+use crate::flac::lpc::window::Window;
+use crate::flac::metadata::{BlockType, CueSheet, CueSheetError, Editor, EditorError, ForeignMetadata, MetadataBlock, MetadataError, Picture, PictureType, StreamInfo, VorbisComment};
+use crate::flac::sample::Sample;
+use crate::ogg::flac::OggFlacReader;
+use crate::ogg::OggError;
+use crate::wav::{PCMWaveFormatChunk, PCMWaveInfo, PCMWaveStream, WaveReader, WaveReaderError, WaveWriter};
+
+pub const USAGE: &str = "\
+Usage:
+  midp encode [OPTIONS] INPUT.wav
+  midp decode [-o OUTPUT.wav] [--ogg] INPUT.flac
+  midp info INPUT.wav
+  midp meta [OPTIONS] INPUT.flac
+  midp analyze [OPTIONS] INPUT.flac
+
+INPUT.wav and the encode output may be - for the standard input and output.
+
+Encode options:
+  -o, --output PATH            Write to PATH instead of INPUT.flac, the default for - is -
+  -b, --block-size N           Block size in samples (default 4096)
+      --variable-blocksize     Split and merge blocks based on their size
+  -e, --exhaustive             Try every subframe candidate
+  -p, --qlp-precision-search   Try every QLP coefficient precision
+  -A, --apodization SPEC       Apodization windows, such as \"tukey(0.5);partial_tukey(2)\"
+  -S, --seek-spacing SPEC      Write a seek point every SPEC samples, or seconds such as \"10s\"
+  -T, --tag FIELD=VALUE        Add a Vorbis comment, may be repeated
+      --tag-from-file FIELD=PATH
+                               Add a Vorbis comment read from a file, may be repeated
+      --picture PATH           Add a PNG or JPEG front cover, may be repeated
+      --cuesheet PATH          Add a CUESHEET block read from a .cue file
+  -P, --padding N              Write a PADDING block of N bytes
+      --keep-foreign-metadata  Keep the other RIFF chunks to restore them when decoding
+      --ogg                    Write an Ogg FLAC stream, the default for an .oga output
+      --stats[=json]           Print how each frame was encoded, as text or JSON
+
+Decode options:
+  -o, --output PATH            Write to PATH instead of INPUT.wav
+      --ogg                    Read an Ogg FLAC stream, the default for an .oga input
+
+Analyze options:
+  -o, --output PATH            Write to PATH instead of the standard output
+      --format FORMAT          Output format, text (default), csv or json
+      --residuals              List every residual of each subframe
+      --ogg                    Read an Ogg FLAC stream, the default for an .oga input
+
+Meta options, applied in order:
+      --list                   List the metadata blocks, the default without edits or exports
+      --export-cuesheet PATH   Write the CUESHEET block to a .cue file
+  -T, --tag FIELD=VALUE        Add a Vorbis comment
+      --set-tag FIELD=VALUE    Replace every Vorbis comment of FIELD
+      --remove-tag FIELD       Remove every Vorbis comment of FIELD
+      --picture PATH           Add a PNG or JPEG front cover
+      --remove-block N         Remove block N, as numbered by --list
+      --remove-type TYPE       Remove every block of TYPE, such as PICTURE or PADDING";
+
+/// Path that stands for the standard input or output
+pub const STDIO_PATH: &str = "-";
+
+/// Represents a Vorbis comment given on the command line
+#[derive(Debug, PartialEq)]
+pub enum TagArg {
+    Value(String, String),
+    File(String, String),
+}
+
+/// Represents the format of the statistics printed by `midp encode --stats`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StatsFormat {
+    Text,
+    Json,
+}
+
+/// Represents the arguments of `midp encode`
+#[derive(Debug, PartialEq)]
+pub struct EncodeArgs {
+    pub input: String,
+    pub output: Option <String>,
+    pub block_size: u64,
+    pub variable_blocksize: bool,
+    pub exhaustive: bool,
+    pub qlp_precision_search: bool,
+    pub apodization: Option <String>,
+    pub seek_spacing: Option <SeekSpacing>,
+    pub tags: Vec <TagArg>,
+    pub pictures: Vec <String>,
+    pub cuesheet: Option <String>,
+    pub padding: Option <u32>,
+    pub keep_foreign_metadata: bool,
+    pub ogg: bool,
+    pub stats: Option <StatsFormat>,
+}
+
+/// Represents the arguments of `midp decode`
+#[derive(Debug, PartialEq)]
+pub struct DecodeArgs {
+    pub input: String,
+    pub output: Option <String>,
+    pub ogg: bool,
+}
+
+/// Represents an edit of `midp meta`
+#[derive(Debug, PartialEq)]
+pub enum MetaEdit {
+    AddTag(String, String),
+    SetTag(String, String),
+    RemoveTag(String),
+    AddPicture(String),
+    RemoveBlock(usize),
+    RemoveType(BlockType),
+}
+
+/// Represents the arguments of `midp meta`
+#[derive(Debug, PartialEq)]
+pub struct MetaArgs {
+    pub input: String,
+    pub list: bool,
+    pub export_cuesheet: Option <String>,
+    pub edits: Vec <MetaEdit>,
+}
+
+/// Represents the arguments of `midp analyze`
+#[derive(Debug, PartialEq)]
+pub struct AnalyzeArgs {
+    pub input: String,
+    pub output: Option <String>,
+    pub format: AnalysisFormat,
+    pub residuals: bool,
+    pub ogg: bool,
+}
+
+/// Represents a command of the command-line interface
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    Encode(EncodeArgs),
+    Decode(DecodeArgs),
+    Info(String),
+    Meta(MetaArgs),
+    Analyze(AnalyzeArgs),
+    Help,
+}
+
+// Represents possible errors in the command-line interface
+#[derive(Debug)]
+#[derive(PartialEq)]
+pub enum CliError {
+    UsageError,
+    UnknownCommandError(String),
+    UnknownOptionError(String),
+    MissingValueError(String),
+    InvalidValueError(String),
+    ReadError(String),
+    WriteError(String),
+    WaveError(WaveReaderError),
+    MetadataError(MetadataError),
+    EditorError(EditorError),
+    CueSheetError(CueSheetError),
+    MissingBlockError(String),
+    DecoderError(DecoderError),
+    EncoderError(EncoderError),
+    OggError(OggError),
+}
+
+/// Map a four-character ID of a WAV LIST/INFO chunk to its Vorbis comment field name
+pub fn riff_info_field(id: &str) -> Option <&'static str> {
+    match id {
+        "IART" => Some("ARTIST"),
+        "INAM" => Some("TITLE"),
+        "IPRD" => Some("ALBUM"),
+        "IPRT" | "ITRK" => Some("TRACKNUMBER"),
+        "ICRD" => Some("DATE"),
+        "IGNR" => Some("GENRE"),
+        "ICMT" => Some("COMMENT"),
+        "ICOP" => Some("COPYRIGHT"),
+        "ISFT" => Some("ENCODER"),
+        _ => None,
+    }
+}
+
+/// Split `--option=value` into the option and its value
+fn split_option(arg: &str) -> (&str, Option <&str>) {
+    match arg.split_once('=') {
+        Some((option, value)) if arg.starts_with("--") => (option, Some(value)),
+        _ => (arg, None),
+    }
+}
+
+/// Get the value of an option, either attached with `=` or as the next argument
+fn option_value <'a> (option: &str, attached: Option <&'a str>, rest: &mut impl Iterator <Item = &'a String>) -> Result <&'a str, CliError> {
+    match attached {
+        Some(value) => Ok(value),
+        None => rest.next().map(|value| value.as_str()).ok_or(CliError::MissingValueError(option.to_string())),
+    }
+}
+
+fn parse_tag(option: &str, value: &str) -> Result <(String, String), CliError> {
+    VorbisComment::parse_tag(value).map_err(|_| CliError::InvalidValueError(format!("{} {}", option, value)))
+}
+
+/// Parse the arguments of `midp encode`, excluding the command itself
+pub fn parse_encode_args(args: &[String]) -> Result <EncodeArgs, CliError> {
+    let mut encode_args = EncodeArgs {
+        input: String::new(),
+        output: None,
+        block_size: 4096,
+        variable_blocksize: false,
+        exhaustive: false,
+        qlp_precision_search: false,
+        apodization: None,
+        seek_spacing: None,
+        tags: Vec::new(),
+        pictures: Vec::new(),
+        cuesheet: None,
+        padding: None,
+        keep_foreign_metadata: false,
+        ogg: false,
+        stats: None,
+    };
+    let mut input = None;
+    let mut rest = args.iter();
+
+    while let Some(arg) = rest.next() {
+        let (option, attached) = split_option(arg);
+        match option {
+            "-o" | "--output" => encode_args.output = Some(option_value(option, attached, &mut rest)?.to_string()),
+            "-b" | "--block-size" => {
+                let value = option_value(option, attached, &mut rest)?;
+                encode_args.block_size = match value.parse() {
+                    Ok(block_size @ 16..=65535) => block_size,
+                    _ => return Err(CliError::InvalidValueError(format!("{} {}", option, value))),
+                };
+            }
+            "--variable-blocksize" => encode_args.variable_blocksize = true,
+            "-e" | "--exhaustive" => encode_args.exhaustive = true,
+            "-p" | "--qlp-precision-search" => encode_args.qlp_precision_search = true,
+            "-A" | "--apodization" => {
+                let value = option_value(option, attached, &mut rest)?;
+                if Window::parse_spec(value).is_err() {
+                    return Err(CliError::InvalidValueError(format!("{} {}", option, value)));
+                }
+                encode_args.apodization = Some(value.to_string());
+            }
+            "-S" | "--seek-spacing" => {
+                let value = option_value(option, attached, &mut rest)?;
+                encode_args.seek_spacing = Some(SeekSpacing::parse(value).ok_or(CliError::InvalidValueError(format!("{} {}", option, value)))?);
+            }
+            "-T" | "--tag" => {
+                let (field, value) = parse_tag(option, option_value(option, attached, &mut rest)?)?;
+                encode_args.tags.push(TagArg::Value(field, value));
+            }
+            "--tag-from-file" => {
+                let (field, path) = parse_tag(option, option_value(option, attached, &mut rest)?)?;
+                encode_args.tags.push(TagArg::File(field, path));
+            }
+            "--picture" => encode_args.pictures.push(option_value(option, attached, &mut rest)?.to_string()),
+            "--cuesheet" => encode_args.cuesheet = Some(option_value(option, attached, &mut rest)?.to_string()),
+            "--keep-foreign-metadata" => encode_args.keep_foreign_metadata = true,
+            "--ogg" => encode_args.ogg = true,
+            // The format is optional, so it can only be attached
+            "--stats" => {
+                encode_args.stats = match attached {
+                    None | Some("text") => Some(StatsFormat::Text),
+                    Some("json") => Some(StatsFormat::Json),
+                    Some(value) => return Err(CliError::InvalidValueError(format!("{} {}", option, value))),
+                };
+            }
+            "-P" | "--padding" => {
+                let value = option_value(option, attached, &mut rest)?;
+                encode_args.padding = match value.parse() {
+                    Ok(padding @ 0..=0xFFFFFF) => Some(padding),
+                    _ => return Err(CliError::InvalidValueError(format!("{} {}", option, value))),
+                };
+            }
+            _ if option.starts_with('-') && option.len() > 1 => return Err(CliError::UnknownOptionError(arg.to_string())),
+            _ if input.is_none() => input = Some(arg.to_string()),
+            _ => return Err(CliError::UsageError),
+        }
+    }
+
+    encode_args.input = input.ok_or(CliError::UsageError)?;
+    encode_args.ogg |= encode_args.output.as_ref().is_some_and(|output| output.ends_with(".oga"));
+    Ok(encode_args)
+}
+
+/// Parse the arguments of `midp decode`, excluding the command itself
+pub fn parse_decode_args(args: &[String]) -> Result <DecodeArgs, CliError> {
+    let mut decode_args = DecodeArgs { input: String::new(), output: None, ogg: false };
+    let mut input = None;
+    let mut rest = args.iter();
+
+    while let Some(arg) = rest.next() {
+        let (option, attached) = split_option(arg);
+        match option {
+            "-o" | "--output" => decode_args.output = Some(option_value(option, attached, &mut rest)?.to_string()),
+            "--ogg" => decode_args.ogg = true,
+            _ if option.starts_with('-') && option.len() > 1 => return Err(CliError::UnknownOptionError(arg.to_string())),
+            _ if input.is_none() => input = Some(arg.to_string()),
+            _ => return Err(CliError::UsageError),
+        }
+    }
+
+    decode_args.input = input.ok_or(CliError::UsageError)?;
+    decode_args.ogg |= decode_args.input.ends_with(".oga");
+    Ok(decode_args)
+}
+
+/// Parse the arguments of `midp meta`, excluding the command itself
+pub fn parse_meta_args(args: &[String]) -> Result <MetaArgs, CliError> {
+    let mut meta_args = MetaArgs { input: String::new(), list: false, export_cuesheet: None, edits: Vec::new() };
+    let mut input = None;
+    let mut rest = args.iter();
+
+    while let Some(arg) = rest.next() {
+        let (option, attached) = split_option(arg);
+        match option {
+            "--list" => meta_args.list = true,
+            "--export-cuesheet" => meta_args.export_cuesheet = Some(option_value(option, attached, &mut rest)?.to_string()),
+            "-T" | "--tag" => {
+                let (field, value) = parse_tag(option, option_value(option, attached, &mut rest)?)?;
+                meta_args.edits.push(MetaEdit::AddTag(field, value));
+            }
+            "--set-tag" => {
+                let (field, value) = parse_tag(option, option_value(option, attached, &mut rest)?)?;
+                meta_args.edits.push(MetaEdit::SetTag(field, value));
+            }
+            "--remove-tag" => meta_args.edits.push(MetaEdit::RemoveTag(option_value(option, attached, &mut rest)?.to_string())),
+            "--picture" => meta_args.edits.push(MetaEdit::AddPicture(option_value(option, attached, &mut rest)?.to_string())),
+            "--remove-block" => {
+                let value = option_value(option, attached, &mut rest)?;
+                match value.parse() {
+                    Ok(index @ 1..) => meta_args.edits.push(MetaEdit::RemoveBlock(index)),
+                    _ => return Err(CliError::InvalidValueError(format!("{} {}", option, value))),
+                }
+            }
+            "--remove-type" => {
+                let value = option_value(option, attached, &mut rest)?;
+                let block_type = (0..=6).filter_map(BlockType::from_code)
+                    .find(|block_type| block_type.name().eq_ignore_ascii_case(value) && *block_type != BlockType::StreamInfo)
+                    .ok_or(CliError::InvalidValueError(format!("{} {}", option, value)))?;
+                meta_args.edits.push(MetaEdit::RemoveType(block_type));
+            }
+            _ if option.starts_with('-') && option.len() > 1 => return Err(CliError::UnknownOptionError(arg.to_string())),
+            _ if input.is_none() => input = Some(arg.to_string()),
+            _ => return Err(CliError::UsageError),
+        }
+    }
+
+    meta_args.input = input.ok_or(CliError::UsageError)?;
+    meta_args.list |= meta_args.edits.is_empty() && meta_args.export_cuesheet.is_none();
+    Ok(meta_args)
+}
+
+/// Parse the arguments of `midp analyze`, excluding the command itself
+pub fn parse_analyze_args(args: &[String]) -> Result <AnalyzeArgs, CliError> {
+    let mut analyze_args = AnalyzeArgs { input: String::new(), output: None, format: AnalysisFormat::Text, residuals: false, ogg: false };
+    let mut input = None;
+    let mut rest = args.iter();
+
+    while let Some(arg) = rest.next() {
+        let (option, attached) = split_option(arg);
+        match option {
+            "-o" | "--output" => analyze_args.output = Some(option_value(option, attached, &mut rest)?.to_string()),
+            "--format" => {
+                let value = option_value(option, attached, &mut rest)?;
+                analyze_args.format = match value {
+                    "text" => AnalysisFormat::Text,
+                    "csv" => AnalysisFormat::Csv,
+                    "json" => AnalysisFormat::Json,
+                    _ => return Err(CliError::InvalidValueError(format!("{} {}", option, value))),
+                };
+            }
+            "--residuals" => analyze_args.residuals = true,
+            "--ogg" => analyze_args.ogg = true,
+            _ if option.starts_with('-') && option.len() > 1 => return Err(CliError::UnknownOptionError(arg.to_string())),
+            _ if input.is_none() => input = Some(arg.to_string()),
+            _ => return Err(CliError::UsageError),
+        }
+    }
+
+    analyze_args.input = input.ok_or(CliError::UsageError)?;
+    analyze_args.ogg |= analyze_args.input.ends_with(".oga");
+    Ok(analyze_args)
+}
+
+/// Parse the command-line arguments, excluding the program name
+pub fn parse_args(args: &[String]) -> Result <Command, CliError> {
+    match args.first().map(|command| command.as_str()) {
+        Some("encode") => Ok(Command::Encode(parse_encode_args(&args[1..])?)),
+        Some("decode") => Ok(Command::Decode(parse_decode_args(&args[1..])?)),
+        Some("info") if args.len() == 2 => Ok(Command::Info(args[1].clone())),
+        Some("info") => Err(CliError::UsageError),
+        Some("meta") => Ok(Command::Meta(parse_meta_args(&args[1..])?)),
+        Some("analyze") => Ok(Command::Analyze(parse_analyze_args(&args[1..])?)),
+        Some("help") | Some("-h") | Some("--help") | None => Ok(Command::Help),
+        Some(command) => Err(CliError::UnknownCommandError(command.to_string())),
+    }
+}
+
+/// Split the interleaved samples of a WAV file into channels
+///
+/// `T` must hold the sample size of the file.
+pub fn read_channels <T: Sample> (wave_info: PCMWaveInfo) -> Vec <Vec <T>> {
+    let mut channels = vec![Vec::new(); wave_info.fmt_header.num_channels as usize];
+    for data_chunk in wave_info.data_chunks {
+        for sample in data_chunk.with_sample_type::<T>() {
+            for (channel, value) in channels.iter_mut().zip(sample) {
+                channel.push(value);
+            }
+        }
+    }
+    channels
+}
+
+/// Build the Vorbis comments of the output
+///
+/// The tags of the WAV LIST/INFO chunk come first, followed by the tags given
+/// on the command line in order.
+fn build_vorbis_comment(info_tags: &[(String, String)], tags: &[TagArg]) -> Result <VorbisComment, CliError> {
+    let mut vorbis_comment = VorbisComment::default();
+    for (id, value) in info_tags {
+        if let Some(field) = riff_info_field(id) {
+            vorbis_comment.add(field, value).map_err(CliError::MetadataError)?;
+        }
+    }
+    for tag in tags {
+        match tag {
+            TagArg::Value(field, value) => vorbis_comment.add(field, value),
+            TagArg::File(field, path) => {
+                let value = fs::read_to_string(path).map_err(|_| CliError::ReadError(path.clone()))?;
+                vorbis_comment.add(field, &value)
+            }
+        }.map_err(CliError::MetadataError)?;
+    }
+    Ok(vorbis_comment)
+}
+
+/// Read a PNG or JPEG image as a front cover picture
+fn read_picture(path: &str) -> Result <Picture, CliError> {
+    let data = fs::read(path).map_err(|_| CliError::ReadError(path.to_string()))?;
+    Picture::from_image(PictureType::FrontCover, "", data).map_err(CliError::MetadataError)
+}
+
+/// Read a .cue file as a CUESHEET block of a stream
+fn read_cue_sheet(path: &str, format: &PCMWaveFormatChunk, total_samples: u64) -> Result <CueSheet, CliError> {
+    let text = fs::read_to_string(path).map_err(|_| CliError::ReadError(path.to_string()))?;
+    CueSheet::from_cue(&text, format.samp_rate, format.bps as u8, format.num_channels as u8, total_samples).map_err(CliError::CueSheetError)
+}
+
+/// Build the encoder options of the arguments and metadata blocks
+fn encoder_options <T: Sample> (args: &EncodeArgs, format: &PCMWaveFormatChunk, metadata: Vec <MetadataBlock>) -> FlacEncoderOptions <T> {
+    let mut options = FlacEncoderOptions::new(format.samp_rate, format.num_channels as u8, format.bps as u8)
+        .block_size(args.block_size)
+        .exhaustive(args.exhaustive)
+        .qlp_precision_search(args.qlp_precision_search)
+        .ogg(args.ogg);
+    if args.variable_blocksize {
+        options = options.blocking(BlockingStrategy::Variable);
+    }
+    if let Some(seek_spacing) = args.seek_spacing {
+        options = options.seek_spacing(seek_spacing);
+    }
+    if let Some(spec) = &args.apodization {
+        options = options.windows(Window::parse_spec(spec).unwrap_or_default());
+    }
+    for block in metadata {
+        options = options.metadata_block(block);
+    }
+    if let Some(padding) = args.padding {
+        options = options.padding(padding);
+    }
+    options
+}
+
+/// Build the metadata blocks of the output, in the order they are written
+fn encoder_metadata(args: &EncodeArgs, info_tags: &[(String, String)], format: &PCMWaveFormatChunk, total_samples: u64) -> Result <Vec <MetadataBlock>, CliError> {
+    let vorbis_comment = build_vorbis_comment(info_tags, &args.tags)?;
+    let mut metadata = Vec::new();
+    if !vorbis_comment.is_empty() {
+        metadata.push(MetadataBlock::VorbisComment(vorbis_comment));
+    }
+    if let Some(path) = &args.cuesheet {
+        metadata.push(MetadataBlock::CueSheet(read_cue_sheet(path, format, total_samples)?));
+    }
+    for path in &args.pictures {
+        metadata.push(MetadataBlock::Picture(read_picture(path)?));
+    }
+    Ok(metadata)
+}
+
+fn encoder_error(output: &str) -> impl Fn(EncoderError) -> CliError + '_ {
+    move |e| match e {
+        EncoderError::MetadataError(e) => CliError::MetadataError(e),
+        EncoderError::WriteError => CliError::WriteError(output.to_string()),
+        e => CliError::EncoderError(e),
+    }
+}
+
+/// Write the output to a file, or to the standard output for `-`
+fn write_output(output: &str, bytes: &[u8]) -> Result <(), CliError> {
+    match output {
+        STDIO_PATH => io::stdout().lock().write_all(bytes),
+        _ => fs::write(output, bytes),
+    }.map_err(|_| CliError::WriteError(output.to_string()))
+}
+
+/// Print the statistics of an encoded stream, on the standard error if the output is the standard output
+fn print_stats(stats: StatsFormat, encode_report: &EncodeReport, output: &str) {
+    let text = match stats {
+        StatsFormat::Text => encode_report.to_string(),
+        StatsFormat::Json => encode_report.to_json(),
+    };
+    match output {
+        STDIO_PATH => eprintln!("{}", text),
+        _ => println!("{}", text),
+    }
+}
+
+/// Report what was written
+///
+/// The report goes to the standard error if the output or the JSON statistics
+/// go to the standard output.
+fn report(args: &EncodeArgs, message: &str, output: &str) {
+    match output == STDIO_PATH || args.stats == Some(StatsFormat::Json) {
+        true => eprintln!("{}: {} to {}", args.input, message, output),
+        false => println!("{}: {} to {}", args.input, message, output),
+    }
+}
+
+/// Encode a WAV file, holding its samples in the smallest type that fits them
+///
+/// The WAV reader only accepts 4 to 32 bits per sample, the range of FLAC.
+fn encode(args: &EncodeArgs) -> Result <(), CliError> {
+    if args.input == STDIO_PATH {
+        return encode_stream(args);
+    }
+
+    let wave_info = WaveReader::open_pcm(&args.input).map_err(CliError::WaveError)?;
+    match wave_info.fmt_header.bps {
+        4..=8 => encode_as::<i8>(args, wave_info),
+        9..=16 => encode_as::<i16>(args, wave_info),
+        17..=32 => encode_as::<i32>(args, wave_info),
+        _ => Err(CliError::WaveError(WaveReaderError::UnsupportedFormatError)),
+    }
+}
+
+fn encode_as <T: Sample> (args: &EncodeArgs, wave_info: PCMWaveInfo) -> Result <(), CliError> {
+    let format = wave_info.fmt_header;
+    let info_tags = wave_info.info_tags.clone();
+    let channels = read_channels::<T>(wave_info);
+    let total_samples = channels.first().map_or(0, |channel| channel.len()) as u64;
+    let mut metadata = encoder_metadata(args, &info_tags, &format, total_samples)?;
+    if args.keep_foreign_metadata {
+        metadata.extend(ForeignMetadata::read_wav(&args.input).map_err(CliError::WaveError)?.to_blocks());
+    }
+
+    let options = encoder_options::<T>(args, &format, metadata);
+    let (bytes, encode_report) = match args.stats {
+        Some(_) => options.encode_with_report(&channels).map(|(bytes, encode_report)| (bytes, Some(encode_report))),
+        None => options.encode(&channels).map(|bytes| (bytes, None)),
+    }.map_err(CliError::MetadataError)?;
+    let output = match &args.output {
+        Some(output) => output.clone(),
+        None => format!("{}.{}", args.input.strip_suffix(".wav").unwrap_or(&args.input), if args.ogg { "oga" } else { "flac" }),
+    };
+    write_output(&output, &bytes)?;
+
+    report(args, &format!("wrote {} bytes", bytes.len()), &output);
+    if let (Some(stats), Some(encode_report)) = (args.stats, encode_report) {
+        print_stats(stats, &encode_report, &output);
+    }
+    Ok(())
+}
+
+/// Encode a WAV stream read from the standard input
+///
+/// The blocks are encoded as the samples arrive. The number of samples is
+/// taken from the size of the data chunk, if the header gives it, and the
+/// other totals are only filled in if the output is a file. Ogg streams are
+/// still built in memory.
+fn encode_stream(args: &EncodeArgs) -> Result <(), CliError> {
+    // The foreign chunks are read again from the input file
+    if args.keep_foreign_metadata {
+        return Err(CliError::InvalidValueError(format!("--keep-foreign-metadata {}", args.input)));
+    }
+    let stream = WaveReader::read_pcm_stream(io::stdin().lock()).map_err(CliError::WaveError)?;
+    match stream.fmt_header.bps {
+        4..=8 => encode_stream_as::<i8>(args, stream),
+        9..=16 => encode_stream_as::<i16>(args, stream),
+        17..=32 => encode_stream_as::<i32>(args, stream),
+        _ => Err(CliError::WaveError(WaveReaderError::UnsupportedFormatError)),
+    }
+}
+
+fn encode_stream_as <T: Sample> (args: &EncodeArgs, mut stream: PCMWaveStream <impl Read>) -> Result <(), CliError> {
+    let format = stream.fmt_header;
+    let total_samples = stream.total_samples();
+    let metadata = encoder_metadata(args, &stream.info_tags, &format, total_samples.unwrap_or(0))?;
+    let options = encoder_options::<T>(args, &format, metadata);
+    let output = args.output.clone().unwrap_or(STDIO_PATH.to_string());
+
+    if args.ogg {
+        let mut channels = vec![Vec::new(); format.num_channels as usize];
+        loop {
+            let block = read_block::<T>(&mut stream, args.block_size)?;
+            if block.first().is_none_or(|channel| channel.is_empty()) {
+                break;
+            }
+            for (channel, samples) in channels.iter_mut().zip(block) {
+                channel.extend(samples);
+            }
+        }
+        let (bytes, encode_report) = match args.stats {
+            Some(_) => options.encode_with_report(&channels).map(|(bytes, encode_report)| (bytes, Some(encode_report))),
+            None => options.encode(&channels).map(|bytes| (bytes, None)),
+        }.map_err(CliError::MetadataError)?;
+        write_output(&output, &bytes)?;
+        report(args, &format!("wrote {} bytes", bytes.len()), &output);
+        if let (Some(stats), Some(encode_report)) = (args.stats, encode_report) {
+            print_stats(stats, &encode_report, &output);
+        }
+        return Ok(());
+    }
+
+    let (stream_info, encode_report) = match output.as_str() {
+        STDIO_PATH => {
+            let mut encoder = FlacStreamEncoder::new(options, io::BufWriter::new(io::stdout().lock()), total_samples).map_err(encoder_error(&output))?;
+            feed_encoder(&mut stream, &mut encoder, args.block_size, &output)?;
+            let (_, stream_info, encode_report) = encoder.finish().map_err(encoder_error(&output))?;
+            (stream_info, encode_report)
+        }
+        _ => {
+            let file = File::create(&output).map_err(|_| CliError::WriteError(output.clone()))?;
+            let mut encoder = FlacStreamEncoder::new(options, io::BufWriter::new(file), total_samples).map_err(encoder_error(&output))?;
+            feed_encoder(&mut stream, &mut encoder, args.block_size, &output)?;
+            let (_, stream_info, encode_report) = encoder.finish_seekable().map_err(encoder_error(&output))?;
+            (stream_info, encode_report)
+        }
+    };
+
+    report(args, &format!("encoded {} samples", stream_info.total_samples), &output);
+    if let Some(stats) = args.stats {
+        print_stats(stats, &encode_report, &output);
+    }
+    Ok(())
+}
+
+fn read_block <T: Sample> (stream: &mut PCMWaveStream <impl Read>, block_size: u64) -> Result <Vec <Vec <T>>, CliError> {
+    stream.read_samples(block_size as usize).map_err(CliError::WaveError)
+}
+
+/// Pass every sample of a WAV stream to a stream encoder, one block at a time
+fn feed_encoder <T: Sample> (stream: &mut PCMWaveStream <impl Read>, encoder: &mut FlacStreamEncoder <impl Write, T>, block_size: u64, output: &str) -> Result <(), CliError> {
+    loop {
+        let block = read_block(stream, block_size)?;
+        if block.first().is_none_or(|channel| channel.is_empty()) {
+            return Ok(());
+        }
+        encoder.write_samples(&block).map_err(encoder_error(output))?;
+    }
+}
+
+/// Decode a FLAC file into a WAV file
+///
+/// The RIFF chunks kept by `--keep-foreign-metadata` are written back around
+/// the samples, so the WAV file matches the original one.
+fn decode(args: &DecodeArgs) -> Result <(), CliError> {
+    let mut bytes = fs::read(&args.input).map_err(|_| CliError::ReadError(args.input.clone()))?;
+    if args.ogg {
+        bytes = OggFlacReader::to_native(&bytes).map_err(CliError::OggError)?;
+    }
+    let mut decoder = FlacDecoder::new(&bytes).map_err(CliError::DecoderError)?;
+    let stream_info = decoder.stream_info().clone();
+    let foreign_metadata = ForeignMetadata::from_blocks(decoder.metadata()).map_err(CliError::MetadataError)?;
+    let channels = decoder.read_samples(u64::MAX).map_err(CliError::DecoderError)?;
+    let output = match &args.output {
+        Some(output) => output.clone(),
+        None => format!("{}.wav", args.input.strip_suffix(if args.ogg { ".oga" } else { ".flac" }).unwrap_or(&args.input)),
+    };
+
+    let format = PCMWaveFormatChunk { num_channels: stream_info.n_channels as u16, samp_rate: stream_info.sample_rate, bps: stream_info.bps as u16 };
+    let mut wav = Vec::new();
+    match &foreign_metadata {
+        Some(foreign_metadata) => {
+            let n_samples = channels.first().map_or(0, |channel| channel.len()) as u64;
+            if foreign_metadata.data_size() as u64 != n_samples * format.block_align() as u64 {
+                return Err(CliError::MetadataError(MetadataError::InvalidForeignMetadataError));
+            }
+            foreign_metadata.write_wav(&mut wav, &format, &channels)
+        }
+        None => WaveWriter::write_pcm(&mut wav, &format, &channels),
+    }.map_err(|_| CliError::WriteError(args.input.clone()))?;
+
+    fs::write(&output, &wav).map_err(|_| CliError::WriteError(output.clone()))?;
+
+    println!("{}: wrote {} bytes to {}", args.input, wav.len(), output);
+    Ok(())
+}
+
+/// Describe every frame and subframe of a FLAC file
+///
+/// The description is written one frame at a time, so that the frames read
+/// before a corrupt frame are still written.
+fn analyze(args: &AnalyzeArgs) -> Result <(), CliError> {
+    let mut bytes = fs::read(&args.input).map_err(|_| CliError::ReadError(args.input.clone()))?;
+    if args.ogg {
+        bytes = OggFlacReader::to_native(&bytes).map_err(CliError::OggError)?;
+    }
+    let mut decoder = FlacDecoder::new(&bytes).map_err(CliError::DecoderError)?;
+    let options = AnalyzerOptions::new(args.format).residuals(args.residuals);
+    let output = args.output.as_deref().unwrap_or(STDIO_PATH);
+    let mut writer: BufWriter <Box <dyn Write>> = BufWriter::new(match output {
+        STDIO_PATH => Box::new(io::stdout().lock()),
+        _ => Box::new(File::create(output).map_err(|_| CliError::WriteError(output.to_string()))?),
+    });
+    let write_error = |_| CliError::WriteError(output.to_string());
+
+    writer.write_all(options.header(decoder.stream_info()).as_bytes()).map_err(write_error)?;
+    let mut index = 0;
+    while let Some((offset, len, frame)) = decoder.parse_frame().map_err(CliError::DecoderError)? {
+        writer.write_all(options.frame(index, offset, len, &frame).as_bytes()).map_err(write_error)?;
+        index += 1;
+    }
+    writer.write_all(options.footer().as_bytes()).map_err(write_error)?;
+    writer.flush().map_err(write_error)
+}
+
+/// Describe the metadata blocks of a FLAC file, numbered from the STREAMINFO block
+fn describe_metadata(editor: &Editor) -> String {
+    let stream_info = editor.stream_info();
+    let md5: String = stream_info.md5.iter().map(|byte| format!("{:02x}", byte)).collect();
+    let mut lines = vec![
+        format!("#0 STREAMINFO, {} bytes", StreamInfo::LENGTH),
+        format!("  {} Hz, {} channels, {}-bit, {} samples", stream_info.sample_rate, stream_info.n_channels, stream_info.bps, stream_info.total_samples),
+        format!("  block size {}-{}, frame size {}-{}", stream_info.min_block_size, stream_info.max_block_size, stream_info.min_frame_size, stream_info.max_frame_size),
+        format!("  MD5 {}", md5),
+    ];
+
+    for (i, block) in editor.blocks().iter().enumerate() {
+        lines.push(format!("#{} {}, {} bytes", i + 1, block.block_type().name(), block.to_bytes().len()));
+        match block {
+            MetadataBlock::SeekTable(seek_table) => {
+                let n_placeholders = seek_table.points.iter().filter(|point| point.is_placeholder()).count();
+                lines.push(format!("  {} seek points, {} placeholders", seek_table.points.len() - n_placeholders, n_placeholders));
+            }
+            MetadataBlock::VorbisComment(vorbis_comment) => {
+                lines.push(format!("  vendor {}", vorbis_comment.vendor));
+                lines.extend(vorbis_comment.iter().map(|(field, value)| format!("  {}={}", field, value)));
+            }
+            MetadataBlock::Application { id, .. } => lines.push(format!("  application ID {}", String::from_utf8_lossy(id))),
+            MetadataBlock::CueSheet(cue_sheet) => {
+                let n_tracks = cue_sheet.tracks.len().saturating_sub(1);
+                lines.push(format!("  {} tracks{}{}", n_tracks, if cue_sheet.is_cd { ", CD" } else { "" }, if cue_sheet.catalog.is_empty() { String::new() } else { format!(", catalog {}", cue_sheet.catalog) }));
+            }
+            MetadataBlock::Picture(picture) => {
+                lines.push(format!("  {:?}, {}, {}x{}, {}-bit, \"{}\"", picture.picture_type, picture.mime_type, picture.width, picture.height, picture.depth, picture.description));
+            }
+            _ => {}
+        }
+    }
+    lines.join("\n")
+}
+
+/// Apply an edit of `midp meta`
+fn apply_edit(editor: &mut Editor, edit: &MetaEdit) -> Result <(), CliError> {
+    match edit {
+        MetaEdit::AddTag(field, value) => editor.vorbis_comment_mut().add(field, value).map_err(CliError::MetadataError)?,
+        MetaEdit::SetTag(field, value) => editor.vorbis_comment_mut().set(field, value).map_err(CliError::MetadataError)?,
+        MetaEdit::RemoveTag(field) => {
+            editor.vorbis_comment_mut().remove(field);
+        }
+        MetaEdit::AddPicture(path) => editor.add(MetadataBlock::Picture(read_picture(path)?)),
+        MetaEdit::RemoveBlock(index) => {
+            editor.remove(index - 1).ok_or(CliError::InvalidValueError(format!("--remove-block {}", index)))?;
+        }
+        MetaEdit::RemoveType(block_type) => {
+            editor.remove_type(*block_type);
+        }
+    }
+    Ok(())
+}
+
+fn meta(args: &MetaArgs) -> Result <(), CliError> {
+    let mut editor = Editor::open(&args.input).map_err(CliError::EditorError)?;
+    for edit in &args.edits {
+        apply_edit(&mut editor, edit)?;
+    }
+    if !args.edits.is_empty() {
+        editor.save().map_err(CliError::EditorError)?;
+    }
+    if let Some(path) = &args.export_cuesheet {
+        let cue_sheet = editor.blocks().iter().find_map(|block| match block {
+            MetadataBlock::CueSheet(cue_sheet) => Some(cue_sheet),
+            _ => None,
+        }).ok_or(CliError::MissingBlockError(BlockType::CueSheet.name().to_string()))?;
+        let file_name = std::path::Path::new(&args.input).file_name().map_or(args.input.clone(), |name| name.to_string_lossy().to_string());
+        fs::write(path, cue_sheet.to_cue(editor.stream_info().sample_rate, &file_name)).map_err(|_| CliError::WriteError(path.clone()))?;
+    }
+    if args.list {
+        println!("{}", describe_metadata(&editor));
+    }
+    Ok(())
+}
+
+/// Run the command-line interface with the given arguments, excluding the program name
+pub fn run(args: &[String]) -> Result <(), CliError> {
+    match parse_args(args)? {
+        Command::Encode(encode_args) => encode(&encode_args),
+        Command::Decode(decode_args) => decode(&decode_args),
+        Command::Info(input) => {
+            let wave_info = WaveReader::open_pcm(&input).map_err(CliError::WaveError)?;
+            println!("{}", wave_info);
+            Ok(())
+        }
+        Command::Meta(meta_args) => meta(&meta_args),
+        Command::Analyze(analyze_args) => analyze(&analyze_args),
+        Command::Help => {
+            println!("{}", USAGE);
+            Ok(())
+        }
+    }
+}
+
+impl error::Error for CliError {}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CliError::UsageError => write!(f, "Invalid arguments\n{}", USAGE),
+            CliError::UnknownCommandError(command) => write!(f, "Unknown command {}\n{}", command, USAGE),
+            CliError::UnknownOptionError(option) => write!(f, "Unknown option {}", option),
+            CliError::MissingValueError(option) => write!(f, "Missing value for {}", option),
+            CliError::InvalidValueError(arg) => write!(f, "Invalid value in {}", arg),
+            CliError::ReadError(path) => write!(f, "Error reading from {}", path),
+            CliError::WriteError(path) => write!(f, "Error writing to {}", path),
+            CliError::WaveError(e) => write!(f, "{}", e),
+            CliError::MetadataError(e) => write!(f, "{}", e),
+            CliError::EditorError(e) => write!(f, "{}", e),
+            CliError::CueSheetError(e) => write!(f, "{}", e),
+            CliError::MissingBlockError(block_type) => write!(f, "No {} block", block_type),
+            CliError::DecoderError(e) => write!(f, "{}", e),
+            CliError::EncoderError(e) => write!(f, "{}", e),
+            CliError::OggError(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec <String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse_encode_tags() {
+        let command = parse_args(&args(&["encode", "--tag", "ARTIST=Alice", "in.wav", "--tag=TITLE=A=B", "--tag-from-file", "LYRICS=lyrics.txt"])).unwrap();
+        let Command::Encode(encode_args) = command else { panic!("not an encode command") };
+
+        assert_eq!(encode_args.input, "in.wav");
+        assert_eq!(encode_args.tags, vec![
+            TagArg::Value("ARTIST".to_string(), "Alice".to_string()),
+            TagArg::Value("TITLE".to_string(), "A=B".to_string()),
+            TagArg::File("LYRICS".to_string(), "lyrics.txt".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn test_parse_encode_options() {
+        let command = parse_args(&args(&["encode", "-b", "1152", "--variable-blocksize", "-e", "-S", "10s", "-o", "out.flac", "--picture", "cover.jpg", "in.wav"])).unwrap();
+        let Command::Encode(encode_args) = command else { panic!("not an encode command") };
+
+        assert_eq!(encode_args.block_size, 1152);
+        assert!(encode_args.variable_blocksize);
+        assert!(encode_args.exhaustive);
+        assert_eq!(encode_args.seek_spacing, Some(SeekSpacing::Seconds(10.0)));
+        assert_eq!(encode_args.output, Some("out.flac".to_string()));
+        assert_eq!(encode_args.pictures, vec!["cover.jpg".to_string()]);
+        assert!(!encode_args.ogg);
+        assert_eq!(encode_args.stats, None);
+    }
+
+    #[test]
+    fn test_parse_stats() {
+        let Ok(Command::Encode(encode_args)) = parse_args(&args(&["encode", "--stats", "in.wav"])) else { panic!("not an encode command") };
+        assert_eq!(encode_args.stats, Some(StatsFormat::Text));
+        let Ok(Command::Encode(encode_args)) = parse_args(&args(&["encode", "--stats=json", "in.wav"])) else { panic!("not an encode command") };
+        assert_eq!(encode_args.stats, Some(StatsFormat::Json));
+        assert_eq!(parse_args(&args(&["encode", "--stats=xml", "in.wav"])), Err(CliError::InvalidValueError("--stats xml".to_string())));
+    }
+
+    #[test]
+    fn test_parse_ogg() {
+        let Ok(Command::Encode(encode_args)) = parse_args(&args(&["encode", "-o", "out.oga", "in.wav"])) else { panic!("not an encode command") };
+        assert!(encode_args.ogg);
+        let Ok(Command::Encode(encode_args)) = parse_args(&args(&["encode", "--ogg", "in.wav"])) else { panic!("not an encode command") };
+        assert!(encode_args.ogg);
+
+        assert_eq!(parse_args(&args(&["decode", "in.oga"])), Ok(Command::Decode(DecodeArgs { input: "in.oga".to_string(), output: None, ogg: true })));
+        assert_eq!(parse_args(&args(&["decode", "--ogg", "in.ogg"])), Ok(Command::Decode(DecodeArgs { input: "in.ogg".to_string(), output: None, ogg: true })));
+        assert_eq!(parse_args(&args(&["decode", "in.flac"])), Ok(Command::Decode(DecodeArgs { input: "in.flac".to_string(), output: None, ogg: false })));
+    }
+
+    #[test]
+    fn test_ogg_round_trip() {
+        let dir = std::env::temp_dir();
+        let wav_path = dir.join("midp_cli_ogg.wav").to_string_lossy().to_string();
+        let oga_path = dir.join("midp_cli_ogg.oga").to_string_lossy().to_string();
+        let decoded_path = dir.join("midp_cli_ogg_decoded.wav").to_string_lossy().to_string();
+
+        let format = PCMWaveFormatChunk { num_channels: 1, samp_rate: 22050, bps: 16 };
+        let mut wav = Vec::new();
+        WaveWriter::write_pcm(&mut wav, &format, &[(0..5000).map(|i| (i % 400) - 200).collect()]).unwrap();
+        fs::write(&wav_path, &wav).unwrap();
+
+        run(&args(&["encode", "-T", "TITLE=Song", "-o", &oga_path, &wav_path])).unwrap();
+        assert_eq!(&fs::read(&oga_path).unwrap()[..4], b"OggS");
+        run(&args(&["decode", "-o", &decoded_path, &oga_path])).unwrap();
+        assert_eq!(fs::read(&decoded_path).unwrap(), wav);
+        assert_eq!(run(&args(&["decode", "--ogg", "-o", &decoded_path, &wav_path])), Err(CliError::OggError(OggError::CaptureError)));
+
+        for path in [&wav_path, &oga_path, &decoded_path] {
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_20_bit_decode() {
+        let dir = std::env::temp_dir();
+        let wav_path = dir.join("midp_cli_20_bit.wav").to_string_lossy().to_string();
+        let flac_path = dir.join("midp_cli_20_bit.flac").to_string_lossy().to_string();
+        let decoded_path = dir.join("midp_cli_20_bit_decoded.wav").to_string_lossy().to_string();
+
+        let format = PCMWaveFormatChunk { num_channels: 2, samp_rate: 44100, bps: 20 };
+        let channels = vec![(0..3000).map(|i| (i * 331 % 1_000_000) - 500_000).collect::<Vec <i64>>(), vec![-(1 << 19); 3000]];
+        let mut wav = Vec::new();
+        WaveWriter::write_pcm(&mut wav, &format, &channels).unwrap();
+        fs::write(&wav_path, &wav).unwrap();
+
+        run(&args(&["encode", "-o", &flac_path, &wav_path])).unwrap();
+        run(&args(&["decode", "-o", &decoded_path, &flac_path])).unwrap();
+        let decoded = fs::read(&decoded_path).unwrap();
+        // 20-bit samples take 3 bytes each
+        assert_eq!(&decoded[32..34], &6u16.to_le_bytes());
+        assert_eq!(&decoded[40..44], &(3000u32 * 6).to_le_bytes());
+        assert_eq!(decoded.len(), 44 + 3000 * 6);
+        assert_eq!(decoded, wav);
+
+        for path in [&wav_path, &flac_path, &decoded_path] {
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_32_bit_round_trip() {
+        let dir = std::env::temp_dir();
+        let wav_path = dir.join("midp_cli_32_bit.wav").to_string_lossy().to_string();
+        let flac_path = dir.join("midp_cli_32_bit.flac").to_string_lossy().to_string();
+        let decoded_path = dir.join("midp_cli_32_bit_decoded.wav").to_string_lossy().to_string();
+
+        // Samples near the limits of i32, whose predictions overflow an i64 with wide coefficients
+        let format = PCMWaveFormatChunk { num_channels: 2, samp_rate: 48000, bps: 32 };
+        let channels = vec![
+            (0..6000).map(|i| i32::MAX as i64 - (i * i) % 5000).collect::<Vec <i64>>(),
+            (0..6000).map(|i| if i % 3 == 0 { i32::MIN as i64 + i } else { i32::MAX as i64 - i }).collect(),
+        ];
+        let mut wav = Vec::new();
+        WaveWriter::write_pcm(&mut wav, &format, &channels).unwrap();
+        fs::write(&wav_path, &wav).unwrap();
+
+        run(&args(&["encode", "-e", "-o", &flac_path, &wav_path])).unwrap();
+        run(&args(&["decode", "-o", &decoded_path, &flac_path])).unwrap();
+        assert_eq!(fs::read(&decoded_path).unwrap(), wav);
+
+        for path in [&wav_path, &flac_path, &decoded_path] {
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_parse_analyze_args() {
+        assert_eq!(parse_args(&args(&["analyze", "--format", "csv", "--residuals", "-o", "out.csv", "in.flac"])), Ok(Command::Analyze(AnalyzeArgs {
+            input: "in.flac".to_string(),
+            output: Some("out.csv".to_string()),
+            format: AnalysisFormat::Csv,
+            residuals: true,
+            ogg: false,
+        })));
+        let Ok(Command::Analyze(analyze_args)) = parse_args(&args(&["analyze", "in.oga"])) else { panic!("not an analyze command") };
+        assert_eq!((analyze_args.format, analyze_args.output, analyze_args.ogg), (AnalysisFormat::Text, None, true));
+        assert_eq!(parse_args(&args(&["analyze", "--format=xml", "in.flac"])), Err(CliError::InvalidValueError("--format xml".to_string())));
+    }
+
+    #[test]
+    fn test_analyze_corrupt_frame() {
+        let dir = std::env::temp_dir();
+        let flac_path = dir.join("midp_cli_analyze.flac").to_string_lossy().to_string();
+        let output_path = dir.join("midp_cli_analyze.txt").to_string_lossy().to_string();
+
+        let channels = vec![(0..10000).map(|i| (i % 300) - 150).collect()];
+        let mut bytes = FlacEncoderOptions::new(44100, 1, 16).encode(&channels).unwrap();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 0xFF;
+        fs::write(&flac_path, &bytes).unwrap();
+
+        assert_eq!(run(&args(&["analyze", "-o", &output_path, &flac_path])), Err(CliError::DecoderError(DecoderError::CrcMismatchError)));
+        // The frames before the corrupt one are still described
+        let text = fs::read_to_string(&output_path).unwrap();
+        assert!(text.lines().any(|line| line.starts_with("frame=1\t")));
+        assert!(!text.lines().any(|line| line.starts_with("frame=2\t")));
+
+        for path in [&flac_path, &output_path] {
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(parse_args(&args(&["encode", "--tag", "NOEQUALS", "in.wav"])), Err(CliError::InvalidValueError("--tag NOEQUALS".to_string())));
+        assert_eq!(parse_args(&args(&["encode", "in.wav", "--tag"])), Err(CliError::MissingValueError("--tag".to_string())));
+        assert_eq!(parse_args(&args(&["encode", "--bogus", "in.wav"])), Err(CliError::UnknownOptionError("--bogus".to_string())));
+        assert_eq!(parse_args(&args(&["encode", "-b", "8", "in.wav"])), Err(CliError::InvalidValueError("-b 8".to_string())));
+        assert_eq!(parse_args(&args(&["encode", "--seek-spacing=0s", "in.wav"])), Err(CliError::InvalidValueError("--seek-spacing 0s".to_string())));
+        assert_eq!(parse_args(&args(&["encode"])), Err(CliError::UsageError));
+        assert_eq!(parse_args(&args(&["decode"])), Err(CliError::UsageError));
+        assert_eq!(parse_args(&args(&["transcode"])), Err(CliError::UnknownCommandError("transcode".to_string())));
+    }
+
+    #[test]
+    fn test_parse_meta_args() {
+        let command = parse_args(&args(&["meta", "in.flac", "--set-tag", "TITLE=Song", "--remove-type=picture", "--remove-block", "2"])).unwrap();
+
+        assert_eq!(command, Command::Meta(MetaArgs {
+            input: "in.flac".to_string(),
+            list: false,
+            export_cuesheet: None,
+            edits: vec![
+                MetaEdit::SetTag("TITLE".to_string(), "Song".to_string()),
+                MetaEdit::RemoveType(BlockType::Picture),
+                MetaEdit::RemoveBlock(2),
+            ],
+        }));
+        assert_eq!(parse_args(&args(&["meta", "in.flac"])), Ok(Command::Meta(MetaArgs { input: "in.flac".to_string(), list: true, export_cuesheet: None, edits: Vec::new() })));
+        assert_eq!(parse_args(&args(&["meta", "in.flac", "--export-cuesheet", "out.cue"])), Ok(Command::Meta(MetaArgs {
+            input: "in.flac".to_string(),
+            list: false,
+            export_cuesheet: Some("out.cue".to_string()),
+            edits: Vec::new(),
+        })));
+        assert_eq!(parse_args(&args(&["meta", "--remove-type", "STREAMINFO", "in.flac"])), Err(CliError::InvalidValueError("--remove-type STREAMINFO".to_string())));
+        assert_eq!(parse_args(&args(&["meta", "--remove-block", "0", "in.flac"])), Err(CliError::InvalidValueError("--remove-block 0".to_string())));
+    }
+
+    #[test]
+    fn test_meta_edits_file() {
+        let path = std::env::temp_dir().join("midp_cli_meta.flac").to_string_lossy().to_string();
+        let bytes = FlacEncoderOptions::new(44100, 1, 16).padding(512).encode(&[vec![0; 1000]]).unwrap();
+        fs::write(&path, bytes).unwrap();
+
+        run(&args(&["meta", &path, "-T", "ARTIST=Alice", "--tag", "ARTIST=Bob", "--set-tag", "TITLE=Song"])).unwrap();
+        run(&args(&["meta", &path, "--remove-tag", "artist"])).unwrap();
+
+        let editor = Editor::open(&path).unwrap();
+        let description = describe_metadata(&editor);
+        assert!(description.contains("#1 VORBIS_COMMENT"), "{}", description);
+        assert!(description.contains("  TITLE=Song\n#2 PADDING"), "{}", description);
+        assert!(!description.contains("ARTIST"), "{}", description);
+        assert_eq!(run(&args(&["meta", &path, "--remove-block", "3"])), Err(CliError::InvalidValueError("--remove-block 3".to_string())));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_export_cuesheet() {
+        let dir = std::env::temp_dir();
+        let path = dir.join("midp_cli_cuesheet.flac").to_string_lossy().to_string();
+        let cue_path = dir.join("midp_cli_cuesheet.cue").to_string_lossy().to_string();
+        let cue_sheet = CueSheet::from_cue("FILE a.wav WAVE\nTRACK 01 AUDIO\nINDEX 01 00:00:00\nTRACK 02 AUDIO\nINDEX 01 00:00:10", 44100, 16, 1, 11760).unwrap();
+        let bytes = FlacEncoderOptions::new(44100, 1, 16)
+            .metadata_block(MetadataBlock::CueSheet(cue_sheet))
+            .encode(&[vec![0; 11760]])
+            .unwrap();
+        fs::write(&path, bytes).unwrap();
+
+        run(&args(&["meta", &path, "--export-cuesheet", &cue_path])).unwrap();
+
+        let text = fs::read_to_string(&cue_path).unwrap();
+        assert!(text.starts_with("FILE \"midp_cli_cuesheet.flac\" WAVE\n"), "{}", text);
+        assert!(text.ends_with("  TRACK 02 AUDIO\n    INDEX 01 00:00:10\n"), "{}", text);
+        fs::remove_file(&cue_path).unwrap();
+        run(&args(&["meta", &path, "--remove-type", "cuesheet"])).unwrap();
+        assert_eq!(run(&args(&["meta", &path, "--export-cuesheet", &cue_path])), Err(CliError::MissingBlockError("CUESHEET".to_string())));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_read_cue_sheet_errors() {
+        let format = PCMWaveFormatChunk { num_channels: 2, samp_rate: 44100, bps: 16 };
+        assert_eq!(read_cue_sheet("midp_missing.cue", &format, 0), Err(CliError::ReadError("midp_missing.cue".to_string())));
+        assert_eq!(read_cue_sheet("Cargo.toml", &format, 0), Err(CliError::CueSheetError(CueSheetError::NoTracksError)));
+    }
+
+    #[test]
+    fn test_keep_foreign_metadata() {
+        let dir = std::env::temp_dir();
+        let wav_path = dir.join("midp_cli_foreign.wav").to_string_lossy().to_string();
+        let flac_path = dir.join("midp_cli_foreign.flac").to_string_lossy().to_string();
+        let decoded_path = dir.join("midp_cli_foreign_decoded.wav").to_string_lossy().to_string();
+
+        let format = PCMWaveFormatChunk { num_channels: 2, samp_rate: 44100, bps: 16 };
+        let channels = vec![(0..3000).map(|i| (i % 300) - 150).collect::<Vec <i64>>(), vec![7; 3000]];
+        let mut wav = Vec::new();
+        WaveWriter::write_pcm(&mut wav, &format, &channels).unwrap();
+        // Insert a bext chunk before the data chunk and append an odd-sized iXML chunk
+        wav.splice(36..36, b"bext\x04\x00\x00\x00abcd".iter().copied());
+        wav.extend_from_slice(b"iXML\x05\x00\x00\x00<x/>\n\x00");
+        let riff_size = (wav.len() as u32 - 8).to_le_bytes();
+        wav[4..8].copy_from_slice(&riff_size);
+        fs::write(&wav_path, &wav).unwrap();
+
+        run(&args(&["encode", "--keep-foreign-metadata", "-o", &flac_path, &wav_path])).unwrap();
+        run(&args(&["decode", "-o", &decoded_path, &flac_path])).unwrap();
+        assert_eq!(fs::read(&decoded_path).unwrap(), wav);
+
+        // Without the foreign metadata, a canonical WAV file is written
+        run(&args(&["meta", "--remove-type", "APPLICATION", &flac_path])).unwrap();
+        run(&args(&["decode", "-o", &decoded_path, &flac_path])).unwrap();
+        let mut canonical = Vec::new();
+        WaveWriter::write_pcm(&mut canonical, &format, &channels).unwrap();
+        assert_eq!(fs::read(&decoded_path).unwrap(), canonical);
+
+        for path in [&wav_path, &flac_path, &decoded_path] {
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_build_vorbis_comment_order() {
+        let info_tags = vec![
+            ("INAM".to_string(), "Song".to_string()),
+            ("IXYZ".to_string(), "ignored".to_string()),
+        ];
+        let tags = vec![TagArg::Value("ARTIST".to_string(), "Alice".to_string())];
+
+        let vorbis_comment = build_vorbis_comment(&info_tags, &tags).unwrap();
+
+        let fields: Vec <(&str, &str)> = vorbis_comment.iter().collect();
+        assert_eq!(fields, vec![("TITLE", "Song"), ("ARTIST", "Alice")]);
+    }
+
+    #[test]
+    fn test_build_vorbis_comment_missing_file() {
+        let tags = vec![TagArg::File("LYRICS".to_string(), "midp_missing_lyrics.txt".to_string())];
+        assert_eq!(build_vorbis_comment(&[], &tags), Err(CliError::ReadError("midp_missing_lyrics.txt".to_string())));
+    }
+
+    #[test]
+    fn test_read_picture_errors() {
+        assert_eq!(read_picture("midp_missing_cover.jpg"), Err(CliError::ReadError("midp_missing_cover.jpg".to_string())));
+        assert_eq!(read_picture("Cargo.toml"), Err(CliError::MetadataError(MetadataError::UnknownImageError)));
+    }
+}
@@ -0,0 +1,287 @@
+use crate::flac::encoder::frame::BlockingStrategy;
+use crate::flac::encoder::report::SubframeReport;
+use crate::flac::encoder::subframe::Subframe;
+use crate::flac::metadata::StreamInfo;
+
+use super::frame::ParsedFrame;
+use super::rice::PartitionCoding;
+use super::subframe::ParsedSubframe;
+
+/// Columns of the CSV output, one row per subframe
+pub const CSV_COLUMNS: &str = "frame,offset,bytes,first_sample,block_size,blocking,number,channel_assignment,crc16,\
+subframe,type,order,bps,wasted_bits,bits,value,qlp_precision,qlp_shift,residual_method,partition_order,warmup,qlp_coeffs,parameters,residuals";
+
+/// Represents the output format of the analyzer
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AnalysisFormat {
+    Text,
+    Csv,
+    Json,
+}
+
+/// Represents the options of the analyzer of a FLAC stream
+///
+/// The output is built one frame at a time: `header`, then `frame` for each
+/// frame in stream order, then `footer`. Residuals are left out unless
+/// `residuals` is set, as they make up most of the output.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AnalyzerOptions {
+    format: AnalysisFormat,
+    residuals: bool,
+}
+
+/// Represents the fields of a subframe shared by every format
+///
+/// Lists are empty and optional fields are `None` where they do not apply to
+/// the subframe.
+struct SubframeFields <'a> {
+    report: SubframeReport,
+    value: Option <i64>,
+    warmup: &'a [i64],
+    qlp_coeffs: &'a [i64],
+    residuals: &'a [i32],
+}
+
+impl <'a> SubframeFields <'a> {
+    fn new(parsed: &'a ParsedSubframe) -> Self {
+        let (value, warmup, qlp_coeffs, residuals): (_, &[i64], &[i64], &[i32]) = match &parsed.subframe {
+            Subframe::Constant { value } => (Some(*value), &[], &[], &[]),
+            Subframe::Verbatim { .. } => (None, &[], &[], &[]),
+            Subframe::Fixed { warmup, residuals } => (None, warmup, &[], residuals),
+            Subframe::Lpc { qlp_coeffs, warmup, residuals, .. } => (None, warmup, qlp_coeffs, residuals),
+        };
+        SubframeFields { report: SubframeReport::new(&parsed.subframe, parsed.wasted_bits), value, warmup, qlp_coeffs, residuals }
+    }
+}
+
+fn blocking_name(blocking: &BlockingStrategy) -> &'static str {
+    match blocking {
+        BlockingStrategy::Fixed => "FIXED",
+        BlockingStrategy::Variable => "VARIABLE",
+    }
+}
+
+fn join <T: ToString> (values: &[T], separator: &str) -> String {
+    values.iter().map(|value| value.to_string()).collect::<Vec <String>>().join(separator)
+}
+
+fn optional <T: ToString> (value: Option <T>, none: &str) -> String {
+    value.map_or(none.to_string(), |value| value.to_string())
+}
+
+impl AnalyzerOptions {
+    pub fn new(format: AnalysisFormat) -> Self {
+        AnalyzerOptions { format, residuals: false }
+    }
+
+    pub fn residuals(mut self, residuals: bool) -> Self {
+        self.residuals = residuals;
+        self
+    }
+
+    /// Describe the stream before its frames
+    pub fn header(&self, stream_info: &StreamInfo) -> String {
+        match self.format {
+            AnalysisFormat::Text => format!(
+                "sample_rate={}\tchannels={}\tbps={}\ttotal_samples={}\tmin_block_size={}\tmax_block_size={}\n",
+                stream_info.sample_rate, stream_info.n_channels, stream_info.bps, stream_info.total_samples,
+                stream_info.min_block_size, stream_info.max_block_size,
+            ),
+            AnalysisFormat::Csv => format!("{}\n", CSV_COLUMNS),
+            AnalysisFormat::Json => format!(
+                "{{\"sample_rate\":{},\"channels\":{},\"bps\":{},\"total_samples\":{},\"min_block_size\":{},\"max_block_size\":{},\"frames\":[\n",
+                stream_info.sample_rate, stream_info.n_channels, stream_info.bps, stream_info.total_samples,
+                stream_info.min_block_size, stream_info.max_block_size,
+            ),
+        }
+    }
+
+    /// Describe the frame number `index`, which starts `offset` bytes into the stream and takes `len` bytes
+    pub fn frame(&self, index: usize, offset: usize, len: usize, frame: &ParsedFrame) -> String {
+        match self.format {
+            AnalysisFormat::Text => self.frame_text(index, offset, len, frame),
+            AnalysisFormat::Csv => self.frame_csv(index, offset, len, frame),
+            AnalysisFormat::Json => self.frame_json(index, offset, len, frame),
+        }
+    }
+
+    /// End the description of the stream after its last frame
+    pub fn footer(&self) -> String {
+        match self.format {
+            AnalysisFormat::Json => "]}\n".to_string(),
+            _ => String::new(),
+        }
+    }
+
+    fn frame_text(&self, index: usize, offset: usize, len: usize, frame: &ParsedFrame) -> String {
+        let header = &frame.header;
+        let mut lines = vec![format!(
+            "frame={}\toffset={}\tbytes={}\tfirst_sample={}\tblock_size={}\tsample_rate={}\tchannels={}\tbps={}\tblocking={}\tnumber={}\tchannel_assignment={}\tcrc16=0x{:04x}",
+            index, offset, len, frame.first_sample, header.block_size, header.sample_rate, header.n_channels, header.bps,
+            blocking_name(&header.blocking), header.number, frame.channel_assignment.name(), frame.crc16,
+        )];
+
+        for (i, parsed) in frame.subframes.iter().enumerate() {
+            let fields = SubframeFields::new(parsed);
+            let mut line = format!(
+                "\tsubframe={}\ttype={}\torder={}\tbps={}\twasted_bits={}\tbits={}",
+                i, fields.report.subframe_type.name(), fields.report.order, parsed.bps, parsed.wasted_bits, parsed.n_bits,
+            );
+            if let Some(value) = fields.value {
+                line += &format!("\tvalue={}", value);
+            }
+            if let (Some(precision), Some(shift)) = (fields.report.qlp_precision, fields.report.qlp_shift) {
+                line += &format!("\tqlp_precision={}\tqlp_shift={}", precision, shift);
+            }
+            if let Some(residual_coding) = &parsed.residual_coding {
+                line += &format!("\tresidual_method={}\tpartition_order={}", residual_coding.method.name(), residual_coding.partition_order);
+            }
+            lines.push(line);
+
+            lines.extend(fields.warmup.iter().enumerate().map(|(j, sample)| format!("\t\twarmup[{}]={}", j, sample)));
+            lines.extend(fields.qlp_coeffs.iter().enumerate().map(|(j, coeff)| format!("\t\tqlp_coeff[{}]={}", j, coeff)));
+            if let Some(residual_coding) = &parsed.residual_coding {
+                lines.extend(residual_coding.partitions.iter().enumerate().map(|(j, partition)| match partition {
+                    PartitionCoding::Rice(parameter) => format!("\t\tparameter[{}]={}", j, parameter),
+                    PartitionCoding::Escaped(n_bits) => format!("\t\traw_bits[{}]={}", j, n_bits),
+                }));
+            }
+            if self.residuals {
+                lines.extend(fields.residuals.iter().enumerate().map(|(j, residual)| format!("\t\tresidual[{}]={}", j, residual)));
+            }
+        }
+
+        lines.join("\n") + "\n"
+    }
+
+    fn frame_csv(&self, index: usize, offset: usize, len: usize, frame: &ParsedFrame) -> String {
+        let header = &frame.header;
+        let mut rows = String::new();
+        for (i, parsed) in frame.subframes.iter().enumerate() {
+            let fields = SubframeFields::new(parsed);
+            let coding = parsed.residual_coding.as_ref();
+            let parameters: Vec <String> = coding.map_or(Vec::new(), |coding| coding.partitions.iter().map(|partition| match partition {
+                PartitionCoding::Rice(parameter) => parameter.to_string(),
+                PartitionCoding::Escaped(n_bits) => format!("raw:{}", n_bits),
+            }).collect());
+
+            rows += &format!(
+                "{},{},{},{},{},{},{},{},0x{:04x},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{}\n",
+                index, offset, len, frame.first_sample, header.block_size, blocking_name(&header.blocking), header.number,
+                frame.channel_assignment.name(), frame.crc16,
+                i, fields.report.subframe_type.name(), fields.report.order, parsed.bps, parsed.wasted_bits, parsed.n_bits,
+                optional(fields.value, ""), optional(fields.report.qlp_precision, ""), optional(fields.report.qlp_shift, ""),
+                optional(coding.map(|coding| coding.method.name()), ""), optional(coding.map(|coding| coding.partition_order), ""),
+                join(fields.warmup, " "), join(fields.qlp_coeffs, " "), parameters.join(" "),
+                if self.residuals { join(fields.residuals, " ") } else { String::new() },
+            );
+        }
+        rows
+    }
+
+    /// Describe a frame as a JSON object, preceded by a comma unless it is the first frame
+    fn frame_json(&self, index: usize, offset: usize, len: usize, frame: &ParsedFrame) -> String {
+        let header = &frame.header;
+        let subframes: Vec <String> = frame.subframes.iter().map(|parsed| {
+            let fields = SubframeFields::new(parsed);
+            let coding = parsed.residual_coding.as_ref();
+            let partitions: Vec <String> = coding.map_or(Vec::new(), |coding| coding.partitions.iter().map(|partition| match partition {
+                PartitionCoding::Rice(parameter) => format!("{{\"parameter\":{}}}", parameter),
+                PartitionCoding::Escaped(n_bits) => format!("{{\"raw_bits\":{}}}", n_bits),
+            }).collect());
+            let residuals = match self.residuals {
+                true => format!(",\"residuals\":[{}]", join(fields.residuals, ",")),
+                false => String::new(),
+            };
+
+            format!(
+                "{{\"type\":\"{}\",\"order\":{},\"bps\":{},\"wasted_bits\":{},\"bits\":{},\"value\":{},\"qlp_precision\":{},\"qlp_shift\":{},\
+\"residual_method\":{},\"partition_order\":{},\"warmup\":[{}],\"qlp_coeffs\":[{}],\"partitions\":[{}]{}}}",
+                fields.report.subframe_type.name(), fields.report.order, parsed.bps, parsed.wasted_bits, parsed.n_bits,
+                optional(fields.value, "null"), optional(fields.report.qlp_precision, "null"), optional(fields.report.qlp_shift, "null"),
+                optional(coding.map(|coding| format!("\"{}\"", coding.method.name())), "null"),
+                optional(coding.map(|coding| coding.partition_order), "null"),
+                join(fields.warmup, ","), join(fields.qlp_coeffs, ","), partitions.join(","), residuals,
+            )
+        }).collect();
+
+        format!(
+            "{}{{\"frame\":{},\"offset\":{},\"bytes\":{},\"first_sample\":{},\"block_size\":{},\"sample_rate\":{},\"channels\":{},\"bps\":{},\
+\"blocking\":\"{}\",\"number\":{},\"channel_assignment\":\"{}\",\"crc16\":{},\"subframes\":[{}]}}\n",
+            if index == 0 { "" } else { "," },
+            index, offset, len, frame.first_sample, header.block_size, header.sample_rate, header.n_channels, header.bps,
+            blocking_name(&header.blocking), header.number, frame.channel_assignment.name(), frame.crc16, subframes.join(","),
+        )
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::flac::decoder::stream::FlacDecoder;
+    use crate::flac::encoder::stream::FlacEncoderOptions;
+
+    fn analyze(bytes: &[u8], options: &AnalyzerOptions) -> String {
+        let mut decoder = FlacDecoder::new(bytes).unwrap();
+        let mut output = options.header(decoder.stream_info());
+        let mut index = 0;
+        while let Some((offset, len, frame)) = decoder.parse_frame().unwrap() {
+            output += &options.frame(index, offset, len, &frame);
+            index += 1;
+        }
+        output + &options.footer()
+    }
+
+    fn stream() -> Vec <u8> {
+        let channels = vec![(0..5000).map(|i| ((i as f64 * 0.05).sin() * 1000.0) as i64).collect(), vec![-3; 5000]];
+        FlacEncoderOptions::new(44100, 2, 16).encode(&channels).unwrap()
+    }
+
+    #[test]
+    fn test_analyze_text() {
+        let bytes = stream();
+        let text = analyze(&bytes, &AnalyzerOptions::new(AnalysisFormat::Text).residuals(true));
+        let lines: Vec <&str> = text.lines().collect();
+
+        assert_eq!(lines[0], "sample_rate=44100\tchannels=2\tbps=16\ttotal_samples=5000\tmin_block_size=4096\tmax_block_size=4096");
+        assert!(lines[1].starts_with("frame=0\toffset=42\t"));
+        assert!(lines[1].contains("\tblock_size=4096\t") && lines[1].contains("\tchannel_assignment=INDEPENDENT\t"));
+        assert!(lines.contains(&"\tsubframe=1\ttype=CONSTANT\torder=0\tbps=16\twasted_bits=0\tbits=24\tvalue=-3"));
+        assert!(lines.iter().any(|line| line.starts_with("\t\twarmup[0]=")));
+        assert!(lines.iter().any(|line| line.starts_with("\t\tparameter[0]=")));
+        assert!(lines.iter().any(|line| line.starts_with("frame=1\t") && line.contains("\tfirst_sample=4096\t")));
+
+        // The residuals of the first subframe are listed up to the end of the block
+        let warmup = lines.iter().filter(|line| line.starts_with("\t\twarmup[")).count();
+        let residuals = lines.iter().filter(|line| line.starts_with("\t\tresidual[")).count();
+        assert_eq!(warmup + residuals, 5000);
+    }
+
+    #[test]
+    fn test_analyze_csv() {
+        let bytes = stream();
+        let csv = analyze(&bytes, &AnalyzerOptions::new(AnalysisFormat::Csv));
+        let rows: Vec <&str> = csv.lines().collect();
+
+        assert_eq!(rows[0], CSV_COLUMNS);
+        assert_eq!(rows.len(), 5);
+        assert!(rows.iter().skip(1).all(|row| row.split(',').count() == CSV_COLUMNS.split(',').count()));
+        assert!(rows[2].starts_with("0,42,"));
+        assert!(rows[2].contains(",1,CONSTANT,0,16,0,24,-3,,,,,,,,"));
+        assert!(rows[2].ends_with(",,,,"));
+    }
+
+    #[test]
+    fn test_analyze_json() {
+        let bytes = stream();
+        let json = analyze(&bytes, &AnalyzerOptions::new(AnalysisFormat::Json));
+
+        assert!(json.starts_with("{\"sample_rate\":44100,\"channels\":2,\"bps\":16,\"total_samples\":5000,"));
+        assert!(json.contains("\"frames\":[\n{\"frame\":0,\"offset\":42,"));
+        assert!(json.contains("\n,{\"frame\":1,"));
+        assert!(json.contains("{\"type\":\"CONSTANT\",\"order\":0,\"bps\":16,\"wasted_bits\":0,\"bits\":24,\"value\":-3,\"qlp_precision\":null,"));
+        assert!(!json.contains("\"residuals\""));
+        assert!(json.ends_with("]}\n"));
+    }
+}
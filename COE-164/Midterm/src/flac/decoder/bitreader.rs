@@ -0,0 +1,136 @@
+use super::DecoderError;
+
+/// Represents a big-endian bit stream reader
+///
+/// This is the counterpart of `BitWriter`: values are read most significant
+/// bit first from a byte slice.
+#[derive(Debug, Clone)]
+pub struct BitReader <'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl <'a> BitReader <'a> {
+    /// Create a reader positioned at the start of `data`
+    pub fn new(data: &'a [u8]) -> Self {
+        BitReader { data, pos: 0 }
+    }
+
+    /// Get the bytes being read
+    pub fn bytes(&self) -> &'a [u8] {
+        self.data
+    }
+
+    /// Read an unsigned value of `n_bits` bits
+    pub fn read_bits(&mut self, n_bits: u8) -> Result <u64, DecoderError> {
+        debug_assert!(n_bits <= 64);
+        if self.bits_left() < n_bits as usize {
+            return Err(DecoderError::UnexpectedEndError);
+        }
+
+        let mut value = 0u64;
+        let mut remaining = n_bits as usize;
+        while remaining > 0 {
+            let byte = self.data[self.pos / 8];
+            let offset = self.pos % 8;
+            let take = remaining.min(8 - offset);
+            let bits = (byte >> (8 - offset - take)) & ((1u16 << take) - 1) as u8;
+
+            value = (value << take) | bits as u64;
+            self.pos += take;
+            remaining -= take;
+        }
+
+        Ok(value)
+    }
+
+    /// Read a signed value of `n_bits` bits in two's complement
+    pub fn read_signed(&mut self, n_bits: u8) -> Result <i64, DecoderError> {
+        if n_bits == 0 {
+            return Ok(0);
+        }
+        let value = self.read_bits(n_bits)?;
+        let unused = 64 - n_bits as u32;
+        Ok(((value << unused) as i64) >> unused)
+    }
+
+    /// Read a unary value written as a run of zeros followed by a single one
+    pub fn read_unary(&mut self) -> Result <u64, DecoderError> {
+        let mut quotient = 0;
+        while self.read_bits(1)? == 0 {
+            quotient += 1;
+        }
+        Ok(quotient)
+    }
+
+    /// Skip the remaining bits of the current byte
+    pub fn align_to_byte(&mut self) {
+        self.pos = self.pos.div_ceil(8) * 8;
+    }
+
+    /// Check if the reader is at a byte boundary
+    pub fn is_byte_aligned(&self) -> bool {
+        self.pos.is_multiple_of(8)
+    }
+
+    /// Get the number of bits read so far
+    pub fn position_bits(&self) -> usize {
+        self.pos
+    }
+
+    /// Get the number of bits left to read
+    pub fn bits_left(&self) -> usize {
+        self.data.len() * 8 - self.pos
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_bits() {
+        let mut reader = BitReader::new(&[0xBE, 0xAB, 0xC0]);
+        assert_eq!(reader.read_bits(3), Ok(0b101));
+        assert_eq!(reader.read_bits(5), Ok(0b11110));
+        assert_eq!(reader.read_bits(12), Ok(0xABC));
+        assert_eq!(reader.bits_left(), 4);
+        assert_eq!(reader.read_bits(5), Err(DecoderError::UnexpectedEndError));
+    }
+
+    #[test]
+    fn test_read_wide_bits() {
+        let mut reader = BitReader::new(&[0x10, 0x12, 0x34, 0x56, 0x78, 0x9A, 0xBC, 0xDE, 0xF0]);
+        assert_eq!(reader.read_bits(4), Ok(1));
+        assert_eq!(reader.read_bits(64), Ok(0x0123_4567_89AB_CDEF));
+    }
+
+    #[test]
+    fn test_read_signed() {
+        let mut reader = BitReader::new(&[0xF8, 0x03]);
+        assert_eq!(reader.read_signed(4), Ok(-1));
+        assert_eq!(reader.read_signed(4), Ok(-8));
+        assert_eq!(reader.read_signed(8), Ok(3));
+        assert_eq!(reader.read_signed(0), Ok(0));
+    }
+
+    #[test]
+    fn test_read_unary() {
+        let mut reader = BitReader::new(&[0b1000_1000, 0x00, 0x01]);
+        assert_eq!(reader.read_unary(), Ok(0));
+        assert_eq!(reader.read_unary(), Ok(3));
+        assert_eq!(reader.read_unary(), Ok(18));
+        assert_eq!(reader.read_unary(), Err(DecoderError::UnexpectedEndError));
+    }
+
+    #[test]
+    fn test_align_to_byte() {
+        let mut reader = BitReader::new(&[0xFF, 0x5A]);
+        reader.read_bits(3).unwrap();
+        assert!(!reader.is_byte_aligned());
+        reader.align_to_byte();
+        assert_eq!(reader.position_bits(), 8);
+        assert_eq!(reader.read_bits(8), Ok(0x5A));
+    }
+}
@@ -0,0 +1,354 @@
+use crate::flac::encoder::crc::CrcOptions;
+use crate::flac::encoder::frame::{BlockingStrategy, Frame, FrameHeader};
+use crate::flac::metadata::StreamInfo;
+
+use super::bitreader::BitReader;
+use super::subframe::{ParsedSubframe, SubframeDecoder};
+use super::utf8::Utf8Decoder;
+use super::DecoderError;
+
+/// Represents how the channels of a frame are coded
+///
+/// Apart from `Independent`, the frame holds two channels, one of which is the
+/// difference of the left and right channels and takes one more bit.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ChannelAssignment {
+    Independent,
+    LeftSide,
+    SideRight,
+    MidSide,
+}
+
+/// Represents a decoded frame
+///
+/// `first_sample` is the number of the first sample of the frame in the stream,
+/// and each element of `channels` holds the samples of one channel.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DecodedFrame {
+    pub header: FrameHeader,
+    pub first_sample: u64,
+    pub channels: Vec <Vec <i64>>,
+}
+
+/// Represents a frame as it is coded in a stream
+///
+/// Each element of `subframes` holds one channel as coded, so a side channel
+/// is kept as the difference of the left and right channels.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParsedFrame {
+    pub header: FrameHeader,
+    pub channel_assignment: ChannelAssignment,
+    pub first_sample: u64,
+    pub subframes: Vec <ParsedSubframe>,
+    pub crc16: u16,
+}
+
+/// Represents a decoder of the frames of a stream
+pub struct FrameDecoder;
+
+impl ChannelAssignment {
+    /// Get the name of the channel assignment, such as `MID_SIDE`
+    pub fn name(&self) -> &'static str {
+        match self {
+            ChannelAssignment::Independent => "INDEPENDENT",
+            ChannelAssignment::LeftSide => "LEFT_SIDE",
+            ChannelAssignment::SideRight => "SIDE_RIGHT",
+            ChannelAssignment::MidSide => "MID_SIDE",
+        }
+    }
+}
+
+impl FrameDecoder {
+    /// Read a frame header, including its CRC-8
+    ///
+    /// Values that the header takes from the STREAMINFO block, such as an
+    /// uncoded sample rate or sample size, are filled in from `stream_info`.
+    ///
+    /// # Errors
+    /// `InvalidSyncError` is returned if the header does not start with a sync
+    /// code or its CRC-8 does not match, and `ReservedValueError` for a reserved
+    /// value in a field.
+    pub fn read_header(reader: &mut BitReader, stream_info: &StreamInfo) -> Result <(FrameHeader, ChannelAssignment), DecoderError> {
+        let start = reader.position_bits() / 8;
+        if !reader.is_byte_aligned() || reader.read_bits(14)? != 0b11111111111110 {
+            return Err(DecoderError::InvalidSyncError);
+        }
+        if reader.read_bits(1)? != 0 {
+            return Err(DecoderError::ReservedValueError);
+        }
+        let blocking = match reader.read_bits(1)? {
+            0 => BlockingStrategy::Fixed,
+            _ => BlockingStrategy::Variable,
+        };
+        let block_size_code = reader.read_bits(4)? as u8;
+        let sample_rate_code = reader.read_bits(4)? as u8;
+        let channel_code = reader.read_bits(4)? as u8;
+        let sample_size_code = reader.read_bits(3)? as u8;
+        if reader.read_bits(1)? != 0 {
+            return Err(DecoderError::ReservedValueError);
+        }
+        let number = Utf8Decoder::read(reader).map_err(|_| DecoderError::InvalidSyncError)?;
+
+        let block_size = match block_size_code {
+            0b0001 => 192,
+            0b0010..=0b0101 => 576 << (block_size_code - 0b0010),
+            0b0110 => reader.read_bits(8)? + 1,
+            0b0111 => reader.read_bits(16)? + 1,
+            0b1000..=0b1111 => 256 << (block_size_code - 0b1000),
+            _ => return Err(DecoderError::ReservedValueError),
+        };
+        let sample_rate = match sample_rate_code {
+            0b0000 => stream_info.sample_rate,
+            0b0001 => 88200,
+            0b0010 => 176400,
+            0b0011 => 192000,
+            0b0100 => 8000,
+            0b0101 => 16000,
+            0b0110 => 22050,
+            0b0111 => 24000,
+            0b1000 => 32000,
+            0b1001 => 44100,
+            0b1010 => 48000,
+            0b1011 => 96000,
+            0b1100 => reader.read_bits(8)? as u32 * 1000,
+            0b1101 => reader.read_bits(16)? as u32,
+            0b1110 => reader.read_bits(16)? as u32 * 10,
+            _ => return Err(DecoderError::InvalidSyncError),
+        };
+        let (n_channels, channel_assignment) = match channel_code {
+            0b0000..=0b0111 => (channel_code + 1, ChannelAssignment::Independent),
+            0b1000 => (2, ChannelAssignment::LeftSide),
+            0b1001 => (2, ChannelAssignment::SideRight),
+            0b1010 => (2, ChannelAssignment::MidSide),
+            _ => return Err(DecoderError::ReservedValueError),
+        };
+        let bps = match sample_size_code {
+            0b000 => stream_info.bps,
+            0b001 => 8,
+            0b010 => 12,
+            0b100 => 16,
+            0b101 => 20,
+            0b110 => 24,
+            0b111 => 32,
+            _ => return Err(DecoderError::ReservedValueError),
+        };
+
+        let end = reader.position_bits() / 8;
+        let crc8 = reader.read_bits(8)? as u8;
+        if crc8 != CrcOptions::crc8_smbus().build_crc8(&reader.bytes()[start..end]) {
+            return Err(DecoderError::InvalidSyncError);
+        }
+
+        let header = FrameHeader { blocking, number, block_size, sample_rate, n_channels, bps };
+        Ok((header, channel_assignment))
+    }
+
+    /// Get the number of the first sample of a frame
+    ///
+    /// Frames of a fixed blocking stream are numbered by frame, and all but the
+    /// last have the largest block size of the stream.
+    pub fn first_sample(header: &FrameHeader, stream_info: &StreamInfo) -> u64 {
+        match header.blocking {
+            BlockingStrategy::Fixed => header.number * stream_info.max_block_size as u64,
+            BlockingStrategy::Variable => header.number,
+        }
+    }
+
+    /// Read a whole frame starting at the start of `bytes`
+    ///
+    /// The decoded frame is returned with its size in bytes.
+    ///
+    /// # Errors
+    /// Returns the errors of `parse_frame` and `SubframeDecoder::restore_samples`.
+    pub fn read_frame(bytes: &[u8], stream_info: &StreamInfo) -> Result <(DecodedFrame, usize), DecoderError> {
+        let (parsed, len) = FrameDecoder::parse_frame(bytes, stream_info)?;
+        let mut channels = parsed.subframes.iter()
+            .map(|subframe| SubframeDecoder::restore_samples(subframe, parsed.header.block_size))
+            .collect::<Result <Vec <Vec <i64>>, _>>()?;
+
+        FrameDecoder::restore_channels(&mut channels, parsed.channel_assignment);
+        Ok((DecodedFrame { header: parsed.header, first_sample: parsed.first_sample, channels }, len))
+    }
+
+    /// Read a whole frame starting at the start of `bytes` without restoring its samples
+    ///
+    /// The parsed frame is returned with its size in bytes.
+    ///
+    /// # Errors
+    /// Returns the errors of `read_header` and `SubframeDecoder::parse_subframe`,
+    /// and `CrcMismatchError` if the CRC-16 of the frame does not match.
+    pub fn parse_frame(bytes: &[u8], stream_info: &StreamInfo) -> Result <(ParsedFrame, usize), DecoderError> {
+        let mut reader = BitReader::new(bytes);
+        let (header, channel_assignment) = FrameDecoder::read_header(&mut reader, stream_info)?;
+
+        let mut subframes = Vec::with_capacity(header.n_channels as usize);
+        for channel in 0..header.n_channels {
+            let is_side = matches!(
+                (channel_assignment, channel),
+                (ChannelAssignment::LeftSide, 1) | (ChannelAssignment::SideRight, 0) | (ChannelAssignment::MidSide, 1)
+            );
+            let bps = header.bps + is_side as u8;
+            subframes.push(SubframeDecoder::parse_subframe(&mut reader, bps, header.block_size)?);
+        }
+
+        reader.align_to_byte();
+        let len = reader.position_bits() / 8;
+        let crc16 = reader.read_bits(16)? as u16;
+        if crc16 != Frame::crc16(&bytes[..len]) {
+            return Err(DecoderError::CrcMismatchError);
+        }
+
+        let first_sample = FrameDecoder::first_sample(&header, stream_info);
+        Ok((ParsedFrame { header, channel_assignment, first_sample, subframes, crc16 }, len + 2))
+    }
+
+    /// Restore the left and right channels from a side channel
+    fn restore_channels(channels: &mut [Vec <i64>], channel_assignment: ChannelAssignment) {
+        if channel_assignment == ChannelAssignment::Independent {
+            return;
+        }
+        let (first, second) = channels.split_at_mut(1);
+        for (a, b) in first[0].iter_mut().zip(second[0].iter_mut()) {
+            match channel_assignment {
+                ChannelAssignment::LeftSide => *b = *a - *b,
+                ChannelAssignment::SideRight => *a += *b,
+                ChannelAssignment::MidSide => {
+                    let mid = (*a << 1) | (*b & 1);
+                    let side = *b;
+                    *a = (mid + side) >> 1;
+                    *b = (mid - side) >> 1;
+                }
+                ChannelAssignment::Independent => {}
+            }
+        }
+    }
+
+    /// Find the first frame that starts at or after `bytes[from]`
+    ///
+    /// A frame is found where a sync code is followed by a valid header. The
+    /// offset of the frame is returned with its header.
+    pub fn find_frame(bytes: &[u8], from: usize, stream_info: &StreamInfo) -> Option <(usize, FrameHeader)> {
+        let mut pos = from;
+        while pos + 1 < bytes.len() {
+            if bytes[pos] == 0xFF && bytes[pos + 1] & 0xFE == 0xF8 {
+                let mut reader = BitReader::new(&bytes[pos..]);
+                if let Ok((header, _)) = FrameDecoder::read_header(&mut reader, stream_info) {
+                    return Some((pos, header));
+                }
+            }
+            pos += 1;
+        }
+        None
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::flac::encoder::bitwriter::BitWriter;
+    use crate::flac::encoder::subframe::Subframe;
+
+    fn stream_info() -> StreamInfo {
+        StreamInfo {
+            min_block_size: 192,
+            max_block_size: 192,
+            min_frame_size: 0,
+            max_frame_size: 0,
+            sample_rate: 44100,
+            n_channels: 2,
+            bps: 16,
+            total_samples: 0,
+            md5: [0; 16],
+        }
+    }
+
+    #[test]
+    fn test_read_frame() {
+        let header = FrameHeader { blocking: BlockingStrategy::Fixed, number: 3, block_size: 192, sample_rate: 11025, n_channels: 2, bps: 16 };
+        let frame = Frame {
+            header: header.clone(),
+            subframes: vec![(Subframe::Constant { value: 5 }, 0), (Subframe::Verbatim { samples: (0..192).collect() }, 0)],
+        };
+        let bytes = frame.to_bytes();
+
+        let (decoded, len) = FrameDecoder::read_frame(&bytes, &stream_info()).unwrap();
+
+        assert_eq!(len, bytes.len());
+        assert_eq!(decoded.header, header);
+        assert_eq!(decoded.first_sample, 576);
+        assert_eq!(decoded.channels, vec![vec![5; 192], (0..192).collect()]);
+    }
+
+    #[test]
+    fn test_read_mid_side_frame() {
+        // Left 3 and right -2 are coded as mid 0 and side 5
+        let mut writer = BitWriter::new();
+        writer.write_bytes(&[0xFF, 0xF8, 0x10, 0xA8, 0x00]);
+        let crc8 = CrcOptions::crc8_smbus().build_crc8(writer.as_bytes());
+        writer.write_bits(crc8 as u64, 8);
+        Subframe::Constant { value: 0 }.write_with_wasted_bits(&mut writer, 16, 0);
+        Subframe::Constant { value: 5 }.write_with_wasted_bits(&mut writer, 17, 0);
+        writer.pad_to_byte();
+        let crc16 = Frame::crc16(writer.as_bytes());
+        writer.write_bits(crc16 as u64, 16);
+
+        let (decoded, _) = FrameDecoder::read_frame(&writer.into_bytes(), &stream_info()).unwrap();
+
+        assert_eq!(decoded.channels, vec![vec![3; 192], vec![-2; 192]]);
+    }
+
+    #[test]
+    fn test_parse_frame() {
+        let header = FrameHeader { blocking: BlockingStrategy::Fixed, number: 0, block_size: 192, sample_rate: 44100, n_channels: 2, bps: 16 };
+        let subframes = vec![(Subframe::Constant { value: 5 }, 2), (Subframe::Verbatim { samples: (0..192).collect() }, 0)];
+        let bytes = Frame { header: header.clone(), subframes }.to_bytes();
+
+        let (parsed, len) = FrameDecoder::parse_frame(&bytes, &stream_info()).unwrap();
+
+        assert_eq!(len, bytes.len());
+        assert_eq!(parsed.header, header);
+        assert_eq!(parsed.channel_assignment, ChannelAssignment::Independent);
+        assert_eq!(parsed.crc16, u16::from_be_bytes([bytes[len - 2], bytes[len - 1]]));
+        assert_eq!((&parsed.subframes[0].subframe, parsed.subframes[0].wasted_bits), (&Subframe::Constant { value: 5 }, 2));
+        assert_eq!(parsed.subframes[1].subframe, Subframe::Verbatim { samples: (0..192).collect() });
+        assert_eq!(parsed.subframes[1].n_bits, 8 + 192 * 16);
+    }
+
+    #[test]
+    fn test_read_corrupt_frame() {
+        let header = FrameHeader { blocking: BlockingStrategy::Fixed, number: 0, block_size: 192, sample_rate: 44100, n_channels: 1, bps: 16 };
+        let mut bytes = Frame { header, subframes: vec![(Subframe::Verbatim { samples: vec![7; 192] }, 0)] }.to_bytes();
+
+        bytes[20] ^= 0x01;
+        assert_eq!(FrameDecoder::read_frame(&bytes, &stream_info()), Err(DecoderError::CrcMismatchError));
+        bytes[4] ^= 0x01;
+        assert_eq!(FrameDecoder::read_frame(&bytes, &stream_info()), Err(DecoderError::InvalidSyncError));
+    }
+
+    #[test]
+    fn test_read_frame_restoring_out_of_range_samples() {
+        let header = FrameHeader { blocking: BlockingStrategy::Fixed, number: 0, block_size: 192, sample_rate: 44100, n_channels: 1, bps: 16 };
+        // Residuals that overflow an i64 with an order 4 fixed predictor
+        let subframe = Subframe::Fixed { warmup: vec![0; 4], residuals: vec![(1 << 30) - 1; 188] };
+        let bytes = Frame { header: header.clone(), subframes: vec![(subframe, 0)] }.to_bytes();
+        assert_eq!(FrameDecoder::read_frame(&bytes, &stream_info()), Err(DecoderError::InvalidResidualError));
+
+        // Residuals that only overflow the 16 bits of the samples
+        let subframe = Subframe::Fixed { warmup: vec![0], residuals: vec![1000; 191] };
+        let bytes = Frame { header, subframes: vec![(subframe, 0)] }.to_bytes();
+        assert_eq!(FrameDecoder::read_frame(&bytes, &stream_info()), Err(DecoderError::InvalidResidualError));
+    }
+
+    #[test]
+    fn test_find_frame() {
+        let header = FrameHeader { blocking: BlockingStrategy::Variable, number: 1000, block_size: 192, sample_rate: 44100, n_channels: 1, bps: 16 };
+        let frame = Frame { header: header.clone(), subframes: vec![(Subframe::Constant { value: 0 }, 0)] }.to_bytes();
+        // A false sync code before the frame
+        let mut bytes = vec![0x00, 0xFF, 0xF8, 0x00];
+        bytes.extend_from_slice(&frame);
+
+        assert_eq!(FrameDecoder::find_frame(&bytes, 0, &stream_info()), Some((4, header)));
+        assert_eq!(FrameDecoder::find_frame(&bytes, 5, &stream_info()), None);
+    }
+}
@@ -0,0 +1,43 @@
+use core::fmt;
+use std::error;
+
+use crate::flac::metadata::MetadataError;
+
+pub mod analyze;
+pub mod bitreader;
+pub mod frame;
+pub mod rice;
+pub mod stream;
+pub mod subframe;
+pub mod utf8;
+
+// Represents possible errors in the FLAC decoder
+#[derive(Debug)]
+#[derive(PartialEq)]
+pub enum DecoderError {
+    UnexpectedEndError,
+    ReservedValueError,
+    InvalidResidualError,
+    NotFlacError,
+    InvalidMetadataError(MetadataError),
+    InvalidSyncError,
+    CrcMismatchError,
+    SeekOutOfRangeError,
+}
+
+impl error::Error for DecoderError {}
+
+impl fmt::Display for DecoderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecoderError::UnexpectedEndError => write!(f, "Unexpected end of stream"),
+            DecoderError::ReservedValueError => write!(f, "Reserved value in stream"),
+            DecoderError::InvalidResidualError => write!(f, "Invalid residual coding"),
+            DecoderError::NotFlacError => write!(f, "Not a FLAC stream"),
+            DecoderError::InvalidMetadataError(e) => write!(f, "Invalid metadata block: {}", e),
+            DecoderError::InvalidSyncError => write!(f, "Invalid frame header"),
+            DecoderError::CrcMismatchError => write!(f, "Frame CRC mismatch"),
+            DecoderError::SeekOutOfRangeError => write!(f, "Seek past the end of stream"),
+        }
+    }
+}
@@ -0,0 +1,174 @@
+use crate::flac::encoder::rice::{RiceCodingMethod, RiceEncodedStream};
+
+use super::bitreader::BitReader;
+use super::DecoderError;
+
+/// Represents how one partition of a residual section is coded
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PartitionCoding {
+    Rice(u8),
+    Escaped(u8),
+}
+
+/// Represents the coding of a residual section as read from a subframe
+///
+/// Each partition holds either its Rice parameter or, if it is escaped, the
+/// size of its raw residuals.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResidualCoding {
+    pub method: RiceCodingMethod,
+    pub partition_order: u8,
+    pub partitions: Vec <PartitionCoding>,
+}
+
+/// Represents a decoder of Rice-coded residuals
+pub struct RiceDecoder;
+
+impl RiceDecoder {
+    /// Read a single Rice-coded residual
+    ///
+    /// The unary quotient and the `rice_param`-bit remainder are joined back into
+    /// the folded value, whose lowest bit holds the sign.
+    ///
+    /// # Errors
+    /// `InvalidResidualError` is returned if the residual does not fit in an `i32`.
+    pub fn read_rice(reader: &mut BitReader, rice_param: u8) -> Result <i32, DecoderError> {
+        let quotient = reader.read_unary()?;
+        if quotient > u32::MAX as u64 >> rice_param {
+            return Err(DecoderError::InvalidResidualError);
+        }
+        let folded = (quotient << rice_param) | reader.read_bits(rice_param)?;
+        if folded > u32::MAX as u64 {
+            return Err(DecoderError::InvalidResidualError);
+        }
+        Ok((folded >> 1) as i32 ^ -((folded & 1) as i32))
+    }
+
+    /// Decode `n_samples` residuals from a stream built by `RiceEncoderOptions::encode`
+    pub fn decode(stream: &RiceEncodedStream, n_samples: usize) -> Result <Vec <i32>, DecoderError> {
+        let mut reader = BitReader::new(&stream.stream);
+        (0..n_samples).map(|_| RiceDecoder::read_rice(&mut reader, stream.param)).collect()
+    }
+
+    /// Read the residual section of a subframe
+    ///
+    /// The returned residuals exclude the `predictor_order` warm-up samples, so it
+    /// contains `block_size - predictor_order` elements.
+    ///
+    /// # Errors
+    /// `ReservedValueError` is returned for a reserved coding method, and
+    /// `InvalidResidualError` if the partition order does not divide the block or
+    /// leaves the first partition smaller than the warm-up.
+    pub fn read_residual(reader: &mut BitReader, block_size: u64, predictor_order: u8) -> Result <Vec <i32>, DecoderError> {
+        RiceDecoder::read_residual_coding(reader, block_size, predictor_order).map(|(_, residuals)| residuals)
+    }
+
+    /// Read the residual section of a subframe like `read_residual`, along with its coding
+    ///
+    /// # Errors
+    /// Returns the errors of `read_residual`.
+    pub fn read_residual_coding(reader: &mut BitReader, block_size: u64, predictor_order: u8) -> Result <(ResidualCoding, Vec <i32>), DecoderError> {
+        let method = match reader.read_bits(2)? {
+            0b00 => RiceCodingMethod::Rice,
+            0b01 => RiceCodingMethod::Rice2,
+            _ => return Err(DecoderError::ReservedValueError),
+        };
+        let partition_order = reader.read_bits(4)? as u8;
+        let partition_size = block_size >> partition_order;
+        if partition_size << partition_order != block_size || partition_size < predictor_order as u64 {
+            return Err(DecoderError::InvalidResidualError);
+        }
+
+        let mut residuals = Vec::with_capacity((block_size - predictor_order as u64) as usize);
+        let mut partitions = Vec::with_capacity(1 << partition_order);
+        for i in 0..1u64 << partition_order {
+            let n_partition_samples = if i == 0 {
+                partition_size - predictor_order as u64
+            } else {
+                partition_size
+            };
+
+            let param = reader.read_bits(method.param_bits())? as u8;
+            if param == method.escape_code() {
+                let raw_bits = reader.read_bits(5)? as u8;
+                for _ in 0..n_partition_samples {
+                    // Raw residuals of up to 31 bits always fit in an i32
+                    residuals.push(reader.read_signed(raw_bits)? as i32);
+                }
+                partitions.push(PartitionCoding::Escaped(raw_bits));
+            } else {
+                for _ in 0..n_partition_samples {
+                    residuals.push(RiceDecoder::read_rice(reader, param)?);
+                }
+                partitions.push(PartitionCoding::Rice(param));
+            }
+        }
+
+        Ok((ResidualCoding { method, partition_order, partitions }, residuals))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_rice() {
+        // 18 folds to 36 = 0b10_0100: quotient 2 and remainder 0b0100 with parameter 4
+        let mut reader = BitReader::new(&[0b0010_1000]);
+        assert_eq!(RiceDecoder::read_rice(&mut reader, 4), Ok(18));
+    }
+
+    #[test]
+    fn test_read_rice_negative() {
+        // -3 folds to 5 = 0b101: quotient 1 and remainder 0b01 with parameter 2
+        let mut reader = BitReader::new(&[0b0101_0000]);
+        assert_eq!(RiceDecoder::read_rice(&mut reader, 2), Ok(-3));
+    }
+
+    #[test]
+    fn test_read_rice_out_of_range() {
+        // Quotient 3 and a remainder of 30 ones with parameter 30 fold to u32::MAX
+        let mut reader = BitReader::new(&[0b0001_1111, 0xFF, 0xFF, 0xFF, 0b1100_0000]);
+        assert_eq!(RiceDecoder::read_rice(&mut reader, 30), Ok(i32::MIN));
+
+        // Quotient 4 takes the folded value past u32::MAX
+        let mut reader = BitReader::new(&[0b0000_1000, 0, 0, 0, 0]);
+        assert_eq!(RiceDecoder::read_rice(&mut reader, 30), Err(DecoderError::InvalidResidualError));
+    }
+
+    #[test]
+    fn test_read_residual_escaped() {
+        // method 00, order 0000, escape 1111, raw size 00000
+        let mut reader = BitReader::new(&[0b0000_0011, 0b1100_0000]);
+        assert_eq!(RiceDecoder::read_residual(&mut reader, 16, 0), Ok(vec![0; 16]));
+    }
+
+    #[test]
+    fn test_read_residual_coding() {
+        // method 01, order 0001, parameter 00010 with 1 and -1, then escaped partition with raw size 00010
+        let mut reader = BitReader::new(&[0b0100_0100, 0b0101_1010, 0b1111_1100, 0b0101_0010]);
+        let (coding, residuals) = RiceDecoder::read_residual_coding(&mut reader, 4, 0).unwrap();
+
+        assert_eq!(coding, ResidualCoding {
+            method: RiceCodingMethod::Rice2,
+            partition_order: 1,
+            partitions: vec![PartitionCoding::Rice(2), PartitionCoding::Escaped(2)],
+        });
+        assert_eq!(residuals, vec![1, -1, -2, 1]);
+    }
+
+    #[test]
+    fn test_read_residual_reserved_method() {
+        let mut reader = BitReader::new(&[0b1000_0000, 0x00]);
+        assert_eq!(RiceDecoder::read_residual(&mut reader, 16, 0), Err(DecoderError::ReservedValueError));
+    }
+
+    #[test]
+    fn test_read_residual_bad_partition_order() {
+        // order 0010 does not divide a block of 10 samples
+        let mut reader = BitReader::new(&[0b0000_1000, 0x00]);
+        assert_eq!(RiceDecoder::read_residual(&mut reader, 10, 0), Err(DecoderError::InvalidResidualError));
+    }
+}
@@ -0,0 +1,335 @@
+use crate::flac::metadata::{BlockType, MetadataBlock, Picture, SeekTable, StreamInfo, VorbisComment};
+
+use super::bitreader::BitReader;
+use super::frame::{DecodedFrame, FrameDecoder, ParsedFrame};
+use super::DecoderError;
+
+/// Represents a decoder of a FLAC stream held in memory
+///
+/// The metadata blocks are read when the decoder is created. The STREAMINFO
+/// block is kept apart from the blocks that follow it. Samples are then read
+/// from the current position, which starts at the first sample and can be
+/// moved with `seek_to_sample`.
+pub struct FlacDecoder <'a> {
+    data: &'a [u8],
+    stream_info: StreamInfo,
+    metadata: Vec <MetadataBlock>,
+    frames_start: usize,
+    next_frame: usize,
+    buffer: Option <DecodedFrame>,
+    buffer_pos: usize,
+}
+
+impl <'a> FlacDecoder <'a> {
+    /// Read the stream marker and every metadata block of a stream
+    ///
+    /// # Errors
+    /// `NotFlacError` is returned if the stream does not start with `fLaC` or
+    /// its first block is not STREAMINFO, `ReservedValueError` if a block has the
+    /// invalid type 127, and `InvalidMetadataError` if a block cannot be parsed.
+    pub fn new(data: &'a [u8]) -> Result <Self, DecoderError> {
+        if !data.starts_with(b"fLaC") {
+            return Err(DecoderError::NotFlacError);
+        }
+
+        let mut pos = 4;
+        let mut stream_info = None;
+        let mut metadata = Vec::new();
+        loop {
+            let mut reader = BitReader::new(data.get(pos..pos + 4).ok_or(DecoderError::UnexpectedEndError)?);
+            let is_last = reader.read_bits(1)? == 1;
+            let block_type = BlockType::from_code(reader.read_bits(7)? as u8).ok_or(DecoderError::ReservedValueError)?;
+            let length = reader.read_bits(24)? as usize;
+            let body = data.get(pos + 4..pos + 4 + length).ok_or(DecoderError::UnexpectedEndError)?;
+            pos += 4 + length;
+
+            match (block_type, &stream_info) {
+                (BlockType::StreamInfo, None) => {
+                    stream_info = Some(StreamInfo::parse(body).map_err(DecoderError::InvalidMetadataError)?);
+                }
+                (_, None) => return Err(DecoderError::NotFlacError),
+                _ => metadata.push(MetadataBlock::parse(block_type, body).map_err(DecoderError::InvalidMetadataError)?),
+            }
+            if is_last {
+                break;
+            }
+        }
+
+        let stream_info = stream_info.ok_or(DecoderError::NotFlacError)?;
+        Ok(FlacDecoder { data, stream_info, metadata, frames_start: pos, next_frame: pos, buffer: None, buffer_pos: 0 })
+    }
+
+    /// Get the STREAMINFO block
+    pub fn stream_info(&self) -> &StreamInfo {
+        &self.stream_info
+    }
+
+    /// Get the metadata blocks after the STREAMINFO block, in stream order
+    pub fn metadata(&self) -> &[MetadataBlock] {
+        &self.metadata
+    }
+
+    /// Get the first VORBIS_COMMENT block, if any
+    pub fn vorbis_comment(&self) -> Option <&VorbisComment> {
+        self.metadata.iter().find_map(|block| match block {
+            MetadataBlock::VorbisComment(vorbis_comment) => Some(vorbis_comment),
+            _ => None,
+        })
+    }
+
+    /// Get the first SEEKTABLE block, if any
+    pub fn seek_table(&self) -> Option <&SeekTable> {
+        self.metadata.iter().find_map(|block| match block {
+            MetadataBlock::SeekTable(seek_table) => Some(seek_table),
+            _ => None,
+        })
+    }
+
+    /// Get every PICTURE block, in stream order
+    pub fn pictures(&self) -> impl Iterator <Item = &Picture> {
+        self.metadata.iter().filter_map(|block| match block {
+            MetadataBlock::Picture(picture) => Some(picture),
+            _ => None,
+        })
+    }
+
+    /// Get the encoded frames that follow the metadata blocks
+    pub fn frame_bytes(&self) -> &'a [u8] {
+        &self.data[self.frames_start..]
+    }
+
+    /// Get the number of the sample at the current position
+    pub fn current_sample(&self) -> u64 {
+        match &self.buffer {
+            Some(frame) => frame.first_sample + self.buffer_pos as u64,
+            None => FrameDecoder::find_frame(self.data, self.next_frame, &self.stream_info)
+                .map_or(self.stream_info.total_samples, |(_, header)| FrameDecoder::first_sample(&header, &self.stream_info)),
+        }
+    }
+
+    /// Read the next frame after the current position
+    ///
+    /// Any samples left from the frame at the current position are skipped.
+    /// `None` is returned at the end of the stream.
+    ///
+    /// # Errors
+    /// Returns the errors of `FrameDecoder::read_frame`.
+    pub fn read_frame(&mut self) -> Result <Option <DecodedFrame>, DecoderError> {
+        self.buffer = None;
+        if self.next_frame >= self.data.len() {
+            return Ok(None);
+        }
+        let (frame, len) = FrameDecoder::read_frame(&self.data[self.next_frame..], &self.stream_info)?;
+        self.next_frame += len;
+        Ok(Some(frame))
+    }
+
+    /// Parse the next frame after the current position without restoring its samples
+    ///
+    /// The frame is returned with its offset in the stream and its size in
+    /// bytes. `None` is returned at the end of the stream.
+    ///
+    /// # Errors
+    /// Returns the errors of `FrameDecoder::parse_frame`.
+    pub fn parse_frame(&mut self) -> Result <Option <(usize, usize, ParsedFrame)>, DecoderError> {
+        self.buffer = None;
+        if self.next_frame >= self.data.len() {
+            return Ok(None);
+        }
+        let offset = self.next_frame;
+        let (frame, len) = FrameDecoder::parse_frame(&self.data[offset..], &self.stream_info)?;
+        self.next_frame += len;
+        Ok(Some((offset, len, frame)))
+    }
+
+    /// Read up to `n_samples` samples of each channel from the current position
+    ///
+    /// Fewer samples are returned at the end of the stream.
+    ///
+    /// # Errors
+    /// Returns the errors of `FrameDecoder::read_frame`.
+    pub fn read_samples(&mut self, n_samples: u64) -> Result <Vec <Vec <i64>>, DecoderError> {
+        let mut channels = vec![Vec::new(); self.stream_info.n_channels as usize];
+        let mut n_left = n_samples;
+
+        while n_left > 0 {
+            if self.buffer.is_none() {
+                match self.read_frame()? {
+                    Some(frame) => self.buffer = Some(frame),
+                    None => break,
+                }
+                self.buffer_pos = 0;
+            }
+            let frame = self.buffer.as_ref().unwrap();
+            let len = frame.header.block_size - self.buffer_pos as u64;
+            let n_taken = len.min(n_left) as usize;
+            for (channel, samples) in channels.iter_mut().zip(&frame.channels) {
+                channel.extend_from_slice(&samples[self.buffer_pos..self.buffer_pos + n_taken]);
+            }
+
+            self.buffer_pos += n_taken;
+            n_left -= n_taken as u64;
+            if self.buffer_pos as u64 == frame.header.block_size {
+                self.buffer = None;
+            }
+        }
+
+        Ok(channels)
+    }
+
+    /// Move the current position to a sample
+    ///
+    /// The search starts from the closest seek point at or before the sample,
+    /// if there is a SEEKTABLE block. It then halves the range of bytes that
+    /// holds the frame of the sample, by looking for the first frame after the
+    /// middle, until the range is about one frame long. The frames in the range
+    /// are then decoded up to the sample.
+    ///
+    /// # Errors
+    /// `SeekOutOfRangeError` is returned if the stream ends before the sample,
+    /// and the errors of `FrameDecoder::read_frame` if a frame cannot be read.
+    pub fn seek_to_sample(&mut self, sample_number: u64) -> Result <(), DecoderError> {
+        let total_samples = self.stream_info.total_samples;
+        if total_samples != 0 && sample_number >= total_samples {
+            return Err(DecoderError::SeekOutOfRangeError);
+        }
+
+        // The frame of the sample starts at or after `low` and before `high`
+        let mut low = self.frames_start;
+        if let Some(point) = self.seek_table().and_then(|seek_table| seek_table.find(sample_number)) {
+            low = (self.frames_start as u64).saturating_add(point.offset).min(self.data.len() as u64) as usize;
+        }
+        let mut high = self.data.len();
+        let frame_size = (self.stream_info.max_frame_size as usize).max(1);
+
+        while high - low > frame_size {
+            let middle = low + (high - low) / 2;
+            match FrameDecoder::find_frame(&self.data[..high], middle, &self.stream_info) {
+                Some((pos, header)) if FrameDecoder::first_sample(&header, &self.stream_info) <= sample_number => low = pos,
+                _ => high = middle,
+            }
+        }
+
+        self.next_frame = low;
+        loop {
+            let frame = self.read_frame()?.ok_or(DecoderError::SeekOutOfRangeError)?;
+            if sample_number < frame.first_sample {
+                return Err(DecoderError::SeekOutOfRangeError);
+            }
+            if sample_number < frame.first_sample + frame.header.block_size {
+                self.buffer_pos = (sample_number - frame.first_sample) as usize;
+                self.buffer = Some(frame);
+                return Ok(());
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::flac::encoder::frame::BlockingStrategy;
+    use crate::flac::encoder::stream::{FlacEncoderOptions, SeekSpacing};
+    use crate::flac::metadata::PictureType;
+    use crate::flac::test_util::XorShift;
+
+    fn channels() -> Vec <Vec <i64>> {
+        vec![(0..1000).map(|i| (i % 100) - 50).collect()]
+    }
+
+    fn cover() -> Picture {
+        Picture {
+            picture_type: PictureType::FrontCover,
+            mime_type: "image/png".to_string(),
+            description: String::new(),
+            width: 1,
+            height: 1,
+            depth: 24,
+            n_colors: 0,
+            data: vec![1, 2, 3],
+        }
+    }
+
+    #[test]
+    fn test_read_metadata() {
+        let mut vorbis_comment = VorbisComment::new("test");
+        vorbis_comment.add("TITLE", "Song").unwrap();
+        let options = FlacEncoderOptions::new(44100, 1, 16)
+            .metadata_block(MetadataBlock::VorbisComment(vorbis_comment.clone()))
+            .metadata_block(MetadataBlock::Picture(cover()));
+        let channels = channels();
+        let bytes = options.encode(&channels).unwrap();
+
+        let decoder = FlacDecoder::new(&bytes).unwrap();
+
+        assert_eq!(decoder.stream_info().total_samples, 1000);
+        assert_eq!(decoder.stream_info().md5, options.md5(&channels));
+        assert_eq!(decoder.metadata().len(), 2);
+        assert_eq!(decoder.vorbis_comment(), Some(&vorbis_comment));
+        assert_eq!(decoder.pictures().collect::<Vec <_>>(), vec![&cover()]);
+        assert_eq!(decoder.frame_bytes()[..2], [0xFF, 0xF8]);
+    }
+
+    fn noise(len: usize, seed: u64) -> Vec <i64> {
+        (0..len).zip(XorShift::new(seed)).map(|(i, state)| {
+            ((i as f64 * 0.01).sin() * 3000.0) as i64 + (state >> 56) as i64
+        }).collect()
+    }
+
+    #[test]
+    fn test_read_samples() {
+        let channels = vec![noise(10000, 1), noise(10000, 2)];
+        let bytes = FlacEncoderOptions::new(44100, 2, 16).encode(&channels).unwrap();
+        let mut decoder = FlacDecoder::new(&bytes).unwrap();
+
+        let mut decoded = decoder.read_samples(1000).unwrap();
+        assert_eq!(decoder.current_sample(), 1000);
+        for (channel, rest) in decoded.iter_mut().zip(decoder.read_samples(u64::MAX).unwrap()) {
+            channel.extend(rest);
+        }
+
+        assert_eq!(decoded, channels);
+        assert_eq!(decoder.current_sample(), 10000);
+        assert_eq!(decoder.read_frame(), Ok(None));
+    }
+
+    #[test]
+    fn test_seek_to_sample() {
+        let channels = vec![noise(50000, 3)];
+        let with_table = FlacEncoderOptions::new(44100, 1, 16)
+            .block_size(1152)
+            .seek_spacing(SeekSpacing::Samples(10000))
+            .encode(&channels)
+            .unwrap();
+        let without_table = FlacEncoderOptions::new(44100, 1, 16)
+            .block_size(1152)
+            .encode(&channels)
+            .unwrap();
+        let variable = FlacEncoderOptions::new(44100, 1, 16)
+            .blocking(BlockingStrategy::Variable)
+            .encode(&channels)
+            .unwrap();
+
+        for bytes in [&with_table, &without_table, &variable] {
+            let mut decoder = FlacDecoder::new(bytes).unwrap();
+            for target in [0, 1, 1151, 1152, 23456, 40000, 49999, 7] {
+                decoder.seek_to_sample(target).unwrap();
+                assert_eq!(decoder.current_sample(), target);
+                let decoded = decoder.read_samples(100).unwrap();
+                let end = (target as usize + 100).min(50000);
+                assert_eq!(decoded[0], channels[0][target as usize..end], "seek to {}", target);
+            }
+            assert_eq!(decoder.seek_to_sample(50000), Err(DecoderError::SeekOutOfRangeError));
+        }
+        assert!(FlacDecoder::new(&with_table).unwrap().seek_table().is_some());
+    }
+
+    #[test]
+    fn test_read_invalid_stream() {
+        let bytes = FlacEncoderOptions::new(44100, 1, 16).encode(&channels()).unwrap();
+
+        assert_eq!(FlacDecoder::new(b"RIFF").err(), Some(DecoderError::NotFlacError));
+        assert_eq!(FlacDecoder::new(&bytes[..30]).err(), Some(DecoderError::UnexpectedEndError));
+    }
+}
@@ -0,0 +1,211 @@
+use crate::flac::encoder::subframe::Subframe;
+use crate::flac::lpc::fixed::FixedPredictor;
+use crate::flac::lpc::var::VarPredictor;
+
+use super::bitreader::BitReader;
+use super::rice::{ResidualCoding, RiceDecoder};
+use super::DecoderError;
+
+/// Represents a subframe as it is coded in a stream
+///
+/// `subframe` follows the convention of the encoder, so the warm-up samples of
+/// `Fixed` and `Lpc` subframes are kept apart from their residuals. `bps` is the
+/// sample size of the channel, before the wasted bits are taken out, and
+/// `n_bits` is the size of the subframe in the stream.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParsedSubframe {
+    pub subframe: Subframe,
+    pub bps: u8,
+    pub wasted_bits: u8,
+    pub residual_coding: Option <ResidualCoding>,
+    pub n_bits: u64,
+}
+
+/// Represents a decoder of the subframes of one channel
+pub struct SubframeDecoder;
+
+impl SubframeDecoder {
+    /// Read a subframe and restore the `block_size` samples of its channel
+    ///
+    /// `bps` is the sample size of the channel. The wasted bits signalled in the
+    /// subframe header are shifted back into every sample.
+    ///
+    /// # Errors
+    /// Returns the errors of `parse_subframe` and `restore_samples`.
+    pub fn read_subframe(reader: &mut BitReader, bps: u8, block_size: u64) -> Result <Vec <i64>, DecoderError> {
+        let parsed = SubframeDecoder::parse_subframe(reader, bps, block_size)?;
+        SubframeDecoder::restore_samples(&parsed, block_size)
+    }
+
+    /// Read a subframe without restoring its samples
+    ///
+    /// # Errors
+    /// `ReservedValueError` is returned for a reserved subframe type or a set
+    /// padding bit, and `InvalidResidualError` if the wasted bits leave no bits
+    /// for the samples or the residuals cannot be read.
+    pub fn parse_subframe(reader: &mut BitReader, bps: u8, block_size: u64) -> Result <ParsedSubframe, DecoderError> {
+        let start = reader.position_bits();
+        if reader.read_bits(1)? != 0 {
+            return Err(DecoderError::ReservedValueError);
+        }
+        let subframe_type = reader.read_bits(6)? as u8;
+        let wasted_bits = if reader.read_bits(1)? == 1 {
+            reader.read_unary()? + 1
+        } else {
+            0
+        };
+        if wasted_bits >= bps as u64 {
+            return Err(DecoderError::InvalidResidualError);
+        }
+        let sample_bps = bps - wasted_bits as u8;
+
+        let (subframe, residual_coding) = match subframe_type {
+            0b000000 => {
+                let value = reader.read_signed(sample_bps)?;
+                (Subframe::Constant { value }, None)
+            }
+            0b000001 => {
+                let samples = (0..block_size).map(|_| reader.read_signed(sample_bps)).collect::<Result <Vec <i64>, _>>()?;
+                (Subframe::Verbatim { samples }, None)
+            }
+            0b001000..=0b001100 => {
+                let order = subframe_type & 0b111;
+                let warmup = SubframeDecoder::read_warmup(reader, sample_bps, block_size, order)?;
+                let (residual_coding, residuals) = RiceDecoder::read_residual_coding(reader, block_size, order)?;
+                (Subframe::Fixed { warmup, residuals }, Some(residual_coding))
+            }
+            0b100000..=0b111111 => {
+                let order = (subframe_type & 0b11111) + 1;
+                SubframeDecoder::read_lpc(reader, sample_bps, block_size, order)?
+            }
+            _ => return Err(DecoderError::ReservedValueError),
+        };
+
+        Ok(ParsedSubframe { subframe, bps, wasted_bits: wasted_bits as u8, residual_coding, n_bits: reader.position_bits() as u64 - start as u64 })
+    }
+
+    /// Restore the `block_size` samples of a parsed subframe
+    ///
+    /// # Errors
+    /// `InvalidResidualError` is returned if the residuals restore a sample that
+    /// does not fit in the bits per sample of the subframe.
+    pub fn restore_samples(parsed: &ParsedSubframe, block_size: u64) -> Result <Vec <i64>, DecoderError> {
+        let mut samples = match &parsed.subframe {
+            Subframe::Constant { value } => Some(vec![*value; block_size as usize]),
+            Subframe::Verbatim { samples } => Some(samples.clone()),
+            Subframe::Fixed { warmup, residuals } => FixedPredictor::restore_samples(warmup, residuals),
+            Subframe::Lpc { qlp_coeffs, shift, warmup, residuals, .. } => VarPredictor::restore_samples(warmup, residuals, qlp_coeffs, *shift),
+        }.ok_or(DecoderError::InvalidResidualError)?;
+
+        let limit = 1i64 << (parsed.bps - parsed.wasted_bits - 1);
+        if samples.iter().any(|&sample| sample < -limit || sample >= limit) {
+            return Err(DecoderError::InvalidResidualError);
+        }
+        if parsed.wasted_bits > 0 {
+            samples.iter_mut().for_each(|sample| *sample <<= parsed.wasted_bits);
+        }
+        Ok(samples)
+    }
+
+    fn read_warmup(reader: &mut BitReader, bps: u8, block_size: u64, order: u8) -> Result <Vec <i64>, DecoderError> {
+        if order as u64 > block_size {
+            return Err(DecoderError::InvalidResidualError);
+        }
+        let mut samples = Vec::with_capacity(order as usize);
+        for _ in 0..order {
+            samples.push(reader.read_signed(bps)?);
+        }
+        Ok(samples)
+    }
+
+    fn read_lpc(reader: &mut BitReader, bps: u8, block_size: u64, order: u8) -> Result <(Subframe, Option <ResidualCoding>), DecoderError> {
+        let warmup = SubframeDecoder::read_warmup(reader, bps, block_size, order)?;
+        let precision = reader.read_bits(4)? as u8 + 1;
+        if precision == 16 {
+            return Err(DecoderError::ReservedValueError);
+        }
+        let shift = reader.read_signed(5)?;
+        if shift < 0 {
+            return Err(DecoderError::InvalidResidualError);
+        }
+        let qlp_coeffs = (0..order).map(|_| reader.read_signed(precision)).collect::<Result <Vec <i64>, _>>()?;
+        let (residual_coding, residuals) = RiceDecoder::read_residual_coding(reader, block_size, order)?;
+
+        Ok((Subframe::Lpc { qlp_coeffs, precision, shift: shift as u8, warmup, residuals }, Some(residual_coding)))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::flac::encoder::bitwriter::BitWriter;
+    use crate::flac::encoder::subframe::{Subframe, SubframeEncoderOptions};
+
+    fn sine(len: usize) -> Vec <i64> {
+        (0..len).map(|i| ((i as f64 * 0.07).sin() * 12000.0) as i64).collect()
+    }
+
+    fn round_trip(subframe: &Subframe, bps: u8, wasted_bits: u8, block_size: u64) -> Result <Vec <i64>, DecoderError> {
+        let mut writer = BitWriter::new();
+        subframe.write_with_wasted_bits(&mut writer, bps, wasted_bits);
+        let bytes = writer.into_bytes();
+        SubframeDecoder::read_subframe(&mut BitReader::new(&bytes), bps, block_size)
+    }
+
+    #[test]
+    fn test_read_constant() {
+        let mut reader = BitReader::new(&[0x00, 0xFE]);
+        assert_eq!(SubframeDecoder::read_subframe(&mut reader, 8, 3), Ok(vec![-2; 3]));
+    }
+
+    #[test]
+    fn test_read_wasted_bits() {
+        // Constant -2 with 4 wasted bits in a 12-bit channel
+        let mut reader = BitReader::new(&[0x01, 0x1F, 0xE0]);
+        assert_eq!(SubframeDecoder::read_subframe(&mut reader, 12, 2), Ok(vec![-32; 2]));
+    }
+
+    #[test]
+    fn test_read_reserved_type() {
+        let mut reader = BitReader::new(&[0b0000_0100, 0x00]);
+        assert_eq!(SubframeDecoder::read_subframe(&mut reader, 8, 1), Err(DecoderError::ReservedValueError));
+    }
+
+    #[test]
+    fn test_round_trip_candidates() {
+        let samples = sine(512);
+        let options = SubframeEncoderOptions::new(16).exhaustive(true);
+        for candidate in options.candidates(&samples) {
+            assert_eq!(round_trip(&candidate, 16, 0, 512), Ok(samples.clone()), "{:?}", candidate.order());
+        }
+    }
+
+    #[test]
+    fn test_parse_subframe() {
+        let samples = sine(512);
+        let subframe = Subframe::lpc(&samples, vec![1800, -900], 10).unwrap();
+        let mut writer = BitWriter::new();
+        subframe.write(&mut writer, 16);
+        let n_bits = writer.len_bits();
+        let bytes = writer.into_bytes();
+
+        let parsed = SubframeDecoder::parse_subframe(&mut BitReader::new(&bytes), 16, 512).unwrap();
+        assert_eq!(parsed.subframe, subframe);
+        assert_eq!((parsed.bps, parsed.wasted_bits, parsed.n_bits), (16, 0, n_bits));
+        let residual_coding = parsed.residual_coding.as_ref().unwrap();
+        assert_eq!(residual_coding.partitions.len(), 1 << residual_coding.partition_order);
+        assert_eq!(SubframeDecoder::restore_samples(&parsed, 512), Ok(samples));
+    }
+
+    #[test]
+    fn test_round_trip_wasted_bits() {
+        let samples: Vec <i64> = sine(1024).iter().map(|&sample| sample << 4).collect();
+        let options = SubframeEncoderOptions::new(20);
+
+        let (subframe, wasted_bits) = options.encode_channel(&samples).unwrap();
+
+        assert_eq!(wasted_bits, 4);
+        assert_eq!(round_trip(&subframe, 20, wasted_bits, 1024), Ok(samples));
+    }
+}
@@ -0,0 +1,105 @@
+use crate::flac::encoder::utf8::Utf8Error;
+
+use super::bitreader::BitReader;
+
+/// Smallest number encoded in each length of sequence, indexed by its length
+const MIN_UTF8_NUM: [u64; 8] = [0, 0, 0x80, 0x800, 0x10000, 0x200000, 0x4000000, 0x80000000];
+
+pub struct Utf8Decoder;
+
+impl Utf8Decoder {
+    /// Decode a number from the start of its UTF-8 equivalent encoding
+    ///
+    /// The number is returned with the length of its encoding in bytes. Trailing
+    /// bytes after the encoding are ignored.
+    ///
+    /// # Errors
+    /// `InvalidSequenceError` is returned if the first byte is a continuation byte
+    /// or `0xFF`, or if a following byte is not a continuation byte.
+    /// `OverlongError` is returned if the number could have been encoded in fewer bytes.
+    /// `UnexpectedEndError` is returned if `bytes` ends before the encoding does.
+    pub fn decode(bytes: &[u8]) -> Result <(u64, usize), Utf8Error> {
+        let mut bytes = bytes.iter().copied();
+        Utf8Decoder::decode_with(|| bytes.next())
+    }
+
+    /// Read a number encoded in UTF-8 from a bit reader
+    ///
+    /// This fails in the same way as `decode`.
+    pub fn read(reader: &mut BitReader) -> Result <u64, Utf8Error> {
+        Utf8Decoder::decode_with(|| reader.read_bits(8).ok().map(|byte| byte as u8)).map(|(num, _)| num)
+    }
+
+    fn decode_with(mut next_byte: impl FnMut() -> Option <u8>) -> Result <(u64, usize), Utf8Error> {
+        let first = next_byte().ok_or(Utf8Error::UnexpectedEndError)?;
+
+        // The number of leading ones of the first byte is the length of the sequence
+        let (len, mut num) = match first.leading_ones() {
+            0 => return Ok((first as u64, 1)),
+            ones @ 2..=6 => (ones as usize, (first & (0x7F >> ones)) as u64),
+            7 => (7, 0),
+            _ => return Err(Utf8Error::InvalidSequenceError),
+        };
+
+        for _ in 1..len {
+            let byte = next_byte().ok_or(Utf8Error::UnexpectedEndError)?;
+            if byte & 0xC0 != 0x80 {
+                return Err(Utf8Error::InvalidSequenceError);
+            }
+            num = (num << 6) | (byte & 0x3F) as u64;
+        }
+
+        if num < MIN_UTF8_NUM[len] {
+            return Err(Utf8Error::OverlongError);
+        }
+        Ok((num, len))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::flac::encoder::utf8::{Utf8Encoder, MAX_UTF8_NUM};
+
+    #[test]
+    fn test_decode_every_length() {
+        for num in [0, 0x7F, 0x80, 0x7FF, 0x800, 0xFFFF, 0x1FFFFF, 0x3FFFFFF, 0x7FFFFFFF, 0x80000000, MAX_UTF8_NUM] {
+            let bytes = Utf8Encoder::encode(num).unwrap();
+            assert_eq!(Utf8Decoder::decode(&bytes), Ok((num, bytes.len())));
+        }
+    }
+
+    #[test]
+    fn test_decode_ignores_trailing_bytes() {
+        assert_eq!(Utf8Decoder::decode(&[0xDF, 0xBF, 0x12]), Ok((0x7FF, 2)));
+    }
+
+    #[test]
+    fn test_decode_invalid_sequence() {
+        assert_eq!(Utf8Decoder::decode(&[0x80]), Err(Utf8Error::InvalidSequenceError));
+        assert_eq!(Utf8Decoder::decode(&[0xFF, 0x80]), Err(Utf8Error::InvalidSequenceError));
+        assert_eq!(Utf8Decoder::decode(&[0xE0, 0xA0, 0x00]), Err(Utf8Error::InvalidSequenceError));
+    }
+
+    #[test]
+    fn test_decode_overlong() {
+        // 0x7F encoded in 2 bytes
+        assert_eq!(Utf8Decoder::decode(&[0xC1, 0xBF]), Err(Utf8Error::OverlongError));
+        // 0x7FFFFFFF encoded in 7 bytes
+        assert_eq!(Utf8Decoder::decode(&[0xFE, 0x81, 0xBF, 0xBF, 0xBF, 0xBF, 0xBF]), Err(Utf8Error::OverlongError));
+    }
+
+    #[test]
+    fn test_decode_unexpected_end() {
+        assert_eq!(Utf8Decoder::decode(&[]), Err(Utf8Error::UnexpectedEndError));
+        assert_eq!(Utf8Decoder::decode(&[0xFE, 0x82, 0x80]), Err(Utf8Error::UnexpectedEndError));
+    }
+
+    #[test]
+    fn test_read() {
+        let mut reader = BitReader::new(&[0xE1, 0x80, 0x80, 0x03]);
+        assert_eq!(Utf8Decoder::read(&mut reader), Ok(4096));
+        assert_eq!(reader.read_bits(8), Ok(0x03));
+    }
+}
@@ -0,0 +1,172 @@
+/// Represents a big-endian bit stream writer
+///
+/// FLAC fields are not byte-aligned, so values are packed most significant
+/// bit first. Whole bytes are flushed into `bytes` as soon as they are complete
+/// and the remaining bits are kept in an accumulator until more bits arrive.
+#[derive(Debug, Default)]
+pub struct BitWriter {
+    bytes: Vec <u8>,
+    acc: u64,
+    acc_len: u8,
+}
+
+impl BitWriter {
+    /// Create an empty bit writer
+    pub fn new() -> Self {
+        BitWriter::default()
+    }
+
+    /// Write the lowest `n_bits` bits of `value`, most significant bit first
+    pub fn write_bits(&mut self, value: u64, n_bits: u8) {
+        debug_assert!(n_bits <= 64);
+        if n_bits == 0 {
+            return;
+        }
+
+        // Split wide values so the accumulator never holds more than 64 bits
+        if n_bits > 32 {
+            self.write_bits(value >> 32, n_bits - 32);
+            self.write_bits(value & 0xFFFF_FFFF, 32);
+            return;
+        }
+
+        let mask = (1u64 << n_bits) - 1;
+        self.acc = (self.acc << n_bits) | (value & mask);
+        self.acc_len += n_bits;
+
+        while self.acc_len >= 8 {
+            self.acc_len -= 8;
+            self.bytes.push((self.acc >> self.acc_len) as u8);
+        }
+        self.acc &= (1u64 << self.acc_len) - 1;
+    }
+
+    /// Write a signed value in `n_bits` bits two's complement
+    pub fn write_signed(&mut self, value: i64, n_bits: u8) {
+        self.write_bits(value as u64, n_bits);
+    }
+
+    /// Get the number of bits needed to store `value` in two's complement
+    pub fn signed_bits(value: i64) -> u8 {
+        let magnitude = if value < 0 { !value } else { value };
+        (64 - magnitude.leading_zeros() + 1) as u8
+    }
+
+    /// Write `quotient` in unary as `quotient` zeros followed by a single one
+    pub fn write_unary(&mut self, quotient: u64) {
+        let mut remaining = quotient;
+        while remaining >= 32 {
+            self.write_bits(0, 32);
+            remaining -= 32;
+        }
+        self.write_bits(1, remaining as u8 + 1);
+    }
+
+    /// Write whole bytes
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        if self.acc_len == 0 {
+            self.bytes.extend_from_slice(bytes);
+        } else {
+            for &byte in bytes {
+                self.write_bits(byte as u64, 8);
+            }
+        }
+    }
+
+    /// Pad the stream with zero bits until it is byte-aligned
+    pub fn pad_to_byte(&mut self) {
+        if self.acc_len > 0 {
+            self.write_bits(0, 8 - self.acc_len);
+        }
+    }
+
+    /// Check if the stream ends on a byte boundary
+    pub fn is_byte_aligned(&self) -> bool {
+        self.acc_len == 0
+    }
+
+    /// Get the total number of bits written so far
+    pub fn len_bits(&self) -> u64 {
+        self.bytes.len() as u64 * 8 + self.acc_len as u64
+    }
+
+    /// Get the completed bytes written so far
+    ///
+    /// Bits of a partially written byte are not included.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// Consume the writer and return the stream, zero-padding the last byte
+    pub fn into_bytes(mut self) -> Vec <u8> {
+        self.pad_to_byte();
+        self.bytes
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_bits() {
+        let mut writer = BitWriter::new();
+        writer.write_bits(0b101, 3);
+        writer.write_bits(0b11110, 5);
+        writer.write_bits(0xABC, 12);
+        assert_eq!(writer.len_bits(), 20);
+        assert_eq!(writer.into_bytes(), vec![0xBE, 0xAB, 0xC0]);
+    }
+
+    #[test]
+    fn test_write_wide_bits() {
+        let mut writer = BitWriter::new();
+        writer.write_bits(1, 4);
+        writer.write_bits(0x0123_4567_89AB_CDEF, 64);
+        writer.write_bits(0, 4);
+        assert_eq!(writer.into_bytes(), vec![0x10, 0x12, 0x34, 0x56, 0x78, 0x9A, 0xBC, 0xDE, 0xF0]);
+    }
+
+    #[test]
+    fn test_write_signed() {
+        let mut writer = BitWriter::new();
+        writer.write_signed(-1, 4);
+        writer.write_signed(-8, 4);
+        writer.write_signed(3, 8);
+        assert_eq!(writer.into_bytes(), vec![0xF8, 0x03]);
+    }
+
+    #[test]
+    fn test_signed_bits() {
+        assert_eq!(BitWriter::signed_bits(0), 1);
+        assert_eq!(BitWriter::signed_bits(-1), 1);
+        assert_eq!(BitWriter::signed_bits(1), 2);
+        assert_eq!(BitWriter::signed_bits(104), 8);
+        assert_eq!(BitWriter::signed_bits(-128), 8);
+        assert_eq!(BitWriter::signed_bits(-129), 9);
+    }
+
+    #[test]
+    fn test_write_unary() {
+        let mut writer = BitWriter::new();
+        writer.write_unary(0);
+        writer.write_unary(3);
+        writer.write_unary(40);
+        assert_eq!(writer.len_bits(), 1 + 4 + 41);
+        let bytes = writer.into_bytes();
+        assert_eq!(bytes[0], 0b1000_1000);
+        assert_eq!(bytes[5], 0b0000_0100);
+    }
+
+    #[test]
+    fn test_write_bytes_unaligned() {
+        let mut writer = BitWriter::new();
+        writer.write_bytes(&[0x12]);
+        writer.write_bits(0xF, 4);
+        writer.write_bytes(&[0x34, 0x56]);
+        assert!(!writer.is_byte_aligned());
+        assert_eq!(writer.as_bytes(), &[0x12, 0xF3, 0x45]);
+        assert_eq!(writer.into_bytes(), vec![0x12, 0xF3, 0x45, 0x60]);
+    }
+}
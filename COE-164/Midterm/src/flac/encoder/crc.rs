@@ -7,58 +7,292 @@
 /// 
 /// Then, the value of `poly` should be 0b0000_0111 (note the missing
 /// MSB `1` bit) and `poly_len` should be `u8`.
+/// 
+/// The register starts at `init`. If `reflect_in` is set, each input byte is
+/// processed least significant bit first. If `reflect_out` is set, the register
+/// is bit-reversed before being XORed with `xor_out` at the end. These follow
+/// the parameters of the catalogue of parametrised CRC algorithms, so the named
+/// presets can be checked against their published check values.
 pub struct CrcOptions <T> {
     poly: T,
     poly_len: T,
+    init: T,
+    reflect_in: bool,
+    reflect_out: bool,
+    xor_out: T,
+}
+
+/// Represents a CRC being computed incrementally
+/// 
+/// Data can be fed in pieces using `update`, for example as a bit writer emits
+/// complete bytes. The CRC of everything fed so far is returned by `finalize`.
+/// Each byte is processed at once using a precomputed table of 256 entries.
+pub struct CrcDigest <T> {
+    table: [T; 256],
+    crc: u64,
+    reflect_in: bool,
+    reflect_out: bool,
+    xor_out: u64,
+}
+
+/// Represents an unsigned integer type that can hold a CRC
+/// 
+/// This is implemented for `u8`, `u16`, `u32` and `u64`. The register of a CRC is
+/// kept in a `u64`, so only the lowest `BITS` bits of it are used.
+pub trait CrcWidth: Copy {
+    const BITS: u32;
+
+    fn to_u64(self) -> u64;
+    fn from_u64(value: u64) -> Self;
+}
+
+macro_rules! impl_crc_width {
+    ($($t:ty),*) => {
+        $(
+            impl CrcWidth for $t {
+                const BITS: u32 = <$t>::BITS;
+
+                fn to_u64(self) -> u64 {
+                    self as u64
+                }
+
+                fn from_u64(value: u64) -> Self {
+                    value as $t
+                }
+            }
+        )*
+    };
 }
 
+impl_crc_width!(u8, u16, u32, u64);
 
-impl <T> CrcOptions <T> {
+/// Reverse the order of the lowest `n_bits` bits of `value`
+fn reflect(value: u64, n_bits: u32) -> u64 {
+    value.reverse_bits() >> (64 - n_bits)
+}
+
+/// Get a mask of the lowest `n_bits` bits
+fn mask(n_bits: u32) -> u64 {
+    u64::MAX >> (64 - n_bits)
+}
+
+
+impl <T: CrcWidth> CrcOptions <T> {
     /// Create a builder to the CRC encoder
+    /// 
+    /// The register starts at zero, nothing is reflected and the result is not XORed.
     pub fn new(poly: T, poly_len: T) -> Self {
-        Self { poly, poly_len }
+        Self {
+            poly,
+            poly_len,
+            init: T::from_u64(0),
+            reflect_in: false,
+            reflect_out: false,
+            xor_out: T::from_u64(0),
+        }
     }
-}
 
-impl CrcOptions <u8> {
-    /// Encode data using CRC8 encoding
+    /// Set the initial value of the register
+    pub fn init(mut self, init: T) -> Self {
+        self.init = init;
+        self
+    }
+
+    /// Set whether each input byte is processed least significant bit first
+    pub fn reflect_in(mut self, reflect_in: bool) -> Self {
+        self.reflect_in = reflect_in;
+        self
+    }
+
+    /// Set whether the register is bit-reversed at the end
+    pub fn reflect_out(mut self, reflect_out: bool) -> Self {
+        self.reflect_out = reflect_out;
+        self
+    }
+
+    /// Set the value XORed with the register at the end
+    pub fn xor_out(mut self, xor_out: T) -> Self {
+        self.xor_out = xor_out;
+        self
+    }
+
+    /// Compute the CRC of every possible byte
     /// 
-    /// This method is available only if `CrcOptions` is of type `u8`.
-    pub fn build_crc8(&self, data: &Vec <u8>) -> u8 {
-        let mut crc: u8 = 0;
+    /// If the input is reflected, the table is computed least significant bit first
+    /// using the reflected polynomial.
+    pub fn table(&self) -> [T; 256] {
+        debug_assert_eq!(self.poly_len.to_u64(), T::BITS as u64);
+        let width = T::BITS;
+        let top_bit = 1u64 << (width - 1);
+        let poly = self.poly.to_u64();
+        let reflected_poly = reflect(poly, width);
+        let mut table = [T::from_u64(0); 256];
 
-        for &byte in data {
-            crc ^= byte;                    // XOR
-            for _ in 0..(self.poly_len as u8) {
-                if crc & 0x80 != 0 {        // (0x80 = 0b1000 0000)
-                    crc = (crc << 1) ^ self.poly;   // If the MSB is 1, XOR the crc shifted by 1 with the polynomial 
-                } else {
-                    crc <<= 1;              // Else, shift the crc to the left by 1 
+        for (i, entry) in table.iter_mut().enumerate() {
+            let mut crc;
+            if self.reflect_in {
+                crc = i as u64;
+                for _ in 0..8 {
+                    if crc & 1 != 0 {
+                        crc = (crc >> 1) ^ reflected_poly;  // If the LSB is 1, XOR the crc shifted by 1 with the polynomial
+                    } else {
+                        crc >>= 1;
+                    }
+                }
+            } else {
+                crc = (i as u64) << (width - 8);
+                for _ in 0..8 {
+                    if crc & top_bit != 0 {
+                        crc = (crc << 1) ^ poly;    // If the MSB is 1, XOR the crc shifted by 1 with the polynomial
+                    } else {
+                        crc <<= 1;                  // Else, shift the crc to the left by 1 
+                    }
                 }
             }
+            *entry = T::from_u64(crc & mask(width));
         }
-        crc
+        table
+    }
+
+    /// Start computing a CRC incrementally
+    pub fn digest(&self) -> CrcDigest <T> {
+        let init = self.init.to_u64();
+        CrcDigest {
+            table: self.table(),
+            // The register of a reflected CRC is kept reflected
+            crc: if self.reflect_in { reflect(init, T::BITS) } else { init },
+            reflect_in: self.reflect_in,
+            reflect_out: self.reflect_out,
+            xor_out: self.xor_out.to_u64(),
+        }
+    }
+
+    /// Encode bytes using CRC encoding
+    pub fn build(&self, data: &[u8]) -> T {
+        let mut digest = self.digest();
+        digest.update(data);
+        digest.finalize()
+    }
+}
+
+impl CrcOptions <u8> {
+    /// CRC-8/SMBUS, the CRC8 used by FLAC frame headers
+    pub fn crc8_smbus() -> Self {
+        CrcOptions::new(0x07, 8)
+    }
+
+    /// CRC-8/MAXIM-DOW, used by 1-Wire devices
+    pub fn crc8_maxim_dow() -> Self {
+        CrcOptions::new(0x31, 8).reflect_in(true).reflect_out(true)
+    }
+
+    /// Encode data using CRC8 encoding
+    /// 
+    /// This method is available only if `CrcOptions` is of type `u8`.
+    pub fn build_crc8(&self, data: &[u8]) -> u8 {
+        self.build(data)
     }
 }
 
 impl CrcOptions <u16> {
-    /// Encode data using CRC16 encoding
+    /// CRC-16/BUYPASS, the CRC16 used by FLAC frames
+    pub fn crc16_buypass() -> Self {
+        CrcOptions::new(0x8005, 16)
+    }
+
+    /// CRC-16/ARC
+    pub fn crc16_arc() -> Self {
+        CrcOptions::new(0x8005, 16).reflect_in(true).reflect_out(true)
+    }
+
+    /// CRC-16/IBM-3740, also known as CRC-16/CCITT-FALSE
+    pub fn crc16_ibm_3740() -> Self {
+        CrcOptions::new(0x1021, 16).init(0xFFFF)
+    }
+
+    /// Encode 16-bit words using CRC16 encoding
     /// 
+    /// Each word is processed as two bytes, most significant byte first.
     /// This method is available only if `CrcOptions` is of type `u16`.
-    pub fn build_crc16(&self, data: &Vec <u16>) -> u16 {
-        let mut crc: u16 = 0;
-
+    pub fn build_crc16(&self, data: &[u16]) -> u16 {
+        let mut digest = self.digest();
         for &word in data {
-            crc ^= word;                    // XOR
-            for _ in 0..(self.poly_len as u16) {
-                if crc & 0x8000 != 0 {      // (0x8000 = 0b1000 0000 0000 0000)
-                    crc = (crc << 1) ^ self.poly;   // If the MSB is 1, XOR the crc shifted by 1 with the polynomial
-                } else {
-                    crc <<= 1;              // Else, shift the crc to the left by 1 
-                }
+            digest.update(&word.to_be_bytes());
+        }
+        digest.finalize()
+    }
+
+    /// Encode bytes using CRC16 encoding
+    /// 
+    /// This is the CRC16 used by FLAC frames.
+    /// This method is available only if `CrcOptions` is of type `u16`.
+    pub fn build_crc16_bytes(&self, data: &[u8]) -> u16 {
+        self.build(data)
+    }
+}
+
+impl CrcOptions <u32> {
+    /// CRC-32/MPEG-2
+    pub fn crc32_mpeg2() -> Self {
+        CrcOptions::new(0x04C11DB7, 32).init(0xFFFFFFFF)
+    }
+
+    /// CRC-32/ISO-HDLC, the CRC32 used by zip, PNG and Ethernet
+    pub fn crc32_iso_hdlc() -> Self {
+        CrcOptions::new(0x04C11DB7, 32).init(0xFFFFFFFF).reflect_in(true).reflect_out(true).xor_out(0xFFFFFFFF)
+    }
+
+    /// CRC-32/CKSUM, used by POSIX `cksum`
+    pub fn crc32_cksum() -> Self {
+        CrcOptions::new(0x04C11DB7, 32).xor_out(0xFFFFFFFF)
+    }
+
+    /// The CRC32 used by Ogg pages, which is not reflected and starts at zero
+    pub fn crc32_ogg() -> Self {
+        CrcOptions::new(0x04C11DB7, 32)
+    }
+}
+
+impl CrcOptions <u64> {
+    /// CRC-64/ECMA-182
+    pub fn crc64_ecma_182() -> Self {
+        CrcOptions::new(0x42F0E1EBA9EA3693, 64)
+    }
+
+    /// CRC-64/XZ
+    pub fn crc64_xz() -> Self {
+        CrcOptions::new(0x42F0E1EBA9EA3693, 64)
+            .init(u64::MAX)
+            .reflect_in(true)
+            .reflect_out(true)
+            .xor_out(u64::MAX)
+    }
+}
+
+impl <T: CrcWidth> CrcDigest <T> {
+    /// Feed more bytes into the CRC
+    pub fn update(&mut self, data: &[u8]) {
+        let width = T::BITS;
+        for &byte in data {
+            if self.reflect_in {
+                let index = ((self.crc ^ byte as u64) & 0xFF) as usize;
+                // Shifting a 64-bit register by 8 still leaves the lowest bits to the table
+                self.crc = (self.crc >> 8) ^ self.table[index].to_u64();
+            } else {
+                let index = (((self.crc >> (width - 8)) ^ byte as u64) & 0xFF) as usize;
+                self.crc = ((self.crc << 8) ^ self.table[index].to_u64()) & mask(width);
             }
         }
-        crc
+    }
+
+    /// Get the CRC of every byte fed so far
+    pub fn finalize(&self) -> T {
+        let crc = if self.reflect_in != self.reflect_out {
+            reflect(self.crc, T::BITS)
+        } else {
+            self.crc
+        };
+        T::from_u64(crc ^ self.xor_out)
     }
 }
 
@@ -91,6 +325,76 @@ mod tests {
 
             assert_eq!(crc16, expected_output);
         }
+    
+        #[test]
+        fn test_check_value_crc8() {
+            let crc_encoder_builder = CrcOptions::new(0x07, 8);
+            let expected_output = 0xF4; // CRC-8/SMBUS check value
+
+            let crc8 = crc_encoder_builder.build_crc8(b"123456789");
+
+            assert_eq!(crc8, expected_output);
+        }
+
+        #[test]
+        fn test_check_value_crc16() {
+            let crc_encoder_builder = CrcOptions::new(0x8005, 16);
+            let expected_output = 0xFEE8; // CRC-16/BUYPASS check value
+
+            let crc16 = crc_encoder_builder.build_crc16_bytes(b"123456789");
+
+            assert_eq!(crc16, expected_output);
+        }
+
+        #[test]
+        fn test_incremental_matches_whole() {
+            let crc_encoder_builder: CrcOptions <u16> = CrcOptions::new(0x8005, 16);
+            let mut digest = crc_encoder_builder.digest();
+
+            digest.update(b"1234");
+            digest.update(b"");
+            digest.update(b"56789");
+
+            assert_eq!(digest.finalize(), 0xFEE8);
+        }
+
+        #[test]
+        fn test_table_matches_bitwise() {
+            let table = CrcOptions::<u8>::new(0x07, 8).table();
+
+            assert_eq!(table[0], 0x00);
+            assert_eq!(table[1], 0x07);
+            assert_eq!(table[0x80], 0x89);
+            assert_eq!(table[0xFF], 0xF3);
+        }
+    
+        #[test]
+        fn test_check_values() {
+            // Check values from the catalogue of parametrised CRC algorithms
+            let check = b"123456789";
+
+            assert_eq!(CrcOptions::crc8_smbus().build(check), 0xF4);
+            assert_eq!(CrcOptions::crc8_maxim_dow().build(check), 0xA1);
+            assert_eq!(CrcOptions::crc16_buypass().build(check), 0xFEE8);
+            assert_eq!(CrcOptions::crc16_arc().build(check), 0xBB3D);
+            assert_eq!(CrcOptions::crc16_ibm_3740().build(check), 0x29B1);
+            assert_eq!(CrcOptions::crc32_mpeg2().build(check), 0x0376E6E7);
+            assert_eq!(CrcOptions::crc32_iso_hdlc().build(check), 0xCBF43926);
+            assert_eq!(CrcOptions::crc32_cksum().build(check), 0x765E7680);
+            assert_eq!(CrcOptions::crc32_ogg().build(check), 0x89A1897F);
+            assert_eq!(CrcOptions::crc64_ecma_182().build(check), 0x6C40DF5F0B497347);
+            assert_eq!(CrcOptions::crc64_xz().build(check), 0x995DC9BBDF1939FA);
+        }
+
+        #[test]
+        fn test_incremental_reflected() {
+            let mut digest = CrcOptions::crc32_iso_hdlc().digest();
+
+            digest.update(b"12345");
+            digest.update(b"6789");
+
+            assert_eq!(digest.finalize(), 0xCBF43926);
+        }
     }
     
 }
\ No newline at end of file
@@ -0,0 +1,238 @@
+use crate::flac::sample::Sample;
+
+use super::bitwriter::BitWriter;
+use super::crc::CrcOptions;
+use super::subframe::Subframe;
+use super::utf8::Utf8Encoder;
+
+/// Represents the blocking strategy of a stream
+///
+/// Frames of a `Fixed` stream all have the same block size, except possibly the
+/// last, and are numbered by frame. Frames of a `Variable` stream may have any
+/// block size and are numbered by their first sample.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BlockingStrategy {
+    Fixed,
+    Variable,
+}
+
+/// Represents the header of a frame
+#[derive(Debug, Clone, PartialEq)]
+pub struct FrameHeader {
+    pub blocking: BlockingStrategy,
+    pub number: u64,
+    pub block_size: u64,
+    pub sample_rate: u32,
+    pub n_channels: u8,
+    pub bps: u8,
+}
+
+/// Represents a frame of `T` samples with the subframe and wasted bits of each channel
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame <T: Sample = i64> {
+    pub header: FrameHeader,
+    pub subframes: Vec <(Subframe <T>, u8)>,
+}
+
+impl FrameHeader {
+    /// Get the 4-bit block size code and the block size stored at the end of the header
+    ///
+    /// The stored block size is returned with its length in bits, and is stored as
+    /// `block_size - 1`.
+    fn block_size_code(block_size: u64) -> (u8, Option <(u64, u8)>) {
+        match block_size {
+            192 => (0b0001, None),
+            576 | 1152 | 2304 | 4608 => (0b0010 + (block_size / 576).trailing_zeros() as u8, None),
+            256 | 512 | 1024 | 2048 | 4096 | 8192 | 16384 | 32768 => (0b1000 + (block_size / 256).trailing_zeros() as u8, None),
+            1..=256 => (0b0110, Some((block_size - 1, 8))),
+            _ => (0b0111, Some((block_size - 1, 16))),
+        }
+    }
+
+    /// Get the 4-bit sample rate code and the sample rate stored at the end of the header
+    fn sample_rate_code(sample_rate: u32) -> (u8, Option <(u64, u8)>) {
+        match sample_rate {
+            88200 => (0b0001, None),
+            176400 => (0b0010, None),
+            192000 => (0b0011, None),
+            8000 => (0b0100, None),
+            16000 => (0b0101, None),
+            22050 => (0b0110, None),
+            24000 => (0b0111, None),
+            32000 => (0b1000, None),
+            44100 => (0b1001, None),
+            48000 => (0b1010, None),
+            96000 => (0b1011, None),
+            _ if sample_rate.is_multiple_of(1000) && sample_rate / 1000 <= 0xFF => (0b1100, Some((sample_rate as u64 / 1000, 8))),
+            _ if sample_rate <= 0xFFFF => (0b1101, Some((sample_rate as u64, 16))),
+            _ if sample_rate.is_multiple_of(10) && sample_rate / 10 <= 0xFFFF => (0b1110, Some((sample_rate as u64 / 10, 16))),
+            // Only the STREAMINFO block can hold this sample rate
+            _ => (0b0000, None),
+        }
+    }
+
+    /// Get the 3-bit sample size code
+    ///
+    /// Sample sizes without a code are taken from the STREAMINFO block.
+    fn sample_size_code(bps: u8) -> u8 {
+        match bps {
+            8 => 0b001,
+            12 => 0b010,
+            16 => 0b100,
+            20 => 0b101,
+            24 => 0b110,
+            32 => 0b111,
+            _ => 0b000,
+        }
+    }
+
+    /// Write the frame header, including its CRC-8
+    ///
+    /// The header starts byte-aligned, so it is always a whole number of bytes.
+    ///
+    /// # Panics
+    /// Panics if the frame or sample number does not fit in 36 bits, which
+    /// cannot happen since STREAMINFO limits the total samples to 36 bits.
+    pub fn write(&self, writer: &mut BitWriter) {
+        let mut header = BitWriter::new();
+        let (block_size_code, block_size_end) = FrameHeader::block_size_code(self.block_size);
+        let (sample_rate_code, sample_rate_end) = FrameHeader::sample_rate_code(self.sample_rate);
+
+        // Sync code, reserved bit and blocking strategy
+        header.write_bits(0b11111111111110, 14);
+        header.write_bits(0, 1);
+        header.write_bits((self.blocking == BlockingStrategy::Variable) as u64, 1);
+
+        header.write_bits(block_size_code as u64, 4);
+        header.write_bits(sample_rate_code as u64, 4);
+        // Independent channels
+        header.write_bits(self.n_channels as u64 - 1, 4);
+        header.write_bits(FrameHeader::sample_size_code(self.bps) as u64, 3);
+        header.write_bits(0, 1);
+
+        header.write_bytes(&Utf8Encoder::encode(self.number).expect("frame and sample numbers fit in 36 bits"));
+        if let Some((value, n_bits)) = block_size_end {
+            header.write_bits(value, n_bits);
+        }
+        if let Some((value, n_bits)) = sample_rate_end {
+            header.write_bits(value, n_bits);
+        }
+
+        let bytes = header.into_bytes();
+        let crc8 = CrcOptions::crc8_smbus().build_crc8(&bytes);
+        writer.write_bytes(&bytes);
+        writer.write_bits(crc8 as u64, 8);
+    }
+
+    /// Get the size of the frame header in bits
+    pub fn encoded_bits(&self) -> u64 {
+        let mut writer = BitWriter::new();
+        self.write(&mut writer);
+        writer.len_bits()
+    }
+}
+
+impl <T: Sample> Frame <T> {
+    /// Estimate the size of the encoded frame in bits
+    ///
+    /// The estimate adds up the frame header, the estimated subframes with their
+    /// wasted bits, and the CRC-16 footer. The padding before the footer is not
+    /// counted.
+    pub fn estimate_bits(&self) -> u64 {
+        let bps = self.header.bps;
+        let subframe_bits: u64 = self.subframes.iter()
+            .map(|(subframe, wasted_bits)| subframe.estimate_bits(bps - wasted_bits) + *wasted_bits as u64)
+            .sum();
+        self.header.encoded_bits() + subframe_bits + 16
+    }
+
+    /// Encode the frame into bytes
+    ///
+    /// The subframes are written after the header and padded to a byte boundary,
+    /// followed by the CRC-16 of the whole frame.
+    pub fn to_bytes(&self) -> Vec <u8> {
+        let mut writer = BitWriter::new();
+        self.header.write(&mut writer);
+        for (subframe, wasted_bits) in &self.subframes {
+            subframe.write_with_wasted_bits(&mut writer, self.header.bps, *wasted_bits);
+        }
+
+        let mut bytes = writer.into_bytes();
+        let crc16 = Frame::crc16(&bytes);
+        bytes.extend_from_slice(&crc16.to_be_bytes());
+        bytes
+    }
+
+}
+
+impl Frame {
+    /// Compute the CRC-16 of a frame, with polynomial `x^16 + x^15 + x^2 + 1`
+    pub fn crc16(bytes: &[u8]) -> u16 {
+        CrcOptions::crc16_buypass().build_crc16_bytes(bytes)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(blocking: BlockingStrategy, number: u64, block_size: u64, sample_rate: u32) -> FrameHeader {
+        FrameHeader { blocking, number, block_size, sample_rate, n_channels: 2, bps: 16 }
+    }
+
+    #[test]
+    fn test_block_size_code() {
+        assert_eq!(FrameHeader::block_size_code(192), (0b0001, None));
+        assert_eq!(FrameHeader::block_size_code(4608), (0b0101, None));
+        assert_eq!(FrameHeader::block_size_code(4096), (0b1100, None));
+        assert_eq!(FrameHeader::block_size_code(100), (0b0110, Some((99, 8))));
+        assert_eq!(FrameHeader::block_size_code(1000), (0b0111, Some((999, 16))));
+    }
+
+    #[test]
+    fn test_sample_rate_code() {
+        assert_eq!(FrameHeader::sample_rate_code(44100), (0b1001, None));
+        assert_eq!(FrameHeader::sample_rate_code(11000), (0b1100, Some((11, 8))));
+        assert_eq!(FrameHeader::sample_rate_code(11025), (0b1101, Some((11025, 16))));
+        assert_eq!(FrameHeader::sample_rate_code(352800), (0b1110, Some((35280, 16))));
+    }
+
+    #[test]
+    fn test_write_fixed_header() {
+        let mut writer = BitWriter::new();
+        header(BlockingStrategy::Fixed, 0, 4096, 44100).write(&mut writer);
+        let bytes = writer.into_bytes();
+        assert_eq!(&bytes[..5], &[0xFF, 0xF8, 0xC9, 0x18, 0x00]);
+        assert_eq!(bytes[5], CrcOptions::crc8_smbus().build_crc8(&bytes[..5]));
+    }
+
+    #[test]
+    fn test_write_variable_header() {
+        let mut writer = BitWriter::new();
+        header(BlockingStrategy::Variable, 4096, 1000, 44100).write(&mut writer);
+        let bytes = writer.into_bytes();
+        // Sample number 4096 takes 2 bytes, followed by the 16-bit block size
+        assert_eq!(&bytes[..4], &[0xFF, 0xF9, 0x79, 0x18]);
+        assert_eq!(&bytes[4..8], &[0xE1, 0x80, 0x80, 0x03]);
+        assert_eq!(bytes.len(), 10);
+    }
+
+    #[test]
+    fn test_crc16() {
+        // CRC-16/BUYPASS check value
+        assert_eq!(Frame::crc16(b"123456789"), 0xFEE8);
+    }
+
+    #[test]
+    fn test_frame_ends_with_crc16() {
+        let frame = Frame {
+            header: header(BlockingStrategy::Fixed, 3, 192, 48000),
+            subframes: vec![(Subframe::Constant { value: 0 }, 0), (Subframe::Constant { value: -1 }, 0)],
+        };
+        let bytes = frame.to_bytes();
+        let n = bytes.len();
+        assert_eq!(u16::from_be_bytes([bytes[n - 2], bytes[n - 1]]), Frame::crc16(&bytes[..n - 2]));
+        assert_eq!(frame.estimate_bits(), n as u64 * 8);
+    }
+}
@@ -1,3 +1,47 @@
+use core::fmt;
+use std::error;
+use std::io;
+
+use crate::flac::metadata::MetadataError;
+
+pub mod bitwriter;
 pub mod crc;
+pub mod frame;
+pub mod report;
 pub mod rice;
-pub mod utf8;
\ No newline at end of file
+pub mod stream;
+pub mod subframe;
+pub mod utf8;
+
+// Represents possible errors in writing an encoded stream
+#[derive(Debug)]
+#[derive(PartialEq)]
+pub enum EncoderError {
+    WriteError,
+    MetadataError(MetadataError),
+    SampleCountError(u64, u64),
+}
+
+impl error::Error for EncoderError {}
+
+impl fmt::Display for EncoderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EncoderError::WriteError => write!(f, "Error writing encoded stream"),
+            EncoderError::MetadataError(e) => write!(f, "{}", e),
+            EncoderError::SampleCountError(expected, actual) => write!(f, "Expected {} samples but the stream ended after {}", expected, actual),
+        }
+    }
+}
+
+impl From <io::Error> for EncoderError {
+    fn from(_: io::Error) -> Self {
+        EncoderError::WriteError
+    }
+}
+
+impl From <MetadataError> for EncoderError {
+    fn from(e: MetadataError) -> Self {
+        EncoderError::MetadataError(e)
+    }
+}
\ No newline at end of file
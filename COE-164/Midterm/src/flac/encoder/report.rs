@@ -0,0 +1,284 @@
+use core::fmt;
+use std::collections::BTreeMap;
+
+use crate::flac::decoder::frame::ChannelAssignment;
+use crate::flac::sample::Sample;
+
+use super::frame::Frame;
+use super::subframe::Subframe;
+
+/// Represents the type of a subframe
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum SubframeType {
+    Constant,
+    Verbatim,
+    Fixed,
+    Lpc,
+}
+
+/// Represents how one channel of a frame was encoded
+///
+/// The QLP fields are only set for `Lpc` subframes, and the Rice partition
+/// order only for `Fixed` and `Lpc` subframes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SubframeReport {
+    pub subframe_type: SubframeType,
+    pub order: u8,
+    pub qlp_precision: Option <u8>,
+    pub qlp_shift: Option <u8>,
+    pub rice_partition_order: Option <u8>,
+    pub wasted_bits: u8,
+}
+
+/// Represents how one frame was encoded
+#[derive(Debug, Clone, PartialEq)]
+pub struct FrameReport {
+    pub first_sample: u64,
+    pub block_size: u64,
+    pub channel_assignment: ChannelAssignment,
+    pub n_bytes: u64,
+    pub subframes: Vec <SubframeReport>,
+}
+
+/// Represents the statistics of an encoded stream
+///
+/// `n_bytes` is the size of the whole output, including the metadata blocks,
+/// while the size of each frame is given by its report.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EncodeReport {
+    pub n_channels: u8,
+    pub bps: u8,
+    pub total_samples: u64,
+    pub n_bytes: u64,
+    pub frames: Vec <FrameReport>,
+}
+
+impl SubframeType {
+    /// Get the name of the subframe type, such as `LPC`
+    pub fn name(&self) -> &'static str {
+        match self {
+            SubframeType::Constant => "CONSTANT",
+            SubframeType::Verbatim => "VERBATIM",
+            SubframeType::Fixed => "FIXED",
+            SubframeType::Lpc => "LPC",
+        }
+    }
+}
+
+impl SubframeReport {
+    /// Describe an encoded subframe with `wasted_bits` zero bits shifted out of its samples
+    pub fn new <T: Sample> (subframe: &Subframe <T>, wasted_bits: u8) -> Self {
+        let (subframe_type, qlp_precision, qlp_shift) = match subframe {
+            Subframe::Constant { .. } => (SubframeType::Constant, None, None),
+            Subframe::Verbatim { .. } => (SubframeType::Verbatim, None, None),
+            Subframe::Fixed { .. } => (SubframeType::Fixed, None, None),
+            Subframe::Lpc { precision, shift, .. } => (SubframeType::Lpc, Some(*precision), Some(*shift)),
+        };
+        SubframeReport {
+            subframe_type,
+            order: subframe.order(),
+            qlp_precision,
+            qlp_shift,
+            rice_partition_order: subframe.rice_partition_order(),
+            wasted_bits,
+        }
+    }
+}
+
+impl FrameReport {
+    /// Describe an encoded frame of `n_bytes` bytes that starts at `first_sample`
+    ///
+    /// The encoder codes every channel independently.
+    pub fn new <T: Sample> (frame: &Frame <T>, first_sample: u64, n_bytes: u64) -> Self {
+        FrameReport {
+            first_sample,
+            block_size: frame.header.block_size,
+            channel_assignment: ChannelAssignment::Independent,
+            n_bytes,
+            subframes: frame.subframes.iter().map(|(subframe, wasted_bits)| SubframeReport::new(subframe, *wasted_bits)).collect(),
+        }
+    }
+}
+
+impl EncodeReport {
+    /// Describe the frames of a stream, given the size of each frame and of the whole output
+    pub fn new <T: Sample> (frames: &[Frame <T>], frame_sizes: &[u64], n_bytes: u64) -> Self {
+        let mut first_sample = 0;
+        let frame_reports = frames.iter().zip(frame_sizes)
+            .map(|(frame, &frame_size)| {
+                let report = FrameReport::new(frame, first_sample, frame_size);
+                first_sample += frame.header.block_size;
+                report
+            })
+            .collect();
+
+        let header = frames.first().map(|frame| &frame.header);
+        EncodeReport::from_frames(header.map_or(0, |header| header.n_channels), header.map_or(0, |header| header.bps), frame_reports, n_bytes)
+    }
+
+    /// Describe a stream from the reports of its frames, given the size of the whole output
+    pub fn from_frames(n_channels: u8, bps: u8, frames: Vec <FrameReport>, n_bytes: u64) -> Self {
+        EncodeReport {
+            n_channels,
+            bps,
+            total_samples: frames.iter().map(|frame| frame.block_size).sum(),
+            n_bytes,
+            frames,
+        }
+    }
+
+    /// Get the size of the output relative to the size of the PCM samples
+    ///
+    /// The PCM samples take the smallest number of bytes that holds the sample
+    /// size, as in a WAV file. Zero is returned for an empty stream.
+    pub fn compression_ratio(&self) -> f64 {
+        let pcm_bytes = self.total_samples * self.n_channels as u64 * (self.bps as u64).div_ceil(8);
+        match pcm_bytes {
+            0 => 0.0,
+            _ => self.n_bytes as f64 / pcm_bytes as f64,
+        }
+    }
+
+    /// Get the average number of bits of the frames per sample of each channel
+    ///
+    /// Zero is returned for an empty stream.
+    pub fn bits_per_sample(&self) -> f64 {
+        let frame_bytes: u64 = self.frames.iter().map(|frame| frame.n_bytes).sum();
+        match self.total_samples * self.n_channels as u64 {
+            0 => 0.0,
+            n_samples => 8.0 * frame_bytes as f64 / n_samples as f64,
+        }
+    }
+
+    /// Count the subframes of each type and predictor order
+    pub fn order_histogram(&self) -> BTreeMap <(SubframeType, u8), usize> {
+        let mut histogram = BTreeMap::new();
+        for subframe in self.frames.iter().flat_map(|frame| &frame.subframes) {
+            *histogram.entry((subframe.subframe_type, subframe.order)).or_insert(0) += 1;
+        }
+        histogram
+    }
+
+    /// Encode the report as a JSON object
+    ///
+    /// Fields that do not apply to a subframe are `null`.
+    pub fn to_json(&self) -> String {
+        let optional = |value: Option <u8>| value.map_or("null".to_string(), |value| value.to_string());
+        let histogram: Vec <String> = self.order_histogram().iter()
+            .map(|((subframe_type, order), count)| format!("{{\"type\":\"{}\",\"order\":{},\"count\":{}}}", subframe_type.name(), order, count))
+            .collect();
+        let frames: Vec <String> = self.frames.iter()
+            .map(|frame| {
+                let subframes: Vec <String> = frame.subframes.iter()
+                    .map(|subframe| format!(
+                        "{{\"type\":\"{}\",\"order\":{},\"qlp_precision\":{},\"qlp_shift\":{},\"rice_partition_order\":{},\"wasted_bits\":{}}}",
+                        subframe.subframe_type.name(),
+                        subframe.order,
+                        optional(subframe.qlp_precision),
+                        optional(subframe.qlp_shift),
+                        optional(subframe.rice_partition_order),
+                        subframe.wasted_bits,
+                    ))
+                    .collect();
+                format!(
+                    "{{\"first_sample\":{},\"block_size\":{},\"channel_assignment\":\"{}\",\"bytes\":{},\"subframes\":[{}]}}",
+                    frame.first_sample, frame.block_size, frame.channel_assignment.name(), frame.n_bytes, subframes.join(","),
+                )
+            })
+            .collect();
+
+        format!(
+            "{{\"channels\":{},\"bps\":{},\"total_samples\":{},\"bytes\":{},\"compression_ratio\":{},\"bits_per_sample\":{},\"order_histogram\":[{}],\"frames\":[{}]}}",
+            self.n_channels, self.bps, self.total_samples, self.n_bytes,
+            self.compression_ratio(), self.bits_per_sample(), histogram.join(","), frames.join(","),
+        )
+    }
+}
+
+impl fmt::Display for SubframeReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.subframe_type.name())?;
+        if matches!(self.subframe_type, SubframeType::Fixed | SubframeType::Lpc) {
+            write!(f, " order {}", self.order)?;
+        }
+        if let (Some(precision), Some(shift)) = (self.qlp_precision, self.qlp_shift) {
+            write!(f, " precision {} shift {}", precision, shift)?;
+        }
+        if let Some(partition_order) = self.rice_partition_order {
+            write!(f, " partition order {}", partition_order)?;
+        }
+        if self.wasted_bits > 0 {
+            write!(f, " wasted bits {}", self.wasted_bits)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for EncodeReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, frame) in self.frames.iter().enumerate() {
+            let subframes: Vec <String> = frame.subframes.iter().map(|subframe| subframe.to_string()).collect();
+            writeln!(f, "Frame {} at sample {}: {} samples, {} bytes, {}: {}",
+                i, frame.first_sample, frame.block_size, frame.n_bytes, frame.channel_assignment.name(), subframes.join(", "))?;
+        }
+        writeln!(f, "Orders:")?;
+        for ((subframe_type, order), count) in self.order_histogram() {
+            writeln!(f, "  {} {}: {}", subframe_type.name(), order, count)?;
+        }
+        write!(f, "{} frames, {} samples, {} bytes, ratio {:.4}, {:.3} bits per sample",
+            self.frames.len(), self.total_samples, self.n_bytes, self.compression_ratio(), self.bits_per_sample())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::flac::encoder::stream::FlacEncoderOptions;
+
+    #[test]
+    fn test_report() {
+        let channels = vec![(0..5000).map(|i| ((i as f64 * 0.05).sin() * 1000.0) as i64).collect(), vec![3; 5000]];
+        let (bytes, report) = FlacEncoderOptions::new(44100, 2, 16).encode_with_report(&channels).unwrap();
+
+        assert_eq!(report.n_bytes, bytes.len() as u64);
+        assert_eq!(report.frames.len(), 2);
+        assert_eq!(report.frames[1].first_sample, 4096);
+        assert_eq!(report.frames.iter().map(|frame| frame.n_bytes).sum::<u64>(), bytes.len() as u64 - 42);
+
+        let constant = &report.frames[0].subframes[1];
+        assert_eq!((constant.subframe_type, constant.order, constant.rice_partition_order), (SubframeType::Constant, 0, None));
+        let tone = &report.frames[0].subframes[0];
+        assert!(matches!(tone.subframe_type, SubframeType::Fixed | SubframeType::Lpc));
+        assert!(tone.rice_partition_order.is_some());
+
+        assert_eq!(report.order_histogram().get(&(SubframeType::Constant, 0)), Some(&2));
+        assert!(report.compression_ratio() > 0.0 && report.compression_ratio() < 0.5);
+        assert_eq!(report.bits_per_sample(), 8.0 * (bytes.len() - 42) as f64 / 10000.0);
+    }
+
+    #[test]
+    fn test_to_json() {
+        let report = EncodeReport {
+            n_channels: 1,
+            bps: 16,
+            total_samples: 100,
+            n_bytes: 100,
+            frames: vec![FrameReport {
+                first_sample: 0,
+                block_size: 100,
+                channel_assignment: ChannelAssignment::Independent,
+                n_bytes: 50,
+                subframes: vec![SubframeReport { subframe_type: SubframeType::Lpc, order: 2, qlp_precision: Some(12), qlp_shift: Some(9), rice_partition_order: Some(0), wasted_bits: 0 }],
+            }],
+        };
+
+        assert_eq!(report.to_json(), concat!(
+            "{\"channels\":1,\"bps\":16,\"total_samples\":100,\"bytes\":100,\"compression_ratio\":0.5,\"bits_per_sample\":4,",
+            "\"order_histogram\":[{\"type\":\"LPC\",\"order\":2,\"count\":1}],",
+            "\"frames\":[{\"first_sample\":0,\"block_size\":100,\"channel_assignment\":\"INDEPENDENT\",\"bytes\":50,",
+            "\"subframes\":[{\"type\":\"LPC\",\"order\":2,\"qlp_precision\":12,\"qlp_shift\":9,\"rice_partition_order\":0,\"wasted_bits\":0}]}]}",
+        ));
+        assert!(report.to_string().ends_with("1 frames, 100 samples, 100 bytes, ratio 0.5000, 4.000 bits per sample"));
+    }
+}
@@ -1,30 +1,46 @@
-use std::ops::{Shl, Shr};
+use std::ops::{Range, RangeInclusive, Shl, Shr};
 
-fn to_binary(mut decimal: u32) -> Vec<u32> { // modified from https://codereview.stackexchange.com/questions/210967/decimal-to-binary-in-rust
-    let mut bits = Vec::new();
-    if decimal == 0 {
-        bits.push(0);
-    } else {
+use crate::flac::sample::Sample;
 
+use super::bitwriter::BitWriter;
 
-        while decimal > 0 {
-            if decimal % 2 == 0 {
-                bits.push(0);
-            } else {
-                bits.push(1);
-            }
+pub struct RiceEncoderOptions {
+    num_samples: u64,
+    predictor_order: u8,
+}
 
-            decimal /= 2;
-        }
-   
-    }
-    return bits
+/// Represents the residual coding method of a subframe
+///
+/// `Rice` stores 4-bit parameters up to 14 and `Rice2` stores 5-bit parameters
+/// up to 30. The largest value of each parameter field is reserved as an escape
+/// code for partitions that are stored as raw signed values instead.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RiceCodingMethod {
+    Rice,
+    Rice2,
 }
 
+/// Largest partition order allowed by the 4-bit partition order field
+pub const MAX_RICE_PARTITION_ORDER: u8 = 15;
 
-pub struct RiceEncoderOptions {
-    num_samples: u64,
-    predictor_order: u8,
+/// Represents the statistics used to choose the parameter of one partition
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+struct PartitionStats {
+    n_samples: u64,
+    folded_sum: u64,
+    raw_bits: u8,
+}
+
+/// Represents the chosen partitioning of a residual
+///
+/// A partition whose parameter is the escape code of `method` is stored as
+/// raw signed values.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RicePartitions {
+    pub method: RiceCodingMethod,
+    pub order: u8,
+    pub params: Vec <u8>,
+    pub bits: u64,
 }
 
 #[derive(Debug)]
@@ -53,94 +69,270 @@ impl RiceEncoderOptions {
 
     /// Get the maximum partition order
     /// 
-    /// The maximum partition order is determined by the position of the least significant 1 bit in the block size
-    fn max_rice_partition_order(mut block_size: u64) -> u8 {
-        let mut order = 0;
-        while block_size > 1 {
-            block_size = block_size.shr(1);
-            order += 1;
+    /// The maximum partition order is determined by the position of the least significant 1 bit in the block size,
+    /// since every partition must have the same size. It is further limited by the 4-bit partition order field and
+    /// by the first partition, which must be larger than the number of warm-up samples it skips.
+    fn max_rice_partition_order(&self) -> u8 {
+        let mut order = (self.num_samples.trailing_zeros() as u8).min(MAX_RICE_PARTITION_ORDER);
+        while order > 0 && self.num_samples.shr(order) <= self.predictor_order as u64 {
+            order -= 1;
         }
         order
     }
 
-    // Compute the best partition order and best Rice parameters for each partition
-    // The best partition order is the one that minimizes the total number of bits in the Rice encoding
-    // There will be 2^order partitions
-    fn best_partition_and_params(&self, residuals: &Vec <i64>) -> (Vec <u8>, u8) {
-        let mut best_order = 0;
-        let mut best_params = vec![];
-        let mut min_bits = u64::MAX;
+    /// Compute the best coding method, partition order and best Rice parameters for each partition
+    ///
+    /// The best partition order is the one that minimizes the total number of bits in the Rice encoding,
+    /// including the parameter of each partition. There will be 2^order partitions.
+    ///
+    /// The statistics of each partition are computed once for the maximum order, and the statistics of
+    /// each lower order are found by merging neighboring partitions. Ties are broken in favor of `Rice`
+    /// and of lower partition orders. Once the partitioning is chosen, its parameters are refined with
+    /// the exact size of each partition.
+    pub fn best_partition_and_params <T: Sample> (&self, residuals: &[T]) -> RicePartitions {
+        let max_order = self.max_rice_partition_order();
+        let mut stats = self.partition_stats(max_order, residuals);
+        let mut best = RicePartitions {
+            method: RiceCodingMethod::Rice,
+            order: 0,
+            params: vec![],
+            bits: u64::MAX,
+        };
         
-        // Iterate over all possible partition orders to find the best one
-        for order in RiceEncoderOptions::min_rice_partition_order()..=RiceEncoderOptions::max_rice_partition_order(self.num_samples) {
-            if let Some((params, bits)) = self.best_parameters(order, residuals) {
-                if bits < min_bits {
-                    min_bits = bits;
-                    best_order = order;
-                    best_params = params;
+        // Iterate over all possible partition orders and methods to find the best one
+        for order in (RiceEncoderOptions::min_rice_partition_order()..=max_order).rev() {
+            for method in [RiceCodingMethod::Rice2, RiceCodingMethod::Rice] {
+                let (params, bits) = RiceEncoderOptions::best_parameters(method, &stats);
+                if bits <= best.bits {
+                    best = RicePartitions { method, order, params, bits };
+                }
+            }
+
+            stats = stats.chunks(2)
+                .map(|pair| pair.iter().fold(PartitionStats::default(), |merged, part| merged.merge(part)))
+                .collect();
+        }
+
+        let (params, bits) = self.exact_parameters(best.method, best.order, residuals);
+        RicePartitions { params, bits, ..best }
+    }
+
+    /// Get the range of residuals covered by the `i`th partition for a given partition order
+    /// 
+    /// The warm-up samples are stored separately from the residuals, so the residuals
+    /// start at the first sample after them and the first partition is shorter than
+    /// the others by the predictor order.
+    fn partition_range(&self, partition_order: u8, i: u64) -> Range <usize> {
+        let partition_size = self.num_samples.shr(partition_order);
+        let start = if i == 0 {
+            0
+        } else {
+            i * partition_size - self.predictor_order as u64
+        };
+        let end = (i + 1) * partition_size - self.predictor_order as u64;
+        start as usize..end as usize
+    }
+
+    /// Compute the statistics of every partition for a given partition order in a single pass
+    fn partition_stats <T: Sample> (&self, partition_order: u8, residuals: &[T]) -> Vec <PartitionStats> {
+        let n_partitions = 1u64.shl(partition_order);
+        (0..n_partitions)
+            .map(|i| PartitionStats::new(&residuals[self.partition_range(partition_order, i)]))
+            .collect()
+    }
+
+    /// Compute the best Rice parameters for a given coding method and partitioning
+    /// 
+    /// The parameter of each partition is estimated in closed form from the mean of its
+    /// folded residuals, so the cost is constant per partition. The returned number of
+    /// bits includes the parameter of each partition. A partition is escaped if storing
+    /// its residuals as raw values in the fewest bits that hold all of them is smaller
+    /// than its Rice encoding.
+    fn best_parameters(method: RiceCodingMethod, stats: &[PartitionStats]) -> (Vec <u8>, u64) {
+        let mut params = Vec::with_capacity(stats.len());
+        let mut total_bits = 0;
+
+        for part in stats {
+            let mut best_param = 0;
+            let mut best_bits = u64::MAX;
+
+            // The optimal parameter is close to lg(mean), so only its neighbors need to be checked
+            for param in RiceEncoderOptions::param_neighborhood(method, part) {
+                let bits = RiceEncoderOptions::bits_in_partition_sums(param, part.n_samples, part.folded_sum);
+                if bits < best_bits {
+                    best_bits = bits;
+                    best_param = param;
                 }
             }
+
+            // Escaped partitions store the 5-bit raw sample size before the residuals
+            let escaped_bits = 5 + part.n_samples * part.raw_bits as u64;
+            if part.raw_bits <= 31 && escaped_bits < best_bits {
+                best_bits = escaped_bits;
+                best_param = method.escape_code();
+            }
+
+            params.push(best_param);
+            total_bits += method.param_bits() as u64 + best_bits;
         }
-        (best_params, best_order)
+
+        (params, total_bits)
     }
 
-    /// Compute the best Rice parameters for a given partition order
-    fn best_parameters(&self, partition_order: u8, residuals: &Vec <i64>) -> Option <(Vec <u8>, u64)> {
-        let n_partitions = 1.shl(partition_order);
-        let mut params = vec![0; n_partitions as usize];
+    /// Compute the best Rice parameters for a given coding method and partition order
+    /// using the exact number of bits of each partition
+    /// 
+    /// This checks the same parameters as `best_parameters`, but counts the bits of every
+    /// residual instead of estimating them from the sums. The returned number of bits is
+    /// exactly the size of the partitions written by `write_residual`.
+    fn exact_parameters <T: Sample> (&self, method: RiceCodingMethod, partition_order: u8, residuals: &[T]) -> (Vec <u8>, u64) {
+        let mut params = Vec::with_capacity(1 << partition_order);
         let mut total_bits = 0;
 
-        // Calculate the best Rice parameter for each partition
-        for i in 0..n_partitions {
-            let start = if partition_order == 0 {
-                self.predictor_order as u64
-            } else {
-                (i as u64 * self.num_samples).shr(partition_order)
-            };
-            let end = ((i as u64 + 1) * self.num_samples).shr(partition_order);
-            let abs_residual_sum = RiceEncoderOptions::sum_abs_residuals(&residuals[start as usize..end as usize]);
+        for i in 0..1u64.shl(partition_order) {
+            let partition = &residuals[self.partition_range(partition_order, i)];
+            let part = PartitionStats::new(partition);
             let mut best_param = 0;
             let mut best_bits = u64::MAX;
 
-            // Try all possible Rice parameters and find the one with the fewest bits
-            for param in 0..15 {
-                let bits = RiceEncoderOptions::bits_in_partition_sums(param, end - start, abs_residual_sum);
+            for param in RiceEncoderOptions::param_neighborhood(method, &part) {
+                let bits = RiceEncoderOptions::bits_in_partition(param, partition);
                 if bits < best_bits {
                     best_bits = bits;
                     best_param = param;
                 }
             }
 
-            params[i as usize] = best_param;
-            total_bits += best_bits;
+            let escaped_bits = 5 + part.n_samples * part.raw_bits as u64;
+            if part.raw_bits <= 31 && escaped_bits < best_bits {
+                best_bits = escaped_bits;
+                best_param = method.escape_code();
+            }
+
+            params.push(best_param);
+            total_bits += method.param_bits() as u64 + best_bits;
         }
 
-        Some((params, total_bits))
+        (params, total_bits)
     }
 
-    fn sum_abs_residuals(residuals: &[i64]) -> u64 {
+    /// Get the parameters worth checking for a partition
+    /// 
+    /// These are the closed-form estimate and its neighbors, within the range of the method.
+    fn param_neighborhood(method: RiceCodingMethod, part: &PartitionStats) -> RangeInclusive <u8> {
+        let estimate = RiceEncoderOptions::estimate_param(part.n_samples, part.folded_sum).min(method.max_param());
+        estimate.saturating_sub(1)..=(estimate + 1).min(method.max_param())
+    }
+
+    /// Estimate the best Rice parameter of a partition as `floor(lg(mean))`
+    fn estimate_param(n_partition_samples: u64, folded_residual_sum: u64) -> u8 {
+        if n_partition_samples == 0 {
+            return 0;
+        }
+        let mean = folded_residual_sum / n_partition_samples;
+        if mean == 0 {
+            0
+        } else {
+            (63 - mean.leading_zeros()) as u8
+        }
+    }
+
+    /// Fold a signed residual into an unsigned value
+    /// 
+    /// The sign is moved into the lowest bit so that small magnitudes stay small:
+    /// 0, -1, 1, -2, 2, ... become 0, 1, 2, 3, 4, ...
+    pub fn fold(residual: i64) -> u64 {
+        ((residual << 1) ^ (residual >> 63)) as u64
+    }
+
+    fn sum_folded_residuals <T: Sample> (residuals: &[T]) -> u64 {
         let mut sum = 0;
         for &residual in residuals {
-            sum += residual.abs() as u64;
+            sum += RiceEncoderOptions::fold(residual.to_i64());
         }
         sum
     }
 
-    /// Find the total number of bits occupied by this encoding
+    /// Get the number of bits needed to store every residual as a raw signed value
+    /// 
+    /// Zero bits are needed if all of the residuals are zero.
+    fn raw_bits <T: Sample> (residuals: &[T]) -> u8 {
+        residuals.iter()
+            .map(|&residual| residual.to_i64())
+            .filter(|&residual| residual != 0)
+            .map(BitWriter::signed_bits)
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Estimate the total number of bits occupied by this encoding
     /// 
     /// Rice encoding uses `q + 1` bits for the unary-encoded quotient `q` and
-    /// `rice_param` bits for the binary remainder
-    fn bits_in_partition_sums(rice_param: u8, n_partition_samples: u64, abs_residual_sum: u64) -> u64 {
-        let mut bits = 0;
-        let divisor = 1.shl(rice_param) as u64;
-
-        // Calculate bits for each residual value
-        for _ in 0..n_partition_samples {
-            let quotient = abs_residual_sum / divisor;
-            bits += quotient + 1 + rice_param as u64;
+    /// `rice_param` bits for the binary remainder. Summed over a partition, the
+    /// quotients add up to roughly `folded_residual_sum >> rice_param`.
+    fn bits_in_partition_sums(rice_param: u8, n_partition_samples: u64, folded_residual_sum: u64) -> u64 {
+        folded_residual_sum.shr(rice_param) + n_partition_samples * (1 + rice_param as u64)
+    }
+
+    /// Find the exact number of bits occupied by the Rice encoding of a partition
+    pub fn bits_in_partition <T: Sample> (rice_param: u8, residuals: &[T]) -> u64 {
+        residuals.iter()
+            .map(|&residual| RiceEncoderOptions::fold(residual.to_i64()).shr(rice_param) + 1 + rice_param as u64)
+            .sum()
+    }
+
+    /// Get the size of the residual section of a subframe in bits
+    /// 
+    /// This includes the 2-bit coding method, the 4-bit partition order and the
+    /// Rice-coded partitions for the best coding method, partition order and parameters.
+    /// It is exactly the number of bits written by `write_residual`.
+    pub fn estimate_bits <T: Sample> (&self, residuals: &[T]) -> u64 {
+        2 + 4 + self.best_partition_and_params(residuals).bits
+    }
+
+    /// Write the residual section of a subframe
+    /// 
+    /// The coding method and partition order are written first, followed by the
+    /// parameter and Rice encoding of each partition. Escaped partitions are written
+    /// as their raw sample size followed by the raw residuals instead. `residuals`
+    /// starts after the warm-up samples, which are written before this section.
+    pub fn write_residual <T: Sample> (&self, writer: &mut BitWriter, residuals: &[T]) {
+        let partitions = self.best_partition_and_params(residuals);
+        let method = partitions.method;
+
+        writer.write_bits(method.code(), 2);
+        writer.write_bits(partitions.order as u64, 4);
+        for (i, &param) in partitions.params.iter().enumerate() {
+            let partition = &residuals[self.partition_range(partitions.order, i as u64)];
+            writer.write_bits(param as u64, method.param_bits());
+
+            if param == method.escape_code() {
+                RiceEncoderOptions::write_escaped(writer, partition);
+            } else {
+                RiceEncoderOptions::write_rice(writer, param, partition);
+            }
         }
+    }
 
-        bits
+    /// Write the Rice encoding of each residual
+    /// 
+    /// Each residual is folded, then its quotient by `2^rice_param` is written in unary
+    /// followed by its `rice_param` lowest bits.
+    fn write_rice <T: Sample> (writer: &mut BitWriter, rice_param: u8, residuals: &[T]) {
+        for &residual in residuals {
+            let folded = RiceEncoderOptions::fold(residual.to_i64());
+            writer.write_unary(folded.shr(rice_param));
+            writer.write_bits(folded, rice_param);
+        }
+    }
+
+    /// Write an escaped partition as its 5-bit raw sample size followed by the raw residuals
+    fn write_escaped <T: Sample> (writer: &mut BitWriter, residuals: &[T]) {
+        let raw_bits = RiceEncoderOptions::raw_bits(residuals);
+        writer.write_bits(raw_bits as u64, 5);
+        for &residual in residuals {
+            writer.write_signed(residual.to_i64(), raw_bits);
+        }
     }
 
     /// Encode residuals into Rice encoding
@@ -148,68 +340,105 @@ impl RiceEncoderOptions {
     /// This function computes the Rice encoding of each residual and returns the
     /// byte-aligned encodings and number of unused bits in the last element, respectively.
     /// Rice encoding is variable-length, so there is a chance that the stream is not
-    /// byte-aligned.
-
-
-    pub fn encode(rice_param: u32, residuals: &Vec <i64>) -> RiceEncodedStream {
-        let mut encoded_bits = Vec::new();
-        let m = rice_param as u32;
-        let m_float = m as f32;
-        let k = m_float.log2() as u32;
-        let mut initial_num_bits = 0;
-        let mut final_num_bits = 0;
-
-        for residual in residuals {
-            // s: u32, m: u32, k: u32
-            let s = *residual as u32;
-            
-            // Unary part: U = S >> K
-            let u = s >> k;
-            // Unary encoding: represent U in unary with '0's followed by a '1'
-            for _ in 0..u {
-                encoded_bits.push(1 as u8);
-            }
-            encoded_bits.push(0 as u8); 
-
-            // Truncated Binary part: B = S & (M - 1)
-            let b = s & (m - 1);
-            // Binary encoding: represent B in binary, padded to the left with zeros until it is of length K
-            for i in (0..k).rev() { // MSB -> LSB
-                encoded_bits.push(((b >> i) & 1) as u8);
-            }
-            initial_num_bits += to_binary(s).len();
-            final_num_bits = encoded_bits.len();
-        }
+    /// byte-aligned. `rice_param` is the number of bits of the binary remainder, as in
+    /// the FLAC residual section.
+    pub fn encode <T: Sample> (rice_param: u8, residuals: &[T]) -> RiceEncodedStream {
+        let mut writer = BitWriter::new();
+        RiceEncoderOptions::write_rice(&mut writer, rice_param, residuals);
+        RiceEncoderOptions::finish_stream(writer, rice_param)
+    }
 
-        let extra_bits_len = final_num_bits - initial_num_bits;
+    fn finish_stream(writer: BitWriter, param: u8) -> RiceEncodedStream {
+        let extra_bits_len = ((8 - writer.len_bits() % 8) % 8) as u8;
         RiceEncodedStream {
-            stream: encoded_bits,
-            param: m as u8,
-            extra_bits_len: extra_bits_len as u8,
+            stream: writer.into_bytes(),
+            param,
+            extra_bits_len,
         }
-        //encoded_bits
     }
 
-
     /// Encode residuals into Rice encoding
     /// 
     /// This function computes the Rice encoding of each residual by first partitioning
     /// the residual into groups. Each group is then found its best Rice parameter and
-    /// then encoded using the parameter. The Rice encoding of each group is then returned.
-    pub fn encode_by_partition(&self, residuals: &Vec <i64>)  -> (Vec <RiceEncodedStream>, u8) {
-        let (best_params, best_order) = self.best_partition_and_params(residuals);
-        let n_partitions = 1.shl(best_order);
+    /// then encoded using the parameter. The Rice encoding of each group is then returned
+    /// along with the partition order and coding method. The stream of an escaped group
+    /// has the escape code of the method as its parameter and starts with the 5-bit raw
+    /// sample size.
+    pub fn encode_by_partition <T: Sample> (&self, residuals: &[T]) -> (Vec <RiceEncodedStream>, u8, RiceCodingMethod) {
+        let RicePartitions { method, order: best_order, params: best_params, .. } = self.best_partition_and_params(residuals);
         let mut streams = vec![];
 
         // Encode each partition
-        for i in 0..n_partitions {
-            let start = (i as u64 * self.num_samples).shr(best_order) as usize;
-            let end = ((i as u64 + 1) * self.num_samples).shr(best_order) as usize;
-            let partition_residuals = residuals[start..end].to_vec();
-            streams.push(RiceEncoderOptions::encode(best_params[i as usize].into(), &partition_residuals));
+        for (i, &param) in best_params.iter().enumerate() {
+            let partition_residuals = &residuals[self.partition_range(best_order, i as u64)];
+            if param == method.escape_code() {
+                let mut writer = BitWriter::new();
+                RiceEncoderOptions::write_escaped(&mut writer, partition_residuals);
+                streams.push(RiceEncoderOptions::finish_stream(writer, param));
+            } else {
+                streams.push(RiceEncoderOptions::encode(param, partition_residuals));
+            }
         }
 
-        (streams, best_order)
+        (streams, best_order, method)
+    }
+}
+
+
+
+impl PartitionStats {
+    fn new <T: Sample> (residuals: &[T]) -> Self {
+        PartitionStats {
+            n_samples: residuals.len() as u64,
+            folded_sum: RiceEncoderOptions::sum_folded_residuals(residuals),
+            raw_bits: RiceEncoderOptions::raw_bits(residuals),
+        }
+    }
+
+    /// Combine the statistics of two neighboring partitions
+    fn merge(&self, other: &PartitionStats) -> PartitionStats {
+        PartitionStats {
+            n_samples: self.n_samples + other.n_samples,
+            folded_sum: self.folded_sum + other.folded_sum,
+            raw_bits: self.raw_bits.max(other.raw_bits),
+        }
+    }
+}
+
+impl RiceCodingMethod {
+    /// Get the name of the method, such as `RICE2`
+    pub fn name(&self) -> &'static str {
+        match self {
+            RiceCodingMethod::Rice => "RICE",
+            RiceCodingMethod::Rice2 => "RICE2",
+        }
+    }
+
+    /// Get the 2-bit code of the method in the residual header
+    pub fn code(&self) -> u64 {
+        match self {
+            RiceCodingMethod::Rice => 0b00,
+            RiceCodingMethod::Rice2 => 0b01,
+        }
+    }
+
+    /// Get the size of the parameter field of each partition
+    pub fn param_bits(&self) -> u8 {
+        match self {
+            RiceCodingMethod::Rice => 4,
+            RiceCodingMethod::Rice2 => 5,
+        }
+    }
+
+    /// Get the parameter value that marks an escaped partition
+    pub fn escape_code(&self) -> u8 {
+        (1 << self.param_bits()) - 1
+    }
+
+    /// Get the largest Rice parameter of the method
+    pub fn max_param(&self) -> u8 {
+        self.escape_code() - 1
     }
 }
 
@@ -218,14 +447,36 @@ impl RiceEncoderOptions {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::flac::decoder::bitreader::BitReader;
+    use crate::flac::decoder::rice::RiceDecoder;
+    use crate::flac::test_util::XorShift;
+
+    /// Generate pseudo-random residuals with magnitudes of up to `max_bits` bits
+    fn random_residuals(seed: u64, len: usize, max_bits: u8) -> Vec <i32> {
+        XorShift::new(seed | 1).take(len).map(|state| {
+            let bits = (state % max_bits as u64) as u32 + 1;
+            ((state >> 8) as i64 >> (64 - bits)) as i32
+        }).collect()
+    }
+
+    #[test]
+    fn test_fold() {
+        assert_eq!(RiceEncoderOptions::fold(0), 0);
+        assert_eq!(RiceEncoderOptions::fold(-1), 1);
+        assert_eq!(RiceEncoderOptions::fold(1), 2);
+        assert_eq!(RiceEncoderOptions::fold(-2), 3);
+        assert_eq!(RiceEncoderOptions::fold(18), 36);
+        assert_eq!(RiceEncoderOptions::fold(i32::MIN as i64), u32::MAX as u64);
+    }
 
     #[test]
     fn test_encode() {
-        let m = 16;
+        let rice_param = 4;
         let input_residuals = vec![18];
-        let expected_output = RiceEncodedStream { stream: vec![1, 0, 0, 0, 1, 0], param: m as u8, extra_bits_len: 1}; // from CoE 164 MidP Annex
+        // 18 folds to 36 = 0b10_0100, so quotient 2 and remainder 0b0100
+        let expected_output = RiceEncodedStream { stream: vec![0b0010_1000], param: rice_param, extra_bits_len: 1 };
         
-        let rice_encoded_stream = RiceEncoderOptions::encode(m, &input_residuals);
+        let rice_encoded_stream = RiceEncoderOptions::encode(rice_param, &input_residuals);
 
         assert_eq!(rice_encoded_stream, expected_output);
     }
@@ -238,7 +489,7 @@ mod tests {
         let test_rice_encoder_options = RiceEncoderOptions::new(input_num_samples, input_predictor_order);
         let expected_order = 0;
         
-        let best_order = test_rice_encoder_options.best_partition_and_params(&input_residuals).1;
+        let best_order = test_rice_encoder_options.best_partition_and_params(&input_residuals).order;
 
         assert_eq!(best_order, expected_order); // There will be 2^order partitions, so there will only be one partition
     }
@@ -249,9 +500,9 @@ mod tests {
         let input_num_samples = 1;
         let input_predictor_order = 0;
         let test_rice_encoder_options = RiceEncoderOptions::new(input_num_samples, input_predictor_order);
-        let expected_output = vec![2];
+        let expected_output = vec![3];
         
-        let best_params = test_rice_encoder_options.best_partition_and_params(&input_residuals).0;
+        let best_params = test_rice_encoder_options.best_partition_and_params(&input_residuals).params;
 
         assert_eq!(best_params, expected_output); // best rice parameter for the single partition
         
@@ -263,14 +514,168 @@ mod tests {
         let input_num_samples = 1;
         let input_predictor_order = 0;
         let test_rice_encoder_options = RiceEncoderOptions::new(input_num_samples, input_predictor_order);
-        let expected_output = vec![RiceEncodedStream {stream: vec![1, 1, 1, 1, 1, 0, 0], param: 2, extra_bits_len: 3 }];
-        // (streams, best_order) 
+        let expected_output = vec![RiceEncodedStream {stream: vec![0b0011_0000], param: 3, extra_bits_len: 2 }];
+        // (streams, best_order, method)
         let test_stream = test_rice_encoder_options.encode_by_partition(&input_residuals).0;
 
         assert_eq!(test_stream, expected_output); 
         
     }
-    
-}
 
-   
\ No newline at end of file
+    #[test]
+    fn test_escape_zero_partition() {
+        let input_residuals = vec![0; 16];
+        let test_rice_encoder_options = RiceEncoderOptions::new(16, 0);
+
+        let partitions = test_rice_encoder_options.best_partition_and_params(&input_residuals);
+
+        assert_eq!(partitions.method, RiceCodingMethod::Rice);
+        assert_eq!(partitions.order, 0);
+        assert_eq!(partitions.params, vec![RiceCodingMethod::Rice.escape_code()]);
+        assert_eq!(partitions.bits, 4 + 5); // parameter and 5-bit raw size of zero
+    }
+
+    #[test]
+    fn test_write_escaped_residual() {
+        let input_residuals = vec![0; 16];
+        let test_rice_encoder_options = RiceEncoderOptions::new(16, 0);
+        let mut writer = BitWriter::new();
+
+        test_rice_encoder_options.write_residual(&mut writer, &input_residuals);
+
+        assert_eq!(writer.len_bits(), test_rice_encoder_options.estimate_bits(&input_residuals));
+        // method 00, order 0000, escape 1111, raw size 00000
+        assert_eq!(writer.into_bytes(), vec![0b0000_0011, 0b1100_0000]);
+    }
+
+    #[test]
+    fn test_rice2_for_large_residuals() {
+        // Mostly moderate residuals with rare outliers, so escaping the partition does not pay off
+        let input_residuals: Vec <i64> = (0..256)
+            .map(|i| if i % 64 == 0 { 2000 << 16 } else { ((i * 7919) % 41 - 20) << 16 })
+            .collect();
+        let test_rice_encoder_options = RiceEncoderOptions::new(256, 0);
+
+        let partitions = test_rice_encoder_options.best_partition_and_params(&input_residuals);
+
+        assert_eq!(partitions.method, RiceCodingMethod::Rice2);
+        assert!(partitions.params.iter().any(|&param| param > RiceCodingMethod::Rice.max_param()));
+    }
+
+    #[test]
+    fn test_coding_method_fields() {
+        assert_eq!(RiceCodingMethod::Rice.escape_code(), 15);
+        assert_eq!(RiceCodingMethod::Rice.max_param(), 14);
+        assert_eq!(RiceCodingMethod::Rice2.escape_code(), 31);
+        assert_eq!(RiceCodingMethod::Rice2.max_param(), 30);
+    }
+
+    #[test]
+    fn test_max_partition_order() {
+        assert_eq!(RiceEncoderOptions::new(4096, 0).max_rice_partition_order(), 12);
+        assert_eq!(RiceEncoderOptions::new(4096, 32).max_rice_partition_order(), 6);
+        assert_eq!(RiceEncoderOptions::new(1152, 2).max_rice_partition_order(), 7);
+        assert_eq!(RiceEncoderOptions::new(1 << 20, 0).max_rice_partition_order(), MAX_RICE_PARTITION_ORDER);
+        assert_eq!(RiceEncoderOptions::new(4095, 0).max_rice_partition_order(), 0);
+    }
+
+    #[test]
+    fn test_partition_range() {
+        let test_rice_encoder_options = RiceEncoderOptions::new(16, 3);
+
+        assert_eq!(test_rice_encoder_options.partition_range(0, 0), 0..13);
+        assert_eq!(test_rice_encoder_options.partition_range(2, 0), 0..1);
+        assert_eq!(test_rice_encoder_options.partition_range(2, 1), 1..5);
+        assert_eq!(test_rice_encoder_options.partition_range(2, 3), 9..13);
+    }
+
+    #[test]
+    fn test_merged_stats_match_direct_stats() {
+        let input_residuals: Vec <i64> = (0..252).map(|i| (i * 7919 % 601) - 300).collect();
+        let test_rice_encoder_options = RiceEncoderOptions::new(256, 4);
+
+        let fine = test_rice_encoder_options.partition_stats(5, &input_residuals);
+        let merged: Vec <PartitionStats> = fine.chunks(2).map(|pair| pair[0].merge(&pair[1])).collect();
+
+        assert_eq!(merged, test_rice_encoder_options.partition_stats(4, &input_residuals));
+    }
+
+    #[test]
+    fn test_estimated_param_matches_full_search() {
+        for n_samples in [1, 7, 64, 4096] {
+            for folded_sum in [0, 1, 10, 999, 123_456, 98_765_432] {
+                let full_search = (0..=RiceCodingMethod::Rice2.max_param())
+                    .map(|param| RiceEncoderOptions::bits_in_partition_sums(param, n_samples, folded_sum))
+                    .min()
+                    .unwrap();
+                let stats = [PartitionStats { n_samples, folded_sum, raw_bits: 32 }];
+                let (_, bits) = RiceEncoderOptions::best_parameters(RiceCodingMethod::Rice2, &stats);
+
+                assert_eq!(bits - 5, full_search, "n = {}, sum = {}", n_samples, folded_sum);
+            }
+        }
+    }
+
+    #[test]
+    fn test_escape_flat_partition() {
+        // Large residuals of nearly equal magnitude are smaller as raw values
+        let input_residuals: Vec <i64> = (0..64).map(|i| if i % 2 == 0 { 1 << 20 } else { -(1 << 20) }).collect();
+        let test_rice_encoder_options = RiceEncoderOptions::new(64, 0);
+
+        let partitions = test_rice_encoder_options.best_partition_and_params(&input_residuals);
+
+        assert_eq!(partitions.order, 0);
+        assert_eq!(partitions.params, vec![RiceCodingMethod::Rice.escape_code()]);
+        assert_eq!(partitions.bits, 4 + 5 + 64 * 22);
+    }
+
+    #[test]
+    fn test_exact_bits_match_encoding() {
+        for n in [1, 7, 8, 16, 33] {
+            let partition = random_residuals(n * 31, n as usize, 12);
+            for rice_param in 0..=14 {
+                let stream = RiceEncoderOptions::encode(rice_param, &partition);
+                let n_bits = stream.stream.len() as u64 * 8 - stream.extra_bits_len as u64;
+
+                assert_eq!(RiceEncoderOptions::bits_in_partition(rice_param, &partition), n_bits);
+            }
+        }
+    }
+
+    #[test]
+    fn test_encode_decode_random_residuals() {
+        for seed in 1..64 {
+            let partition = random_residuals(seed, 1 + seed as usize * 5, 24);
+            let rice_param = (seed % 20) as u8;
+
+            let stream = RiceEncoderOptions::encode(rice_param, &partition);
+
+            assert_eq!(RiceDecoder::decode(&stream, partition.len()).unwrap(), partition);
+        }
+    }
+
+    #[test]
+    fn test_write_read_random_residuals() {
+        let block_sizes = [16, 192, 576, 1152, 4096, 4608];
+        for seed in 1..48u64 {
+            let block_size = block_sizes[seed as usize % block_sizes.len()];
+            let predictor_order = (seed % 9) as u8;
+            let max_bits = [1, 4, 12, 20, 30][seed as usize % 5];
+            let mut residuals = random_residuals(seed, block_size as usize - predictor_order as usize, max_bits);
+            // Make some partitions flat or silent so escapes get exercised
+            if seed % 3 == 0 {
+                residuals[..block_size as usize / 2].iter_mut().for_each(|residual| *residual = 0);
+            }
+            let test_rice_encoder_options = RiceEncoderOptions::new(block_size, predictor_order);
+            let mut writer = BitWriter::new();
+
+            test_rice_encoder_options.write_residual(&mut writer, &residuals);
+
+            assert_eq!(writer.len_bits(), test_rice_encoder_options.estimate_bits(&residuals), "seed = {}", seed);
+            let bytes = writer.into_bytes();
+            let mut reader = BitReader::new(&bytes);
+            let decoded = RiceDecoder::read_residual(&mut reader, block_size, predictor_order).unwrap();
+            assert_eq!(decoded, residuals, "seed = {}", seed);
+        }
+    }
+}
\ No newline at end of file
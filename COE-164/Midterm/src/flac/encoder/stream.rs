@@ -0,0 +1,842 @@
+use std::io::{Seek, SeekFrom, Write};
+use std::rc::Rc;
+
+use crate::flac::lpc::predictor::Predictor;
+use crate::flac::lpc::window::{Window, DEFAULT_WINDOW_SPEC};
+use crate::flac::metadata::{MetadataBlock, MetadataError, SeekPoint, SeekTable, StreamInfo};
+use crate::flac::sample::Sample;
+use crate::ogg::flac::OggFlacWriter;
+
+use super::bitwriter::BitWriter;
+use super::EncoderError;
+use super::frame::{BlockingStrategy, Frame, FrameHeader};
+use super::report::{EncodeReport, FrameReport};
+use super::subframe::SubframeEncoderOptions;
+
+/// Smallest block size tried when splitting blocks
+pub const MIN_SPLIT_BLOCK_SIZE: u64 = 16;
+/// Number of times a block is halved when splitting blocks, so blocks are split up to quarters
+pub const MAX_SPLIT_DEPTH: u8 = 2;
+
+/// Represents the spacing of the seek points of a stream
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SeekSpacing {
+    Samples(u64),
+    Seconds(f64),
+}
+
+impl SeekSpacing {
+    /// Parse a spacing such as `"44100"` for samples or `"10s"` for seconds
+    ///
+    /// `None` is returned if the spacing is not positive.
+    pub fn parse(spec: &str) -> Option <SeekSpacing> {
+        match spec.strip_suffix('s') {
+            Some(seconds) => seconds.parse().ok().filter(|&seconds: &f64| seconds > 0.0).map(SeekSpacing::Seconds),
+            None => spec.parse().ok().filter(|&samples| samples > 0).map(SeekSpacing::Samples),
+        }
+    }
+
+    /// Get the spacing in samples, which is at least one
+    pub fn samples(&self, sample_rate: u32) -> u64 {
+        match *self {
+            SeekSpacing::Samples(samples) => samples.max(1),
+            SeekSpacing::Seconds(seconds) => ((seconds * sample_rate as f64) as u64).max(1),
+        }
+    }
+}
+
+/// Represents the options used to encode a stream of `T` samples
+///
+/// `T` must hold samples of the sample size of the stream, such as `i16` for
+/// 16-bit audio.
+pub struct FlacEncoderOptions <T: Sample = i64> {
+    sample_rate: u32,
+    n_channels: u8,
+    bps: u8,
+    block_size: u64,
+    blocking: BlockingStrategy,
+    windows: Vec <Window>,
+    exhaustive: bool,
+    qlp_precision_search: bool,
+    predictors: Option <Vec <Rc <dyn Predictor <T>>>>,
+    metadata: Vec <MetadataBlock>,
+    seek_spacing: Option <SeekSpacing>,
+    padding: Option <u32>,
+    ogg: bool,
+}
+
+impl <T: Sample> FlacEncoderOptions <T> {
+    /// Create a builder to the stream encoder
+    ///
+    /// The block size defaults to 4096 samples with the fixed blocking strategy,
+    /// and the subframes use the defaults of `SubframeEncoderOptions`.
+    pub fn new(sample_rate: u32, n_channels: u8, bps: u8) -> Self {
+        debug_assert!(T::holds(bps));
+        FlacEncoderOptions {
+            sample_rate,
+            n_channels,
+            bps,
+            block_size: 4096,
+            blocking: BlockingStrategy::Fixed,
+            windows: Window::parse_spec(DEFAULT_WINDOW_SPEC).unwrap_or_default(),
+            exhaustive: false,
+            qlp_precision_search: false,
+            predictors: None,
+            metadata: Vec::new(),
+            seek_spacing: None,
+            padding: None,
+            ogg: false,
+        }
+    }
+
+    /// Set the block size
+    ///
+    /// With the variable blocking strategy, this is the largest block size.
+    pub fn block_size(mut self, block_size: u64) -> Self {
+        self.block_size = block_size;
+        self
+    }
+
+    /// Set the blocking strategy
+    pub fn blocking(mut self, blocking: BlockingStrategy) -> Self {
+        self.blocking = blocking;
+        self
+    }
+
+    /// Set the apodization windows tried for the LPC analysis
+    pub fn windows(mut self, windows: Vec <Window>) -> Self {
+        self.windows = windows;
+        self
+    }
+
+    /// Set whether every subframe candidate is actually encoded to pick the smallest one
+    pub fn exhaustive(mut self, exhaustive: bool) -> Self {
+        self.exhaustive = exhaustive;
+        self
+    }
+
+    /// Set whether every QLP coefficient precision is tried for the LPC candidates
+    pub fn qlp_precision_search(mut self, qlp_precision_search: bool) -> Self {
+        self.qlp_precision_search = qlp_precision_search;
+        self
+    }
+
+    /// Set the predictors tried for every channel, replacing the fixed and LPC predictors
+    pub fn predictors(mut self, predictors: Vec <Rc <dyn Predictor <T>>>) -> Self {
+        self.predictors = Some(predictors);
+        self
+    }
+
+    /// Add a metadata block written after the STREAMINFO block
+    ///
+    /// The blocks are written in the order they are added.
+    pub fn metadata_block(mut self, block: MetadataBlock) -> Self {
+        self.metadata.push(block);
+        self
+    }
+
+    /// Set the spacing of the seek points written to a SEEKTABLE block
+    ///
+    /// No SEEKTABLE block is written by default.
+    pub fn seek_spacing(mut self, seek_spacing: SeekSpacing) -> Self {
+        self.seek_spacing = Some(seek_spacing);
+        self
+    }
+
+    /// Set the length in bytes of the PADDING block written after every other metadata block
+    ///
+    /// Padding leaves room to edit the metadata later without rewriting the
+    /// whole file. No PADDING block is written by default.
+    pub fn padding(mut self, length: u32) -> Self {
+        self.padding = Some(length);
+        self
+    }
+
+    /// Set whether the stream is encapsulated in Ogg instead of a native FLAC file
+    ///
+    /// No SEEKTABLE block is written to Ogg streams, since its offsets would
+    /// point into the native stream.
+    pub fn ogg(mut self, ogg: bool) -> Self {
+        self.ogg = ogg;
+        self
+    }
+
+    /// Build the seek table of the encoded frames, given their sizes in bytes
+    ///
+    /// One point is reserved for every `spacing` samples, then each point is
+    /// filled with the frame that holds its sample. Points that fall in the same
+    /// frame are merged, and the points left over stay placeholders.
+    fn seek_table(&self, spacing: SeekSpacing, block_sizes: &[u64], frame_sizes: &[u64]) -> SeekTable {
+        let total_samples: u64 = block_sizes.iter().sum();
+        let spacing = spacing.samples(self.sample_rate);
+        let mut seek_table = SeekTable::placeholders(total_samples.div_ceil(spacing) as usize);
+
+        let mut points = Vec::with_capacity(seek_table.points.len());
+        let (mut sample_number, mut offset) = (0, 0);
+        let mut target = 0;
+        for (&block_size, &frame_size) in block_sizes.iter().zip(frame_sizes) {
+            if target < sample_number + block_size {
+                points.push(SeekPoint { sample_number, offset, n_samples: block_size as u16 });
+                target = (sample_number + block_size).div_ceil(spacing) * spacing;
+            }
+            sample_number += block_size;
+            offset += frame_size;
+        }
+
+        seek_table.points[..points.len()].copy_from_slice(&points);
+        seek_table
+    }
+
+    /// Encode one block starting at sample `start` into a frame
+    ///
+    /// `number` is the frame number or the sample number, depending on the blocking
+    /// strategy.
+    fn encode_frame(&self, channels: &[Vec <T>], start: usize, len: usize, number: u64) -> Frame <T> {
+        let mut subframe_options = SubframeEncoderOptions::new(self.bps)
+            .windows(self.windows.clone())
+            .exhaustive(self.exhaustive)
+            .qlp_precision_search(self.qlp_precision_search);
+        if let Some(predictors) = &self.predictors {
+            subframe_options = subframe_options.predictors(predictors.clone());
+        }
+        let subframes = channels.iter()
+            .filter_map(|channel| subframe_options.encode_channel(&channel[start..start + len]))
+            .collect();
+
+        Frame {
+            header: FrameHeader {
+                blocking: self.blocking,
+                number,
+                block_size: len as u64,
+                sample_rate: self.sample_rate,
+                n_channels: self.n_channels,
+                bps: self.bps,
+            },
+            subframes,
+        }
+    }
+
+    /// Encode a block, splitting it into smaller blocks if they are smaller in total
+    ///
+    /// The block is halved up to `depth` times. Both halves are split on their
+    /// own, then merged back into the whole block if its estimated size is not
+    /// larger than theirs.
+    fn split_block(&self, channels: &[Vec <T>], start: usize, len: usize, depth: u8) -> Vec <Frame <T>> {
+        let whole = self.encode_frame(channels, start, len, start as u64);
+        if depth == 0 || (len as u64) < 2 * MIN_SPLIT_BLOCK_SIZE {
+            return vec![whole];
+        }
+
+        let half = len / 2;
+        let mut split = self.split_block(channels, start, half, depth - 1);
+        split.extend(self.split_block(channels, start + half, len - half, depth - 1));
+
+        let split_bits: u64 = split.iter().map(|frame| frame.estimate_bits()).sum();
+        if split_bits < whole.estimate_bits() {
+            split
+        } else {
+            vec![whole]
+        }
+    }
+
+    /// Merge neighboring frames if the merged frame is smaller than both of them
+    ///
+    /// This catches blocks split on both sides of a block boundary, which
+    /// `split_block` cannot merge back. Merged frames never exceed the block size.
+    fn merge_neighbors(&self, channels: &[Vec <T>], frames: Vec <Frame <T>>) -> Vec <Frame <T>> {
+        let mut merged: Vec <Frame <T>> = Vec::with_capacity(frames.len());
+
+        for frame in frames {
+            if let Some(last) = merged.last() {
+                let len = last.header.block_size + frame.header.block_size;
+                if len <= self.block_size {
+                    let start = last.header.number as usize;
+                    let candidate = self.encode_frame(channels, start, len as usize, start as u64);
+                    if candidate.estimate_bits() < last.estimate_bits() + frame.estimate_bits() {
+                        *merged.last_mut().unwrap() = candidate;
+                        continue;
+                    }
+                }
+            }
+            merged.push(frame);
+        }
+
+        merged
+    }
+
+    /// Encode the channels of a stream into frames
+    ///
+    /// Each element of `channels` holds every sample of one channel. With the
+    /// variable blocking strategy, each block may be split into halves or quarters
+    /// and neighboring frames may be merged based on their estimated size, which
+    /// helps around transients.
+    pub fn frames(&self, channels: &[Vec <T>]) -> Vec <Frame <T>> {
+        let n_samples = channels.first().map_or(0, |channel| channel.len());
+        let block_size = self.block_size as usize;
+        let mut frames = Vec::new();
+
+        for (i, start) in (0..n_samples).step_by(block_size).enumerate() {
+            let len = block_size.min(n_samples - start);
+            match self.blocking {
+                BlockingStrategy::Fixed => frames.push(self.encode_frame(channels, start, len, i as u64)),
+                BlockingStrategy::Variable => frames.extend(self.split_block(channels, start, len, MAX_SPLIT_DEPTH)),
+            }
+        }
+
+        if self.blocking == BlockingStrategy::Variable {
+            frames = self.merge_neighbors(channels, frames);
+        }
+        frames
+    }
+
+    /// Compute the MD5 signature of the samples
+    ///
+    /// The samples are interleaved and written as little-endian signed integers
+    /// of the smallest number of bytes that holds the sample size.
+    pub fn md5(&self, channels: &[Vec <T>]) -> [u8; 16] {
+        let mut context = md5::Context::new();
+        self.update_md5(&mut context, channels, channels.first().map_or(0, |channel| channel.len()));
+        context.compute().0
+    }
+
+    /// Add the first `n_samples` samples of the channels to an MD5 signature
+    fn update_md5(&self, context: &mut md5::Context, channels: &[Vec <T>], n_samples: usize) {
+        let n_bytes = (self.bps as usize).div_ceil(8);
+        let mut buf = Vec::with_capacity(n_bytes * channels.len());
+
+        for i in 0..n_samples {
+            buf.clear();
+            for channel in channels {
+                buf.extend_from_slice(&channel[i].to_i64().to_le_bytes()[..n_bytes]);
+            }
+            context.consume(&buf);
+        }
+    }
+
+    /// Build the STREAMINFO block of the encoded frames, given their sizes in bytes
+    fn stream_info(&self, block_sizes: &[u64], frame_sizes: &[u64], md5: [u8; 16]) -> StreamInfo {
+        // The last block may be shorter than the others, so it does not count toward the minimum
+        let sized_blocks = if block_sizes.len() > 1 { &block_sizes[..block_sizes.len() - 1] } else { block_sizes };
+        StreamInfo {
+            min_block_size: sized_blocks.iter().copied().min().unwrap_or(self.block_size) as u16,
+            max_block_size: block_sizes.iter().copied().max().unwrap_or(self.block_size) as u16,
+            min_frame_size: frame_sizes.iter().copied().min().unwrap_or(0) as u32,
+            max_frame_size: frame_sizes.iter().copied().max().unwrap_or(0) as u32,
+            sample_rate: self.sample_rate,
+            n_channels: self.n_channels,
+            bps: self.bps,
+            total_samples: block_sizes.iter().sum(),
+            md5,
+        }
+    }
+
+    /// Get the metadata blocks written after the STREAMINFO block
+    fn metadata(&self, seek_table: Option <SeekTable>) -> Vec <MetadataBlock> {
+        let mut metadata = Vec::with_capacity(self.metadata.len() + 2);
+        metadata.extend(seek_table.map(MetadataBlock::SeekTable));
+        metadata.extend(self.metadata.iter().cloned());
+        if let Some(length) = self.padding {
+            metadata.push(MetadataBlock::Padding(length));
+        }
+        metadata
+    }
+
+    /// Encode the channels of a stream into a FLAC file
+    ///
+    /// The file holds the `fLaC` marker, the STREAMINFO block, the SEEKTABLE
+    /// block if a seek spacing is set, the other metadata blocks, the PADDING
+    /// block if a padding is set and the frames. With `ogg`, the same blocks and
+    /// frames are written as Ogg FLAC packets instead.
+    ///
+    /// # Errors
+    /// `BlockTooLargeError` is returned if a metadata block does not fit in a block.
+    pub fn encode(&self, channels: &[Vec <T>]) -> Result <Vec <u8>, MetadataError> {
+        let frames = self.frames(channels);
+        let block_sizes: Vec <u64> = frames.iter().map(|frame| frame.header.block_size).collect();
+        let frames: Vec <Vec <u8>> = frames.iter().map(|frame| frame.to_bytes()).collect();
+        self.write_stream(channels, &block_sizes, &frames)
+    }
+
+    /// Encode the channels of a stream like `encode`, along with how each frame was encoded
+    ///
+    /// # Errors
+    /// `BlockTooLargeError` is returned if a metadata block does not fit in a block.
+    pub fn encode_with_report(&self, channels: &[Vec <T>]) -> Result <(Vec <u8>, EncodeReport), MetadataError> {
+        let frames = self.frames(channels);
+        let block_sizes: Vec <u64> = frames.iter().map(|frame| frame.header.block_size).collect();
+        let frame_bytes: Vec <Vec <u8>> = frames.iter().map(|frame| frame.to_bytes()).collect();
+        let bytes = self.write_stream(channels, &block_sizes, &frame_bytes)?;
+
+        let frame_sizes: Vec <u64> = frame_bytes.iter().map(|frame| frame.len() as u64).collect();
+        let report = EncodeReport::new(&frames, &frame_sizes, bytes.len() as u64);
+        Ok((bytes, report))
+    }
+
+    /// Write the header followed by the encoded frames
+    fn write_stream(&self, channels: &[Vec <T>], block_sizes: &[u64], frames: &[Vec <u8>]) -> Result <Vec <u8>, MetadataError> {
+        let frame_sizes: Vec <u64> = frames.iter().map(|frame| frame.len() as u64).collect();
+        let stream_info = self.stream_info(block_sizes, &frame_sizes, self.md5(channels));
+
+        let seek_table = self.seek_spacing
+            .filter(|_| !self.ogg)
+            .map(|spacing| self.seek_table(spacing, block_sizes, &frame_sizes));
+        let metadata = self.metadata(seek_table);
+
+        if self.ogg {
+            let serial = u32::from_le_bytes([stream_info.md5[0], stream_info.md5[1], stream_info.md5[2], stream_info.md5[3]]);
+            let mut writer = OggFlacWriter::new(serial, &stream_info, &metadata)?;
+            for (frame, &block_size) in frames.iter().zip(block_sizes) {
+                writer.write_frame(frame, block_size);
+            }
+            return Ok(writer.finish());
+        }
+
+        let mut writer = BitWriter::new();
+        writer.write_bytes(b"fLaC");
+        stream_info.write(&mut writer, metadata.is_empty());
+        for (i, block) in metadata.iter().enumerate() {
+            block.write(&mut writer, i + 1 == metadata.len())?;
+        }
+        for frame in frames {
+            writer.write_bytes(frame);
+        }
+        Ok(writer.into_bytes())
+    }
+}
+
+/// Represents an encoder that writes each block to a sink as soon as it is full
+///
+/// The header is written before the samples are known, so the STREAMINFO
+/// block holds `total_samples` if it is given and zeros for the frame sizes
+/// and the MD5 signature, which stand for unknown values. With a seek spacing
+/// and `total_samples`, the SEEKTABLE block is written as placeholders.
+///
+/// `finish_seekable` seeks back to fill in these blocks once every frame is
+/// written, while `finish` leaves them as they are for sinks such as pipes.
+/// With the variable blocking strategy, frames are only merged within a block.
+/// Ogg streams are not supported, since their pages are built by `encode`.
+pub struct FlacStreamEncoder <W: Write, T: Sample = i64> {
+    options: FlacEncoderOptions <T>,
+    writer: W,
+    is_stream_info_last: bool,
+    total_samples: Option <u64>,
+    n_seek_points: Option <usize>,
+    header_len: u64,
+    md5: md5::Context,
+    buffer: Vec <Vec <T>>,
+    block_sizes: Vec <u64>,
+    frame_sizes: Vec <u64>,
+    frame_reports: Vec <FrameReport>,
+}
+
+impl <W: Write, T: Sample> FlacStreamEncoder <W, T> {
+    /// Write the header of the stream to `writer`
+    ///
+    /// # Errors
+    /// `BlockTooLargeError` is returned if a metadata block does not fit in a
+    /// block, and `WriteError` if the header cannot be written.
+    pub fn new(options: FlacEncoderOptions <T>, mut writer: W, total_samples: Option <u64>) -> Result <Self, EncoderError> {
+        let min_block_size = match options.blocking {
+            BlockingStrategy::Fixed => options.block_size,
+            BlockingStrategy::Variable => MIN_SPLIT_BLOCK_SIZE,
+        };
+        let stream_info = StreamInfo {
+            min_block_size: min_block_size as u16,
+            max_block_size: options.block_size as u16,
+            min_frame_size: 0,
+            max_frame_size: 0,
+            sample_rate: options.sample_rate,
+            n_channels: options.n_channels,
+            bps: options.bps,
+            total_samples: total_samples.unwrap_or(0),
+            md5: [0; 16],
+        };
+
+        let seek_table = options.seek_spacing.zip(total_samples)
+            .map(|(spacing, total_samples)| SeekTable::placeholders(total_samples.div_ceil(spacing.samples(options.sample_rate)) as usize));
+        let n_seek_points = seek_table.as_ref().map(|seek_table| seek_table.points.len());
+        let metadata = options.metadata(seek_table);
+
+        let mut header = BitWriter::new();
+        header.write_bytes(b"fLaC");
+        stream_info.write(&mut header, metadata.is_empty());
+        for (i, block) in metadata.iter().enumerate() {
+            block.write(&mut header, i + 1 == metadata.len())?;
+        }
+        let header = header.into_bytes();
+        writer.write_all(&header)?;
+
+        Ok(FlacStreamEncoder {
+            buffer: vec![Vec::new(); options.n_channels as usize],
+            options,
+            writer,
+            is_stream_info_last: metadata.is_empty(),
+            total_samples,
+            n_seek_points,
+            header_len: header.len() as u64,
+            md5: md5::Context::new(),
+            block_sizes: Vec::new(),
+            frame_sizes: Vec::new(),
+            frame_reports: Vec::new(),
+        })
+    }
+
+    /// Add samples of each channel, writing the blocks that are full
+    ///
+    /// # Errors
+    /// `WriteError` is returned if a frame cannot be written.
+    pub fn write_samples(&mut self, channels: &[Vec <T>]) -> Result <(), EncoderError> {
+        for (buffer, channel) in self.buffer.iter_mut().zip(channels) {
+            buffer.extend_from_slice(channel);
+        }
+
+        let block_size = self.options.block_size as usize;
+        while self.buffer.first().map_or(0, |buffer| buffer.len()) >= block_size {
+            self.write_block(block_size)?;
+        }
+        Ok(())
+    }
+
+    /// Encode the first `len` buffered samples into frames and write them
+    fn write_block(&mut self, len: usize) -> Result <(), EncoderError> {
+        let mut first_sample: u64 = self.block_sizes.iter().sum();
+        let block_start = first_sample;
+        let frames = match self.options.blocking {
+            BlockingStrategy::Fixed => vec![self.options.encode_frame(&self.buffer, 0, len, self.block_sizes.len() as u64)],
+            BlockingStrategy::Variable => {
+                let frames = self.options.split_block(&self.buffer, 0, len, MAX_SPLIT_DEPTH);
+                self.options.merge_neighbors(&self.buffer, frames)
+            }
+        };
+
+        for mut frame in frames {
+            // Variable-size frames are numbered by their first sample in the buffer
+            if frame.header.blocking == BlockingStrategy::Variable {
+                frame.header.number += block_start;
+            }
+            let bytes = frame.to_bytes();
+            self.writer.write_all(&bytes)?;
+            self.block_sizes.push(frame.header.block_size);
+            self.frame_sizes.push(bytes.len() as u64);
+            self.frame_reports.push(FrameReport::new(&frame, first_sample, bytes.len() as u64));
+            first_sample += frame.header.block_size;
+        }
+
+        self.options.update_md5(&mut self.md5, &self.buffer, len);
+        for buffer in &mut self.buffer {
+            buffer.drain(..len);
+        }
+        Ok(())
+    }
+
+    /// Write the last block, which may be short, and build the final STREAMINFO block
+    fn write_last_block(&mut self) -> Result <StreamInfo, EncoderError> {
+        let len = self.buffer.first().map_or(0, |buffer| buffer.len());
+        if len > 0 {
+            self.write_block(len)?;
+        }
+        self.writer.flush()?;
+
+        let md5 = std::mem::replace(&mut self.md5, md5::Context::new()).compute().0;
+        Ok(self.options.stream_info(&self.block_sizes, &self.frame_sizes, md5))
+    }
+
+    /// Describe how each written frame was encoded
+    fn report(&mut self) -> EncodeReport {
+        let n_bytes = self.header_len + self.frame_sizes.iter().sum::<u64>();
+        EncodeReport::from_frames(self.options.n_channels, self.options.bps, std::mem::take(&mut self.frame_reports), n_bytes)
+    }
+
+    /// Write the last block and get the sink with the final STREAMINFO block and
+    /// how each frame was encoded
+    ///
+    /// The STREAMINFO block in the stream keeps the values written by `new`.
+    ///
+    /// # Errors
+    /// `WriteError` is returned if a frame cannot be written, and
+    /// `SampleCountError` if `total_samples` was given to `new` but a different
+    /// number of samples was written, since the stream then contradicts its
+    /// STREAMINFO block.
+    pub fn finish(mut self) -> Result <(W, StreamInfo, EncodeReport), EncoderError> {
+        let stream_info = self.write_last_block()?;
+        if let Some(total_samples) = self.total_samples.filter(|&total_samples| total_samples != stream_info.total_samples) {
+            return Err(EncoderError::SampleCountError(total_samples, stream_info.total_samples));
+        }
+        let report = self.report();
+        Ok((self.writer, stream_info, report))
+    }
+}
+
+impl <W: Write + Seek, T: Sample> FlacStreamEncoder <W, T> {
+    /// Write the last block, then fill in the STREAMINFO and SEEKTABLE blocks
+    ///
+    /// Like `finish`, the final STREAMINFO block and how each frame was encoded
+    /// are returned along with the sink. The stream may start anywhere in the sink, which is left at its end.
+    ///
+    /// # Errors
+    /// `WriteError` is returned if a frame cannot be written or the sink
+    /// cannot seek back to the header.
+    pub fn finish_seekable(mut self) -> Result <(W, StreamInfo, EncodeReport), EncoderError> {
+        let stream_info = self.write_last_block()?;
+        let end = self.writer.stream_position()?;
+        let start = end - self.header_len - self.frame_sizes.iter().sum::<u64>();
+
+        let mut header = BitWriter::new();
+        stream_info.write(&mut header, self.is_stream_info_last);
+        // The SEEKTABLE block comes right after STREAMINFO
+        if let Some((spacing, n_points)) = self.options.seek_spacing.zip(self.n_seek_points) {
+            let mut seek_table = self.options.seek_table(spacing, &self.block_sizes, &self.frame_sizes);
+            seek_table.points.resize(n_points, SeekPoint::placeholder());
+            MetadataBlock::SeekTable(seek_table).write(&mut header, self.options.metadata.is_empty() && self.options.padding.is_none())?;
+        }
+
+        self.writer.seek(SeekFrom::Start(start + 4))?;
+        self.writer.write_all(&header.into_bytes())?;
+        self.writer.seek(SeekFrom::Start(end))?;
+        self.writer.flush()?;
+        let report = self.report();
+        Ok((self.writer, stream_info, report))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io;
+    use crate::flac::decoder::stream::FlacDecoder;
+    use crate::flac::encoder::subframe::Subframe;
+    use crate::flac::lpc::fixed::FixedPredictor;
+    use crate::flac::metadata::VorbisComment;
+    use crate::flac::test_util::XorShift;
+
+    /// Generate a quiet tone interrupted by short bursts of noise
+    fn transients(len: usize) -> Vec <i64> {
+        (0..len).zip(XorShift::new(0x2545_F491_4F6C_DD1D)).map(|(i, state)| {
+            let tone = ((i as f64 * 0.02).sin() * 200.0) as i64;
+            if i % 4096 >= 3000 && i % 4096 < 3300 {
+                tone + (state >> 50) as i64 - (1 << 13)
+            } else {
+                tone
+            }
+        }).collect()
+    }
+
+    #[test]
+    fn test_fixed_frames() {
+        let channels = vec![transients(10000), transients(10000)];
+        let frames = FlacEncoderOptions::new(44100, 2, 16).frames(&channels);
+
+        let block_sizes: Vec <u64> = frames.iter().map(|frame| frame.header.block_size).collect();
+        assert_eq!(block_sizes, vec![4096, 4096, 1808]);
+        assert_eq!(frames[2].header.number, 2);
+        assert!(frames.iter().all(|frame| frame.subframes.len() == 2));
+    }
+
+    #[test]
+    fn test_fixed_predictor_only() {
+        let channels = vec![transients(10000)];
+        let frames = FlacEncoderOptions::new(44100, 1, 16).predictors(vec![Rc::new(FixedPredictor)]).frames(&channels);
+
+        assert!(frames.iter().flat_map(|frame| &frame.subframes).all(|(subframe, _)| !matches!(subframe, Subframe::Lpc { .. })));
+        let bytes = FlacEncoderOptions::new(44100, 1, 16).predictors(vec![Rc::new(FixedPredictor)]).encode(&channels).unwrap();
+        assert_eq!(FlacDecoder::new(&bytes).unwrap().read_samples(u64::MAX).unwrap(), channels);
+    }
+
+    #[test]
+    fn test_sample_types_encode_alike() {
+        let channels = vec![transients(10000), transients(7000).into_iter().chain(vec![-32768; 3000]).collect()];
+        let narrow: Vec <Vec <i16>> = channels.iter().map(|channel| channel.iter().map(|&sample| sample as i16).collect()).collect();
+        let wide: Vec <Vec <i32>> = channels.iter().map(|channel| channel.iter().map(|&sample| sample as i32).collect()).collect();
+
+        for blocking in [BlockingStrategy::Fixed, BlockingStrategy::Variable] {
+            let bytes = FlacEncoderOptions::new(44100, 2, 16).blocking(blocking).encode(&channels).unwrap();
+            assert_eq!(FlacEncoderOptions::new(44100, 2, 16).blocking(blocking).encode(&narrow).unwrap(), bytes);
+            assert_eq!(FlacEncoderOptions::new(44100, 2, 16).blocking(blocking).encode(&wide).unwrap(), bytes);
+        }
+    }
+
+    #[test]
+    fn test_full_scale_32_bit_round_trip() {
+        let noise: Vec <i64> = XorShift::new(7).take(6000).map(|state| (state >> 32) as u32 as i32 as i64).collect();
+        let sine: Vec <i64> = (0..6000).map(|i| ((i as f64 * 0.01).sin() * i32::MAX as f64) as i64).collect();
+        let square: Vec <i64> = (0..6000).map(|i| if i / 50 % 2 == 0 { i32::MAX as i64 } else { i32::MIN as i64 }).collect();
+        let channels = vec![noise, sine, square];
+
+        for options in [FlacEncoderOptions::new(44100, 3, 32), FlacEncoderOptions::new(44100, 3, 32).exhaustive(true)] {
+            let frames = options.frames(&channels);
+            assert!(frames.iter().flat_map(|frame| &frame.subframes).all(|(subframe, _)| subframe.residuals_fit()));
+            let bytes = options.encode(&channels).unwrap();
+            assert_eq!(FlacDecoder::new(&bytes).unwrap().read_samples(u64::MAX).unwrap(), channels);
+        }
+    }
+
+    #[test]
+    fn test_variable_frames_cover_stream() {
+        let channels = vec![transients(10000)];
+        let frames = FlacEncoderOptions::new(44100, 1, 16).blocking(BlockingStrategy::Variable).frames(&channels);
+
+        let mut next_sample = 0;
+        for frame in &frames {
+            assert_eq!(frame.header.blocking, BlockingStrategy::Variable);
+            assert_eq!(frame.header.number, next_sample);
+            next_sample += frame.header.block_size;
+        }
+        assert_eq!(next_sample, 10000);
+        assert!(frames.iter().any(|frame| frame.header.block_size < 4096));
+    }
+
+    #[test]
+    fn test_variable_blocking_smaller_for_transients() {
+        let channels = vec![transients(16384)];
+        let fixed = FlacEncoderOptions::new(44100, 1, 16).encode(&channels).unwrap();
+        let variable = FlacEncoderOptions::new(44100, 1, 16).blocking(BlockingStrategy::Variable).encode(&channels).unwrap();
+
+        assert!(variable.len() < fixed.len(), "{} >= {}", variable.len(), fixed.len());
+    }
+
+    #[test]
+    fn test_encode_header() {
+        let channels = vec![vec![0; 100], vec![1; 100]];
+        let bytes = FlacEncoderOptions::new(48000, 2, 16).encode(&channels).unwrap();
+
+        assert_eq!(&bytes[..4], b"fLaC");
+        // Last metadata block, STREAMINFO, 34 bytes
+        assert_eq!(&bytes[4..8], &[0x80, 0x00, 0x00, 0x22]);
+        // A single block of 100 samples
+        assert_eq!(&bytes[8..12], &[0x00, 0x64, 0x00, 0x64]);
+        // Frame sync code
+        assert_eq!(&bytes[42..44], &[0xFF, 0xF8]);
+    }
+
+    #[test]
+    fn test_encode_metadata_blocks() {
+        let mut vorbis_comment = VorbisComment::new("ab");
+        vorbis_comment.add("TITLE", "x").unwrap();
+        let bytes = FlacEncoderOptions::new(48000, 1, 16)
+            .metadata_block(MetadataBlock::VorbisComment(vorbis_comment))
+            .encode(&[vec![0; 100]])
+            .unwrap();
+
+        // STREAMINFO is no longer the last block
+        assert_eq!(&bytes[4..8], &[0x00, 0x00, 0x00, 0x22]);
+        assert_eq!(&bytes[42..46], &[0x84, 0x00, 0x00, 0x15]);
+        assert_eq!(&bytes[67..69], &[0xFF, 0xF8]);
+    }
+
+    #[test]
+    fn test_seek_spacing() {
+        assert_eq!(SeekSpacing::parse("10s"), Some(SeekSpacing::Seconds(10.0)));
+        assert_eq!(SeekSpacing::parse("4096"), Some(SeekSpacing::Samples(4096)));
+        assert_eq!(SeekSpacing::parse("0"), None);
+        assert_eq!(SeekSpacing::parse("-1s"), None);
+        assert_eq!(SeekSpacing::Seconds(0.5).samples(44100), 22050);
+    }
+
+    #[test]
+    fn test_encode_seek_table() {
+        let channels = vec![transients(10000)];
+        let bytes = FlacEncoderOptions::new(44100, 1, 16)
+            .seek_spacing(SeekSpacing::Samples(3000))
+            .encode(&channels)
+            .unwrap();
+
+        // 4 points reserved, for samples 0, 3000, 6000 and 9000
+        assert_eq!(&bytes[42..46], &[0x83, 0x00, 0x00, 4 * 18]);
+        let seek_table = SeekTable::parse(&bytes[46..46 + 4 * 18]).unwrap();
+        let frames_start = 46 + 4 * 18;
+
+        // Samples 0 and 3000 are both in the first frame
+        let sample_numbers: Vec <u64> = seek_table.points.iter().map(|point| point.sample_number).collect();
+        assert_eq!(sample_numbers, vec![0, 4096, 8192, u64::MAX]);
+        for point in &seek_table.points[..3] {
+            let offset = frames_start + point.offset as usize;
+            assert_eq!(&bytes[offset..offset + 2], &[0xFF, 0xF8]);
+        }
+        assert_eq!(seek_table.points[2].n_samples, 1808);
+    }
+
+    #[test]
+    fn test_encode_padding() {
+        let bytes = FlacEncoderOptions::new(48000, 1, 16)
+            .padding(10)
+            .encode(&[vec![0; 100]])
+            .unwrap();
+
+        assert_eq!(&bytes[42..46], &[0x81, 0x00, 0x00, 0x0A]);
+        assert_eq!(&bytes[46..56], &[0; 10]);
+        assert_eq!(&bytes[56..58], &[0xFF, 0xF8]);
+    }
+
+    #[test]
+    fn test_md5() {
+        let options = FlacEncoderOptions::new(44100, 2, 16);
+        // Interleaved 16-bit little-endian samples
+        let expected = md5::compute([0x01, 0x00, 0xFF, 0xFF, 0x02, 0x00, 0x00, 0x80]).0;
+        assert_eq!(options.md5(&[vec![1, 2], vec![-1, -32768]]), expected);
+    }
+
+    #[test]
+    fn test_stream_encoder_seekable() {
+        let channels = vec![transients(10000), transients(10000)];
+        let options = || FlacEncoderOptions::new(44100, 2, 16).seek_spacing(SeekSpacing::Samples(3000)).padding(10);
+        let (expected, expected_report) = options().encode_with_report(&channels).unwrap();
+
+        let mut encoder = FlacStreamEncoder::new(options(), io::Cursor::new(Vec::new()), Some(10000)).unwrap();
+        // Feed samples in pieces that do not line up with the blocks
+        for start in (0..10000).step_by(3000) {
+            let end = (start + 3000).min(10000);
+            encoder.write_samples(&[channels[0][start..end].to_vec(), channels[1][start..end].to_vec()]).unwrap();
+        }
+        let (cursor, stream_info, report) = encoder.finish_seekable().unwrap();
+
+        assert_eq!(cursor.position(), expected.len() as u64);
+        assert_eq!(cursor.into_inner(), expected);
+        assert_eq!(stream_info.md5, options().md5(&channels));
+        assert_eq!(report, expected_report);
+    }
+
+    #[test]
+    fn test_stream_encoder_unknown_totals() {
+        let channels = vec![transients(10000)];
+        let options = || FlacEncoderOptions::new(44100, 1, 16).blocking(BlockingStrategy::Variable).seek_spacing(SeekSpacing::Samples(3000));
+
+        let mut encoder = FlacStreamEncoder::new(options(), Vec::new(), None).unwrap();
+        encoder.write_samples(&channels).unwrap();
+        let (bytes, stream_info, report) = encoder.finish().unwrap();
+
+        // The header is not patched, and no SEEKTABLE block is reserved
+        let written = StreamInfo::parse(&bytes[8..42]).unwrap();
+        assert_eq!(bytes[4], 0x80);
+        assert_eq!((written.min_block_size, written.max_block_size), (16, 4096));
+        assert_eq!((written.min_frame_size, written.total_samples, written.md5), (0, 0, [0; 16]));
+        assert_eq!(stream_info.total_samples, 10000);
+        assert_eq!(stream_info.md5, options().md5(&channels));
+
+        // Variable-size frames are reported from their first sample across blocks
+        assert_eq!((report.total_samples, report.n_bytes), (10000, bytes.len() as u64));
+        assert_eq!(report.frames.iter().map(|frame| frame.n_bytes).sum::<u64>(), bytes.len() as u64 - 42);
+        let mut first_sample = 0;
+        for frame in &report.frames {
+            assert_eq!(frame.first_sample, first_sample);
+            first_sample += frame.block_size;
+        }
+
+        let mut decoder = FlacDecoder::new(&bytes).unwrap();
+        assert_eq!(decoder.read_samples(u64::MAX).unwrap(), channels);
+    }
+
+    #[test]
+    fn test_stream_encoder_short_stream() {
+        let channels = vec![transients(10000)];
+        let options = || FlacEncoderOptions::new(44100, 1, 16);
+
+        let mut encoder = FlacStreamEncoder::new(options(), Vec::new(), Some(20000)).unwrap();
+        encoder.write_samples(&channels).unwrap();
+        assert_eq!(encoder.finish().err(), Some(EncoderError::SampleCountError(20000, 10000)));
+
+        let mut encoder = FlacStreamEncoder::new(options(), Vec::new(), Some(10000)).unwrap();
+        encoder.write_samples(&channels).unwrap();
+        assert_eq!(encoder.finish().unwrap().1.total_samples, 10000);
+    }
+}
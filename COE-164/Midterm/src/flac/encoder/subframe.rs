@@ -0,0 +1,514 @@
+use std::rc::Rc;
+
+use crate::flac::lpc::fixed::FixedPredictor;
+use crate::flac::lpc::predictor::{self, Candidate, Predictor};
+use crate::flac::lpc::var::VarPredictor;
+use crate::flac::lpc::window::{Window, DEFAULT_WINDOW_SPEC};
+use crate::flac::sample::Sample;
+
+use super::bitwriter::BitWriter;
+use super::rice::RiceEncoderOptions;
+
+/// Represents a candidate encoding of one channel of a block of `T` samples
+///
+/// The `Fixed` and `Lpc` variants keep their `order` warm-up samples apart from
+/// the residuals of the rest of the block, which fit in an `i32` unlike the
+/// samples of a side channel. The decoder reads subframes as `Subframe <i64>`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Subframe <T: Sample = i64> {
+    Constant { value: T },
+    Verbatim { samples: Vec <T> },
+    Fixed { warmup: Vec <T>, residuals: Vec <i32> },
+    Lpc { qlp_coeffs: Vec <i64>, precision: u8, shift: u8, warmup: Vec <T>, residuals: Vec <i32> },
+}
+
+/// Represents the options used to pick the subframe of a channel of `T` samples
+#[derive(Clone)]
+pub struct SubframeEncoderOptions <T: Sample = i64> {
+    bps: u8,
+    windows: Vec <Window>,
+    exhaustive: bool,
+    qlp_precision_search: bool,
+    predictors: Option <Vec <Rc <dyn Predictor <T>>>>,
+}
+
+impl <T: Sample> Subframe <T> {
+    /// Build a fixed subframe of a predictor order from 0 to 4
+    ///
+    /// `None` is returned for the same orders and blocks as
+    /// `FixedPredictor::get_residuals`, including when a residual does not fit
+    /// in a subframe.
+    pub fn fixed(samples: &[T], order: u8) -> Option <Subframe <T>> {
+        let residuals = FixedPredictor::get_residuals(samples, order)?;
+        Some(Subframe::Fixed { warmup: samples[..order as usize].to_vec(), residuals })
+    }
+
+    /// Build an LPC subframe from quantized coefficients
+    ///
+    /// The written coefficient precision is the smallest number of bits that
+    /// holds every coefficient. `None` is returned if the coefficients or shift
+    /// do not fit in the fields of the subframe header, or if a residual does
+    /// not fit in a subframe.
+    pub fn lpc(samples: &[T], qlp_coeffs: Vec <i64>, shift: u8) -> Option <Subframe <T>> {
+        let order = qlp_coeffs.len();
+        if order == 0 || order > 32 || order > samples.len() || shift > 15 {
+            return None;
+        }
+
+        let precision = qlp_coeffs.iter().map(|&coeff| BitWriter::signed_bits(coeff)).max().unwrap_or(1);
+        if precision > 15 {
+            return None;
+        }
+
+        let residuals = VarPredictor::get_residuals(samples, &qlp_coeffs, order as u8, shift)?;
+        Some(Subframe::Lpc { qlp_coeffs, precision, shift, warmup: samples[..order].to_vec(), residuals })
+    }
+
+    /// Check whether every residual fits in a subframe
+    ///
+    /// Residuals must not exceed `MAX_RESIDUAL` in magnitude, which only a
+    /// subframe built by hand can break. `Constant` and `Verbatim` subframes
+    /// always fit.
+    pub fn residuals_fit(&self) -> bool {
+        match self {
+            Subframe::Constant { .. } | Subframe::Verbatim { .. } => true,
+            Subframe::Fixed { residuals, .. } | Subframe::Lpc { residuals, .. } => predictor::residuals_fit(residuals),
+        }
+    }
+
+    /// Get the predictor order of the subframe
+    ///
+    /// `Constant` and `Verbatim` subframes have no predictor and return zero.
+    pub fn order(&self) -> u8 {
+        match self {
+            Subframe::Constant { .. } | Subframe::Verbatim { .. } => 0,
+            Subframe::Fixed { warmup, .. } => warmup.len() as u8,
+            Subframe::Lpc { qlp_coeffs, .. } => qlp_coeffs.len() as u8,
+        }
+    }
+
+    /// Get the Rice partition order of the residuals, which only `Fixed` and `Lpc` subframes have
+    pub fn rice_partition_order(&self) -> Option <u8> {
+        match self {
+            Subframe::Constant { .. } | Subframe::Verbatim { .. } => None,
+            Subframe::Fixed { residuals, .. } | Subframe::Lpc { residuals, .. } => {
+                Some(Self::rice_options(residuals, self.order()).best_partition_and_params(residuals).order)
+            }
+        }
+    }
+
+    fn rice_options(residuals: &[i32], order: u8) -> RiceEncoderOptions {
+        RiceEncoderOptions::new(residuals.len() as u64 + order as u64, order)
+    }
+
+    /// Estimate the size of the encoded subframe in bits
+    ///
+    /// The estimate adds up the 8-bit subframe header, the warm-up samples, the
+    /// quantized coefficients with their precision and shift fields, and the
+    /// partitioned Rice encoding of the residuals.
+    pub fn estimate_bits(&self, bps: u8) -> u64 {
+        let bps = bps as u64;
+        let body = match self {
+            Subframe::Constant { .. } => bps,
+            Subframe::Verbatim { samples } => bps * samples.len() as u64,
+            Subframe::Fixed { warmup, residuals } => {
+                let order = warmup.len() as u8;
+                order as u64 * bps + Self::rice_options(residuals, order).estimate_bits(residuals)
+            }
+            Subframe::Lpc { qlp_coeffs, precision, residuals, .. } => {
+                let order = qlp_coeffs.len() as u8;
+                order as u64 * (bps + *precision as u64) + 4 + 5
+                    + Self::rice_options(residuals, order).estimate_bits(residuals)
+            }
+        };
+
+        8 + body
+    }
+
+    /// Write the subframe with its header
+    ///
+    /// `bps` is the sample size of the channel being written.
+    pub fn write(&self, writer: &mut BitWriter, bps: u8) {
+        self.write_with_wasted_bits(writer, bps, 0);
+    }
+
+    /// Write the subframe with its header, signalling `wasted_bits` zero bits
+    /// shifted out of every sample
+    ///
+    /// `bps` is the sample size of the channel being written, so the samples of
+    /// the subframe are written in `bps - wasted_bits` bits.
+    pub fn write_with_wasted_bits(&self, writer: &mut BitWriter, bps: u8, wasted_bits: u8) {
+        let bps = bps - wasted_bits;
+        let header = match self {
+            Subframe::Constant { .. } => 0b000000,
+            Subframe::Verbatim { .. } => 0b000001,
+            Subframe::Fixed { warmup, .. } => 0b001000 | warmup.len() as u64,
+            Subframe::Lpc { qlp_coeffs, .. } => 0b100000 | (qlp_coeffs.len() - 1) as u64,
+        };
+
+        // Zero padding bit, subframe type and wasted bits flag
+        writer.write_bits(0, 1);
+        writer.write_bits(header, 6);
+        if wasted_bits == 0 {
+            writer.write_bits(0, 1);
+        } else {
+            writer.write_bits(1, 1);
+            writer.write_unary(wasted_bits as u64 - 1);
+        }
+
+        match self {
+            Subframe::Constant { value } => {
+                writer.write_signed(value.to_i64(), bps);
+            }
+            Subframe::Verbatim { samples } => {
+                for &sample in samples {
+                    writer.write_signed(sample.to_i64(), bps);
+                }
+            }
+            Subframe::Fixed { warmup, residuals } => {
+                for &sample in warmup {
+                    writer.write_signed(sample.to_i64(), bps);
+                }
+                Self::rice_options(residuals, warmup.len() as u8).write_residual(writer, residuals);
+            }
+            Subframe::Lpc { qlp_coeffs, precision, shift, warmup, residuals } => {
+                let order = qlp_coeffs.len() as u8;
+                for &sample in warmup {
+                    writer.write_signed(sample.to_i64(), bps);
+                }
+                writer.write_bits((*precision - 1) as u64, 4);
+                writer.write_signed(*shift as i64, 5);
+                for &coeff in qlp_coeffs {
+                    writer.write_signed(coeff, *precision);
+                }
+                Self::rice_options(residuals, order).write_residual(writer, residuals);
+            }
+        }
+    }
+
+    /// Get the exact size of the encoded subframe in bits by writing it
+    pub fn encoded_bits(&self, bps: u8) -> u64 {
+        let mut writer = BitWriter::new();
+        self.write(&mut writer, bps);
+        writer.len_bits()
+    }
+}
+
+impl <T: Sample> SubframeEncoderOptions <T> {
+    /// Create a builder to the subframe encoder
+    ///
+    /// The windows default to `DEFAULT_WINDOW_SPEC` and the exhaustive
+    /// search is disabled. The predictors default to `FixedPredictor` and
+    /// `VarPredictor`, configured by the options.
+    pub fn new(bps: u8) -> Self {
+        SubframeEncoderOptions {
+            bps,
+            windows: Window::parse_spec(DEFAULT_WINDOW_SPEC).unwrap_or_default(),
+            exhaustive: false,
+            qlp_precision_search: false,
+            predictors: None,
+        }
+    }
+
+    /// Set the apodization windows tried for the LPC analysis
+    pub fn windows(mut self, windows: Vec <Window>) -> Self {
+        self.windows = windows;
+        self
+    }
+
+    /// Set whether every candidate is actually encoded to pick the smallest one
+    ///
+    /// By default, the candidates are compared using their estimated size and
+    /// only the best fixed and LPC predictors are considered.
+    pub fn exhaustive(mut self, exhaustive: bool) -> Self {
+        self.exhaustive = exhaustive;
+        self
+    }
+
+    /// Set whether every QLP coefficient precision is tried for the LPC candidates
+    ///
+    /// By default, a single precision is derived from the sample size and block size.
+    /// The search tries every precision from `MIN_QLP_PRECISION` until
+    /// `MAX_QLP_PRECISION` bits instead, similar to `flac -p`.
+    pub fn qlp_precision_search(mut self, qlp_precision_search: bool) -> Self {
+        self.qlp_precision_search = qlp_precision_search;
+        self
+    }
+
+    /// Set the predictors tried for every channel, in order
+    ///
+    /// This replaces the default predictors, so the windows and precision search
+    /// only apply to a `VarPredictor` in `predictors`.
+    pub fn predictors(mut self, predictors: Vec <Rc <dyn Predictor <T>>>) -> Self {
+        self.predictors = Some(predictors);
+        self
+    }
+
+    /// Get the predictors tried for every channel
+    fn active_predictors(&self) -> Vec <Rc <dyn Predictor <T>>> {
+        match &self.predictors {
+            Some(predictors) => predictors.clone(),
+            None => vec![
+                Rc::new(FixedPredictor),
+                Rc::new(VarPredictor::new().windows(self.windows.clone()).qlp_precision_search(self.qlp_precision_search)),
+            ],
+        }
+    }
+
+    /// Get the number of zero bits shared by the end of every sample
+    ///
+    /// Silent blocks have no wasted bits since they are already written as a
+    /// `Constant` subframe. At least one bit is always left for the samples.
+    pub fn wasted_bits(&self, samples: &[T]) -> u8 {
+        let all_bits = samples.iter().fold(0, |acc, &sample| acc | sample.to_i64());
+        if all_bits == 0 {
+            return 0;
+        }
+        (all_bits.trailing_zeros() as u8).min(self.bps.saturating_sub(1))
+    }
+
+    /// Get the subframe candidates for the given samples
+    ///
+    /// A `Constant` candidate is included only if all samples are equal. In
+    /// exhaustive mode, every candidate of every predictor is returned.
+    /// Otherwise, only the best candidate of each predictor by estimated size is
+    /// returned.
+    pub fn candidates(&self, samples: &[T]) -> Vec <Subframe <T>> {
+        self.costed_candidates(samples).into_iter().map(|candidate| candidate.subframe).collect()
+    }
+
+    /// Get the subframe candidates for the given samples with their cost
+    ///
+    /// In exhaustive mode, the cost is the exact encoded size. Otherwise, it is
+    /// the estimate of the predictor that found the candidate. Candidates whose
+    /// residuals do not fit in a subframe are left out, so `Verbatim` is the
+    /// last resort.
+    fn costed_candidates(&self, samples: &[T]) -> Vec <Candidate <T>> {
+        let bps = self.bps;
+        if samples.is_empty() {
+            return Vec::new();
+        }
+
+        let mut subframes = Vec::new();
+
+        if samples.iter().all(|&sample| sample == samples[0]) {
+            subframes.push(Subframe::Constant { value: samples[0] });
+        }
+        subframes.push(Subframe::Verbatim { samples: samples.to_vec() });
+
+        if self.exhaustive {
+            for predictor in self.active_predictors() {
+                subframes.extend(predictor.candidates(samples, bps).into_iter().filter(Subframe::residuals_fit));
+            }
+            return subframes.into_iter()
+                .map(|subframe| Candidate { cost: subframe.encoded_bits(bps), subframe })
+                .collect();
+        }
+
+        let mut candidates: Vec <Candidate <T>> = subframes.into_iter()
+            .map(|subframe| Candidate { cost: subframe.estimate_bits(bps), subframe })
+            .collect();
+        for predictor in self.active_predictors() {
+            candidates.extend(predictor.analyze(samples, bps).filter(|candidate| candidate.subframe.residuals_fit()));
+        }
+        candidates
+    }
+
+    /// Get the smallest subframe for the given samples
+    ///
+    /// `None` is returned only if `samples` is empty.
+    pub fn best_subframe(&self, samples: &[T]) -> Option <Subframe <T>> {
+        self.costed_candidates(samples).into_iter()
+            .min_by_key(|candidate| candidate.cost)
+            .map(|candidate| candidate.subframe)
+    }
+
+    /// Get the smallest subframe for one channel of a block and its wasted bits
+    ///
+    /// The wasted bits are shifted out of every sample before prediction, so the
+    /// subframe should be written using `Subframe::write_with_wasted_bits`.
+    /// `None` is returned only if `samples` is empty.
+    pub fn encode_channel(&self, samples: &[T]) -> Option <(Subframe <T>, u8)> {
+        let wasted_bits = self.wasted_bits(samples);
+        if wasted_bits == 0 {
+            return self.best_subframe(samples).map(|subframe| (subframe, 0));
+        }
+
+        let shifted: Vec <T> = samples.iter().map(|&sample| T::from_i64(sample.to_i64() >> wasted_bits)).collect();
+        let options = SubframeEncoderOptions { bps: self.bps - wasted_bits, ..self.clone() };
+        options.best_subframe(&shifted).map(|subframe| (subframe, wasted_bits))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::flac::lpc::predictor::PredictorParams;
+
+    fn sine(len: usize) -> Vec <i64> {
+        (0..len).map(|i| ((i as f64 * 0.07).sin() * 12000.0) as i64).collect()
+    }
+
+    #[test]
+    fn test_write_constant() {
+        let mut writer = BitWriter::new();
+        Subframe::Constant { value: -2 }.write(&mut writer, 8);
+        assert_eq!(writer.into_bytes(), vec![0x00, 0xFE]);
+    }
+
+    #[test]
+    fn test_write_verbatim() {
+        let mut writer = BitWriter::new();
+        Subframe::Verbatim { samples: vec![1, -1] }.write(&mut writer, 8);
+        assert_eq!(writer.into_bytes(), vec![0x02, 0x01, 0xFF]);
+    }
+
+    #[test]
+    fn test_write_fixed_header() {
+        let mut writer = BitWriter::new();
+        Subframe::Fixed { warmup: vec![5, 6], residuals: vec![0, 0] }.write(&mut writer, 8);
+        let bytes = writer.into_bytes();
+        assert_eq!(&bytes[..3], &[0x14, 0x05, 0x06]);
+    }
+
+    #[test]
+    fn test_lpc_rejects_unwritable_coefficients() {
+        let samples = vec![1, 2, 3, 4, 5];
+        assert!(Subframe::lpc(&samples, vec![104, -15], 7).is_some());
+        assert!(Subframe::lpc(&samples, vec![1 << 15], 7).is_none());
+        assert!(Subframe::lpc(&samples, vec![104], 16).is_none());
+    }
+
+    #[test]
+    fn test_estimate_matches_verbatim_and_constant_exactly() {
+        let verbatim = Subframe::Verbatim { samples: vec![1, 2, 3] };
+        assert_eq!(verbatim.estimate_bits(16), verbatim.encoded_bits(16));
+        let constant = Subframe::Constant { value: 7 };
+        assert_eq!(constant.estimate_bits(16), constant.encoded_bits(16));
+    }
+
+    #[test]
+    fn test_best_subframe_constant() {
+        let options = SubframeEncoderOptions::new(16);
+        let best = options.best_subframe(&vec![42; 64]);
+        assert_eq!(best, Some(Subframe::Constant { value: 42 }));
+    }
+
+    #[test]
+    fn test_best_subframe_prefers_prediction() {
+        let options = SubframeEncoderOptions::new(16);
+        let best = options.best_subframe(&sine(1024)).unwrap();
+        assert!(matches!(best, Subframe::Fixed { .. } | Subframe::Lpc { .. }));
+    }
+
+    #[test]
+    fn test_best_subframe_verbatim_for_short_noise() {
+        let options = SubframeEncoderOptions::new(16);
+        let best = options.best_subframe(&[-32768, 32767]).unwrap();
+        assert!(matches!(best, Subframe::Verbatim { .. }));
+    }
+
+    #[test]
+    fn test_exhaustive_not_larger_than_estimated() {
+        let samples = sine(512);
+        let estimated = SubframeEncoderOptions::new(16).best_subframe(&samples).unwrap();
+        let exhaustive = SubframeEncoderOptions::new(16).exhaustive(true).best_subframe(&samples).unwrap();
+        assert!(exhaustive.encoded_bits(16) <= estimated.encoded_bits(16));
+    }
+
+    #[test]
+    fn test_precision_search_not_larger_than_default() {
+        let samples: Vec <i64> = (0..1024).map(|i| ((i as f64 * 0.013).sin() * 9000.0 + (i as f64 * 0.41).sin() * 300.0) as i64).collect();
+        let default = SubframeEncoderOptions::new(16).best_subframe(&samples).unwrap();
+        let searched = SubframeEncoderOptions::new(16).qlp_precision_search(true).best_subframe(&samples).unwrap();
+        assert!(searched.estimate_bits(16) <= default.estimate_bits(16));
+    }
+
+    struct ZeroPredictor;
+
+    impl Predictor for ZeroPredictor {
+        fn candidates(&self, samples: &[i64], _bps: u8) -> Vec <Subframe> {
+            vec![Subframe::Fixed { warmup: Vec::new(), residuals: samples.iter().map(|&sample| sample as i32).collect() }]
+        }
+
+        fn residuals(&self, samples: &[i64], _params: &PredictorParams) -> Option <Vec <i32>> {
+            Some(samples.iter().map(|&sample| sample as i32).collect())
+        }
+
+        fn restore(&self, _warmup: &[i64], residuals: &[i32], _params: &PredictorParams) -> Option <Vec <i64>> {
+            Some(residuals.iter().map(|&residual| residual as i64).collect())
+        }
+
+        // Claims to beat every other candidate
+        fn cost_estimate(&self, _subframe: &Subframe, _bps: u8) -> u64 {
+            0
+        }
+    }
+
+    #[test]
+    fn test_custom_predictors() {
+        let samples = sine(1024);
+        let options = SubframeEncoderOptions::new(16).predictors(vec![Rc::new(ZeroPredictor)]);
+        assert_eq!(options.best_subframe(&samples), Some(Subframe::fixed(&samples, 0).unwrap()));
+        assert_eq!(options.candidates(&samples).len(), 2);
+
+        // Exhaustive mode compares the encoded sizes instead
+        let options = options.exhaustive(true);
+        assert_eq!(options.best_subframe(&[1, 1, 1]), Some(Subframe::Constant { value: 1 }));
+    }
+
+    #[test]
+    fn test_full_scale_32_bit_falls_back() {
+        // A full-scale square wave overflows the residual range of every fixed order but 0
+        let square: Vec <i64> = (0..1024).map(|i| if i / 3 % 2 == 0 { i32::MAX as i64 } else { i32::MIN as i64 }).collect();
+        for options in [SubframeEncoderOptions::new(32), SubframeEncoderOptions::new(32).exhaustive(true)] {
+            let candidates = options.candidates(&square);
+            assert!(candidates.iter().all(|candidate| candidate.residuals_fit()));
+            assert!(!candidates.iter().any(|candidate| matches!(candidate, Subframe::Fixed { .. }) && candidate.order() > 0));
+            assert!(options.best_subframe(&square).unwrap().residuals_fit());
+        }
+    }
+
+    #[test]
+    fn test_lpc_rejects_residuals_out_of_range() {
+        let samples = vec![i32::MAX as i64, i32::MIN as i64, i32::MAX as i64];
+        assert!(Subframe::lpc(&samples, vec![1], 0).is_none());
+        assert!(Subframe::lpc(&[0, 1, 2], vec![1], 0).is_some());
+    }
+
+    #[test]
+    fn test_wasted_bits() {
+        let options = SubframeEncoderOptions::new(24);
+        assert_eq!(options.wasted_bits(&[256, -512, 0, 1024]), 8);
+        assert_eq!(options.wasted_bits(&[256, -512, 3, 1024]), 0);
+        assert_eq!(options.wasted_bits(&[0, 0, 0, 0]), 0);
+        assert_eq!(SubframeEncoderOptions::new(16).wasted_bits(&[-32768]), 15);
+    }
+
+    #[test]
+    fn test_write_wasted_bits_header() {
+        let mut writer = BitWriter::new();
+        Subframe::Constant { value: -2 }.write_with_wasted_bits(&mut writer, 12, 4);
+        // Wasted bits flag and unary 3, then the constant in 8 bits
+        assert_eq!(writer.into_bytes(), vec![0x01, 0x1F, 0xE0]);
+    }
+
+    #[test]
+    fn test_encode_channel_shifts_out_wasted_bits() {
+        let samples: Vec <i64> = sine(1024).iter().map(|&sample| sample << 8).collect();
+        let options = SubframeEncoderOptions::new(24);
+
+        let (subframe, wasted_bits) = options.encode_channel(&samples).unwrap();
+
+        assert_eq!(wasted_bits, 8);
+        let mut writer = BitWriter::new();
+        subframe.write_with_wasted_bits(&mut writer, 24, wasted_bits);
+        assert!(writer.len_bits() < options.best_subframe(&samples).unwrap().encoded_bits(24));
+    }
+
+    #[test]
+    fn test_best_subframe_empty() {
+        assert_eq!(SubframeEncoderOptions::<i16>::new(16).best_subframe(&[]), None);
+    }
+}
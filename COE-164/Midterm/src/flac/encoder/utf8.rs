@@ -1,11 +1,31 @@
+use core::fmt;
+use std::error;
+
+/// Largest number that can be encoded, which takes 36 bits
+pub const MAX_UTF8_NUM: u64 = 0xFFFFFFFFF;
+
 pub struct Utf8Encoder;
 
+// Represents possible errors in the UTF-8 encoder and decoder
+#[derive(Debug)]
+#[derive(PartialEq)]
+pub enum Utf8Error {
+    OutOfRangeError,
+    InvalidSequenceError,
+    OverlongError,
+    UnexpectedEndError,
+}
+
 impl Utf8Encoder {
-    /// Encode a number into its UTF-9 equivalent encoding
+    /// Encode a number into its UTF-8 equivalent encoding
     /// 
     /// Although UTF-8 encoding is for characters, characters are
-    /// mapped to certain numbers.
-    pub fn encode(num: u64) -> Vec <u8> {
+    /// mapped to certain numbers. FLAC extends the encoding to 7 bytes
+    /// with a leading `0xFE` byte, so numbers of up to 36 bits can be encoded.
+    /// 
+    /// # Errors
+    /// `OutOfRangeError` is returned if `num` is larger than `MAX_UTF8_NUM`.
+    pub fn encode(num: u64) -> Result <Vec <u8>, Utf8Error> {
         let mut encoded_bytes = vec![];
 
         if num <= 0x7F {
@@ -61,8 +81,31 @@ impl Utf8Encoder {
             encoded_bytes.push(0x80 | ((num >> 6) & 0x3F) as u8); 
             // Sixth byte: 10xxxxxx
             encoded_bytes.push(0x80 | (num & 0x3F) as u8); 
+        } else if num <= MAX_UTF8_NUM {
+            encoded_bytes.push(0xFE);
+            encoded_bytes.push(0x80 | ((num >> 30) & 0x3F) as u8);
+            encoded_bytes.push(0x80 | ((num >> 24) & 0x3F) as u8);
+            encoded_bytes.push(0x80 | ((num >> 18) & 0x3F) as u8);
+            encoded_bytes.push(0x80 | ((num >> 12) & 0x3F) as u8);
+            encoded_bytes.push(0x80 | ((num >> 6) & 0x3F) as u8);
+            encoded_bytes.push(0x80 | (num & 0x3F) as u8);
+        } else {
+            return Err(Utf8Error::OutOfRangeError);
         }
-        encoded_bytes
+        Ok(encoded_bytes)
+    }
+}
+
+impl error::Error for Utf8Error {}
+
+impl fmt::Display for Utf8Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", match self {
+            Utf8Error::OutOfRangeError => "Number too large for UTF-8 encoding",
+            Utf8Error::InvalidSequenceError => "Invalid UTF-8 sequence",
+            Utf8Error::OverlongError => "Overlong UTF-8 sequence",
+            Utf8Error::UnexpectedEndError => "Unexpected end of UTF-8 sequence",
+        })
     }
 }
 
@@ -78,7 +121,7 @@ mod tests {
         
         let encoded_bytes = Utf8Encoder::encode(num);
 
-        assert_eq!(encoded_bytes, expected_output);
+        assert_eq!(encoded_bytes, Ok(expected_output));
     }
 
     #[test]
@@ -88,7 +131,7 @@ mod tests {
         
         let encoded_bytes = Utf8Encoder::encode(num);
 
-        assert_eq!(encoded_bytes, expected_output);
+        assert_eq!(encoded_bytes, Ok(expected_output));
     }
 
     #[test]
@@ -98,7 +141,7 @@ mod tests {
 
         let encoded_bytes = Utf8Encoder::encode(num);
 
-        assert_eq!(encoded_bytes, expected_output);
+        assert_eq!(encoded_bytes, Ok(expected_output));
     }
 
     #[test]
@@ -108,7 +151,7 @@ mod tests {
         
         let encoded_bytes = Utf8Encoder::encode(num);
 
-        assert_eq!(encoded_bytes, expected_output);
+        assert_eq!(encoded_bytes, Ok(expected_output));
     }
 
     #[test]
@@ -118,7 +161,7 @@ mod tests {
         
         let encoded_bytes = Utf8Encoder::encode(num);
 
-        assert_eq!(encoded_bytes, expected_output);
+        assert_eq!(encoded_bytes, Ok(expected_output));
     }
 
     #[test]
@@ -128,6 +171,33 @@ mod tests {
 
         let encoded_bytes = Utf8Encoder::encode(num);
 
-        assert_eq!(encoded_bytes, expected_output);
+        assert_eq!(encoded_bytes, Ok(expected_output));
+    }
+
+    #[test]
+    fn test_utf8_encoder_7_bytes() {
+        let num = MAX_UTF8_NUM; // 68719476735 in decimal, the largest 36-bit number
+        let expected_output = vec![0xFE, 0xBF, 0xBF, 0xBF, 0xBF, 0xBF, 0xBF];
+
+        let encoded_bytes = Utf8Encoder::encode(num);
+
+        assert_eq!(encoded_bytes, Ok(expected_output));
+    }
+
+    #[test]
+    fn test_utf8_encoder_7_bytes_smallest() {
+        let num = 0x80000000; // 2147483648 in decimal, one more than the largest 6-byte number
+        let expected_output = vec![0xFE, 0x82, 0x80, 0x80, 0x80, 0x80, 0x80];
+
+        let encoded_bytes = Utf8Encoder::encode(num);
+
+        assert_eq!(encoded_bytes, Ok(expected_output));
+    }
+
+    #[test]
+    fn test_utf8_encoder_out_of_range() {
+        let encoded_bytes = Utf8Encoder::encode(MAX_UTF8_NUM + 1);
+
+        assert_eq!(encoded_bytes, Err(Utf8Error::OutOfRangeError));
     }
 }
\ No newline at end of file
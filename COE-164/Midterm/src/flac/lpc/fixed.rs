@@ -1,20 +1,30 @@
+use crate::flac::encoder::subframe::Subframe;
+use crate::flac::sample::Sample;
+
+use super::predictor::{self, Predictor, PredictorParams};
+
+/// Coefficients of each fixed predictor order, applied from the latest sample
+pub const FIXED_COEFFS: [&[i64]; 5] = [&[], &[1], &[2, -1], &[3, -3, 1], &[4, -6, 4, -1]];
+
 pub struct FixedPredictor;
 
 impl FixedPredictor {
-    /// Get order that yields the least sum of residuals
+    /// Get order that yields the smallest subframe
     /// 
     /// The predictor orders are from 0 to 4 inclusive and is retrieved
-    /// by finding the predictor that yields the *minimum* absolute
-    /// sum of residuals for the given `data` and derived predictor.
-    pub fn best_predictor_order(data: &Vec <i64>) -> Option <u8> {
-        let mut min_residual_sum = i64::MAX;
+    /// by finding the predictor that yields the *minimum* estimated
+    /// subframe size in bits for the given `data` and derived predictor.
+    /// The estimate accounts for the `bps`-bit warm-up samples as well as
+    /// the Rice-coded residuals.
+    pub fn best_predictor_order <T: Sample> (data: &[T], bps: u8) -> Option <u8> {
+        let mut min_bits = u64::MAX;
         let mut best_order = None;
 
         for order in 0..=4 {
-            if let Some(residuals) = FixedPredictor::get_residuals(data, order) {
-                let residual_sum = FixedPredictor::get_sum(&residuals);
-                if residual_sum < min_residual_sum {
-                    min_residual_sum = residual_sum;
+            if let Some(subframe) = Subframe::fixed(data, order) {
+                let bits = subframe.estimate_bits(bps);
+                if bits < min_bits {
+                    min_bits = bits;
                     best_order = Some(order);
                 }
             }
@@ -23,14 +33,6 @@ impl FixedPredictor {
         best_order
     }
 
-    fn get_sum(residuals: &Vec <i64>) -> i64 {
-        let mut sum = 0 as i64;
-        for &residual in residuals {
-            sum += residual.abs() as i64;
-        }
-        sum
-    }
-
     /// Get residuals of a fixed predictor order 
     /// 
     /// The predictor orders are from 0 to 4 inclusive and corresponds
@@ -43,35 +45,104 @@ impl FixedPredictor {
     /// 3: r[i] = 3 * data[i - 1] - 3 * data[i - 2] + data[i - 3]
     /// 4: r[i] = 4 * data[i - 1] - 6 * data[i - 2] + 4 data[i - 3] - data[i - 4]
     /// 
-    /// This function returns a vector with each element containing data[i] - r[i].
+    /// This function returns a vector with each element containing data[i] - r[i],
+    /// starting after the `predictor_order` warm-up samples. The prediction is
+    /// computed in `i64`, or in `i128` if it may overflow an `i64`, which only
+    /// `i64` samples can do.
     /// 
     /// # Errors
     /// `None` is returned if an error occurs in the function. This includes whether
-    /// the predictor order provided is not within 0 and 4 inclusive and whether the
-    /// size of `data` is less than the predictor order.
-    pub fn get_residuals(data: &Vec <i64>, predictor_order: u8) -> Option <Vec <i64>> {
+    /// the predictor order provided is not within 0 and 4 inclusive, whether the
+    /// size of `data` is less than the predictor order or zero, and whether a
+    /// residual does not fit in a subframe.
+    pub fn get_residuals <T: Sample> (data: &[T], predictor_order: u8) -> Option <Vec <i32>> {
         // Check if predictor order is between 0 and 4 inclusive and if the size of the data is at least the predictor order
-        if predictor_order > 4 || data.len() < predictor_order as usize {
+        if predictor_order > 4 || data.len() < predictor_order as usize || data.is_empty() {
             return None;
         }
+        let coeffs = FIXED_COEFFS[predictor_order as usize];
+        if predictor::may_overflow(coeffs, predictor::magnitude_bound(data)) {
+            return FixedPredictor::get_residuals_wide(data, coeffs);
+        }
 
-        let mut residuals = Vec::new();
-        for i in 0..data.len() {
+        let mut residuals = Vec::with_capacity(data.len() - predictor_order as usize);
+        let x = |i: usize| data[i].to_i64();
+        for i in predictor_order as usize..data.len() {
             let r_i = match predictor_order {
                 0 => 0,
-                1 => if i >= 1 { data[i - 1] } else { 0 },
-                2 => if i >= 2 { 2 * data[i - 1] - data[i - 2] } else { 0 },
-                3 => if i >= 3 { 3 * data[i - 1] - 3 * data[i - 2] + data[i - 3] } else { 0 },
-                4 => if i >= 4 { 4 * data[i - 1] - 6 * data[i - 2] + 4 * data[i - 3] - data[i - 4] } else { 0 },
+                1 => x(i - 1),
+                2 => 2 * x(i - 1) - x(i - 2),
+                3 => 3 * x(i - 1) - 3 * x(i - 2) + x(i - 3),
+                4 => 4 * x(i - 1) - 6 * x(i - 2) + 4 * x(i - 3) - x(i - 4),
                 _ => return None,
             };
-            residuals.push(data[i] - r_i);
+            residuals.push(predictor::residual(x(i) - r_i)?);
         }
-        if residuals.is_empty() {
-            return None;
+        Some(residuals)
+    }
+
+    /// Get the residuals like `get_residuals`, with the prediction computed in `i128`
+    fn get_residuals_wide <T: Sample> (data: &[T], coeffs: &[i64]) -> Option <Vec <i32>> {
+        let order = coeffs.len();
+        let mut residuals = Vec::with_capacity(data.len() - order);
+        for i in order..data.len() {
+            let prediction: i128 = coeffs.iter().enumerate().map(|(j, &coeff)| coeff as i128 * data[i - j - 1].to_i64() as i128).sum();
+            residuals.push(predictor::wide_residual(data[i].to_i64() as i128 - prediction)?);
         }
         Some(residuals)
     }
+
+    /// Restore the samples from the warm-up samples and residuals of a fixed predictor
+    ///
+    /// This undoes `get_residuals`, with the predictor order given by the number
+    /// of warm-up samples. The prediction is computed with checked arithmetic,
+    /// since the residuals of a corrupt stream may overflow an `i64`.
+    ///
+    /// # Errors
+    /// `None` is returned for more than 4 warm-up samples, for an empty block,
+    /// and if a restored sample overflows an `i64`.
+    pub fn restore_samples <T: Sample> (warmup: &[T], residuals: &[i32]) -> Option <Vec <i64>> {
+        let order = warmup.len();
+        if order > 4 || (warmup.is_empty() && residuals.is_empty()) {
+            return None;
+        }
+
+        let coeffs = FIXED_COEFFS[order];
+        let mut samples = Vec::with_capacity(order + residuals.len());
+        samples.extend(warmup.iter().map(|&sample| sample.to_i64()));
+        for &residual in residuals {
+            let i = samples.len();
+            let prediction = coeffs.iter().enumerate()
+                .try_fold(0i64, |sum, (j, &coeff)| sum.checked_add(coeff.checked_mul(samples[i - j - 1])?))?;
+            samples.push(prediction.checked_add(residual as i64)?);
+        }
+        Some(samples)
+    }
+}
+
+impl <T: Sample> Predictor <T> for FixedPredictor {
+    /// Get the subframes of every fixed predictor order that fits the block
+    ///
+    /// Orders whose residuals do not fit in a subframe are left out.
+    fn candidates(&self, samples: &[T], _bps: u8) -> Vec <Subframe <T>> {
+        (0..=4)
+            .filter_map(|order| Subframe::fixed(samples, order))
+            .collect()
+    }
+
+    fn residuals(&self, samples: &[T], params: &PredictorParams) -> Option <Vec <i32>> {
+        match params {
+            PredictorParams::Fixed { order } => FixedPredictor::get_residuals(samples, *order),
+            _ => None,
+        }
+    }
+
+    fn restore(&self, warmup: &[T], residuals: &[i32], params: &PredictorParams) -> Option <Vec <i64>> {
+        match params {
+            PredictorParams::Fixed { order } if warmup.len() == *order as usize => FixedPredictor::restore_samples(warmup, residuals),
+            _ => None,
+        }
+    }
 }
 
 
@@ -85,10 +156,18 @@ mod tests {
 
         #[test]
         fn test_best_predictor_order() {    
-            let data = vec![1, 2, 3, 4, 5, 6, 7, 8, 9, 10];
-            let best_order = FixedPredictor::best_predictor_order(&data);
+            let data: Vec <i64> = (1..=64).collect();
+            let best_order = FixedPredictor::best_predictor_order(&data, 16);
             assert_eq!(best_order, Some(2)); 
         }
+
+        #[test]
+        fn test_best_predictor_order_counts_warmup() {
+            // Too short for the extra 24-bit warm-up sample of order 2 to pay off
+            let data = vec![1, 2, 3, 4, 5, 6, 7, 8, 9, 10];
+            let best_order = FixedPredictor::best_predictor_order(&data, 24);
+            assert_eq!(best_order, Some(1));
+        }
     
         #[test]
         fn test_get_residuals_order_0() {   // Check residual for order 0
@@ -101,14 +180,14 @@ mod tests {
         fn test_get_residuals_order_1() {   // Check residual for order 1
             let data = vec![1, 2, 3, 4, 5];
             let residuals = FixedPredictor::get_residuals(&data, 1).unwrap();
-            assert_eq!(residuals, vec![1, 1, 1, 1, 1]);
+            assert_eq!(residuals, vec![1, 1, 1, 1]);
         }
     
         #[test]
         fn test_get_residuals_order_2() {   // Check residual for order 2
             let data = vec![1, 2, 3, 4, 5];
             let residuals = FixedPredictor::get_residuals(&data, 2).unwrap();
-            assert_eq!(residuals, vec![1, 2, 0, 0, 0]); 
+            assert_eq!(residuals, vec![0, 0, 0]); 
         }
 
         
@@ -116,42 +195,42 @@ mod tests {
         fn test_get_residuals_order_3() {
             let data = vec![1, 2, 3, 4, 5];
             let residuals = FixedPredictor::get_residuals(&data, 3).unwrap();
-            assert_eq!(residuals, vec![1, 2, 3, 0, 0]); 
+            assert_eq!(residuals, vec![0, 0]); 
         }
 
         #[test]
         fn test_get_residuals_order_4() {
             let data = vec![1, 2, 3, 4, 5];
             let residuals = FixedPredictor::get_residuals(&data, 4).unwrap();
-            assert_eq!(residuals, vec![1, 2, 3, 4, 0]); 
+            assert_eq!(residuals, vec![0]); 
         }
 
         #[test]
         fn test_get_residuals_non_linear_order_1() {
             let data = vec![1, 3, 6, 10, 15];
             let residuals = FixedPredictor::get_residuals(&data, 1).unwrap();
-            assert_eq!(residuals, vec![1, 2, 3, 4, 5]); // For order 1, r[i] = data[i-1], residuals should be [2, 3, 4, 5]
+            assert_eq!(residuals, vec![2, 3, 4, 5]); // For order 1, r[i] = data[i-1], residuals should be [2, 3, 4, 5]
         }
 
         #[test]
         fn test_get_residuals_non_linear_order_2() {
             let data = vec![1, 3, 6, 10, 15];
             let residuals = FixedPredictor::get_residuals(&data, 2).unwrap();
-            assert_eq!(residuals, vec![1, 3, 1, 1, 1]);
+            assert_eq!(residuals, vec![1, 1, 1]);
         }
 
         #[test]
         fn test_get_residuals_non_linear_order_3() {
             let data = vec![1, 3, 6, 10, 15];
             let residuals = FixedPredictor::get_residuals(&data, 3).unwrap();
-            assert_eq!(residuals, vec![1, 3, 6, 0, 0]);
+            assert_eq!(residuals, vec![0, 0]);
         }
 
         #[test]
         fn test_get_residuals_non_linear_order_4() {
             let data = vec![1, 3, 6, 10, 15];
             let residuals = FixedPredictor::get_residuals(&data, 4).unwrap();
-            assert_eq!(residuals, vec![1, 3, 6, 10, 0]); 
+            assert_eq!(residuals, vec![0]); 
         }
     
         #[test]
@@ -179,13 +258,81 @@ mod tests {
         fn test_get_residuals_repeated_values() {
             let data = vec![5, 5, 5, 5, 5];
             let residuals = FixedPredictor::get_residuals(&data, 1).unwrap();
-            assert_eq!(residuals, vec![5, 0, 0, 0, 0]); 
+            assert_eq!(residuals, vec![0, 0, 0, 0]); 
         }
     
+        #[test]
+        fn test_get_residuals_wide() {
+            // Large enough for order 4 to overflow an i64, though a ramp is predicted exactly
+            let ramp: Vec <i64> = (0..16).map(|i| (1 << 60) + i).collect();
+            assert!(predictor::may_overflow(FIXED_COEFFS[4], predictor::magnitude_bound(&ramp)));
+            assert_eq!(FixedPredictor::get_residuals(&ramp, 4), Some(vec![0; 12]));
+            assert_eq!(FixedPredictor::get_residuals(&ramp, 1), Some(vec![1; 15]));
+
+            // The residuals of full-scale alternating samples do not fit in a subframe
+            let data: Vec <i64> = (0..16).map(|i| if i % 2 == 0 { 1 << 59 } else { -(1 << 59) }).collect();
+            assert_eq!(FixedPredictor::get_residuals(&data, 4), None);
+            assert_eq!(FixedPredictor::get_residuals(&data, 0), None);
+        }
+
+        #[test]
+        fn test_get_residuals_sample_types() {
+            let data: Vec <i16> = vec![i16::MAX, i16::MIN, i16::MAX, i16::MIN, 0, i16::MAX];
+            let wide: Vec <i64> = data.iter().map(|&sample| sample as i64).collect();
+            for order in 0..=4 {
+                // The prediction of 16-bit samples does not fit in 16 bits
+                let residuals = FixedPredictor::get_residuals(&data, order).unwrap();
+                assert_eq!(residuals, FixedPredictor::get_residuals(&wide, order).unwrap());
+                assert_eq!(FixedPredictor::restore_samples(&data[..order as usize], &residuals), Some(wide.clone()));
+            }
+        }
+
+        #[test]
+        fn test_candidates_full_scale_32_bit() {
+            // Alternating full-scale samples only fit the residual range with order 0
+            let data: Vec <i64> = (0..64).map(|i| if i % 2 == 0 { i32::MAX as i64 } else { -(i32::MAX as i64) }).collect();
+            let candidates = FixedPredictor.candidates(&data, 32);
+            assert_eq!(candidates.iter().map(|subframe| subframe.order()).collect::<Vec <u8>>(), vec![0]);
+            assert_eq!(FixedPredictor.analyze(&data, 32).unwrap().subframe.order(), 0);
+        }
+
+        #[test]
+        fn test_restore_samples() {
+            let data = vec![3, -7, 12, 40, 41, 39, -100, 5];
+            for order in 0..=4 {
+                let residuals = FixedPredictor::get_residuals(&data, order).unwrap();
+                assert_eq!(FixedPredictor::restore_samples(&data[..order as usize], &residuals), Some(data.clone()));
+            }
+            assert_eq!(FixedPredictor::restore_samples(&data[..5], &[0]), None);
+            assert_eq!(FixedPredictor::restore_samples::<i64>(&[], &[]), None);
+        }
+
+        #[test]
+        fn test_restore_samples_overflow() {
+            assert_eq!(FixedPredictor::restore_samples(&[0, 0, 0, i64::MAX / 2], &[0]), None);
+            assert_eq!(FixedPredictor::restore_samples(&[i64::MAX], &[1]), None);
+            assert_eq!(FixedPredictor::restore_samples(&[i64::MAX], &[-1]), Some(vec![i64::MAX, i64::MAX - 1]));
+        }
+
+        #[test]
+        fn test_predictor_analyze_matches_best_predictor_order() {
+            let data: Vec <i64> = (0..256).map(|i| ((i as f64 * 0.1).sin() * 500.0) as i64).collect();
+            let candidate = FixedPredictor.analyze(&data, 16).unwrap();
+            let order = FixedPredictor::best_predictor_order(&data, 16).unwrap();
+            assert_eq!(candidate.subframe, Subframe::fixed(&data, order).unwrap());
+            assert_eq!(candidate.cost, candidate.subframe.estimate_bits(16));
+
+            let params = PredictorParams::Fixed { order };
+            let residuals = FixedPredictor.residuals(&data, &params).unwrap();
+            let warmup = &data[..order as usize];
+            assert_eq!(FixedPredictor.restore(warmup, &residuals, &params), Some(data.clone()));
+            assert_eq!(FixedPredictor.restore(warmup, &residuals, &PredictorParams::Fixed { order: order + 1 }), None);
+        }
+
         #[test]
         fn test_best_predictor_order_non_linear() {
-            let data = vec![1, 4, 9, 16, 25, 36, 49, 64, 81, 100];
-            let best_order = FixedPredictor::best_predictor_order(&data);
+            let data: Vec <i64> = (1..=64).map(|i| i * i).collect();
+            let best_order = FixedPredictor::best_predictor_order(&data, 16);
             assert_eq!(best_order, Some(3)); 
         }
     }
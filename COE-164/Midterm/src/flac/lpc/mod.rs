@@ -1,2 +1,4 @@
 pub mod fixed;
-pub mod var;
\ No newline at end of file
+pub mod predictor;
+pub mod var;
+pub mod window;
\ No newline at end of file
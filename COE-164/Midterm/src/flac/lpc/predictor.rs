@@ -0,0 +1,149 @@
+use crate::flac::encoder::subframe::Subframe;
+use crate::flac::sample::Sample;
+
+use super::var::QlpCoeffs;
+
+/// Largest magnitude of a residual, which must fit in a signed 32-bit integer
+pub const MAX_RESIDUAL: i64 = i32::MAX as i64;
+/// Largest magnitude of a sample restored by the decoder
+///
+/// The encoder only writes independent channels of its input, so its samples
+/// stay below `1 << 31` for input of up to 32 bits, and shifting out wasted
+/// bits only makes them smaller. The decoder also restores the side channels
+/// of streams from other encoders, which have one more bit than their input.
+pub const MAX_SAMPLE: u64 = 1 << 32;
+
+/// Represents the parameters of a predictor, as needed to compute or undo its residuals
+#[derive(Debug, Clone, PartialEq)]
+pub enum PredictorParams {
+    Fixed { order: u8 },
+    Lpc(QlpCoeffs),
+}
+
+/// Represents the subframe a predictor picked for a block, with its estimated size in bits
+#[derive(Debug, Clone, PartialEq)]
+pub struct Candidate <T: Sample = i64> {
+    pub subframe: Subframe <T>,
+    pub cost: u64,
+}
+
+/// Represents a predictor tried by the subframe encoder for samples of type `T`
+///
+/// Residuals follow the convention of `Subframe`: they start after the `order`
+/// warm-up samples, which are kept as samples. A predictor only handles its own
+/// kind of parameters and returns `None` for the others. Restored samples are
+/// `i64`, since the decoder restores side channels wider than their input.
+pub trait Predictor <T: Sample = i64> {
+    /// Get every subframe the predictor tries for a block of `bps`-bit samples
+    fn candidates(&self, samples: &[T], bps: u8) -> Vec <Subframe <T>>;
+
+    /// Get the candidate with the smallest estimated cost for a block of `bps`-bit samples
+    ///
+    /// `None` is returned if the predictor has no candidate for the block.
+    fn analyze(&self, samples: &[T], bps: u8) -> Option <Candidate <T>> {
+        self.candidates(samples, bps).into_iter()
+            .map(|subframe| Candidate { cost: self.cost_estimate(&subframe, bps), subframe })
+            .min_by_key(|candidate| candidate.cost)
+    }
+
+    /// Compute the residuals of a block of samples
+    ///
+    /// `None` is also returned if a residual does not fit in a subframe.
+    fn residuals(&self, samples: &[T], params: &PredictorParams) -> Option <Vec <i32>>;
+
+    /// Restore the samples of a block from its warm-up samples and residuals
+    fn restore(&self, warmup: &[T], residuals: &[i32], params: &PredictorParams) -> Option <Vec <i64>>;
+
+    /// Estimate the size of a subframe of the predictor in bits
+    fn cost_estimate(&self, subframe: &Subframe <T>, bps: u8) -> u64 {
+        subframe.estimate_bits(bps)
+    }
+}
+
+/// Get the largest magnitude of the samples
+pub fn max_magnitude <T: Sample> (samples: &[T]) -> u64 {
+    samples.iter().map(|sample| sample.to_i64().unsigned_abs()).max().unwrap_or(0)
+}
+
+/// Get a bound on the magnitude of the samples to check predictions for overflow
+///
+/// Samples of up to 32 bits never overflow an `i64` with the coefficients of a
+/// subframe, so the range of their type is used without looking at them.
+pub fn magnitude_bound <T: Sample> (samples: &[T]) -> u64 {
+    if T::BITS <= 32 {
+        T::max_magnitude()
+    } else {
+        max_magnitude(samples)
+    }
+}
+
+/// Check whether predicting with `coeffs` may overflow an `i64` for samples up to `max_sample` in magnitude
+///
+/// The worst case adds up the magnitude of every coefficient times `max_sample`,
+/// plus one more sample for the residual or restored sample.
+pub fn may_overflow(coeffs: &[i64], max_sample: u64) -> bool {
+    let coeff_sum: u128 = coeffs.iter().map(|coeff| coeff.unsigned_abs() as u128).sum();
+    match (coeff_sum + 1).checked_mul(max_sample as u128) {
+        Some(worst_case) => worst_case > i64::MAX as u128,
+        None => true,
+    }
+}
+
+/// Convert a residual computed in `i64` to the `i32` stored in a subframe
+///
+/// `None` is returned if the residual is larger than `MAX_RESIDUAL` in magnitude.
+pub fn residual(value: i64) -> Option <i32> {
+    (value.unsigned_abs() <= MAX_RESIDUAL as u64).then_some(value as i32)
+}
+
+/// Convert a residual computed in `i128` like `residual`
+pub fn wide_residual(value: i128) -> Option <i32> {
+    i64::try_from(value).ok().and_then(residual)
+}
+
+/// Check whether every residual fits in a subframe
+///
+/// Only `i32::MIN` is out of range, since its magnitude exceeds `MAX_RESIDUAL`.
+pub fn residuals_fit(residuals: &[i32]) -> bool {
+    residuals.iter().all(|&residual| residual != i32::MIN)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_may_overflow() {
+        // Order 32 with 15-bit coefficients and 32-bit samples
+        assert!(!may_overflow(&[1 << 14; 32], 1 << 31));
+        assert!(may_overflow(&[1 << 14; 32], 1 << 48));
+        assert!(!may_overflow(&[4, -6, 4, -1], 1 << 58));
+        assert!(may_overflow(&[4, -6, 4, -1], 1 << 59));
+        assert!(may_overflow(&[i64::MAX; 32], u64::MAX));
+        assert!(!may_overflow(&[], i64::MAX as u64));
+    }
+
+    #[test]
+    fn test_magnitude_bound() {
+        assert_eq!(magnitude_bound(&[1i16, -3]), 1 << 15);
+        assert_eq!(magnitude_bound(&[1i64, -3]), 3);
+        assert_eq!(magnitude_bound::<i64>(&[]), 0);
+    }
+
+    #[test]
+    fn test_residual() {
+        assert_eq!(residual(MAX_RESIDUAL), Some(i32::MAX));
+        assert_eq!(residual(-MAX_RESIDUAL), Some(-i32::MAX));
+        assert_eq!(residual(MAX_RESIDUAL + 1), None);
+        assert_eq!(residual(i32::MIN as i64), None);
+        assert_eq!(wide_residual(-7), Some(-7));
+        assert_eq!(wide_residual(i64::MAX as i128 + 1), None);
+    }
+
+    #[test]
+    fn test_residuals_fit() {
+        assert!(residuals_fit(&[i32::MAX, -i32::MAX, 0]));
+        assert!(!residuals_fit(&[0, i32::MIN]));
+    }
+}
@@ -1,20 +1,71 @@
-pub struct VarPredictor;
+use crate::flac::encoder::subframe::Subframe;
+use crate::flac::sample::Sample;
+
+use super::predictor::{self, Candidate, Predictor, PredictorParams, MAX_SAMPLE};
+use super::window::{Window, DEFAULT_WINDOW_SPEC};
+
+/// Smallest coefficient precision tried by the precision search
+pub const MIN_QLP_PRECISION: u8 = 5;
+/// Largest coefficient precision allowed by the 4-bit precision field
+pub const MAX_QLP_PRECISION: u8 = 15;
+/// Largest shift allowed by the 5-bit signed shift field
+pub const MAX_QLP_SHIFT: u8 = 15;
+
+/// Represents quantized LPC coefficients with their precision and shift
+///
+/// `precision` is the number of bits the coefficients were quantized for, which
+/// may be larger than the precision written to the subframe.
+#[derive(Debug, Clone, PartialEq)]
+pub struct QlpCoeffs {
+    pub coeffs: Vec <i64>,
+    pub precision: u8,
+    pub shift: u8,
+}
+
+/// Represents the LPC predictor and the options of its analysis
+pub struct VarPredictor {
+    windows: Vec <Window>,
+    qlp_precision_search: bool,
+}
 
 impl VarPredictor {
+    /// Create a builder to the LPC predictor
+    ///
+    /// The windows default to `DEFAULT_WINDOW_SPEC` and the precision search is
+    /// disabled.
+    pub fn new() -> Self {
+        VarPredictor {
+            windows: Window::parse_spec(DEFAULT_WINDOW_SPEC).unwrap_or_default(),
+            qlp_precision_search: false,
+        }
+    }
+
+    /// Set the apodization windows tried for the analysis
+    pub fn windows(mut self, windows: Vec <Window>) -> Self {
+        self.windows = windows;
+        self
+    }
+
+    /// Set whether every QLP coefficient precision is tried
+    pub fn qlp_precision_search(mut self, qlp_precision_search: bool) -> Self {
+        self.qlp_precision_search = qlp_precision_search;
+        self
+    }
+
     /// Get the autocorrelation of a vector of samples
     ///
     /// The function computes the autocorrelations of the provided vector of
     /// data from `R[0]` until `R[max_lag]`. For example, if `max_lag` is 2, then
     /// the output contains three elements corresponding to R[0] until R[3],
     /// respectively
-    pub fn get_autocorrelation(samples: &Vec <i64>, max_lag: u8) -> Vec <f64> {
+    pub fn get_autocorrelation <T: Sample> (samples: &[T], max_lag: u8) -> Vec <f64> {
         let max_lag = max_lag as usize;
         let mut autocorrelations = vec![0.0; max_lag + 1];
 
         for lag in 0..=max_lag {
             let mut sum = 0.0;
             for i in 0..(samples.len() - lag) {
-                sum += samples[i] as f64 * samples[i + lag] as f64;
+                sum += samples[i].to_i64() as f64 * samples[i + lag].to_i64() as f64;
             }
             autocorrelations[lag] = sum;
         }
@@ -28,12 +79,37 @@ impl VarPredictor {
     /// the autocorrelation value of lag `i - 1`. `predictor_order` should be
     /// less than `autoc.len()`. The coefficients are computed using the Levinson-Durbin
     /// algorithm.
-    pub fn get_predictor_coeffs(autoc: &Vec<f64>, predictor_order: u8) -> Vec<f64> {
-        let predictor_order = predictor_order as usize;
-        let mut coeffs = vec![0.0; predictor_order];
+    pub fn get_predictor_coeffs(autoc: &[f64], predictor_order: u8) -> Vec<f64> {
+        let mut coeffs = VarPredictor::build_predictor_coeffs(autoc, predictor_order)
+            .pop()
+            .unwrap_or_default();
+        coeffs.resize(predictor_order as usize, 0.0);
+
+        coeffs
+    }
+
+    /// Get a the list of LPC coefficients until some provided predictor order inclusive.
+    /// 
+    /// For the return value `lpc_list`, `lpc_list[i]` contains a `Vec` of coefficients
+    /// for predictor order `i + 1`. The Levinson-Durbin algorithm is used to progressively
+    /// compute the LPC coefficients across multiple predictor orders: a single pass of the
+    /// recursion up to `max_predictor_order` yields the solution of every lower order
+    /// along the way, so each order is only computed once.
+    /// 
+    /// The recursion stops early if the prediction error reaches zero, since higher
+    /// orders cannot improve on a perfect prediction. The returned list may thus be
+    /// shorter than `max_predictor_order`.
+    pub fn build_predictor_coeffs(autoc: &[f64], max_predictor_order: u8) -> Vec <Vec <f64>> {
+        let max_predictor_order = (max_predictor_order as usize).min(autoc.len().saturating_sub(1));
+        let mut lpc_list = Vec::with_capacity(max_predictor_order);
+        let mut coeffs = vec![0.0; max_predictor_order];
         let mut e = autoc[0];
 
-        for m in 0..predictor_order {
+        for m in 0..max_predictor_order {
+            if e <= 0.0 {
+                break;
+            }
+
             let mut lambda = autoc[m + 1];
             for i in 0..m {
                 lambda -= coeffs[i] * autoc[m - i];
@@ -41,25 +117,17 @@ impl VarPredictor {
             lambda /= e;
 
             coeffs[m] = lambda;
-            for i in 0..m {
-                coeffs[i] -= lambda * coeffs[m - i - 1];
+            for i in 0..m / 2 {
+                let (front, back) = (coeffs[i], coeffs[m - i - 1]);
+                coeffs[i] = front - lambda * back;
+                coeffs[m - i - 1] = back - lambda * front;
+            }
+            if m % 2 == 1 {
+                coeffs[m / 2] -= lambda * coeffs[m / 2];
             }
 
             e *= 1.0 - lambda * lambda;
-        }
-
-        coeffs
-    }
-    /// Get a the list of LPC coefficients until some provided predictor order inclusive.
-    /// 
-    /// For the return value `lpc_list`, `lpc_list[i]` contains a `Vec` of coefficients
-    /// for predictor order `i + 1`. The Levinson-Durbin algorithm is used to progressively
-    /// compute the LPC coefficients across multiple predictor orders.
-    pub fn build_predictor_coeffs(autoc: &Vec <f64>, max_predictor_order: u8) -> Vec <Vec <f64>> {
-        let mut lpc_list = Vec::new();
-
-        for order in 1..=max_predictor_order {
-            lpc_list.push(VarPredictor::get_predictor_coeffs(autoc, order));
+            lpc_list.push(coeffs[..=m].to_vec());
         }
 
         lpc_list
@@ -69,8 +137,9 @@ impl VarPredictor {
     /// 
     /// The shift factor `S` is computed from the maximum absolute value of a coefficient
     /// `L_max`. This value is computed as `precision - lg(L_max)` or to
-    /// the maximum shift value of 1 << 5 = 31, whichever is smaller. Note that it is
-    /// possible for this shift factor to be negative. In that case, the shift value
+    /// the maximum shift value of 15 allowed by the 5-bit signed field of the subframe
+    /// header, whichever is smaller. Note that it is possible for this shift factor to be
+    /// negative. Decoders do not support negative shifts, so in that case the shift value
     /// will still be used in quantizing the coefficients but its effective value
     /// will be zero.
     /// 
@@ -82,7 +151,7 @@ impl VarPredictor {
     /// Then, `L_i_r + \epsilon` is rounded away from zero to get the quantized coefficient.
     /// The new rounding error `\epsilon = L_i_r + \epsilon - round(L_i_r)` is then updated for the
     /// next coefficient.
-    pub fn quantize_coeffs(lpc_coefs: &Vec <f64>, mut precision: u8) -> (Vec <i64>, u8) {
+    pub fn quantize_coeffs(lpc_coefs: &[f64], precision: u8) -> (Vec <i64>, u8) {
         let mut l_max = 0.0;
         for &coef in lpc_coefs.iter() {
             if coef.abs() > l_max {
@@ -90,87 +159,246 @@ impl VarPredictor {
             }
         }
 
-        let shift = (precision as f64 - l_max.log2()) as i32;
-
-        precision = if shift > 31 { 31 } else { shift as u8 };
+        // Saturates at i32::MAX if all coefficients are zero
+        let shift = ((precision as f64 - l_max.log2()) as i32).min(MAX_QLP_SHIFT as i32);
+        let scale = if shift >= 0 {
+            (1u64 << shift) as f64
+        } else {
+            1.0 / (1u64 << shift.unsigned_abs().min(63)) as f64
+        };
 
         let mut quantized = Vec::new();
         let mut error = 0.0;
 
         for &coef in lpc_coefs.iter() {
-            let raw = coef * (1 << precision) as f64;
+            let raw = coef * scale;
             let quant = (raw + error).round();
             error = raw + error - quant;
             quantized.push(quant as i64);
         }
 
-        (quantized, precision)
+        (quantized, shift.max(0) as u8)
     }
 
     /// Compute the residuals from a given linear predictor
     /// 
     /// The resulting vector `residual[i]` corresponds to the `i + predictor_order`th
-    /// signal. The first `predictor_order` values of the residual are the "warm-up"
-    /// samples, or the unencoded samples, equivalent to `&samples[..predictor_order]`.
+    /// signal. The first `predictor_order` samples are the "warm-up" samples, or the
+    /// unencoded samples, which have no residual.
     /// 
     /// The residuals are computed with the `samples` reversed. For some `i`th residual,
     /// `residual[i] = data[i] - (sum(dot(qlp_coefs, samples[i..(i - predictor_order)])) >> qlp_shift)`.
-    pub fn get_residuals(samples: &Vec <i64>, qlp_coefs: &Vec <i64>, predictor_order: u8, qlp_shift: u8) -> Vec <i64> {
-        let mut residuals = Vec::new();
-        for i in 0..predictor_order as usize {
-            residuals.push(samples[i]);
+    /// The sum is computed in `i64`, or in `i128` if it may overflow an `i64`
+    /// for the largest sample.
+    ///
+    /// # Errors
+    /// `None` is returned if a residual does not fit in a subframe.
+    pub fn get_residuals <T: Sample> (samples: &[T], qlp_coefs: &[i64], predictor_order: u8, qlp_shift: u8) -> Option <Vec <i32>> {
+        let mut residuals = Vec::with_capacity(samples.len() - predictor_order as usize);
+
+        let coefs = &qlp_coefs[..predictor_order as usize];
+        if predictor::may_overflow(coefs, predictor::magnitude_bound(samples)) {
+            for i in predictor_order as usize..samples.len() {
+                let prediction: i128 = coefs.iter().enumerate().map(|(j, &coef)| coef as i128 * samples[i - j - 1].to_i64() as i128).sum();
+                residuals.push(predictor::wide_residual(samples[i].to_i64() as i128 - (prediction >> qlp_shift))?);
+            }
+            return Some(residuals);
         }
 
         for i in predictor_order as usize..samples.len() {
             let mut prediction = 0;
             for j in 0..predictor_order as usize {
-                prediction += qlp_coefs[j] * samples[i - j - 1];
+                prediction += qlp_coefs[j] * samples[i - j - 1].to_i64();
+            }
+            residuals.push(predictor::residual(samples[i].to_i64() - (prediction >> qlp_shift))?);
+        }
+
+        Some(residuals)
+    }
+
+    /// Restore the samples from the warm-up samples and residuals of a linear predictor
+    ///
+    /// This undoes `get_residuals`, with the predictor order given by the number
+    /// of warm-up samples. The sum is computed in `i128` if it may overflow an
+    /// `i64` for samples up to `MAX_SAMPLE` in magnitude, and with checked
+    /// arithmetic either way, since the residuals of a corrupt stream may
+    /// overflow it.
+    ///
+    /// # Errors
+    /// `None` is returned if there are not as many coefficients as warm-up
+    /// samples, or if a restored sample overflows an `i64`.
+    pub fn restore_samples <T: Sample> (warmup: &[T], residuals: &[i32], qlp_coefs: &[i64], qlp_shift: u8) -> Option <Vec <i64>> {
+        let order = warmup.len();
+        if qlp_coefs.len() != order {
+            return None;
+        }
+        let mut samples = Vec::with_capacity(order + residuals.len());
+        samples.extend(warmup.iter().map(|&sample| sample.to_i64()));
+
+        let coefs = qlp_coefs;
+        if predictor::may_overflow(coefs, MAX_SAMPLE) {
+            for &residual in residuals {
+                let i = samples.len();
+                // Each product fits in an i128, but the sum of 32 of them may not
+                let prediction = coefs.iter().enumerate()
+                    .try_fold(0i128, |sum, (j, &coef)| sum.checked_add(coef as i128 * samples[i - j - 1] as i128))?;
+                samples.push(i64::try_from(residual as i128 + (prediction >> qlp_shift)).ok()?);
             }
-            residuals.push(samples[i] - (prediction >> qlp_shift));
+            return Some(samples);
         }
 
-        residuals
+        for &residual in residuals {
+            let i = samples.len();
+            let mut prediction: i64 = 0;
+            for j in 0..order {
+                prediction = prediction.checked_add(coefs[j].checked_mul(samples[i - j - 1])?)?;
+            }
+            samples.push((prediction >> qlp_shift).checked_add(residual as i64)?);
+        }
+
+        Some(samples)
     }
 
     /// compute the quantized LPC coefficients, precision, and shift for the given
     /// predictor order
-    pub fn get_predictor_coeffs_from_samples(samples: &Vec <i64>, predictor_order: u8, bps: u8, block_size: u64) -> (Vec <i64>, u8, u8) {
+    pub fn get_predictor_coeffs_from_samples <T: Sample> (samples: &[T], predictor_order: u8, bps: u8, block_size: u64) -> QlpCoeffs {
         let autoc = VarPredictor::get_autocorrelation(samples, predictor_order);
         let lpc_coefs = VarPredictor::get_predictor_coeffs(&autoc, predictor_order);
         let precision = VarPredictor::get_best_precision(bps, block_size);
         let (quantized, shift) = VarPredictor::quantize_coeffs(&lpc_coefs, precision);
-        (quantized, precision, shift)
+        QlpCoeffs { coeffs: quantized, precision, shift }
     }
 
     /// Get the quantized LPC coefficients, precision, and shift for the best predictor order
     /// for the given sample
     /// 
     /// This function selects the best predictor order by finding the order that yields the
-    /// absolute minimum sum of residuals. Note that the maximmum predictor order is 32.
-    pub fn get_best_lpc(samples: &Vec <i64>, bps: u8, block_size: u64) -> (Vec <i64>, u8, u8) {
-        let max_order = 32.min(samples.len() as u8);
-        let mut best_coeffs = Vec::new();
-        let mut best_precision = 0;
-        let mut best_shift = 0;
-        let mut min_residual_sum = i64::MAX;
-
-        for order in 1..=max_order {
-            let (coeffs, precision, shift) = VarPredictor::get_predictor_coeffs_from_samples(samples, order, bps, block_size);
-            let residuals = VarPredictor::get_residuals(samples, &coeffs, order, shift);
-            let mut residual_sum = 0;
-            for &residual in &residuals {
-                residual_sum += residual.abs();
+    /// smallest estimated subframe. Note that the maximmum predictor order is 32.
+    /// The autocorrelation is computed once up to the maximum lag and the coefficients of
+    /// every order are taken from a single Levinson-Durbin pass.
+    pub fn get_best_lpc <T: Sample> (samples: &[T], bps: u8, block_size: u64) -> QlpCoeffs {
+        VarPredictor::get_best_lpc_windowed(samples, bps, block_size, &[Window::Rectangle])
+    }
+
+    /// Get the autocorrelation of a vector of samples after applying an apodization window
+    ///
+    /// The samples are multiplied by `window` before computing `R[0]` until `R[max_lag]`.
+    /// A rectangle window gives the same result as `get_autocorrelation`.
+    pub fn get_windowed_autocorrelation <T: Sample> (samples: &[T], window: &Window, max_lag: u8) -> Vec <f64> {
+        let windowed = window.apply(samples);
+        let max_lag = (max_lag as usize).min(windowed.len().saturating_sub(1));
+        let mut autocorrelations = vec![0.0; max_lag + 1];
+
+        for (lag, autoc) in autocorrelations.iter_mut().enumerate() {
+            *autoc = windowed[..windowed.len() - lag].iter()
+                .zip(&windowed[lag..])
+                .map(|(a, b)| a * b)
+                .sum();
+        }
+
+        autocorrelations
+    }
+
+    /// Get the unquantized LPC coefficients of every window and predictor order
+    ///
+    /// Each window in `windows` is applied to the samples before the analysis and every
+    /// predictor order up to 32 is computed for it. Degenerate solutions are left out.
+    fn get_windowed_coeffs <T: Sample> (samples: &[T], windows: &[Window]) -> Vec <Vec <f64>> {
+        let max_order = samples.len().min(32) as u8;
+        let mut lpc_list = Vec::new();
+
+        for window in windows {
+            let autoc = VarPredictor::get_windowed_autocorrelation(samples, window, max_order);
+            // A window can zero out the whole block, leaving nothing to analyze
+            if autoc[0] == 0.0 {
+                continue;
             }
 
-            if residual_sum < min_residual_sum {
-                min_residual_sum = residual_sum;
-                best_coeffs = coeffs;
-                best_precision = precision;
-                best_shift = shift;
+            lpc_list.extend(VarPredictor::build_predictor_coeffs(&autoc, max_order)
+                .into_iter()
+                .filter(|lpc_coefs| lpc_coefs.iter().all(|coef| coef.is_finite())));
+        }
+
+        lpc_list
+    }
+
+    /// Get the quantized LPC coefficients, precision, and shift of every window and
+    /// predictor order for the given sample
+    ///
+    /// Each window in `windows` is applied to the samples before the analysis and every
+    /// predictor order up to 32 is quantized for it using the precision from
+    /// `get_best_precision`.
+    pub fn get_lpc_candidates <T: Sample> (samples: &[T], bps: u8, block_size: u64, windows: &[Window]) -> Vec <QlpCoeffs> {
+        let precision = VarPredictor::get_best_precision(bps, block_size);
+
+        VarPredictor::get_windowed_coeffs(samples, windows).iter()
+            .map(|lpc_coefs| {
+                let (coeffs, shift) = VarPredictor::quantize_coeffs(lpc_coefs, precision);
+                QlpCoeffs { coeffs, precision, shift }
+            })
+            .collect()
+    }
+
+    /// Get the quantized LPC coefficients, precision, and shift of every window, predictor
+    /// order and coefficient precision for the given sample
+    ///
+    /// Like `get_lpc_candidates`, but every coefficient precision from `MIN_QLP_PRECISION`
+    /// until `MAX_QLP_PRECISION` bits is tried instead of the one from `get_best_precision`.
+    /// The coefficients of a candidate are quantized so that they fit in `precision` signed bits.
+    pub fn get_lpc_candidates_precision_search <T: Sample> (samples: &[T], windows: &[Window]) -> Vec <QlpCoeffs> {
+        let mut candidates = Vec::new();
+
+        for lpc_coefs in VarPredictor::get_windowed_coeffs(samples, windows) {
+            for precision in MIN_QLP_PRECISION..=MAX_QLP_PRECISION {
+                let (coeffs, shift) = VarPredictor::quantize_coeffs(&lpc_coefs, precision - 1);
+                candidates.push(QlpCoeffs { coeffs, precision, shift });
             }
         }
 
-        (best_coeffs, best_precision, best_shift)
+        candidates
+    }
+
+    /// Get the candidate that yields the smallest estimated subframe
+    ///
+    /// The residuals are always computed from the raw samples. Candidates that do not fit
+    /// in an LPC subframe are skipped, and an empty set of coefficients is returned if
+    /// none is left.
+    fn get_best_candidate <T: Sample> (samples: &[T], bps: u8, candidates: Vec <QlpCoeffs>) -> QlpCoeffs {
+        let mut best = QlpCoeffs { coeffs: Vec::new(), precision: 0, shift: 0 };
+        let mut min_bits = u64::MAX;
+
+        for candidate in candidates {
+            let bits = match Subframe::lpc(samples, candidate.coeffs.clone(), candidate.shift) {
+                Some(subframe) => subframe.estimate_bits(bps),
+                None => continue,
+            };
+
+            if bits < min_bits {
+                min_bits = bits;
+                best = candidate;
+            }
+        }
+
+        best
+    }
+
+    /// Get the quantized LPC coefficients, precision, and shift for the best window and
+    /// predictor order for the given sample
+    ///
+    /// The candidates of `get_lpc_candidates` are compared by their estimated subframe size.
+    pub fn get_best_lpc_windowed <T: Sample> (samples: &[T], bps: u8, block_size: u64, windows: &[Window]) -> QlpCoeffs {
+        let candidates = VarPredictor::get_lpc_candidates(samples, bps, block_size, windows);
+        VarPredictor::get_best_candidate(samples, bps, candidates)
+    }
+
+    /// Get the quantized LPC coefficients, precision, and shift for the best window,
+    /// predictor order and coefficient precision for the given sample
+    ///
+    /// The candidates of `get_lpc_candidates_precision_search` are compared by their
+    /// estimated subframe size.
+    pub fn get_best_lpc_precision_search <T: Sample> (samples: &[T], bps: u8, windows: &[Window]) -> QlpCoeffs {
+        let candidates = VarPredictor::get_lpc_candidates_precision_search(samples, windows);
+        VarPredictor::get_best_candidate(samples, bps, candidates)
     }
 
     /// Get the best coefficient precision
@@ -211,6 +439,55 @@ impl VarPredictor {
     }
 }
 
+impl Default for VarPredictor {
+    fn default() -> Self {
+        VarPredictor::new()
+    }
+}
+
+impl <T: Sample> Predictor <T> for VarPredictor {
+    /// Get the subframes of every window, predictor order and, with the precision
+    /// search, coefficient precision
+    ///
+    /// Candidates that do not fit in an LPC subframe are left out.
+    fn candidates(&self, samples: &[T], bps: u8) -> Vec <Subframe <T>> {
+        let candidates = if self.qlp_precision_search {
+            VarPredictor::get_lpc_candidates_precision_search(samples, &self.windows)
+        } else {
+            VarPredictor::get_lpc_candidates(samples, bps, samples.len() as u64, &self.windows)
+        };
+        candidates.into_iter()
+            .filter_map(|candidate| Subframe::lpc(samples, candidate.coeffs, candidate.shift))
+            .collect()
+    }
+
+    /// Get the candidate with the smallest estimated cost without keeping every candidate
+    fn analyze(&self, samples: &[T], bps: u8) -> Option <Candidate <T>> {
+        let best = if self.qlp_precision_search {
+            VarPredictor::get_best_lpc_precision_search(samples, bps, &self.windows)
+        } else {
+            VarPredictor::get_best_lpc_windowed(samples, bps, samples.len() as u64, &self.windows)
+        };
+        Subframe::lpc(samples, best.coeffs, best.shift).map(|subframe| Candidate { cost: <Self as Predictor <T>>::cost_estimate(self, &subframe, bps), subframe })
+    }
+
+    fn residuals(&self, samples: &[T], params: &PredictorParams) -> Option <Vec <i32>> {
+        match params {
+            PredictorParams::Lpc(qlp_coeffs) if qlp_coeffs.coeffs.len() <= samples.len() => {
+                VarPredictor::get_residuals(samples, &qlp_coeffs.coeffs, qlp_coeffs.coeffs.len() as u8, qlp_coeffs.shift)
+            }
+            _ => None,
+        }
+    }
+
+    fn restore(&self, warmup: &[T], residuals: &[i32], params: &PredictorParams) -> Option <Vec <i64>> {
+        match params {
+            PredictorParams::Lpc(qlp_coeffs) => VarPredictor::restore_samples(warmup, residuals, &qlp_coeffs.coeffs, qlp_coeffs.shift),
+            _ => None,
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -237,6 +514,31 @@ mod tests {
         assert_eq!(coeffs, vec![vec![0.7272727272727273], vec![0.8140350877192983, -0.1192982456140352]]);
     }
 
+    #[test]
+    fn test_build_predictor_coeffs_solves_normal_equations() {
+        let samples: Vec <i64> = (0..256).map(|i| ((i as f64 * 0.05).sin() * 3000.0 + (i as f64 * 0.31).cos() * 500.0) as i64).collect();
+        let autoc = VarPredictor::get_autocorrelation(&samples, 8);
+        let lpc_list = VarPredictor::build_predictor_coeffs(&autoc, 8);
+        assert_eq!(lpc_list.len(), 8);
+
+        // Every order must satisfy sum_j(a_j * R[|i - j|]) = R[i + 1]
+        for coeffs in &lpc_list {
+            for i in 0..coeffs.len() {
+                let lhs: f64 = coeffs.iter().enumerate().map(|(j, a)| a * autoc[i.abs_diff(j)]).sum();
+                assert!((lhs - autoc[i + 1]).abs() < 1e-6 * autoc[0]);
+            }
+        }
+        assert_eq!(VarPredictor::get_predictor_coeffs(&autoc, 5), lpc_list[4]);
+    }
+
+    #[test]
+    fn test_build_predictor_coeffs_stops_on_perfect_prediction() {
+        let autoc = vec![4.0, 4.0, 4.0, 4.0];
+        let lpc_list = VarPredictor::build_predictor_coeffs(&autoc, 3);
+        assert_eq!(lpc_list, vec![vec![1.0]]);
+        assert_eq!(VarPredictor::get_predictor_coeffs(&autoc, 3), vec![1.0, 0.0, 0.0]);
+    }
+
     #[test]
     fn test_quantize_coeffs() {
         let lpc_coefs = vec![0.8140350877192983, -0.1192982456140352];
@@ -246,6 +548,55 @@ mod tests {
         assert_eq!(shift, 7);
     }
 
+    #[test]
+    fn test_quantize_coeffs_negative_shift() {
+        // 40 needs a shift of 3 - lg(40) < 0, so the coefficients are divided instead
+        let lpc_coefs = vec![40.0, -20.0];
+        let (quantized, shift) = VarPredictor::quantize_coeffs(&lpc_coefs, 3);
+        assert_eq!(shift, 0);
+        assert_eq!(quantized, vec![10, -5]);
+    }
+
+    #[test]
+    fn test_quantize_coeffs_max_shift() {
+        let lpc_coefs = vec![0.0001, 0.0];
+        let (quantized, shift) = VarPredictor::quantize_coeffs(&lpc_coefs, 14);
+        assert_eq!(shift, MAX_QLP_SHIFT);
+        assert_eq!(quantized, vec![3, 0]);
+
+        let (quantized, shift) = VarPredictor::quantize_coeffs(&[0.0, 0.0], 14);
+        assert_eq!(shift, MAX_QLP_SHIFT);
+        assert_eq!(quantized, vec![0, 0]);
+    }
+
+    #[test]
+    fn test_get_lpc_candidates_precision_search() {
+        let samples: Vec <i64> = (0..64).map(|i| ((i as f64 * 0.2).sin() * 1000.0) as i64).collect();
+        let candidates = VarPredictor::get_lpc_candidates_precision_search(&samples, &[Window::Rectangle]);
+        let n_precisions = (MAX_QLP_PRECISION - MIN_QLP_PRECISION + 1) as usize;
+        assert_eq!(candidates.len() % n_precisions, 0);
+
+        for QlpCoeffs { coeffs, precision, shift } in candidates {
+            assert!((MIN_QLP_PRECISION..=MAX_QLP_PRECISION).contains(&precision));
+            assert!(shift <= MAX_QLP_SHIFT);
+            // Each coefficient fits in `precision` signed bits, give or take the rounding
+            assert!(coeffs.iter().all(|&coeff| coeff.abs() <= 1 << (precision - 1)));
+        }
+    }
+
+    #[test]
+    fn test_get_best_lpc_precision_search() {
+        let samples: Vec <i64> = (0..256).map(|i| ((i as f64 * 0.05).sin() * 20000.0) as i64).collect();
+        let windows = [Window::Tukey(0.5)];
+        let QlpCoeffs { coeffs, precision, shift } = VarPredictor::get_best_lpc_precision_search(&samples, 16, &windows);
+        let searched = Subframe::lpc(&samples, coeffs, shift).unwrap();
+        assert!((MIN_QLP_PRECISION..=MAX_QLP_PRECISION).contains(&precision));
+
+        let QlpCoeffs { coeffs, shift, .. } = VarPredictor::get_best_lpc_windowed(&samples, 16, 256, &windows);
+        let default = Subframe::lpc(&samples, coeffs, shift).unwrap();
+        assert!(searched.estimate_bits(16) <= default.estimate_bits(16));
+    }
+
     #[test]
     fn test_get_residuals() {
         let samples = vec![1, 2, 3, 4, 5];
@@ -253,7 +604,66 @@ mod tests {
         let predictor_order = 2;
         let qlp_shift = 7;
         let residuals = VarPredictor::get_residuals(&samples, &qlp_coefs, predictor_order, qlp_shift);
-        assert_eq!(residuals, vec![1, 2, 2, 2, 3]);
+        assert_eq!(residuals, Some(vec![2, 2, 3]));
+    }
+
+    #[test]
+    fn test_restore_samples() {
+        let samples = vec![1, 2, 3, 4, 5];
+        let residuals = VarPredictor::get_residuals(&samples, &[104, -15], 2, 7).unwrap();
+        assert_eq!(VarPredictor::restore_samples(&samples[..2], &residuals, &[104, -15], 7), Some(samples.clone()));
+        assert_eq!(VarPredictor::restore_samples(&samples[..1], &residuals, &[104, -15], 7), None);
+        assert_eq!(VarPredictor::restore_samples(&[0, i64::MAX], &[i32::MAX], &[256, 0], 7), None);
+    }
+
+    #[test]
+    fn test_get_residuals_wide() {
+        // Order 32 with 15-bit coefficients on samples well beyond 32 bits, which
+        // add up to one so that the residuals of a slow ramp stay small
+        let samples: Vec <i64> = (0..64).map(|i| (1 << 48) - i * (1 << 20)).collect();
+        let qlp_coefs: Vec <i64> = (0..32).map(|j| match j { 31 => 1, _ if j % 2 == 0 => 16383, _ => -16383 }).collect();
+        assert!(predictor::may_overflow(&qlp_coefs, predictor::magnitude_bound(&samples)));
+        let residuals = VarPredictor::get_residuals(&samples, &qlp_coefs, 32, 14).unwrap();
+
+        assert_eq!(residuals.len(), 32);
+        for i in 32..samples.len() {
+            let prediction: i128 = (0..32).map(|j| qlp_coefs[j] as i128 * samples[i - j - 1] as i128).sum();
+            assert_eq!(residuals[i - 32] as i128, samples[i] as i128 - (prediction >> 14));
+        }
+        assert_eq!(VarPredictor::restore_samples(&samples[..32], &residuals, &qlp_coefs, 14), Some(samples.clone()));
+
+        // The residuals of an unrelated predictor do not fit in a subframe
+        assert_eq!(VarPredictor::get_residuals(&samples, &[16383; 32], 32, 14), None);
+    }
+
+    #[test]
+    fn test_restore_samples_wide() {
+        // Coefficients that may overflow an i64 for samples up to MAX_SAMPLE,
+        // though not for these samples
+        let samples: Vec <i64> = (0..40).map(|i| (i * 7919 % 2001) - 1000).collect();
+        let qlp_coefs = vec![(1 << 40) + 1, -(1 << 40)];
+        let residuals = VarPredictor::get_residuals(&samples, &qlp_coefs, 2, 40).unwrap();
+        assert!(predictor::may_overflow(&qlp_coefs, MAX_SAMPLE));
+        assert_eq!(VarPredictor::restore_samples(&samples[..2], &residuals, &qlp_coefs, 40), Some(samples.clone()));
+        assert_eq!(VarPredictor::restore_samples(&[i64::MAX, i64::MAX], &[0], &[1 << 40, 1 << 40], 40), None);
+    }
+
+    #[test]
+    fn test_predictor() {
+        let samples: Vec <i64> = (0..256).map(|i| ((i as f64 * 0.05).sin() * 20000.0) as i64).collect();
+        let predictor = VarPredictor::new().windows(vec![Window::Tukey(0.5)]);
+
+        let candidate = predictor.analyze(&samples, 16).unwrap();
+        let QlpCoeffs { coeffs, shift, .. } = VarPredictor::get_best_lpc_windowed(&samples, 16, 256, &[Window::Tukey(0.5)]);
+        assert_eq!(candidate.subframe, Subframe::lpc(&samples, coeffs, shift).unwrap());
+        assert!(predictor.candidates(&samples, 16).contains(&candidate.subframe));
+
+        let Subframe::Lpc { qlp_coeffs, precision, shift, .. } = candidate.subframe else { panic!("not an LPC subframe") };
+        let params = PredictorParams::Lpc(QlpCoeffs { coeffs: qlp_coeffs, precision, shift });
+        let residuals = predictor.residuals(&samples, &params).unwrap();
+        let warmup = &samples[..samples.len() - residuals.len()];
+        assert_eq!(predictor.restore(warmup, &residuals, &params), Some(samples.clone()));
+        assert_eq!(predictor.restore(warmup, &residuals, &PredictorParams::Fixed { order: 2 }), None);
     }
 
     #[test]
@@ -262,7 +672,7 @@ mod tests {
         let predictor_order = 2;
         let bps = 16;
         let block_size = 192;
-        let (coeffs, precision, shift) = VarPredictor::get_predictor_coeffs_from_samples(&samples, predictor_order, bps, block_size);
+        let QlpCoeffs { coeffs, precision, shift } = VarPredictor::get_predictor_coeffs_from_samples(&samples, predictor_order, bps, block_size);
         assert_eq!(coeffs, vec![104, -15]);
         assert_eq!(precision, 7);
         assert_eq!(shift, 7);
@@ -273,12 +683,53 @@ mod tests {
         let samples = vec![1, 2, 3, 4, 5];
         let bps = 16;
         let block_size = 192;
-        let (coeffs, precision, shift) = VarPredictor::get_best_lpc(&samples, bps, block_size);
+        let QlpCoeffs { coeffs, precision, shift } = VarPredictor::get_best_lpc(&samples, bps, block_size);
+        assert_eq!(coeffs, vec![93]);
+        assert_eq!(precision, 7);
+        assert_eq!(shift, 7);
+    }
+
+    #[test]
+    fn test_get_windowed_autocorrelation_rectangle() {
+        let samples = vec![1, 2, 3, 4, 5];
+        let autoc = VarPredictor::get_windowed_autocorrelation(&samples, &Window::Rectangle, 2);
+        assert_eq!(autoc, VarPredictor::get_autocorrelation(&samples, 2));
+    }
+
+    #[test]
+    fn test_get_windowed_autocorrelation_bartlett() {
+        let samples = vec![10, 20, 30, 20, 10];
+        let autoc = VarPredictor::get_windowed_autocorrelation(&samples, &Window::Bartlett, 1);
+        assert_eq!(autoc, vec![1100.0, 600.0]);
+    }
+
+    #[test]
+    fn test_get_best_lpc_windowed() {
+        let samples = vec![1, 2, 3, 4, 5];
+        let bps = 16;
+        let block_size = 192;
+        let windows = vec![Window::Rectangle];
+        let QlpCoeffs { coeffs, precision, shift } = VarPredictor::get_best_lpc_windowed(&samples, bps, block_size, &windows);
         assert_eq!(coeffs, vec![93]);
         assert_eq!(precision, 7);
         assert_eq!(shift, 7);
     }
 
+    #[test]
+    fn test_get_best_lpc_windowed_keeps_cheapest_window() {
+        let samples: Vec <i64> = (0..64).map(|i| ((i as f64 * 0.3).sin() * 1000.0) as i64).collect();
+        let windows = Window::parse_spec("rectangle;tukey(0.5);hann").unwrap();
+        let estimated_bits = |window: &[Window]| {
+            let QlpCoeffs { coeffs, shift, .. } = VarPredictor::get_best_lpc_windowed(&samples, 16, 64, window);
+            Subframe::lpc(&samples, coeffs, shift).unwrap().estimate_bits(16)
+        };
+
+        let best = estimated_bits(&windows);
+        for window in &windows {
+            assert!(best <= estimated_bits(std::slice::from_ref(window)));
+        }
+    }
+
     #[test]
     fn test_get_best_precision() {
         assert_eq!(VarPredictor::get_best_precision(15, 100), 9);
@@ -287,4 +738,42 @@ mod tests {
         assert_eq!(VarPredictor::get_best_precision(16, 4608), 12);
         assert_eq!(VarPredictor::get_best_precision(17, 100), 14);
     }
+
+    /// Get the best LPC coefficients like `get_best_lpc`, but recompute the
+    /// autocorrelation and the Levinson-Durbin recursion for every order
+    fn get_best_lpc_per_order(samples: &[i64], bps: u8) -> QlpCoeffs {
+        let candidates = (1..=samples.len().min(32) as u8)
+            .map(|order| VarPredictor::get_predictor_coeffs_from_samples(samples, order, bps, samples.len() as u64))
+            .collect();
+        VarPredictor::get_best_candidate(samples, bps, candidates)
+    }
+
+    // Run with `cargo test --release -- --ignored --nocapture bench_`
+    #[test]
+    #[ignore]
+    fn bench_get_best_lpc_sample_audio() {
+        use std::time::{Duration, Instant};
+        use crate::cli::read_channels;
+        use crate::wav::WaveReader;
+
+        for name in ["music_3s.wav", "music_b.wav", "music_c.wav", "music_c_mono.wav"] {
+            let path = format!("{}/sample_audio/{}", env!("CARGO_MANIFEST_DIR"), name);
+            let wave_info = WaveReader::open_pcm(&path).unwrap();
+            let bps = wave_info.fmt_header.bps as u8;
+            let channels: Vec <Vec <i64>> = read_channels(wave_info);
+
+            let (mut per_order, mut shared) = (Duration::ZERO, Duration::ZERO);
+            for block in channels.iter().flat_map(|channel| channel.chunks(4096)) {
+                let start = Instant::now();
+                std::hint::black_box(get_best_lpc_per_order(block, bps));
+                per_order += start.elapsed();
+
+                let start = Instant::now();
+                std::hint::black_box(VarPredictor::get_best_lpc(block, bps, block.len() as u64));
+                shared += start.elapsed();
+            }
+            println!("{}: per order {:.1} ms, shared {:.1} ms, {:.2}x",
+                name, per_order.as_secs_f64() * 1e3, shared.as_secs_f64() * 1e3, per_order.as_secs_f64() / shared.as_secs_f64());
+        }
+    }
 }
\ No newline at end of file
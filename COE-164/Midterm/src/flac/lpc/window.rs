@@ -0,0 +1,321 @@
+use core::fmt;
+use std::error;
+use std::f64::consts::PI;
+
+use crate::flac::sample::Sample;
+
+/// Default apodization used when no window specification is given
+pub const DEFAULT_WINDOW_SPEC: &str = "tukey(0.5)";
+
+/// Represents an apodization window applied before LPC analysis
+///
+/// Windowing tapers the edges of a block so that the autocorrelation is not
+/// dominated by the discontinuity at the block boundaries. The variants and
+/// their shapes follow the ones offered by the reference FLAC encoder.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Window {
+    Rectangle,
+    Welch,
+    Hann,
+    Bartlett,
+    Tukey(f64),
+    /// Tukey window covering only `start..end` of the block (as fractions of
+    /// the block length) and zero elsewhere
+    PartialTukey { p: f64, start: f64, end: f64 },
+    /// Tukey window over the whole block with `start..end` zeroed out
+    PunchoutTukey { p: f64, start: f64, end: f64 },
+    Gauss(f64),
+}
+
+// Represents possible errors while parsing a window specification
+#[derive(Debug)]
+#[derive(PartialEq)]
+pub enum WindowSpecError {
+    EmptySpecError,
+    UnknownWindowError,
+    ParameterError,
+}
+
+impl Window {
+    /// Parse a window specification string
+    ///
+    /// The specification is a list of windows separated by `;`, for example
+    /// `"tukey(0.5);partial_tukey(2);punchout_tukey(3)"`. `partial_tukey(n)` and
+    /// `punchout_tukey(n)` expand into `n` windows, one for each part of the
+    /// block, so the returned list may be longer than the number of entries.
+    pub fn parse_spec(spec: &str) -> Result <Vec <Window>, WindowSpecError> {
+        let mut windows = Vec::new();
+
+        for entry in spec.split(';').map(str::trim).filter(|entry| !entry.is_empty()) {
+            let (name, args) = match entry.find('(') {
+                Some(open) => {
+                    if !entry.ends_with(')') {
+                        return Err(WindowSpecError::ParameterError);
+                    }
+                    (&entry[..open], Some(&entry[open + 1..entry.len() - 1]))
+                }
+                None => (entry, None),
+            };
+
+            match (name, args) {
+                ("rectangle", None) => windows.push(Window::Rectangle),
+                ("welch", None) => windows.push(Window::Welch),
+                ("hann", None) => windows.push(Window::Hann),
+                ("bartlett", None) => windows.push(Window::Bartlett),
+                ("tukey", Some(args)) => {
+                    let p = Window::parse_param(args)?;
+                    if !(0.0..=1.0).contains(&p) {
+                        return Err(WindowSpecError::ParameterError);
+                    }
+                    windows.push(Window::Tukey(p));
+                }
+                ("gauss", Some(args)) => {
+                    let stddev = Window::parse_param(args)?;
+                    if !(stddev > 0.0 && stddev <= 0.5) {
+                        return Err(WindowSpecError::ParameterError);
+                    }
+                    windows.push(Window::Gauss(stddev));
+                }
+                ("partial_tukey", Some(args)) => {
+                    for (p, start, end) in Window::parse_parts(args, 0.1)? {
+                        windows.push(Window::PartialTukey { p, start, end });
+                    }
+                }
+                ("punchout_tukey", Some(args)) => {
+                    for (p, start, end) in Window::parse_parts(args, 0.2)? {
+                        windows.push(Window::PunchoutTukey { p, start, end });
+                    }
+                }
+                ("rectangle" | "welch" | "hann" | "bartlett", Some(_)) |
+                ("tukey" | "gauss" | "partial_tukey" | "punchout_tukey", None) => {
+                    return Err(WindowSpecError::ParameterError);
+                }
+                _ => return Err(WindowSpecError::UnknownWindowError),
+            }
+        }
+
+        if windows.is_empty() {
+            return Err(WindowSpecError::EmptySpecError);
+        }
+        Ok(windows)
+    }
+
+    fn parse_param(arg: &str) -> Result <f64, WindowSpecError> {
+        arg.trim().parse::<f64>().map_err(|_| WindowSpecError::ParameterError)
+    }
+
+    /// Split the arguments of `partial_tukey(n[/ov[/p]])` or `punchout_tukey(n[/ov[/p]])`
+    /// into the Tukey parameter and the bounds of each of the `n` parts
+    ///
+    /// Consecutive parts overlap by a fraction `ov` of their length. If `n` is 1,
+    /// the single part spans the whole block.
+    fn parse_parts(args: &str, default_overlap: f64) -> Result <Vec <(f64, f64, f64)>, WindowSpecError> {
+        let mut fields = args.split('/');
+        let n_parts = fields.next()
+            .and_then(|field| field.trim().parse::<u32>().ok())
+            .ok_or(WindowSpecError::ParameterError)?;
+        let overlap = match fields.next() {
+            Some(field) => Window::parse_param(field)?.min(0.99),
+            None => default_overlap,
+        };
+        let p = match fields.next() {
+            Some(field) => Window::parse_param(field)?,
+            None => 0.2,
+        };
+        if n_parts == 0 || overlap < 0.0 || !(0.0..=1.0).contains(&p) || fields.next().is_some() {
+            return Err(WindowSpecError::ParameterError);
+        }
+
+        let overlap_units = 1.0 / (1.0 - overlap) - 1.0;
+        let total = n_parts as f64 + overlap_units;
+        let mut parts = Vec::new();
+        for m in 0..n_parts {
+            let start = m as f64 / total;
+            let end = (m as f64 + 1.0 + overlap_units) / total;
+            parts.push((p, start, end.min(1.0)));
+        }
+        Ok(parts)
+    }
+
+    /// Compute the window coefficients for a block of `len` samples
+    pub fn build(&self, len: usize) -> Vec <f64> {
+        if len <= 1 {
+            return vec![1.0; len];
+        }
+
+        let n_last = (len - 1) as f64;
+        match *self {
+            Window::Rectangle => vec![1.0; len],
+            Window::Welch => {
+                let half = n_last / 2.0;
+                (0..len).map(|n| 1.0 - ((n as f64 - half) / half).powi(2)).collect()
+            }
+            Window::Hann => {
+                (0..len).map(|n| 0.5 - 0.5 * (2.0 * PI * n as f64 / n_last).cos()).collect()
+            }
+            Window::Bartlett => {
+                (0..len).map(|n| {
+                    let x = 2.0 * n as f64 / n_last;
+                    if x <= 1.0 { x } else { 2.0 - x }
+                }).collect()
+            }
+            Window::Tukey(p) => Window::tukey(len, p),
+            Window::PartialTukey { p, start, end } => {
+                let start_n = ((start * len as f64) as usize).min(len);
+                let end_n = ((end * len as f64) as usize).min(len);
+                let mut window = vec![0.0; len];
+                let part = Window::tukey(end_n - start_n, p);
+                window[start_n..end_n].copy_from_slice(&part);
+                window
+            }
+            Window::PunchoutTukey { p, start, end } => {
+                let start_n = ((start * len as f64) as usize).min(len);
+                let end_n = ((end * len as f64) as usize).min(len);
+                let mut window = vec![0.0; len];
+                window[..start_n].copy_from_slice(&Window::tukey(start_n, p));
+                window[end_n..].copy_from_slice(&Window::tukey(len - end_n, p));
+                window
+            }
+            Window::Gauss(stddev) => {
+                let half = n_last / 2.0;
+                (0..len).map(|n| {
+                    let k = (n as f64 - half) / (stddev * half);
+                    (-0.5 * k * k).exp()
+                }).collect()
+            }
+        }
+    }
+
+    /// Tukey window of length `len`
+    ///
+    /// A fraction `p` of the window is tapered with a cosine, half on each
+    /// side, and the rest is flat. `p = 0` is a rectangle and `p = 1` is a Hann window.
+    fn tukey(len: usize, p: f64) -> Vec <f64> {
+        let mut window = vec![1.0; len];
+        let taper = ((p.clamp(0.0, 1.0) / 2.0) * len as f64) as usize;
+
+        for i in 0..taper.min(len / 2) {
+            let w = 0.5 - 0.5 * (PI * (i + 1) as f64 / (taper + 1) as f64).cos();
+            window[i] = w;
+            window[len - i - 1] = w;
+        }
+        window
+    }
+
+    /// Multiply `samples` element-wise by this window
+    pub fn apply <T: Sample> (&self, samples: &[T]) -> Vec <f64> {
+        self.build(samples.len()).iter()
+            .zip(samples)
+            .map(|(&w, &sample)| w * sample.to_i64() as f64)
+            .collect()
+    }
+}
+
+impl error::Error for WindowSpecError {}
+
+impl fmt::Display for WindowSpecError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", match self {
+            WindowSpecError::EmptySpecError => "Window specification is empty",
+            WindowSpecError::UnknownWindowError => "Unknown window function",
+            WindowSpecError::ParameterError => "Invalid window parameter",
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_spec_single() {
+        let windows = Window::parse_spec("tukey(0.5)").unwrap();
+        assert_eq!(windows, vec![Window::Tukey(0.5)]);
+    }
+
+    #[test]
+    fn test_parse_spec_multiple() {
+        let windows = Window::parse_spec("hann; welch;bartlett;gauss(0.25);rectangle").unwrap();
+        assert_eq!(windows, vec![Window::Hann, Window::Welch, Window::Bartlett, Window::Gauss(0.25), Window::Rectangle]);
+    }
+
+    #[test]
+    fn test_parse_spec_partial_tukey_expands() {
+        let windows = Window::parse_spec("tukey(0.5);partial_tukey(2)").unwrap();
+        assert_eq!(windows.len(), 3);
+        assert!(matches!(windows[1], Window::PartialTukey { start, .. } if start == 0.0));
+        assert!(matches!(windows[2], Window::PartialTukey { end, .. } if end == 1.0));
+    }
+
+    #[test]
+    fn test_parse_spec_punchout_tukey_expands() {
+        let windows = Window::parse_spec("punchout_tukey(3)").unwrap();
+        assert_eq!(windows.len(), 3);
+        assert!(windows.iter().all(|w| matches!(w, Window::PunchoutTukey { .. })));
+    }
+
+    #[test]
+    fn test_parse_spec_errors() {
+        assert_eq!(Window::parse_spec(""), Err(WindowSpecError::EmptySpecError));
+        assert_eq!(Window::parse_spec("kaiser(2)"), Err(WindowSpecError::UnknownWindowError));
+        assert_eq!(Window::parse_spec("tukey"), Err(WindowSpecError::ParameterError));
+        assert_eq!(Window::parse_spec("tukey(abc)"), Err(WindowSpecError::ParameterError));
+        assert_eq!(Window::parse_spec("hann(0.5)"), Err(WindowSpecError::ParameterError));
+        assert_eq!(Window::parse_spec("gauss(0.9)"), Err(WindowSpecError::ParameterError));
+        assert_eq!(Window::parse_spec("partial_tukey(0)"), Err(WindowSpecError::ParameterError));
+        assert_eq!(Window::parse_spec("gauss(NaN)"), Err(WindowSpecError::ParameterError));
+        assert_eq!(Window::parse_spec("partial_tukey(2/0.1/7)"), Err(WindowSpecError::ParameterError));
+        assert_eq!(Window::parse_spec("punchout_tukey(2/0.1/-3)"), Err(WindowSpecError::ParameterError));
+        assert_eq!(Window::parse_spec("partial_tukey(2/0.1/NaN)"), Err(WindowSpecError::ParameterError));
+    }
+
+    #[test]
+    fn test_build_rectangle() {
+        assert_eq!(Window::Rectangle.build(4), vec![1.0; 4]);
+    }
+
+    #[test]
+    fn test_build_bartlett() {
+        assert_eq!(Window::Bartlett.build(5), vec![0.0, 0.5, 1.0, 0.5, 0.0]);
+    }
+
+    #[test]
+    fn test_build_welch() {
+        assert_eq!(Window::Welch.build(5), vec![0.0, 0.75, 1.0, 0.75, 0.0]);
+    }
+
+    #[test]
+    fn test_build_hann_is_symmetric() {
+        let window = Window::Hann.build(8);
+        assert!(window[0].abs() < 1e-12);
+        for i in 0..4 {
+            assert!((window[i] - window[7 - i]).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn test_build_tukey_edges() {
+        let window = Window::Tukey(0.5).build(16);
+        assert!(window[0] > 0.0 && window[0] < 1.0);
+        assert!(window[15] > 0.0 && window[15] < 1.0);
+        assert_eq!(window[8], 1.0);
+        assert_eq!(Window::Tukey(0.0).build(16), vec![1.0; 16]);
+    }
+
+    #[test]
+    fn test_build_partial_and_punchout_tukey() {
+        let partial = Window::PartialTukey { p: 0.2, start: 0.5, end: 1.0 }.build(16);
+        assert!(partial[..8].iter().all(|&w| w == 0.0));
+        assert!(partial[8..].contains(&1.0));
+
+        let punchout = Window::PunchoutTukey { p: 0.2, start: 0.25, end: 0.75 }.build(16);
+        assert!(punchout[4..12].iter().all(|&w| w == 0.0));
+        assert!(punchout[..4].iter().all(|&w| w > 0.0));
+    }
+
+    #[test]
+    fn test_apply() {
+        let samples = vec![10, 20, 30, 20, 10];
+        assert_eq!(Window::Bartlett.apply(&samples), vec![0.0, 10.0, 30.0, 10.0, 0.0]);
+    }
+}
@@ -0,0 +1,556 @@
+use core::fmt;
+use std::error;
+
+use super::MetadataError;
+
+/// Number of samples in a CD frame, which CD-DA offsets are multiples of
+pub const CD_FRAME_SAMPLES: u64 = 588;
+/// Number of CD frames in a second, the `ff` in `mm:ss:ff` times
+pub const CD_FRAMES_PER_SECOND: u64 = 75;
+/// Number of lead-in samples of a CD, which is 2 seconds at 44.1 kHz
+pub const CD_LEAD_IN: u64 = 88200;
+/// Track number of the lead-out track of a CD
+pub const CD_LEAD_OUT: u8 = 170;
+/// Track number of the lead-out track of other cue sheets
+pub const LEAD_OUT: u8 = 255;
+
+/// Size of a CUESHEET block without its tracks, in bytes
+const HEADER_LENGTH: usize = 128 + 8 + 259 + 1;
+/// Size of a track without its index points, in bytes
+const TRACK_LENGTH: usize = 8 + 1 + 12 + 14 + 1;
+/// Size of an index point, in bytes
+const INDEX_LENGTH: usize = 8 + 1 + 3;
+
+/// Represents an index point of a track
+///
+/// `offset` is the number of samples from the start of the track.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CueSheetIndex {
+    pub offset: u64,
+    pub number: u8,
+}
+
+/// Represents a track of a cue sheet
+///
+/// `offset` is the number of the first sample of the track, which is the
+/// sample of its first index point. The lead-out track has no index points.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CueSheetTrack {
+    pub offset: u64,
+    pub number: u8,
+    pub isrc: String,
+    pub is_audio: bool,
+    pub pre_emphasis: bool,
+    pub indices: Vec <CueSheetIndex>,
+}
+
+/// Represents the CUESHEET metadata block
+///
+/// The last track is the lead-out track, which starts after the last sample.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CueSheet {
+    pub catalog: String,
+    pub lead_in: u64,
+    pub is_cd: bool,
+    pub tracks: Vec <CueSheetTrack>,
+}
+
+// Represents possible errors in reading a cue sheet
+//
+// Errors of a line hold the line number, starting from 1, and errors of a track
+// hold the track number.
+#[derive(Debug)]
+#[derive(PartialEq)]
+pub enum CueSheetError {
+    SyntaxError(usize),
+    InvalidTimeError(usize),
+    MultipleFilesError(usize),
+    NoTracksError,
+    TrackNumberError(u8),
+    IndexNumberError(u8),
+    OffsetOrderError(u8),
+    OffsetRangeError(u8),
+    CdAlignmentError(u8),
+}
+
+/// Split a line of a cue sheet into words, keeping quoted strings together
+fn split_words(line: &str) -> Vec <String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut in_quotes = false;
+    let mut has_word = false;
+
+    for c in line.chars() {
+        match c {
+            '"' => {
+                in_quotes = !in_quotes;
+                has_word = true;
+            }
+            _ if c.is_whitespace() && !in_quotes => {
+                if has_word {
+                    words.push(std::mem::take(&mut word));
+                    has_word = false;
+                }
+            }
+            _ => {
+                word.push(c);
+                has_word = true;
+            }
+        }
+    }
+    if has_word {
+        words.push(word);
+    }
+    words
+}
+
+/// Parse a `mm:ss:ff` time into a number of CD frames
+fn parse_time(time: &str) -> Option <u64> {
+    let mut parts = time.split(':').map(|part| part.parse::<u64>().ok());
+    let (minutes, seconds, frames) = (parts.next()??, parts.next()??, parts.next()??);
+    if parts.next().is_some() || seconds >= 60 || frames >= CD_FRAMES_PER_SECOND {
+        return None;
+    }
+    Some((minutes * 60 + seconds) * CD_FRAMES_PER_SECOND + frames)
+}
+
+/// Format a number of CD frames as a `mm:ss:ff` time
+fn format_time(frames: u64) -> String {
+    let seconds = frames / CD_FRAMES_PER_SECOND;
+    format!("{:02}:{:02}:{:02}", seconds / 60, seconds % 60, frames % CD_FRAMES_PER_SECOND)
+}
+
+impl CueSheet {
+    /// Read a text cue sheet describing a stream of `total_samples` samples
+    ///
+    /// The `CATALOG`, `FILE`, `TRACK`, `FLAGS`, `ISRC`, `INDEX` and `PREGAP`
+    /// commands are read, and other commands such as `TITLE` are skipped. A
+    /// pregap is silence that is not part of the file, so it is checked but not
+    /// stored. The cue sheet is a CD cue sheet if the stream is CD-DA, as
+    /// checked by `is_cd_format`.
+    ///
+    /// # Errors
+    /// `SyntaxError`, `InvalidTimeError` and `MultipleFilesError` are returned
+    /// for an invalid line, including a `PREGAP` after the first `INDEX` of its
+    /// track, and the errors of `validate` for invalid tracks.
+    pub fn from_cue(text: &str, sample_rate: u32, bps: u8, n_channels: u8, total_samples: u64) -> Result <CueSheet, CueSheetError> {
+        let is_cd = CueSheet::is_cd_format(sample_rate, bps, n_channels);
+        let mut cue_sheet = CueSheet { catalog: String::new(), lead_in: if is_cd { CD_LEAD_IN } else { 0 }, is_cd, tracks: Vec::new() };
+        let mut has_file = false;
+        // Absolute offsets of the index points of the current track
+        let mut offsets: Vec <u64> = Vec::new();
+
+        for (i, line) in text.trim_start_matches('\u{feff}').lines().enumerate() {
+            let line_number = i + 1;
+            let words = split_words(line);
+            let Some(command) = words.first() else { continue };
+            let arg = |n: usize| words.get(n).map(|word| word.as_str()).ok_or(CueSheetError::SyntaxError(line_number));
+
+            match command.to_ascii_uppercase().as_str() {
+                "CATALOG" => {
+                    let catalog = arg(1)?;
+                    if catalog.len() != 13 || !catalog.bytes().all(|byte| byte.is_ascii_digit()) {
+                        return Err(CueSheetError::SyntaxError(line_number));
+                    }
+                    cue_sheet.catalog = catalog.to_string();
+                }
+                "FILE" => {
+                    arg(1)?;
+                    if has_file {
+                        return Err(CueSheetError::MultipleFilesError(line_number));
+                    }
+                    has_file = true;
+                }
+                "TRACK" => {
+                    let number = arg(1)?.parse().map_err(|_| CueSheetError::SyntaxError(line_number))?;
+                    let is_audio = arg(2)?.eq_ignore_ascii_case("AUDIO");
+                    cue_sheet.finish_track(&offsets);
+                    offsets.clear();
+                    cue_sheet.tracks.push(CueSheetTrack { offset: 0, number, isrc: String::new(), is_audio, pre_emphasis: false, indices: Vec::new() });
+                }
+                "FLAGS" | "ISRC" | "INDEX" | "PREGAP" if cue_sheet.tracks.is_empty() => return Err(CueSheetError::SyntaxError(line_number)),
+                "FLAGS" => {
+                    let track = cue_sheet.tracks.last_mut().unwrap();
+                    track.pre_emphasis = words[1..].iter().any(|flag| flag.eq_ignore_ascii_case("PRE"));
+                }
+                "ISRC" => {
+                    let isrc = arg(1)?;
+                    if isrc.len() != 12 || !isrc.bytes().all(|byte| byte.is_ascii_alphanumeric()) {
+                        return Err(CueSheetError::SyntaxError(line_number));
+                    }
+                    cue_sheet.tracks.last_mut().unwrap().isrc = isrc.to_ascii_uppercase();
+                }
+                "INDEX" => {
+                    let number = arg(1)?.parse().map_err(|_| CueSheetError::SyntaxError(line_number))?;
+                    let frames = parse_time(arg(2)?).ok_or(CueSheetError::InvalidTimeError(line_number))?;
+                    cue_sheet.tracks.last_mut().unwrap().indices.push(CueSheetIndex { offset: 0, number });
+                    offsets.push(frames * sample_rate as u64 / CD_FRAMES_PER_SECOND);
+                }
+                "PREGAP" => {
+                    parse_time(arg(1)?).ok_or(CueSheetError::InvalidTimeError(line_number))?;
+                    if !cue_sheet.tracks.last().unwrap().indices.is_empty() {
+                        return Err(CueSheetError::SyntaxError(line_number));
+                    }
+                }
+                _ => {}
+            }
+        }
+        cue_sheet.finish_track(&offsets);
+
+        let lead_out = if is_cd { CD_LEAD_OUT } else { LEAD_OUT };
+        cue_sheet.tracks.push(CueSheetTrack { offset: total_samples, number: lead_out, isrc: String::new(), is_audio: true, pre_emphasis: false, indices: Vec::new() });
+        cue_sheet.validate(total_samples)?;
+        Ok(cue_sheet)
+    }
+
+    /// Check whether a stream is CD-DA, which is 16-bit mono or stereo audio at
+    /// 44.1 kHz
+    pub fn is_cd_format(sample_rate: u32, bps: u8, n_channels: u8) -> bool {
+        sample_rate == 44100 && bps == 16 && matches!(n_channels, 1 | 2)
+    }
+
+    /// Make the index points of the last track relative to its first index point
+    fn finish_track(&mut self, offsets: &[u64]) {
+        let Some(track) = self.tracks.last_mut() else { return };
+        track.offset = offsets.first().copied().unwrap_or(0);
+        for (index, offset) in track.indices.iter_mut().zip(offsets) {
+            index.offset = offset.saturating_sub(track.offset);
+        }
+    }
+
+    /// Check the tracks against a stream of `total_samples` samples
+    ///
+    /// Every track but the lead-out track needs index points numbered in order
+    /// from 0 or 1, including index point 1, and every index point must start
+    /// after the previous one and before the end of the stream. The lead-out
+    /// track starts at the end of the stream. CD cue sheets also need track
+    /// numbers up to 99 and offsets on CD frame boundaries, including the
+    /// offset of the lead-out track.
+    ///
+    /// # Errors
+    /// Returns the error of the first track that breaks a rule.
+    pub fn validate(&self, total_samples: u64) -> Result <(), CueSheetError> {
+        let Some((lead_out, tracks)) = self.tracks.split_last() else { return Err(CueSheetError::NoTracksError) };
+        if tracks.is_empty() {
+            return Err(CueSheetError::NoTracksError);
+        }
+        let lead_out_number = if self.is_cd { CD_LEAD_OUT } else { LEAD_OUT };
+        if lead_out.number != lead_out_number || !lead_out.indices.is_empty() {
+            return Err(CueSheetError::TrackNumberError(lead_out.number));
+        }
+        if lead_out.offset != total_samples {
+            return Err(CueSheetError::OffsetRangeError(lead_out.number));
+        }
+
+        let mut previous_number = 0;
+        let mut previous_offset = None;
+        for track in tracks {
+            if track.number <= previous_number || (self.is_cd && track.number > 99) || track.number == lead_out_number {
+                return Err(CueSheetError::TrackNumberError(track.number));
+            }
+            previous_number = track.number;
+
+            let first_index = track.indices.first().map(|index| index.number);
+            let is_ordered = track.indices.windows(2).all(|pair| pair[1].number == pair[0].number + 1);
+            if !matches!(first_index, Some(0) | Some(1)) || !is_ordered || !track.indices.iter().any(|index| index.number == 1) {
+                return Err(CueSheetError::IndexNumberError(track.number));
+            }
+
+            for index in &track.indices {
+                let offset = track.offset + index.offset;
+                if previous_offset.is_some_and(|previous| offset <= previous) {
+                    return Err(CueSheetError::OffsetOrderError(track.number));
+                }
+                if offset >= total_samples {
+                    return Err(CueSheetError::OffsetRangeError(track.number));
+                }
+                if self.is_cd && !offset.is_multiple_of(CD_FRAME_SAMPLES) {
+                    return Err(CueSheetError::CdAlignmentError(track.number));
+                }
+                previous_offset = Some(offset);
+            }
+        }
+        if self.is_cd && !lead_out.offset.is_multiple_of(CD_FRAME_SAMPLES) {
+            return Err(CueSheetError::CdAlignmentError(lead_out.number));
+        }
+        Ok(())
+    }
+
+    /// Write the cue sheet as text for the file `file_name`
+    ///
+    /// Offsets are rounded down to CD frames at the given sample rate.
+    pub fn to_cue(&self, sample_rate: u32, file_name: &str) -> String {
+        let mut lines = Vec::new();
+        if !self.catalog.is_empty() {
+            lines.push(format!("CATALOG {}", self.catalog));
+        }
+        lines.push(format!("FILE \"{}\" WAVE", file_name));
+
+        let lead_out_number = if self.is_cd { CD_LEAD_OUT } else { LEAD_OUT };
+        for track in self.tracks.iter().filter(|track| track.number != lead_out_number) {
+            lines.push(format!("  TRACK {:02} {}", track.number, if track.is_audio { "AUDIO" } else { "MODE1/2352" }));
+            if track.pre_emphasis {
+                lines.push("    FLAGS PRE".to_string());
+            }
+            if !track.isrc.is_empty() {
+                lines.push(format!("    ISRC {}", track.isrc));
+            }
+            for index in &track.indices {
+                let frames = (track.offset + index.offset) * CD_FRAMES_PER_SECOND / sample_rate as u64;
+                lines.push(format!("    INDEX {:02} {}", index.number, format_time(frames)));
+            }
+        }
+
+        lines.push(String::new());
+        lines.join("\n")
+    }
+
+    /// Encode the block body into bytes
+    pub fn to_bytes(&self) -> Vec <u8> {
+        let n_indices: usize = self.tracks.iter().map(|track| track.indices.len()).sum();
+        let mut bytes = Vec::with_capacity(HEADER_LENGTH + self.tracks.len() * TRACK_LENGTH + n_indices * INDEX_LENGTH);
+
+        let mut catalog = [0u8; 128];
+        catalog[..self.catalog.len().min(128)].copy_from_slice(&self.catalog.as_bytes()[..self.catalog.len().min(128)]);
+        bytes.extend_from_slice(&catalog);
+        bytes.extend_from_slice(&self.lead_in.to_be_bytes());
+        bytes.push((self.is_cd as u8) << 7);
+        bytes.extend_from_slice(&[0; 258]);
+        bytes.push(self.tracks.len() as u8);
+
+        for track in &self.tracks {
+            bytes.extend_from_slice(&track.offset.to_be_bytes());
+            bytes.push(track.number);
+            let mut isrc = [0u8; 12];
+            isrc[..track.isrc.len().min(12)].copy_from_slice(&track.isrc.as_bytes()[..track.isrc.len().min(12)]);
+            bytes.extend_from_slice(&isrc);
+            bytes.push(((!track.is_audio as u8) << 7) | ((track.pre_emphasis as u8) << 6));
+            bytes.extend_from_slice(&[0; 13]);
+            bytes.push(track.indices.len() as u8);
+
+            for index in &track.indices {
+                bytes.extend_from_slice(&index.offset.to_be_bytes());
+                bytes.push(index.number);
+                bytes.extend_from_slice(&[0; 3]);
+            }
+        }
+        bytes
+    }
+
+    /// Parse the block body
+    ///
+    /// # Errors
+    /// `UnexpectedEndError` is returned if a track or index point runs past the
+    /// end of `bytes`, and `InvalidUtf8Error` if the catalog number or an ISRC
+    /// is not valid UTF-8.
+    pub fn parse(bytes: &[u8]) -> Result <CueSheet, MetadataError> {
+        let header = bytes.get(..HEADER_LENGTH).ok_or(MetadataError::UnexpectedEndError)?;
+        let catalog = CueSheet::read_string(&header[..128])?;
+        let lead_in = u64::from_be_bytes(header[128..136].try_into().unwrap());
+        let is_cd = header[136] & 0x80 != 0;
+        let n_tracks = header[HEADER_LENGTH - 1];
+
+        let mut pos = HEADER_LENGTH;
+        let mut tracks = Vec::with_capacity(n_tracks as usize);
+        for _ in 0..n_tracks {
+            let track = bytes.get(pos..pos + TRACK_LENGTH).ok_or(MetadataError::UnexpectedEndError)?;
+            let n_indices = track[TRACK_LENGTH - 1] as usize;
+            pos += TRACK_LENGTH;
+
+            let mut indices = Vec::with_capacity(n_indices);
+            for _ in 0..n_indices {
+                let index = bytes.get(pos..pos + INDEX_LENGTH).ok_or(MetadataError::UnexpectedEndError)?;
+                indices.push(CueSheetIndex { offset: u64::from_be_bytes(index[..8].try_into().unwrap()), number: index[8] });
+                pos += INDEX_LENGTH;
+            }
+
+            tracks.push(CueSheetTrack {
+                offset: u64::from_be_bytes(track[..8].try_into().unwrap()),
+                number: track[8],
+                isrc: CueSheet::read_string(&track[9..21])?,
+                is_audio: track[21] & 0x80 == 0,
+                pre_emphasis: track[21] & 0x40 != 0,
+                indices,
+            });
+        }
+
+        Ok(CueSheet { catalog, lead_in, is_cd, tracks })
+    }
+
+    /// Read a NUL-padded string
+    fn read_string(bytes: &[u8]) -> Result <String, MetadataError> {
+        let len = bytes.iter().position(|&byte| byte == 0).unwrap_or(bytes.len());
+        String::from_utf8(bytes[..len].to_vec()).map_err(|_| MetadataError::InvalidUtf8Error)
+    }
+}
+
+impl error::Error for CueSheetError {}
+
+impl fmt::Display for CueSheetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CueSheetError::SyntaxError(line) => write!(f, "Invalid cue sheet command on line {}", line),
+            CueSheetError::InvalidTimeError(line) => write!(f, "Invalid mm:ss:ff time on line {}", line),
+            CueSheetError::MultipleFilesError(line) => write!(f, "Cue sheet refers to more than one file on line {}", line),
+            CueSheetError::NoTracksError => write!(f, "Cue sheet has no tracks"),
+            CueSheetError::TrackNumberError(track) => write!(f, "Invalid track number {}", track),
+            CueSheetError::IndexNumberError(track) => write!(f, "Invalid index points in track {}", track),
+            CueSheetError::OffsetOrderError(track) => write!(f, "Index points out of order in track {}", track),
+            CueSheetError::OffsetRangeError(track) => write!(f, "Track {} is past the end of the stream", track),
+            CueSheetError::CdAlignmentError(track) => write!(f, "Track {} is not on a CD frame boundary", track),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CUE: &str = "\
+REM GENRE Electronic
+CATALOG 0123456789012
+PERFORMER \"Some DJ\"
+FILE \"mix 1.wav\" WAVE
+  TRACK 01 AUDIO
+    TITLE \"Intro\"
+    ISRC USABC1234567
+    INDEX 01 00:00:00
+  TRACK 02 AUDIO
+    FLAGS DCP PRE
+    PREGAP 00:02:00
+    INDEX 00 01:30:00
+    INDEX 01 01:32:37
+";
+
+    #[test]
+    fn test_split_words() {
+        assert_eq!(split_words("  FILE \"mix 1.wav\" WAVE"), vec!["FILE", "mix 1.wav", "WAVE"]);
+        assert_eq!(split_words("TITLE \"\""), vec!["TITLE", ""]);
+    }
+
+    #[test]
+    fn test_parse_time() {
+        assert_eq!(parse_time("01:32:37"), Some(92 * 75 + 37));
+        assert_eq!(parse_time("00:60:00"), None);
+        assert_eq!(parse_time("00:00:75"), None);
+        assert_eq!(parse_time("00:00"), None);
+        assert_eq!(format_time(92 * 75 + 37), "01:32:37");
+    }
+
+    #[test]
+    fn test_from_cue() {
+        let cue_sheet = CueSheet::from_cue(CUE, 44100, 16, 2, 44100 * 300).unwrap();
+
+        assert_eq!(cue_sheet.catalog, "0123456789012");
+        assert!(cue_sheet.is_cd);
+        assert_eq!(cue_sheet.lead_in, CD_LEAD_IN);
+        assert_eq!(cue_sheet.tracks.len(), 3);
+        assert_eq!(cue_sheet.tracks[0].isrc, "USABC1234567");
+        assert_eq!(cue_sheet.tracks[1], CueSheetTrack {
+            offset: 90 * 44100,
+            number: 2,
+            isrc: String::new(),
+            is_audio: true,
+            pre_emphasis: true,
+            indices: vec![CueSheetIndex { offset: 0, number: 0 }, CueSheetIndex { offset: 2 * 44100 + 37 * 588, number: 1 }],
+        });
+        assert_eq!(cue_sheet.tracks[2].number, CD_LEAD_OUT);
+        assert_eq!(cue_sheet.tracks[2].offset, 44100 * 300);
+    }
+
+    #[test]
+    fn test_from_invalid_cue() {
+        assert_eq!(CueSheet::from_cue(CUE, 44100, 16, 2, 44100 * 60), Err(CueSheetError::OffsetRangeError(2)));
+        assert_eq!(CueSheet::from_cue("FILE a WAVE\nFILE b WAVE", 44100, 16, 2, 1000), Err(CueSheetError::MultipleFilesError(2)));
+        assert_eq!(CueSheet::from_cue("INDEX 01 00:00:00", 44100, 16, 2, 1000), Err(CueSheetError::SyntaxError(1)));
+        assert_eq!(CueSheet::from_cue("TRACK 01 AUDIO\nINDEX 01 0:0:x", 44100, 16, 2, 1000), Err(CueSheetError::InvalidTimeError(2)));
+        assert_eq!(CueSheet::from_cue("FILE a WAVE", 44100, 16, 2, 1000), Err(CueSheetError::NoTracksError));
+        assert_eq!(CueSheet::from_cue("TRACK 01 AUDIO\nINDEX 02 00:00:00", 44100, 16, 2, 1000), Err(CueSheetError::IndexNumberError(1)));
+        assert_eq!(
+            CueSheet::from_cue("TRACK 02 AUDIO\nINDEX 01 00:00:10\nTRACK 03 AUDIO\nINDEX 01 00:00:05", 44100, 16, 2, 100000),
+            Err(CueSheetError::OffsetOrderError(3)),
+        );
+        assert_eq!(
+            CueSheet::from_cue("TRACK 02 AUDIO\nINDEX 01 00:00:00\nTRACK 02 AUDIO\nINDEX 01 00:00:05", 44100, 16, 2, 100000),
+            Err(CueSheetError::TrackNumberError(2)),
+        );
+    }
+
+    #[test]
+    fn test_validate_cd_alignment() {
+        let mut cue_sheet = CueSheet::from_cue(CUE, 44100, 16, 2, 44100 * 300).unwrap();
+        cue_sheet.tracks[1].indices[1].offset += 1;
+        assert_eq!(cue_sheet.validate(44100 * 300), Err(CueSheetError::CdAlignmentError(2)));
+    }
+
+    #[test]
+    fn test_validate_cd_lead_out() {
+        assert_eq!(CueSheet::from_cue(CUE, 44100, 16, 2, 44100 * 300 + 1), Err(CueSheetError::CdAlignmentError(CD_LEAD_OUT)));
+        assert!(CueSheet::from_cue(CUE, 48000, 16, 2, 48000 * 300 + 1).is_ok());
+    }
+
+    #[test]
+    fn test_pregap() {
+        let text = "TRACK 01 AUDIO\nINDEX 01 00:00:00\nTRACK 02 AUDIO\nPREGAP 00:02:00\nINDEX 01 00:10:00";
+        let cue_sheet = CueSheet::from_cue(text, 44100, 16, 2, 44100 * 60).unwrap();
+        assert_eq!(cue_sheet.tracks[1].offset, 10 * 44100);
+        assert_eq!(cue_sheet.tracks[1].indices, vec![CueSheetIndex { offset: 0, number: 1 }]);
+
+        let text = "TRACK 01 AUDIO\nINDEX 01 00:00:00\nTRACK 02 AUDIO\nPREGAP 00:02:75\nINDEX 01 00:10:00";
+        assert_eq!(CueSheet::from_cue(text, 44100, 16, 2, 44100 * 60), Err(CueSheetError::InvalidTimeError(4)));
+        let text = "TRACK 01 AUDIO\nINDEX 01 00:00:00\nTRACK 02 AUDIO\nINDEX 01 00:10:00\nPREGAP 00:02:00";
+        assert_eq!(CueSheet::from_cue(text, 44100, 16, 2, 44100 * 60), Err(CueSheetError::SyntaxError(5)));
+    }
+
+    #[test]
+    fn test_is_cd_format() {
+        assert!(CueSheet::is_cd_format(44100, 16, 1));
+        assert!(CueSheet::is_cd_format(44100, 16, 2));
+        assert!(!CueSheet::is_cd_format(44100, 24, 2));
+        assert!(!CueSheet::is_cd_format(44100, 16, 6));
+        assert!(!CueSheet::is_cd_format(48000, 16, 2));
+        assert!(!CueSheet::from_cue(CUE, 44100, 24, 2, 44100 * 300).unwrap().is_cd);
+    }
+
+    #[test]
+    fn test_non_cd_cue() {
+        let cue_sheet = CueSheet::from_cue(CUE, 48000, 16, 2, 48000 * 300).unwrap();
+
+        assert!(!cue_sheet.is_cd);
+        assert_eq!(cue_sheet.lead_in, 0);
+        assert_eq!(cue_sheet.tracks[1].indices[1].offset, 2 * 48000 + 37 * 640);
+        assert_eq!(cue_sheet.tracks[2].number, LEAD_OUT);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let cue_sheet = CueSheet::from_cue(CUE, 44100, 16, 2, 44100 * 300).unwrap();
+        let bytes = cue_sheet.to_bytes();
+
+        assert_eq!(bytes.len(), HEADER_LENGTH + 3 * TRACK_LENGTH + 3 * INDEX_LENGTH);
+        assert_eq!(&bytes[..13], b"0123456789012");
+        assert_eq!(bytes[136], 0x80);
+        assert_eq!(CueSheet::parse(&bytes), Ok(cue_sheet));
+        assert_eq!(CueSheet::parse(&bytes[..bytes.len() - 1]), Err(MetadataError::UnexpectedEndError));
+    }
+
+    #[test]
+    fn test_to_cue() {
+        let cue_sheet = CueSheet::from_cue(CUE, 44100, 16, 2, 44100 * 300).unwrap();
+        let text = cue_sheet.to_cue(44100, "mix.flac");
+
+        assert_eq!(text, "\
+CATALOG 0123456789012
+FILE \"mix.flac\" WAVE
+  TRACK 01 AUDIO
+    ISRC USABC1234567
+    INDEX 01 00:00:00
+  TRACK 02 AUDIO
+    FLAGS PRE
+    INDEX 00 01:30:00
+    INDEX 01 01:32:37
+");
+        assert_eq!(CueSheet::from_cue(&text, 44100, 16, 2, 44100 * 300), Ok(cue_sheet));
+    }
+}
@@ -0,0 +1,319 @@
+use core::fmt;
+use std::error;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+use crate::flac::encoder::bitwriter::BitWriter;
+
+use super::{BlockType, MetadataBlock, MetadataError, StreamInfo, VorbisComment, MAX_BLOCK_LENGTH};
+
+/// Length in bytes of the PADDING block written when the file is rewritten
+pub const DEFAULT_PADDING: u32 = 8192;
+
+/// Represents an editor of the metadata blocks of a FLAC file
+///
+/// Only the metadata blocks are read when the file is opened. The blocks after
+/// STREAMINFO can then be changed, and are written back by `save`, which
+/// leaves the frames untouched whenever the new blocks fit in the old ones.
+pub struct Editor {
+    path: String,
+    stream_info: StreamInfo,
+    blocks: Vec <MetadataBlock>,
+    metadata_len: u64,
+}
+
+// Represents possible errors in editing the metadata of a FLAC file
+#[derive(Debug)]
+#[derive(PartialEq)]
+pub enum EditorError {
+    NotFlacError,
+    ReadError,
+    WriteError,
+    MetadataError(MetadataError),
+}
+
+impl Editor {
+    /// Read the metadata blocks of a FLAC file
+    ///
+    /// # Errors
+    /// `NotFlacError` is returned if the file does not start with `fLaC` and a
+    /// STREAMINFO block, `ReadError` if the file ends within the metadata blocks,
+    /// and `MetadataError` if a block cannot be parsed.
+    pub fn open(path: &str) -> Result <Editor, EditorError> {
+        let mut file = File::open(path)?;
+        let mut marker = [0u8; 4];
+        file.read_exact(&mut marker)?;
+        if &marker != b"fLaC" {
+            return Err(EditorError::NotFlacError);
+        }
+
+        let mut metadata_len = 4;
+        let mut stream_info = None;
+        let mut blocks = Vec::new();
+        loop {
+            let mut header = [0u8; 4];
+            file.read_exact(&mut header)?;
+            let is_last = header[0] & 0x80 != 0;
+            let block_type = BlockType::from_code(header[0] & 0x7F).ok_or(EditorError::NotFlacError)?;
+            let length = u32::from_be_bytes([0, header[1], header[2], header[3]]);
+            let mut body = vec![0u8; length as usize];
+            file.read_exact(&mut body)?;
+            metadata_len += 4 + length as u64;
+
+            match (block_type, &stream_info) {
+                (BlockType::StreamInfo, None) => stream_info = Some(StreamInfo::parse(&body)?),
+                (_, None) => return Err(EditorError::NotFlacError),
+                _ => blocks.push(MetadataBlock::parse(block_type, &body)?),
+            }
+            if is_last {
+                break;
+            }
+        }
+
+        let stream_info = stream_info.ok_or(EditorError::NotFlacError)?;
+        Ok(Editor { path: path.to_string(), stream_info, blocks, metadata_len })
+    }
+
+    /// Get the STREAMINFO block, which cannot be edited
+    pub fn stream_info(&self) -> &StreamInfo {
+        &self.stream_info
+    }
+
+    /// Get the metadata blocks after the STREAMINFO block, in file order
+    pub fn blocks(&self) -> &[MetadataBlock] {
+        &self.blocks
+    }
+
+    /// Add a block after every other block
+    pub fn add(&mut self, block: MetadataBlock) {
+        self.blocks.push(block);
+    }
+
+    /// Remove the block at `index`, returning it if it exists
+    pub fn remove(&mut self, index: usize) -> Option <MetadataBlock> {
+        (index < self.blocks.len()).then(|| self.blocks.remove(index))
+    }
+
+    /// Remove every block of a type, returning the number of blocks removed
+    pub fn remove_type(&mut self, block_type: BlockType) -> usize {
+        let len = self.blocks.len();
+        self.blocks.retain(|block| block.block_type() != block_type);
+        len - self.blocks.len()
+    }
+
+    /// Replace the block at `index`, returning the old block if it exists
+    pub fn replace(&mut self, index: usize, block: MetadataBlock) -> Option <MetadataBlock> {
+        self.blocks.get_mut(index).map(|old| std::mem::replace(old, block))
+    }
+
+    /// Get the first VORBIS_COMMENT block, adding an empty one if there is none
+    pub fn vorbis_comment_mut(&mut self) -> &mut VorbisComment {
+        let index = match self.blocks.iter().position(|block| block.block_type() == BlockType::VorbisComment) {
+            Some(index) => index,
+            None => {
+                self.blocks.push(MetadataBlock::VorbisComment(VorbisComment::default()));
+                self.blocks.len() - 1
+            }
+        };
+        match &mut self.blocks[index] {
+            MetadataBlock::VorbisComment(vorbis_comment) => vorbis_comment,
+            _ => unreachable!(),
+        }
+    }
+
+    /// Encode the metadata blocks, from the `fLaC` marker to the last block
+    ///
+    /// Every PADDING block is left out, and a single PADDING block of `padding`
+    /// bytes is written last instead, if given.
+    fn metadata_bytes(&self, padding: Option <u32>) -> Result <Vec <u8>, MetadataError> {
+        let mut blocks: Vec <&MetadataBlock> = self.blocks.iter()
+            .filter(|block| block.block_type() != BlockType::Padding)
+            .collect();
+        let padding_block = padding.map(MetadataBlock::Padding);
+        blocks.extend(padding_block.as_ref());
+
+        let mut writer = BitWriter::new();
+        writer.write_bytes(b"fLaC");
+        self.stream_info.write(&mut writer, blocks.is_empty());
+        for (i, block) in blocks.iter().enumerate() {
+            block.write(&mut writer, i + 1 == blocks.len())?;
+        }
+        Ok(writer.into_bytes())
+    }
+
+    /// Write the metadata blocks back to the file
+    ///
+    /// The PADDING blocks are merged into one PADDING block after the other
+    /// blocks. If the other blocks fit in the space of the old metadata blocks,
+    /// they are written in place and the padding takes the rest of the space.
+    /// Otherwise, the file is rewritten with `DEFAULT_PADDING` bytes of padding.
+    ///
+    /// # Errors
+    /// `MetadataError` is returned if a block is too large, and `ReadError` or
+    /// `WriteError` if the file cannot be read or written.
+    pub fn save(&mut self) -> Result <(), EditorError> {
+        let unpadded_len = self.metadata_bytes(None)?.len() as u64;
+
+        if unpadded_len == self.metadata_len {
+            self.write_in_place(None)
+        } else if unpadded_len + 4 <= self.metadata_len && self.metadata_len - unpadded_len - 4 <= MAX_BLOCK_LENGTH as u64 {
+            self.write_in_place(Some((self.metadata_len - unpadded_len - 4) as u32))
+        } else {
+            self.rewrite(DEFAULT_PADDING)
+        }
+    }
+
+    fn write_in_place(&mut self, padding: Option <u32>) -> Result <(), EditorError> {
+        let bytes = self.metadata_bytes(padding)?;
+        debug_assert_eq!(bytes.len() as u64, self.metadata_len);
+
+        let mut file = OpenOptions::new().write(true).open(&self.path).map_err(|_| EditorError::WriteError)?;
+        file.write_all(&bytes).map_err(|_| EditorError::WriteError)?;
+        self.update_padding(padding);
+        Ok(())
+    }
+
+    /// Write the metadata blocks and the frames to a temporary file, then replace the file with it
+    fn rewrite(&mut self, padding: u32) -> Result <(), EditorError> {
+        let bytes = self.metadata_bytes(Some(padding))?;
+        let mut file = File::open(&self.path)?;
+        file.seek(SeekFrom::Start(self.metadata_len))?;
+
+        let temp_path = format!("{}.tmp", self.path);
+        let result = (|| {
+            let mut temp_file = File::create(&temp_path)?;
+            temp_file.write_all(&bytes)?;
+            io::copy(&mut file, &mut temp_file)?;
+            temp_file.sync_all()?;
+            fs::rename(&temp_path, &self.path)
+        })();
+        if result.is_err() {
+            let _ = fs::remove_file(&temp_path);
+            return Err(EditorError::WriteError);
+        }
+
+        self.metadata_len = bytes.len() as u64;
+        self.update_padding(Some(padding));
+        Ok(())
+    }
+
+    /// Make the blocks match the file after the PADDING blocks were merged
+    fn update_padding(&mut self, padding: Option <u32>) {
+        self.blocks.retain(|block| block.block_type() != BlockType::Padding);
+        self.blocks.extend(padding.map(MetadataBlock::Padding));
+    }
+}
+
+impl error::Error for EditorError {}
+
+impl fmt::Display for EditorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EditorError::NotFlacError => write!(f, "Not a FLAC file"),
+            EditorError::ReadError => write!(f, "Error reading from file"),
+            EditorError::WriteError => write!(f, "Error writing to file"),
+            EditorError::MetadataError(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl From <io::Error> for EditorError {
+    fn from(_: io::Error) -> Self {
+        EditorError::ReadError
+    }
+}
+
+impl From <MetadataError> for EditorError {
+    fn from(e: MetadataError) -> Self {
+        EditorError::MetadataError(e)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::flac::decoder::stream::FlacDecoder;
+    use crate::flac::encoder::stream::FlacEncoderOptions;
+
+    fn channels() -> Vec <Vec <i64>> {
+        vec![(0..5000).map(|i| ((i as f64 * 0.05).sin() * 1000.0) as i64).collect()]
+    }
+
+    /// Write a FLAC file with the given padding to the temporary directory
+    fn create_flac(name: &str, padding: Option <u32>) -> String {
+        let mut options = FlacEncoderOptions::new(44100, 1, 16);
+        if let Some(length) = padding {
+            options = options.padding(length);
+        }
+        let path = std::env::temp_dir().join(name).to_string_lossy().to_string();
+        fs::write(&path, options.encode(&channels()).unwrap()).unwrap();
+        path
+    }
+
+    fn decoded_samples(bytes: &[u8]) -> Vec <Vec <i64>> {
+        FlacDecoder::new(bytes).unwrap().read_samples(u64::MAX).unwrap()
+    }
+
+    #[test]
+    fn test_save_in_place() {
+        let path = create_flac("midp_editor_in_place.flac", Some(1000));
+        let len = fs::metadata(&path).unwrap().len();
+
+        let mut editor = Editor::open(&path).unwrap();
+        assert_eq!(editor.blocks(), &[MetadataBlock::Padding(1000)]);
+        editor.vorbis_comment_mut().add("TITLE", "Song").unwrap();
+        editor.save().unwrap();
+
+        let bytes = fs::read(&path).unwrap();
+        assert_eq!(bytes.len() as u64, len);
+        let editor = Editor::open(&path).unwrap();
+        assert_eq!(editor.blocks().len(), 2);
+        assert_eq!(editor.blocks()[1], MetadataBlock::Padding(1000 - 4 - editor.blocks()[0].to_bytes().len() as u32));
+        assert_eq!(decoded_samples(&bytes), channels());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_save_rewrites_without_padding() {
+        let path = create_flac("midp_editor_rewrite.flac", None);
+
+        let mut editor = Editor::open(&path).unwrap();
+        editor.vorbis_comment_mut().set("ARTIST", "Alice").unwrap();
+        editor.save().unwrap();
+
+        let bytes = fs::read(&path).unwrap();
+        let decoder = FlacDecoder::new(&bytes).unwrap();
+        assert_eq!(decoder.vorbis_comment().map(|vorbis_comment| vorbis_comment.get("artist")), Some(vec!["Alice"]));
+        assert_eq!(decoder.metadata().last(), Some(&MetadataBlock::Padding(DEFAULT_PADDING)));
+        assert_eq!(decoded_samples(&bytes), channels());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_remove_and_replace() {
+        let path = create_flac("midp_editor_remove.flac", Some(100));
+
+        let mut editor = Editor::open(&path).unwrap();
+        editor.add(MetadataBlock::Application { id: *b"midp", data: vec![1] });
+        assert_eq!(editor.replace(1, MetadataBlock::Application { id: *b"test", data: vec![2] }).map(|block| block.block_type()), Some(BlockType::Application));
+        assert_eq!(editor.replace(2, MetadataBlock::Padding(0)), None);
+        editor.save().unwrap();
+
+        let mut editor = Editor::open(&path).unwrap();
+        assert_eq!(editor.blocks()[0], MetadataBlock::Application { id: *b"test", data: vec![2] });
+        assert_eq!(editor.remove_type(BlockType::Application), 1);
+        assert_eq!(editor.remove(5), None);
+        editor.save().unwrap();
+
+        // The padding takes back the space of the removed block
+        assert_eq!(Editor::open(&path).unwrap().blocks(), &[MetadataBlock::Padding(100)]);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_open_errors() {
+        assert_eq!(Editor::open("midp_missing.flac").err(), Some(EditorError::ReadError));
+        assert_eq!(Editor::open("Cargo.toml").err(), Some(EditorError::NotFlacError));
+    }
+}
@@ -0,0 +1,187 @@
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+use crate::wav::{PCMWaveFormatChunk, WaveReaderError, WaveWriter};
+
+use super::{MetadataBlock, MetadataError};
+
+/// Application ID of the blocks that hold RIFF chunks
+pub const RIFF_APPLICATION_ID: [u8; 4] = *b"riff";
+
+/// Represents the RIFF chunks of a WAV file other than its samples
+///
+/// The chunks are kept byte for byte in file order, starting with the 12-byte
+/// `RIFF` header. The `data` chunk is kept as its 8-byte header only, since
+/// the samples are stored in the frames. Each chunk is stored in its own
+/// APPLICATION block with the `riff` ID, like the reference encoder does.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ForeignMetadata {
+    chunks: Vec <Vec <u8>>,
+}
+
+impl ForeignMetadata {
+    /// Read the chunk layout of a WAV file
+    ///
+    /// # Errors
+    /// `NotRiffError` or `NotWaveError` is returned if the file does not start
+    /// with a `RIFF`/`WAVE` header, `ChunkTypeError` if it has no `data` chunk or
+    /// more than one, and `ReadError` if a chunk runs past the end of the file.
+    pub fn read_wav(file_path: &str) -> Result <ForeignMetadata, WaveReaderError> {
+        let mut file = File::open(file_path)?;
+        let file_len = file.metadata()?.len();
+
+        let mut riff_header = [0u8; 12];
+        file.read_exact(&mut riff_header)?;
+        if &riff_header[0..4] != b"RIFF" {
+            return Err(WaveReaderError::NotRiffError);
+        }
+        if &riff_header[8..12] != b"WAVE" {
+            return Err(WaveReaderError::NotWaveError);
+        }
+
+        let mut chunks = vec![riff_header.to_vec()];
+        let mut has_data = false;
+        let mut chunk_header = [0u8; 8];
+        while file.read_exact(&mut chunk_header).is_ok() {
+            let size_bytes = u32::from_le_bytes([chunk_header[4], chunk_header[5], chunk_header[6], chunk_header[7]]) as u64;
+            // Chunks are padded to an even size
+            let padded_size = size_bytes + (size_bytes & 1);
+
+            if &chunk_header[0..4] == b"data" {
+                if has_data {
+                    return Err(WaveReaderError::ChunkTypeError);
+                }
+                has_data = true;
+                chunks.push(chunk_header.to_vec());
+                file.seek(SeekFrom::Current(padded_size as i64))?;
+            } else {
+                if file.stream_position()? + padded_size > file_len {
+                    return Err(WaveReaderError::ReadError);
+                }
+                let mut chunk = chunk_header.to_vec();
+                chunk.resize(8 + padded_size as usize, 0);
+                file.read_exact(&mut chunk[8..])?;
+                chunks.push(chunk);
+            }
+        }
+
+        if !has_data {
+            return Err(WaveReaderError::ChunkTypeError);
+        }
+        Ok(ForeignMetadata { chunks })
+    }
+
+    /// Get the APPLICATION blocks that hold the chunks, in file order
+    pub fn to_blocks(&self) -> Vec <MetadataBlock> {
+        self.chunks.iter()
+            .map(|chunk| MetadataBlock::Application { id: RIFF_APPLICATION_ID, data: chunk.clone() })
+            .collect()
+    }
+
+    /// Collect the chunks of the `riff` APPLICATION blocks among `blocks`
+    ///
+    /// `None` is returned if there are no such blocks.
+    ///
+    /// # Errors
+    /// `InvalidForeignMetadataError` is returned if the blocks do not start with
+    /// a `RIFF` header or do not hold exactly one `data` chunk header.
+    pub fn from_blocks(blocks: &[MetadataBlock]) -> Result <Option <ForeignMetadata>, MetadataError> {
+        let chunks: Vec <Vec <u8>> = blocks.iter()
+            .filter_map(|block| match block {
+                MetadataBlock::Application { id, data } if *id == RIFF_APPLICATION_ID => Some(data.clone()),
+                _ => None,
+            })
+            .collect();
+        if chunks.is_empty() {
+            return Ok(None);
+        }
+
+        let is_riff = chunks[0].len() == 12 && chunks[0].starts_with(b"RIFF") && chunks[0].ends_with(b"WAVE");
+        let n_data = chunks.iter().filter(|chunk| chunk.len() == 8 && chunk.starts_with(b"data")).count();
+        let is_well_formed = chunks[1..].iter().all(|chunk| chunk.len() >= 8);
+        if !is_riff || n_data != 1 || !is_well_formed {
+            return Err(MetadataError::InvalidForeignMetadataError);
+        }
+        Ok(Some(ForeignMetadata { chunks }))
+    }
+
+    /// Get the size of the samples as stored in the `data` chunk header, in bytes
+    pub fn data_size(&self) -> u32 {
+        let data = self.chunks.iter().find(|chunk| chunk.len() == 8 && chunk.starts_with(b"data")).unwrap();
+        u32::from_le_bytes([data[4], data[5], data[6], data[7]])
+    }
+
+    /// Write the WAV file with the samples of each channel in the `data` chunk
+    ///
+    /// The samples must take up exactly the size of the original `data` chunk,
+    /// which `data_size` gives, so that the file matches the original.
+    pub fn write_wav(&self, writer: &mut impl Write, format: &PCMWaveFormatChunk, channels: &[Vec <i64>]) -> io::Result <()> {
+        for chunk in &self.chunks {
+            writer.write_all(chunk)?;
+            if chunk.len() == 8 && chunk.starts_with(b"data") {
+                WaveWriter::write_samples(writer, format, channels)?;
+                if self.data_size() & 1 == 1 {
+                    writer.write_all(&[0])?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn wav_bytes() -> Vec <u8> {
+        let mut bytes = b"RIFF\x3E\x00\x00\x00WAVE".to_vec();
+        bytes.extend_from_slice(b"fmt \x10\x00\x00\x00\x01\x00\x01\x00\x44\xAC\x00\x00\x88\x58\x01\x00\x02\x00\x10\x00");
+        // An odd-sized chunk before the samples, with its pad byte
+        bytes.extend_from_slice(b"iXML\x03\x00\x00\x00<x>\x00");
+        bytes.extend_from_slice(b"data\x04\x00\x00\x00\x01\x00\xFF\xFF");
+        bytes.extend_from_slice(b"smpl\x02\x00\x00\x00\xAB\xCD");
+        bytes
+    }
+
+    #[test]
+    fn test_read_and_write_wav() {
+        let path = std::env::temp_dir().join("midp_foreign.wav").to_string_lossy().to_string();
+        fs::write(&path, wav_bytes()).unwrap();
+
+        let foreign = ForeignMetadata::read_wav(&path).unwrap();
+        let chunk_ids: Vec <&[u8]> = foreign.chunks.iter().map(|chunk| &chunk[..4]).collect();
+        assert_eq!(chunk_ids, vec![b"RIFF", b"fmt ", b"iXML", b"data", b"smpl"]);
+        assert_eq!(foreign.data_size(), 4);
+
+        let format = PCMWaveFormatChunk { num_channels: 1, samp_rate: 44100, bps: 16 };
+        let mut bytes = Vec::new();
+        foreign.write_wav(&mut bytes, &format, &[vec![1, -1]]).unwrap();
+        assert_eq!(bytes, wav_bytes());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_blocks_round_trip() {
+        let path = std::env::temp_dir().join("midp_foreign_blocks.wav").to_string_lossy().to_string();
+        fs::write(&path, wav_bytes()).unwrap();
+        let foreign = ForeignMetadata::read_wav(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let mut blocks = vec![MetadataBlock::Padding(4)];
+        blocks.extend(foreign.to_blocks());
+        assert_eq!(blocks[1].to_bytes()[..8], *b"riffRIFF");
+        assert_eq!(ForeignMetadata::from_blocks(&blocks), Ok(Some(foreign)));
+        assert_eq!(ForeignMetadata::from_blocks(&blocks[..1]), Ok(None));
+        assert_eq!(ForeignMetadata::from_blocks(&blocks[..3]), Err(MetadataError::InvalidForeignMetadataError));
+    }
+
+    #[test]
+    fn test_read_wav_without_data() {
+        let path = std::env::temp_dir().join("midp_foreign_no_data.wav").to_string_lossy().to_string();
+        fs::write(&path, &wav_bytes()[..48]).unwrap();
+        assert_eq!(ForeignMetadata::read_wav(&path), Err(WaveReaderError::ChunkTypeError));
+        fs::remove_file(&path).unwrap();
+    }
+}
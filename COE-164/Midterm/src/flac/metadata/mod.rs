@@ -0,0 +1,197 @@
+use core::fmt;
+use std::error;
+
+use crate::flac::encoder::bitwriter::BitWriter;
+
+pub mod cuesheet;
+pub mod editor;
+pub mod foreign;
+pub mod picture;
+pub mod seektable;
+pub mod streaminfo;
+pub mod vorbis_comment;
+
+pub use cuesheet::{CueSheet, CueSheetError};
+pub use editor::{Editor, EditorError};
+pub use foreign::ForeignMetadata;
+pub use picture::{Picture, PictureType};
+pub use seektable::{SeekPoint, SeekTable};
+pub use streaminfo::StreamInfo;
+pub use vorbis_comment::VorbisComment;
+
+/// Largest size of a block body in bytes, since it is stored in 24 bits
+pub const MAX_BLOCK_LENGTH: usize = (1 << 24) - 1;
+
+/// Represents the type of a metadata block
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BlockType {
+    StreamInfo,
+    Padding,
+    Application,
+    SeekTable,
+    VorbisComment,
+    CueSheet,
+    Picture,
+    Reserved(u8),
+}
+
+impl BlockType {
+    /// Get the 7-bit code of the block type
+    pub fn code(&self) -> u8 {
+        match self {
+            BlockType::StreamInfo => 0,
+            BlockType::Padding => 1,
+            BlockType::Application => 2,
+            BlockType::SeekTable => 3,
+            BlockType::VorbisComment => 4,
+            BlockType::CueSheet => 5,
+            BlockType::Picture => 6,
+            BlockType::Reserved(code) => *code,
+        }
+    }
+
+    /// Get the block type of a 7-bit code
+    ///
+    /// Codes without a defined block type are kept as `Reserved`, except 127,
+    /// which is invalid.
+    pub fn from_code(code: u8) -> Option <BlockType> {
+        match code {
+            0 => Some(BlockType::StreamInfo),
+            1 => Some(BlockType::Padding),
+            2 => Some(BlockType::Application),
+            3 => Some(BlockType::SeekTable),
+            4 => Some(BlockType::VorbisComment),
+            5 => Some(BlockType::CueSheet),
+            6 => Some(BlockType::Picture),
+            7..=126 => Some(BlockType::Reserved(code)),
+            _ => None,
+        }
+    }
+
+    /// Get the name of the block type, as used in the FLAC format specification
+    pub fn name(&self) -> &'static str {
+        match self {
+            BlockType::StreamInfo => "STREAMINFO",
+            BlockType::Padding => "PADDING",
+            BlockType::Application => "APPLICATION",
+            BlockType::SeekTable => "SEEKTABLE",
+            BlockType::VorbisComment => "VORBIS_COMMENT",
+            BlockType::CueSheet => "CUESHEET",
+            BlockType::Picture => "PICTURE",
+            BlockType::Reserved(_) => "RESERVED",
+        }
+    }
+}
+
+/// Represents a metadata block that follows the STREAMINFO block
+///
+/// A PADDING block holds its length in bytes, and an APPLICATION block holds
+/// the 4-byte ID of its application followed by its data. Blocks whose body is
+/// not interpreted are kept as `Other` with their raw body.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MetadataBlock {
+    Padding(u32),
+    Application { id: [u8; 4], data: Vec <u8> },
+    SeekTable(SeekTable),
+    VorbisComment(VorbisComment),
+    CueSheet(CueSheet),
+    Picture(Picture),
+    Other { block_type: BlockType, data: Vec <u8> },
+}
+
+// Represents possible errors in reading or writing metadata blocks
+#[derive(Debug)]
+#[derive(PartialEq)]
+pub enum MetadataError {
+    UnexpectedEndError,
+    InvalidFieldNameError,
+    InvalidUtf8Error,
+    BlockTooLargeError,
+    UnknownImageError,
+    InvalidForeignMetadataError,
+}
+
+impl MetadataBlock {
+    /// Get the type of the block
+    pub fn block_type(&self) -> BlockType {
+        match self {
+            MetadataBlock::Padding(_) => BlockType::Padding,
+            MetadataBlock::Application { .. } => BlockType::Application,
+            MetadataBlock::SeekTable(_) => BlockType::SeekTable,
+            MetadataBlock::VorbisComment(_) => BlockType::VorbisComment,
+            MetadataBlock::CueSheet(_) => BlockType::CueSheet,
+            MetadataBlock::Picture(_) => BlockType::Picture,
+            MetadataBlock::Other { block_type, .. } => *block_type,
+        }
+    }
+
+    /// Encode the block body into bytes
+    pub fn to_bytes(&self) -> Vec <u8> {
+        match self {
+            MetadataBlock::Padding(length) => vec![0; *length as usize],
+            MetadataBlock::Application { id, data } => [&id[..], data].concat(),
+            MetadataBlock::SeekTable(seek_table) => seek_table.to_bytes(),
+            MetadataBlock::VorbisComment(vorbis_comment) => vorbis_comment.to_bytes(),
+            MetadataBlock::CueSheet(cue_sheet) => cue_sheet.to_bytes(),
+            MetadataBlock::Picture(picture) => picture.to_bytes(),
+            MetadataBlock::Other { data, .. } => data.clone(),
+        }
+    }
+
+    /// Parse the body of a block of the given type
+    ///
+    /// # Errors
+    /// Returns the error of the parser of the block type.
+    pub fn parse(block_type: BlockType, bytes: &[u8]) -> Result <MetadataBlock, MetadataError> {
+        match block_type {
+            BlockType::Padding => Ok(MetadataBlock::Padding(bytes.len() as u32)),
+            BlockType::Application => {
+                let id = bytes.get(..4).ok_or(MetadataError::UnexpectedEndError)?;
+                Ok(MetadataBlock::Application { id: [id[0], id[1], id[2], id[3]], data: bytes[4..].to_vec() })
+            }
+            BlockType::SeekTable => Ok(MetadataBlock::SeekTable(SeekTable::parse(bytes)?)),
+            BlockType::VorbisComment => Ok(MetadataBlock::VorbisComment(VorbisComment::parse(bytes)?)),
+            BlockType::CueSheet => Ok(MetadataBlock::CueSheet(CueSheet::parse(bytes)?)),
+            BlockType::Picture => Ok(MetadataBlock::Picture(Picture::parse(bytes)?)),
+            _ => Ok(MetadataBlock::Other { block_type, data: bytes.to_vec() }),
+        }
+    }
+
+    /// Write the block with its header
+    ///
+    /// # Errors
+    /// `BlockTooLargeError` is returned if the body is longer than `MAX_BLOCK_LENGTH` bytes.
+    pub fn write(&self, writer: &mut BitWriter, is_last: bool) -> Result <(), MetadataError> {
+        let body = self.to_bytes();
+        if body.len() > MAX_BLOCK_LENGTH {
+            return Err(MetadataError::BlockTooLargeError);
+        }
+        write_block_header(writer, is_last, self.block_type(), body.len() as u32);
+        writer.write_bytes(&body);
+        Ok(())
+    }
+}
+
+impl error::Error for MetadataError {}
+
+impl fmt::Display for MetadataError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", match self {
+            MetadataError::UnexpectedEndError => "Unexpected end of metadata block",
+            MetadataError::InvalidFieldNameError => "Invalid Vorbis comment field name",
+            MetadataError::InvalidUtf8Error => "Invalid UTF-8 string in metadata block",
+            MetadataError::BlockTooLargeError => "Metadata block too large",
+            MetadataError::UnknownImageError => "Unknown image format, expected PNG or JPEG",
+            MetadataError::InvalidForeignMetadataError => "Invalid foreign RIFF metadata",
+        })
+    }
+}
+
+/// Write the header of a metadata block
+///
+/// `length` is the size of the block body in bytes, excluding the 4-byte header.
+pub fn write_block_header(writer: &mut BitWriter, is_last: bool, block_type: BlockType, length: u32) {
+    writer.write_bits(is_last as u64, 1);
+    writer.write_bits(block_type.code() as u64, 7);
+    writer.write_bits(length as u64, 24);
+}
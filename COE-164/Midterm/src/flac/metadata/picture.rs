@@ -0,0 +1,319 @@
+use super::MetadataError;
+
+/// Represents the kind of a picture, as defined by ID3v2 APIC frames
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PictureType {
+    Other,
+    FileIcon,
+    OtherFileIcon,
+    FrontCover,
+    BackCover,
+    LeafletPage,
+    Media,
+    LeadArtist,
+    Artist,
+    Conductor,
+    Band,
+    Composer,
+    Lyricist,
+    RecordingLocation,
+    DuringRecording,
+    DuringPerformance,
+    ScreenCapture,
+    BrightColoredFish,
+    Illustration,
+    BandLogo,
+    PublisherLogo,
+}
+
+/// Represents the PICTURE metadata block
+///
+/// `depth` is the number of bits per pixel, and `n_colors` is the number of
+/// colors of an indexed picture or zero otherwise.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Picture {
+    pub picture_type: PictureType,
+    pub mime_type: String,
+    pub description: String,
+    pub width: u32,
+    pub height: u32,
+    pub depth: u32,
+    pub n_colors: u32,
+    pub data: Vec <u8>,
+}
+
+/// Represents the dimensions and format read from the header of an image
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImageInfo {
+    pub mime_type: &'static str,
+    pub width: u32,
+    pub height: u32,
+    pub depth: u32,
+    pub n_colors: u32,
+}
+
+const PICTURE_TYPES: [PictureType; 21] = [
+    PictureType::Other,
+    PictureType::FileIcon,
+    PictureType::OtherFileIcon,
+    PictureType::FrontCover,
+    PictureType::BackCover,
+    PictureType::LeafletPage,
+    PictureType::Media,
+    PictureType::LeadArtist,
+    PictureType::Artist,
+    PictureType::Conductor,
+    PictureType::Band,
+    PictureType::Composer,
+    PictureType::Lyricist,
+    PictureType::RecordingLocation,
+    PictureType::DuringRecording,
+    PictureType::DuringPerformance,
+    PictureType::ScreenCapture,
+    PictureType::BrightColoredFish,
+    PictureType::Illustration,
+    PictureType::BandLogo,
+    PictureType::PublisherLogo,
+];
+
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+
+impl PictureType {
+    /// Get the code of the picture type
+    pub fn code(&self) -> u32 {
+        PICTURE_TYPES.iter().position(|picture_type| picture_type == self).unwrap_or(0) as u32
+    }
+
+    /// Get the picture type of a code, if it is defined
+    pub fn from_code(code: u32) -> Option <PictureType> {
+        PICTURE_TYPES.get(code as usize).copied()
+    }
+}
+
+impl ImageInfo {
+    /// Read the dimensions and format of a PNG or JPEG image from its header
+    ///
+    /// `None` is returned if the image is in neither format or its header is truncated.
+    pub fn detect(data: &[u8]) -> Option <ImageInfo> {
+        if data.starts_with(&PNG_SIGNATURE) {
+            ImageInfo::detect_png(data)
+        } else if data.starts_with(&[0xFF, 0xD8]) {
+            ImageInfo::detect_jpeg(data)
+        } else {
+            None
+        }
+    }
+
+    /// Read the IHDR chunk, which always comes first
+    fn detect_png(data: &[u8]) -> Option <ImageInfo> {
+        let ihdr = data.get(8..29)?;
+        if &ihdr[4..8] != b"IHDR" {
+            return None;
+        }
+        let width = u32::from_be_bytes([ihdr[8], ihdr[9], ihdr[10], ihdr[11]]);
+        let height = u32::from_be_bytes([ihdr[12], ihdr[13], ihdr[14], ihdr[15]]);
+        let bit_depth = ihdr[16] as u32;
+
+        // Number of samples per pixel of each color type
+        // Palette indices are at most 8 bits, which also keeps the number of colors from overflowing
+        let (depth, n_colors) = match ihdr[17] {
+            0 => (bit_depth, 0),
+            2 => (bit_depth * 3, 0),
+            3 if bit_depth <= 8 => (bit_depth, 1 << bit_depth),
+            4 => (bit_depth * 2, 0),
+            6 => (bit_depth * 4, 0),
+            _ => return None,
+        };
+        Some(ImageInfo { mime_type: "image/png", width, height, depth, n_colors })
+    }
+
+    /// Walk the markers until the first start-of-frame segment
+    fn detect_jpeg(data: &[u8]) -> Option <ImageInfo> {
+        let mut pos = 2;
+        loop {
+            // Markers may be preceded by any number of 0xFF fill bytes
+            while *data.get(pos)? == 0xFF && *data.get(pos + 1)? == 0xFF {
+                pos += 1;
+            }
+            if *data.get(pos)? != 0xFF {
+                return None;
+            }
+            let marker = *data.get(pos + 1)?;
+            pos += 2;
+
+            // Standalone markers have no length
+            if marker == 0x01 || (0xD0..=0xD7).contains(&marker) {
+                continue;
+            }
+            if marker == 0xD9 || marker == 0xDA {
+                return None;
+            }
+
+            let segment = data.get(pos..pos + 2)?;
+            let len = u16::from_be_bytes([segment[0], segment[1]]) as usize;
+            let is_start_of_frame = matches!(marker, 0xC0..=0xC3 | 0xC5..=0xC7 | 0xC9..=0xCB | 0xCD..=0xCF);
+            if is_start_of_frame {
+                let frame = data.get(pos + 2..pos + 8)?;
+                let precision = frame[0] as u32;
+                let height = u16::from_be_bytes([frame[1], frame[2]]) as u32;
+                let width = u16::from_be_bytes([frame[3], frame[4]]) as u32;
+                let n_components = frame[5] as u32;
+                return Some(ImageInfo { mime_type: "image/jpeg", width, height, depth: precision * n_components, n_colors: 0 });
+            }
+            pos += len;
+        }
+    }
+}
+
+impl Picture {
+    /// Build a picture from a PNG or JPEG image, reading its format and dimensions from its header
+    ///
+    /// # Errors
+    /// `UnknownImageError` is returned if the image is neither a PNG nor a JPEG image.
+    pub fn from_image(picture_type: PictureType, description: &str, data: Vec <u8>) -> Result <Picture, MetadataError> {
+        let info = ImageInfo::detect(&data).ok_or(MetadataError::UnknownImageError)?;
+        Ok(Picture {
+            picture_type,
+            mime_type: info.mime_type.to_string(),
+            description: description.to_string(),
+            width: info.width,
+            height: info.height,
+            depth: info.depth,
+            n_colors: info.n_colors,
+            data,
+        })
+    }
+
+    /// Encode the block body into bytes
+    ///
+    /// Every number is a big-endian 32-bit integer, and the strings and picture
+    /// data are prefixed with their length.
+    pub fn to_bytes(&self) -> Vec <u8> {
+        let mut bytes = Vec::with_capacity(32 + self.mime_type.len() + self.description.len() + self.data.len());
+        bytes.extend_from_slice(&self.picture_type.code().to_be_bytes());
+        bytes.extend_from_slice(&(self.mime_type.len() as u32).to_be_bytes());
+        bytes.extend_from_slice(self.mime_type.as_bytes());
+        bytes.extend_from_slice(&(self.description.len() as u32).to_be_bytes());
+        bytes.extend_from_slice(self.description.as_bytes());
+        for value in [self.width, self.height, self.depth, self.n_colors, self.data.len() as u32] {
+            bytes.extend_from_slice(&value.to_be_bytes());
+        }
+        bytes.extend_from_slice(&self.data);
+        bytes
+    }
+
+    /// Parse the block body
+    ///
+    /// Picture types beyond the defined ones are read as `PictureType::Other`.
+    ///
+    /// # Errors
+    /// `UnexpectedEndError` is returned if a length runs past the end of `bytes`,
+    /// and `InvalidUtf8Error` if the MIME type or description is not valid UTF-8.
+    pub fn parse(bytes: &[u8]) -> Result <Picture, MetadataError> {
+        let mut pos = 0;
+        let picture_type = PictureType::from_code(Picture::read_u32(bytes, &mut pos)?).unwrap_or(PictureType::Other);
+        let mime_type = Picture::read_string(bytes, &mut pos)?;
+        let description = Picture::read_string(bytes, &mut pos)?;
+        let width = Picture::read_u32(bytes, &mut pos)?;
+        let height = Picture::read_u32(bytes, &mut pos)?;
+        let depth = Picture::read_u32(bytes, &mut pos)?;
+        let n_colors = Picture::read_u32(bytes, &mut pos)?;
+        let data = Picture::read_bytes(bytes, &mut pos)?.to_vec();
+
+        Ok(Picture { picture_type, mime_type, description, width, height, depth, n_colors, data })
+    }
+
+    fn read_u32(bytes: &[u8], pos: &mut usize) -> Result <u32, MetadataError> {
+        let field = bytes.get(*pos..*pos + 4).ok_or(MetadataError::UnexpectedEndError)?;
+        *pos += 4;
+        Ok(u32::from_be_bytes([field[0], field[1], field[2], field[3]]))
+    }
+
+    fn read_bytes <'a> (bytes: &'a [u8], pos: &mut usize) -> Result <&'a [u8], MetadataError> {
+        let len = Picture::read_u32(bytes, pos)? as usize;
+        let field = bytes.get(*pos..*pos + len).ok_or(MetadataError::UnexpectedEndError)?;
+        *pos += len;
+        Ok(field)
+    }
+
+    fn read_string(bytes: &[u8], pos: &mut usize) -> Result <String, MetadataError> {
+        let field = Picture::read_bytes(bytes, pos)?;
+        String::from_utf8(field.to_vec()).map_err(|_| MetadataError::InvalidUtf8Error)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn png(width: u32, height: u32, bit_depth: u8, color_type: u8) -> Vec <u8> {
+        let mut data = PNG_SIGNATURE.to_vec();
+        data.extend_from_slice(&[0, 0, 0, 13]);
+        data.extend_from_slice(b"IHDR");
+        data.extend_from_slice(&width.to_be_bytes());
+        data.extend_from_slice(&height.to_be_bytes());
+        data.extend_from_slice(&[bit_depth, color_type, 0, 0, 0]);
+        data.extend_from_slice(&[0; 4]);
+        data
+    }
+
+    fn jpeg(width: u16, height: u16) -> Vec <u8> {
+        let mut data = vec![0xFF, 0xD8];
+        // APP0 segment, then a baseline start of frame with 3 components
+        data.extend_from_slice(&[0xFF, 0xE0, 0x00, 0x06, b'J', b'F', b'I', b'F']);
+        data.extend_from_slice(&[0xFF, 0xFF, 0xC0, 0x00, 0x11, 0x08]);
+        data.extend_from_slice(&height.to_be_bytes());
+        data.extend_from_slice(&width.to_be_bytes());
+        data.extend_from_slice(&[0x03, 0x01, 0x22, 0x00, 0x02, 0x11, 0x01, 0x03, 0x11, 0x01]);
+        data.extend_from_slice(&[0xFF, 0xD9]);
+        data
+    }
+
+    #[test]
+    fn test_detect_png() {
+        assert_eq!(ImageInfo::detect(&png(640, 480, 8, 6)), Some(ImageInfo { mime_type: "image/png", width: 640, height: 480, depth: 32, n_colors: 0 }));
+        assert_eq!(ImageInfo::detect(&png(16, 16, 4, 3)), Some(ImageInfo { mime_type: "image/png", width: 16, height: 16, depth: 4, n_colors: 16 }));
+    }
+
+    #[test]
+    fn test_detect_jpeg() {
+        assert_eq!(ImageInfo::detect(&jpeg(1200, 1000)), Some(ImageInfo { mime_type: "image/jpeg", width: 1200, height: 1000, depth: 24, n_colors: 0 }));
+    }
+
+    #[test]
+    fn test_detect_unknown_or_truncated() {
+        assert_eq!(ImageInfo::detect(b"GIF89a"), None);
+        assert_eq!(ImageInfo::detect(&png(1, 1, 8, 2)[..20]), None);
+        assert_eq!(ImageInfo::detect(&jpeg(1, 1)[..12]), None);
+    }
+
+    #[test]
+    fn test_detect_png_palette_depth_too_large() {
+        assert_eq!(ImageInfo::detect(&png(16, 16, 16, 3)), None);
+        assert_eq!(ImageInfo::detect(&png(16, 16, 255, 3)), None);
+        assert_eq!(Picture::from_image(PictureType::FrontCover, "", png(16, 16, 32, 3)), Err(MetadataError::UnknownImageError));
+    }
+
+    #[test]
+    fn test_picture_type_code() {
+        assert_eq!(PictureType::FrontCover.code(), 3);
+        assert_eq!(PictureType::from_code(20), Some(PictureType::PublisherLogo));
+        assert_eq!(PictureType::from_code(21), None);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let picture = Picture::from_image(PictureType::FrontCover, "Cover", jpeg(300, 200)).unwrap();
+        let bytes = picture.to_bytes();
+
+        assert_eq!(&bytes[..4], &[0, 0, 0, 3]);
+        assert_eq!(&bytes[4..18], b"\x00\x00\x00\x0Aimage/jpeg");
+        assert_eq!(Picture::parse(&bytes), Ok(picture));
+    }
+
+    #[test]
+    fn test_from_unknown_image() {
+        assert_eq!(Picture::from_image(PictureType::FrontCover, "", b"GIF89a".to_vec()), Err(MetadataError::UnknownImageError));
+    }
+}
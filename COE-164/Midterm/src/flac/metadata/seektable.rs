@@ -0,0 +1,119 @@
+use super::MetadataError;
+
+/// Sample number of a placeholder seek point
+pub const PLACEHOLDER: u64 = u64::MAX;
+
+/// Represents a seek point, which maps the first sample of a frame to its offset
+///
+/// `offset` is the number of bytes from the first frame to the frame, and
+/// `n_samples` is the block size of the frame.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SeekPoint {
+    pub sample_number: u64,
+    pub offset: u64,
+    pub n_samples: u16,
+}
+
+/// Represents the SEEKTABLE metadata block
+///
+/// The seek points are sorted by sample number, followed by any placeholder
+/// points, which reserve space for points to be filled in later.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct SeekTable {
+    pub points: Vec <SeekPoint>,
+}
+
+impl SeekPoint {
+    /// Size of a seek point in bytes
+    pub const LENGTH: usize = 18;
+
+    /// Create a placeholder seek point
+    pub fn placeholder() -> Self {
+        SeekPoint { sample_number: PLACEHOLDER, offset: 0, n_samples: 0 }
+    }
+
+    /// Check if the seek point is a placeholder
+    pub fn is_placeholder(&self) -> bool {
+        self.sample_number == PLACEHOLDER
+    }
+}
+
+impl SeekTable {
+    /// Create a table of `n_points` placeholder points
+    pub fn placeholders(n_points: usize) -> Self {
+        SeekTable { points: vec![SeekPoint::placeholder(); n_points] }
+    }
+
+    /// Get the last seek point at or before a sample, ignoring placeholders
+    pub fn find(&self, sample_number: u64) -> Option <&SeekPoint> {
+        self.points.iter()
+            .filter(|point| !point.is_placeholder() && point.sample_number <= sample_number)
+            .max_by_key(|point| point.sample_number)
+    }
+
+    /// Encode the block body into bytes
+    pub fn to_bytes(&self) -> Vec <u8> {
+        let mut bytes = Vec::with_capacity(self.points.len() * SeekPoint::LENGTH);
+        for point in &self.points {
+            bytes.extend_from_slice(&point.sample_number.to_be_bytes());
+            bytes.extend_from_slice(&point.offset.to_be_bytes());
+            bytes.extend_from_slice(&point.n_samples.to_be_bytes());
+        }
+        bytes
+    }
+
+    /// Parse the block body
+    ///
+    /// # Errors
+    /// `UnexpectedEndError` is returned if the body is not a whole number of seek points.
+    pub fn parse(bytes: &[u8]) -> Result <SeekTable, MetadataError> {
+        if !bytes.len().is_multiple_of(SeekPoint::LENGTH) {
+            return Err(MetadataError::UnexpectedEndError);
+        }
+        let points = bytes.chunks_exact(SeekPoint::LENGTH)
+            .map(|point| SeekPoint {
+                sample_number: u64::from_be_bytes(point[0..8].try_into().unwrap()),
+                offset: u64::from_be_bytes(point[8..16].try_into().unwrap()),
+                n_samples: u16::from_be_bytes([point[16], point[17]]),
+            })
+            .collect();
+        Ok(SeekTable { points })
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table() -> SeekTable {
+        SeekTable {
+            points: vec![
+                SeekPoint { sample_number: 0, offset: 0, n_samples: 4096 },
+                SeekPoint { sample_number: 40960, offset: 0x1234, n_samples: 4096 },
+                SeekPoint::placeholder(),
+            ],
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let bytes = table().to_bytes();
+
+        assert_eq!(bytes.len(), 3 * SeekPoint::LENGTH);
+        assert_eq!(&bytes[18..36], &[0, 0, 0, 0, 0, 0, 0xA0, 0, 0, 0, 0, 0, 0, 0, 0x12, 0x34, 0x10, 0x00]);
+        assert_eq!(&bytes[36..44], &[0xFF; 8]);
+        assert_eq!(SeekTable::parse(&bytes), Ok(table()));
+        assert_eq!(SeekTable::parse(&bytes[..20]), Err(MetadataError::UnexpectedEndError));
+    }
+
+    #[test]
+    fn test_find() {
+        let table = table();
+
+        assert_eq!(table.find(40959).map(|point| point.offset), Some(0));
+        assert_eq!(table.find(40960).map(|point| point.offset), Some(0x1234));
+        assert_eq!(table.find(u64::MAX).map(|point| point.offset), Some(0x1234));
+        assert_eq!(SeekTable::placeholders(2).find(0), None);
+    }
+}
@@ -0,0 +1,99 @@
+use crate::flac::decoder::bitreader::BitReader;
+use crate::flac::encoder::bitwriter::BitWriter;
+
+use super::{write_block_header, BlockType, MetadataError};
+
+/// Represents the STREAMINFO metadata block
+///
+/// Frame sizes of zero mean that they are unknown, as does an MD5 signature of
+/// all zeros.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StreamInfo {
+    pub min_block_size: u16,
+    pub max_block_size: u16,
+    pub min_frame_size: u32,
+    pub max_frame_size: u32,
+    pub sample_rate: u32,
+    pub n_channels: u8,
+    pub bps: u8,
+    pub total_samples: u64,
+    pub md5: [u8; 16],
+}
+
+impl StreamInfo {
+    /// Size of the block body in bytes
+    pub const LENGTH: u32 = 34;
+
+    /// Write the block with its header
+    pub fn write(&self, writer: &mut BitWriter, is_last: bool) {
+        write_block_header(writer, is_last, BlockType::StreamInfo, StreamInfo::LENGTH);
+        writer.write_bits(self.min_block_size as u64, 16);
+        writer.write_bits(self.max_block_size as u64, 16);
+        writer.write_bits(self.min_frame_size as u64, 24);
+        writer.write_bits(self.max_frame_size as u64, 24);
+        writer.write_bits(self.sample_rate as u64, 20);
+        writer.write_bits(self.n_channels as u64 - 1, 3);
+        writer.write_bits(self.bps as u64 - 1, 5);
+        writer.write_bits(self.total_samples, 36);
+        writer.write_bytes(&self.md5);
+    }
+
+    /// Parse the block body
+    ///
+    /// # Errors
+    /// `UnexpectedEndError` is returned if the body is shorter than `LENGTH` bytes.
+    pub fn parse(bytes: &[u8]) -> Result <StreamInfo, MetadataError> {
+        if bytes.len() < StreamInfo::LENGTH as usize {
+            return Err(MetadataError::UnexpectedEndError);
+        }
+        let mut reader = BitReader::new(bytes);
+        let mut read = |n_bits| reader.read_bits(n_bits).map_err(|_| MetadataError::UnexpectedEndError);
+
+        let min_block_size = read(16)? as u16;
+        let max_block_size = read(16)? as u16;
+        let min_frame_size = read(24)? as u32;
+        let max_frame_size = read(24)? as u32;
+        let sample_rate = read(20)? as u32;
+        let n_channels = read(3)? as u8 + 1;
+        let bps = read(5)? as u8 + 1;
+        let total_samples = read(36)?;
+        let mut md5 = [0; 16];
+        md5.copy_from_slice(&bytes[18..34]);
+
+        Ok(StreamInfo { min_block_size, max_block_size, min_frame_size, max_frame_size, sample_rate, n_channels, bps, total_samples, md5 })
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write() {
+        let stream_info = StreamInfo {
+            min_block_size: 4096,
+            max_block_size: 4096,
+            min_frame_size: 14,
+            max_frame_size: 0x123456,
+            sample_rate: 44100,
+            n_channels: 2,
+            bps: 16,
+            total_samples: 0x9_8765_4321,
+            md5: [0xAB; 16],
+        };
+        let mut writer = BitWriter::new();
+
+        stream_info.write(&mut writer, true);
+
+        let bytes = writer.into_bytes();
+        assert_eq!(bytes.len(), 4 + StreamInfo::LENGTH as usize);
+        assert_eq!(&bytes[..4], &[0x80, 0x00, 0x00, 0x22]);
+        assert_eq!(&bytes[4..14], &[0x10, 0x00, 0x10, 0x00, 0x00, 0x00, 0x0E, 0x12, 0x34, 0x56]);
+        // 20-bit sample rate, 3-bit channels, 5-bit sample size and 36-bit total samples
+        assert_eq!(&bytes[14..22], &[0x0A, 0xC4, 0x42, 0xF9, 0x87, 0x65, 0x43, 0x21]);
+        assert_eq!(&bytes[22..], &[0xAB; 16]);
+        assert_eq!(StreamInfo::parse(&bytes[4..]), Ok(stream_info));
+        assert_eq!(StreamInfo::parse(&bytes[4..20]), Err(MetadataError::UnexpectedEndError));
+    }
+}
@@ -0,0 +1,256 @@
+use crate::flac::encoder::bitwriter::BitWriter;
+
+use super::{MetadataBlock, MetadataError};
+
+/// Vendor string written by this encoder
+pub const VENDOR_STRING: &str = concat!("midp ", env!("CARGO_PKG_VERSION"));
+
+/// Represents the VORBIS_COMMENT metadata block
+///
+/// The comments are kept in the order they were added. A field may appear more
+/// than once, such as several `ARTIST` fields, and field names are compared
+/// case-insensitively. Unlike the rest of FLAC, the lengths in this block are
+/// little-endian.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VorbisComment {
+    pub vendor: String,
+    comments: Vec <(String, String)>,
+}
+
+impl VorbisComment {
+    /// Create an empty set of comments with the given vendor string
+    pub fn new(vendor: &str) -> Self {
+        VorbisComment { vendor: vendor.to_string(), comments: Vec::new() }
+    }
+
+    /// Check if a field name is valid
+    ///
+    /// Field names are not empty and contain only printable ASCII characters
+    /// from `0x20` through `0x7D`, except `=`.
+    pub fn is_valid_field(field: &str) -> bool {
+        !field.is_empty() && field.bytes().all(|byte| (0x20..=0x7D).contains(&byte) && byte != b'=')
+    }
+
+    /// Add a comment after every existing comment
+    ///
+    /// # Errors
+    /// `InvalidFieldNameError` is returned if `field` is not a valid field name.
+    pub fn add(&mut self, field: &str, value: &str) -> Result <(), MetadataError> {
+        if !VorbisComment::is_valid_field(field) {
+            return Err(MetadataError::InvalidFieldNameError);
+        }
+        self.comments.push((field.to_string(), value.to_string()));
+        Ok(())
+    }
+
+    /// Replace every comment of a field with a single comment
+    ///
+    /// The comment takes the place of the first existing comment of the field,
+    /// or is added last if there is none.
+    pub fn set(&mut self, field: &str, value: &str) -> Result <(), MetadataError> {
+        if !VorbisComment::is_valid_field(field) {
+            return Err(MetadataError::InvalidFieldNameError);
+        }
+        match self.comments.iter().position(|(name, _)| name.eq_ignore_ascii_case(field)) {
+            Some(first) => {
+                let comments = std::mem::take(&mut self.comments);
+                for (i, (name, old_value)) in comments.into_iter().enumerate() {
+                    if i == first {
+                        self.comments.push((field.to_string(), value.to_string()));
+                    } else if !name.eq_ignore_ascii_case(field) {
+                        self.comments.push((name, old_value));
+                    }
+                }
+            }
+            None => self.comments.push((field.to_string(), value.to_string())),
+        }
+        Ok(())
+    }
+
+    /// Get every value of a field, in order
+    pub fn get(&self, field: &str) -> Vec <&str> {
+        self.comments.iter()
+            .filter(|(name, _)| name.eq_ignore_ascii_case(field))
+            .map(|(_, value)| value.as_str())
+            .collect()
+    }
+
+    /// Remove every comment of a field and return how many were removed
+    pub fn remove(&mut self, field: &str) -> usize {
+        let len = self.comments.len();
+        self.comments.retain(|(name, _)| !name.eq_ignore_ascii_case(field));
+        len - self.comments.len()
+    }
+
+    /// Iterate over every field name and value, in order
+    pub fn iter(&self) -> impl Iterator <Item = (&str, &str)> {
+        self.comments.iter().map(|(name, value)| (name.as_str(), value.as_str()))
+    }
+
+    /// Get the number of comments
+    pub fn len(&self) -> usize {
+        self.comments.len()
+    }
+
+    /// Check if there are no comments
+    pub fn is_empty(&self) -> bool {
+        self.comments.is_empty()
+    }
+
+    /// Split a `FIELD=VALUE` tag into its field name and value
+    ///
+    /// The value may itself contain `=`.
+    ///
+    /// # Errors
+    /// `InvalidFieldNameError` is returned if there is no `=` or the field name is invalid.
+    pub fn parse_tag(tag: &str) -> Result <(String, String), MetadataError> {
+        let (field, value) = tag.split_once('=').ok_or(MetadataError::InvalidFieldNameError)?;
+        if !VorbisComment::is_valid_field(field) {
+            return Err(MetadataError::InvalidFieldNameError);
+        }
+        Ok((field.to_string(), value.to_string()))
+    }
+
+    /// Encode the block body into bytes
+    ///
+    /// The body holds the length-prefixed vendor string, the number of comments
+    /// and each length-prefixed `FIELD=VALUE` comment.
+    pub fn to_bytes(&self) -> Vec <u8> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&(self.vendor.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(self.vendor.as_bytes());
+        bytes.extend_from_slice(&(self.comments.len() as u32).to_le_bytes());
+        for (field, value) in &self.comments {
+            bytes.extend_from_slice(&((field.len() + 1 + value.len()) as u32).to_le_bytes());
+            bytes.extend_from_slice(field.as_bytes());
+            bytes.push(b'=');
+            bytes.extend_from_slice(value.as_bytes());
+        }
+        bytes
+    }
+
+    /// Write the block with its header
+    pub fn write(&self, writer: &mut BitWriter, is_last: bool) -> Result <(), MetadataError> {
+        MetadataBlock::VorbisComment(self.clone()).write(writer, is_last)
+    }
+
+    /// Parse the block body
+    ///
+    /// # Errors
+    /// `UnexpectedEndError` is returned if a length runs past the end of `bytes`,
+    /// `InvalidUtf8Error` if a string is not valid UTF-8, and `InvalidFieldNameError`
+    /// if a comment has no `=` or an invalid field name.
+    pub fn parse(bytes: &[u8]) -> Result <VorbisComment, MetadataError> {
+        let mut pos = 0;
+        let vendor = VorbisComment::read_string(bytes, &mut pos)?;
+        let n_comments = VorbisComment::read_u32(bytes, &mut pos)?;
+
+        let mut vorbis_comment = VorbisComment::new(&vendor);
+        for _ in 0..n_comments {
+            let (field, value) = VorbisComment::parse_tag(&VorbisComment::read_string(bytes, &mut pos)?)?;
+            vorbis_comment.comments.push((field, value));
+        }
+        Ok(vorbis_comment)
+    }
+
+    fn read_u32(bytes: &[u8], pos: &mut usize) -> Result <u32, MetadataError> {
+        let field = bytes.get(*pos..*pos + 4).ok_or(MetadataError::UnexpectedEndError)?;
+        *pos += 4;
+        Ok(u32::from_le_bytes([field[0], field[1], field[2], field[3]]))
+    }
+
+    fn read_string(bytes: &[u8], pos: &mut usize) -> Result <String, MetadataError> {
+        let len = VorbisComment::read_u32(bytes, pos)? as usize;
+        let string = bytes.get(*pos..*pos + len).ok_or(MetadataError::UnexpectedEndError)?;
+        *pos += len;
+        String::from_utf8(string.to_vec()).map_err(|_| MetadataError::InvalidUtf8Error)
+    }
+}
+
+impl Default for VorbisComment {
+    fn default() -> Self {
+        VorbisComment::new(VENDOR_STRING)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tagged() -> VorbisComment {
+        let mut vorbis_comment = VorbisComment::new("midp");
+        vorbis_comment.add("ARTIST", "Alice").unwrap();
+        vorbis_comment.add("TITLE", "Song").unwrap();
+        vorbis_comment.add("artist", "Bob").unwrap();
+        vorbis_comment
+    }
+
+    #[test]
+    fn test_get_case_insensitive() {
+        let vorbis_comment = tagged();
+        assert_eq!(vorbis_comment.get("Artist"), vec!["Alice", "Bob"]);
+        assert_eq!(vorbis_comment.get("ALBUM"), Vec::<&str>::new());
+    }
+
+    #[test]
+    fn test_set_replaces_in_place() {
+        let mut vorbis_comment = tagged();
+        vorbis_comment.set("ARTIST", "Carol").unwrap();
+        let fields: Vec <(&str, &str)> = vorbis_comment.iter().collect();
+        assert_eq!(fields, vec![("ARTIST", "Carol"), ("TITLE", "Song")]);
+    }
+
+    #[test]
+    fn test_remove() {
+        let mut vorbis_comment = tagged();
+        assert_eq!(vorbis_comment.remove("artist"), 2);
+        assert_eq!(vorbis_comment.len(), 1);
+    }
+
+    #[test]
+    fn test_invalid_field_names() {
+        let mut vorbis_comment = VorbisComment::default();
+        assert_eq!(vorbis_comment.add("", "x"), Err(MetadataError::InvalidFieldNameError));
+        assert_eq!(vorbis_comment.add("A=B", "x"), Err(MetadataError::InvalidFieldNameError));
+        assert_eq!(vorbis_comment.add("TÍTULO", "x"), Err(MetadataError::InvalidFieldNameError));
+        assert_eq!(vorbis_comment.add("~", "x"), Err(MetadataError::InvalidFieldNameError));
+        assert!(vorbis_comment.is_empty());
+    }
+
+    #[test]
+    fn test_parse_tag() {
+        assert_eq!(VorbisComment::parse_tag("COMMENT=a=b"), Ok(("COMMENT".to_string(), "a=b".to_string())));
+        assert_eq!(VorbisComment::parse_tag("COMMENT"), Err(MetadataError::InvalidFieldNameError));
+    }
+
+    #[test]
+    fn test_to_bytes() {
+        let mut vorbis_comment = VorbisComment::new("ab");
+        vorbis_comment.add("A", "é").unwrap();
+        assert_eq!(vorbis_comment.to_bytes(), vec![
+            2, 0, 0, 0, b'a', b'b',
+            1, 0, 0, 0,
+            4, 0, 0, 0, b'A', b'=', 0xC3, 0xA9,
+        ]);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let vorbis_comment = tagged();
+        assert_eq!(VorbisComment::parse(&vorbis_comment.to_bytes()), Ok(vorbis_comment));
+    }
+
+    #[test]
+    fn test_parse_truncated() {
+        let bytes = tagged().to_bytes();
+        assert_eq!(VorbisComment::parse(&bytes[..bytes.len() - 1]), Err(MetadataError::UnexpectedEndError));
+    }
+
+    #[test]
+    fn test_write_header() {
+        let mut writer = BitWriter::new();
+        VorbisComment::new("ab").write(&mut writer, true).unwrap();
+        assert_eq!(writer.into_bytes()[..4], [0x84, 0x00, 0x00, 0x0A]);
+    }
+}
@@ -1,2 +1,7 @@
+pub mod decoder;
 pub mod encoder;
 pub mod lpc;
+pub mod metadata;
+pub mod sample;
+#[cfg(test)]
+pub mod test_util;
@@ -0,0 +1,85 @@
+use std::fmt;
+
+/// Represents the integer type holding the samples of a channel
+///
+/// The encoder is generic over the sample type, so 16-bit audio is held in
+/// `i16` instead of `i64`. Predictions and residuals need more bits than the
+/// samples, so they are still computed in `i64`, or `i128` if even that may
+/// overflow.
+pub trait Sample: Copy + Default + PartialEq + fmt::Debug + Into <i64> + 'static {
+    /// Number of bits of the type
+    const BITS: u8;
+
+    /// Convert a value that fits in the type, truncating it otherwise
+    fn from_i64(value: i64) -> Self;
+
+    /// Convert the sample to an `i64`
+    fn to_i64(self) -> i64 {
+        self.into()
+    }
+
+    /// Check whether the type holds samples of `bps` bits
+    fn holds(bps: u8) -> bool {
+        bps <= Self::BITS
+    }
+
+    /// Get the largest magnitude of a sample of the type
+    fn max_magnitude() -> u64 {
+        1 << (Self::BITS - 1)
+    }
+}
+
+impl Sample for i8 {
+    const BITS: u8 = 8;
+
+    fn from_i64(value: i64) -> Self {
+        value as i8
+    }
+}
+
+impl Sample for i16 {
+    const BITS: u8 = 16;
+
+    fn from_i64(value: i64) -> Self {
+        value as i16
+    }
+}
+
+impl Sample for i32 {
+    const BITS: u8 = 32;
+
+    fn from_i64(value: i64) -> Self {
+        value as i32
+    }
+}
+
+impl Sample for i64 {
+    const BITS: u8 = 64;
+
+    fn from_i64(value: i64) -> Self {
+        value
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_conversions() {
+        assert_eq!(i16::from_i64(-32768).to_i64(), -32768);
+        assert_eq!(i8::from_i64(200), -56);
+        assert_eq!(i32::from_i64(i32::MIN as i64), i32::MIN);
+    }
+
+    #[test]
+    fn test_holds() {
+        assert!(i8::holds(8));
+        assert!(!i16::holds(17));
+        assert!(i32::holds(24));
+        assert!(i64::holds(32));
+        assert_eq!(i16::max_magnitude(), 32768);
+        assert_eq!(i64::max_magnitude(), 1 << 63);
+    }
+}
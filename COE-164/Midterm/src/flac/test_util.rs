@@ -0,0 +1,22 @@
+/// Represents a xorshift64 generator of pseudo-random numbers for tests
+///
+/// The same seed always gives the same numbers, so test inputs are stable.
+pub struct XorShift(u64);
+
+impl XorShift {
+    /// Start a generator from a nonzero seed
+    pub fn new(seed: u64) -> Self {
+        XorShift(seed)
+    }
+}
+
+impl Iterator for XorShift {
+    type Item = u64;
+
+    fn next(&mut self) -> Option <u64> {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        Some(self.0)
+    }
+}
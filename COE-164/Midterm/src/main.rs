@@ -1,22 +1,13 @@
+pub mod cli;
 pub mod wav;
 pub mod flac;
+pub mod ogg;
 
 fn main() {
-    let file_path = String::from("sample_audio/ietf_01.wav");
+    let args: Vec <String> = std::env::args().skip(1).collect();
 
-    let wave_obj = wav::WaveReader::open_pcm(&file_path);
-
-    if let Err(e) = wave_obj {
-        println!("Error while reading file {}: {}", file_path, e);
-    }
-    else if let Ok(wave_obj_safe) = wave_obj {
-        println!("File {} opened successfully!", file_path);
-        println!("{}", wave_obj_safe);
-
-        for each_chunk in wave_obj_safe.data_chunks {
-            for (i, each_sample) in each_chunk.chunks_byte_rate().enumerate() {
-                println!("each sample index {} len {}", i, each_sample.len());
-            }
-        }
+    if let Err(e) = cli::run(&args) {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
     }
 }
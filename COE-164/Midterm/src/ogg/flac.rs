@@ -0,0 +1,208 @@
+use crate::flac::encoder::bitwriter::BitWriter;
+use crate::flac::metadata::{MetadataBlock, MetadataError, StreamInfo, VorbisComment};
+
+use super::{OggError, OggReader, OggWriter};
+
+/// First byte of the header packet of an Ogg FLAC stream
+pub const PACKET_TYPE: u8 = 0x7F;
+/// Major version of the Ogg FLAC mapping
+pub const MAPPING_MAJOR_VERSION: u8 = 1;
+/// Minor version of the Ogg FLAC mapping
+pub const MAPPING_MINOR_VERSION: u8 = 0;
+/// Size of the header packet, which ends with the STREAMINFO block, in bytes
+pub const HEADER_PACKET_LENGTH: usize = 51;
+
+/// Represents a writer of FLAC frames into an Ogg stream
+///
+/// The first page holds only the header packet, which carries the STREAMINFO
+/// block. Each other metadata block is its own packet, starting with the
+/// VORBIS_COMMENT block the mapping requires, and the frames start on a new
+/// page with one frame per packet. The granule position of a page is the
+/// number of samples up to the end of its last complete frame.
+pub struct OggFlacWriter {
+    writer: OggWriter,
+    granule_position: u64,
+}
+
+/// Represents a reader of the FLAC stream held in an Ogg stream
+pub struct OggFlacReader;
+
+impl OggFlacWriter {
+    /// Start an Ogg FLAC stream and write its header packets
+    ///
+    /// The VORBIS_COMMENT block of `metadata` is moved to the front, and an
+    /// empty one is added if there is none.
+    ///
+    /// # Errors
+    /// `BlockTooLargeError` is returned if a metadata block does not fit in a block.
+    pub fn new(serial: u32, stream_info: &StreamInfo, metadata: &[MetadataBlock]) -> Result <Self, MetadataError> {
+        let default_comment = MetadataBlock::VorbisComment(VorbisComment::default());
+        let comment = metadata.iter().position(|block| matches!(block, MetadataBlock::VorbisComment(_)));
+        let blocks: Vec <&MetadataBlock> = std::iter::once(comment.map_or(&default_comment, |i| &metadata[i]))
+            .chain(metadata.iter().enumerate().filter(|&(i, _)| Some(i) != comment).map(|(_, block)| block))
+            .collect();
+
+        let mut header = BitWriter::new();
+        header.write_bytes(&[PACKET_TYPE]);
+        header.write_bytes(b"FLAC");
+        header.write_bytes(&[MAPPING_MAJOR_VERSION, MAPPING_MINOR_VERSION]);
+        header.write_bytes(&(blocks.len() as u16).to_be_bytes());
+        header.write_bytes(b"fLaC");
+        stream_info.write(&mut header, false);
+
+        let mut writer = OggWriter::new(serial);
+        writer.write_packet(&header.into_bytes(), 0);
+        writer.flush();
+        for (i, block) in blocks.iter().enumerate() {
+            let mut packet = BitWriter::new();
+            block.write(&mut packet, i + 1 == blocks.len())?;
+            writer.write_packet(&packet.into_bytes(), 0);
+        }
+        writer.flush();
+
+        Ok(OggFlacWriter { writer, granule_position: 0 })
+    }
+
+    /// Write a frame of `block_size` samples as its own packet
+    pub fn write_frame(&mut self, frame: &[u8], block_size: u64) {
+        self.granule_position += block_size;
+        self.writer.write_packet(frame, self.granule_position);
+    }
+
+    /// Write the last page and get the Ogg stream
+    pub fn finish(self) -> Vec <u8> {
+        self.writer.finish()
+    }
+}
+
+impl OggFlacReader {
+    /// Rebuild the native FLAC stream held in an Ogg FLAC stream
+    ///
+    /// The metadata packets are kept as they are, so the last block keeps its
+    /// last-metadata-block flag. Packets that start with a frame sync code are
+    /// frames, which lets streams that do not give their number of header
+    /// packets be read too.
+    ///
+    /// # Errors
+    /// Returns the errors of `OggReader::read_packet`, `NotFlacError` if the
+    /// first packet is not an Ogg FLAC header packet, and `VersionError` if its
+    /// major mapping version is not supported.
+    pub fn to_native(data: &[u8]) -> Result <Vec <u8>, OggError> {
+        let mut reader = OggReader::new(data);
+        let header = reader.read_packet()?.ok_or(OggError::NotFlacError)?;
+        if header.len() != HEADER_PACKET_LENGTH || header[0] != PACKET_TYPE || &header[1..5] != b"FLAC" || &header[9..13] != b"fLaC" {
+            return Err(OggError::NotFlacError);
+        }
+        if header[5] != MAPPING_MAJOR_VERSION {
+            return Err(OggError::VersionError);
+        }
+
+        let mut bytes = header[9..].to_vec();
+        while let Some(packet) = reader.read_packet()? {
+            bytes.extend_from_slice(&packet);
+        }
+        Ok(bytes)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::flac::decoder::stream::FlacDecoder;
+    use crate::flac::encoder::stream::FlacEncoderOptions;
+    use crate::flac::metadata::VorbisComment;
+    use crate::ogg::{OggPage, BEGIN_OF_STREAM, END_OF_STREAM};
+
+    fn pages(bytes: &[u8]) -> Vec <OggPage> {
+        let mut pages = Vec::new();
+        let mut pos = 0;
+        while pos < bytes.len() {
+            let (page, len) = OggPage::parse(&bytes[pos..]).unwrap();
+            pages.push(page);
+            pos += len;
+        }
+        pages
+    }
+
+    fn channels() -> Vec <Vec <i64>> {
+        vec![(0..10000).map(|i| ((i as f64 * 0.01).sin() * 3000.0) as i64).collect(), (0..10000).map(|i| i % 100).collect()]
+    }
+
+    #[test]
+    fn test_page_layout() {
+        let mut vorbis_comment = VorbisComment::new("midp");
+        vorbis_comment.add("TITLE", "Song").unwrap();
+        let bytes = FlacEncoderOptions::new(44100, 2, 16)
+            .metadata_block(MetadataBlock::VorbisComment(vorbis_comment))
+            .ogg(true)
+            .encode(&channels())
+            .unwrap();
+        let pages = pages(&bytes);
+
+        // The first page holds only the header packet
+        assert_eq!(pages[0].flags, BEGIN_OF_STREAM);
+        assert_eq!(pages[0].segments, vec![HEADER_PACKET_LENGTH as u8]);
+        assert_eq!(&pages[0].data[..13], b"\x7FFLAC\x01\x00\x00\x01fLaC");
+        assert_eq!(pages[0].granule_position, 0);
+        // The VORBIS_COMMENT block, with the last-metadata-block flag
+        assert_eq!(pages[1].segments.len(), 1);
+        assert_eq!(pages[1].data[0], 0x84);
+        assert_eq!(pages[1].granule_position, 0);
+        // The frames start on a new page
+        assert_eq!(&pages[2].data[..2], &[0xFF, 0xF8]);
+
+        let last = pages.last().unwrap();
+        assert_eq!(last.flags & END_OF_STREAM, END_OF_STREAM);
+        assert_eq!(last.granule_position, 10000);
+        assert!(pages.iter().all(|page| page.serial == pages[0].serial));
+    }
+
+    #[test]
+    fn test_vorbis_comment_first() {
+        let untagged = FlacEncoderOptions::new(44100, 2, 16).padding(8).ogg(true).encode(&channels()).unwrap();
+        let tagged = FlacEncoderOptions::new(44100, 2, 16)
+            .metadata_block(MetadataBlock::Padding(4))
+            .metadata_block(MetadataBlock::VorbisComment(VorbisComment::new("midp")))
+            .ogg(true)
+            .encode(&channels())
+            .unwrap();
+
+        // An empty VORBIS_COMMENT block is added, or the one given is moved, before the PADDING block
+        for (bytes, vorbis_comment) in [(untagged, VorbisComment::default()), (tagged, VorbisComment::new("midp"))] {
+            let pages = pages(&bytes);
+            assert_eq!(&pages[0].data[7..9], &[0x00, 0x02]);
+            assert_eq!(pages[0].data[13], 0x00);
+            let comment_len = pages[1].segments[0] as usize;
+            assert_eq!(pages[1].segments.len(), 2);
+            assert_eq!(pages[1].data[0], 0x04);
+            assert_eq!(VorbisComment::parse(&pages[1].data[4..comment_len]), Ok(vorbis_comment));
+            assert_eq!(pages[1].data[comment_len], 0x81);
+            assert_eq!(&pages[2].data[..2], &[0xFF, 0xF8]);
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let channels = channels();
+        let native = FlacEncoderOptions::new(44100, 2, 16)
+            .block_size(1152)
+            .metadata_block(MetadataBlock::VorbisComment(VorbisComment::default()))
+            .encode(&channels)
+            .unwrap();
+        let ogg = FlacEncoderOptions::new(44100, 2, 16).block_size(1152).ogg(true).encode(&channels).unwrap();
+
+        let unwrapped = OggFlacReader::to_native(&ogg).unwrap();
+        assert_eq!(unwrapped, native);
+        let mut decoder = FlacDecoder::new(&unwrapped).unwrap();
+        assert_eq!(decoder.read_samples(u64::MAX).unwrap(), channels);
+    }
+
+    #[test]
+    fn test_not_flac() {
+        let mut writer = OggWriter::new(1);
+        writer.write_packet(b"\x01vorbis", 0);
+        assert_eq!(OggFlacReader::to_native(&writer.finish()), Err(OggError::NotFlacError));
+        assert_eq!(OggFlacReader::to_native(&[]), Err(OggError::NotFlacError));
+    }
+}
@@ -0,0 +1,382 @@
+use core::fmt;
+use std::error;
+
+use crate::flac::encoder::crc::CrcOptions;
+
+pub mod flac;
+
+/// Flag of a page that continues a packet from the previous page
+pub const CONTINUED: u8 = 0x01;
+/// Flag of the first page of a logical stream
+pub const BEGIN_OF_STREAM: u8 = 0x02;
+/// Flag of the last page of a logical stream
+pub const END_OF_STREAM: u8 = 0x04;
+/// Granule position of a page on which no packet ends
+pub const NO_GRANULE: u64 = u64::MAX;
+/// Size of the page data after which the writer starts a new page
+pub const PAGE_DATA_TARGET: usize = 4096;
+
+/// Size of a page header without its segment table, in bytes
+const HEADER_LENGTH: usize = 27;
+
+/// Represents an Ogg page
+///
+/// `segments` is the segment table, which gives the size of each segment of
+/// `data`. A packet is split into segments of 255 bytes followed by one
+/// shorter segment, so a segment of less than 255 bytes ends a packet.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OggPage {
+    pub flags: u8,
+    pub granule_position: u64,
+    pub serial: u32,
+    pub sequence: u32,
+    pub segments: Vec <u8>,
+    pub data: Vec <u8>,
+}
+
+/// Represents a writer of the pages of a logical stream
+///
+/// Packets are added to the current page until it holds `PAGE_DATA_TARGET`
+/// bytes or 255 segments, and packets that do not fit are continued on the
+/// next page.
+pub struct OggWriter {
+    serial: u32,
+    sequence: u32,
+    page: OggPage,
+    bytes: Vec <u8>,
+}
+
+/// Represents a reader of the packets of a logical stream
+pub struct OggReader <'a> {
+    data: &'a [u8],
+    pos: usize,
+    serial: Option <u32>,
+    sequence: u32,
+    segments: Vec <u8>,
+    page_data: &'a [u8],
+    is_continued: bool,
+}
+
+// Represents possible errors in reading Ogg pages
+#[derive(Debug)]
+#[derive(PartialEq)]
+pub enum OggError {
+    CaptureError,
+    VersionError,
+    CrcMismatchError,
+    UnexpectedEndError,
+    SequenceError,
+    NotFlacError,
+}
+
+impl OggPage {
+    /// Compute the CRC-32 of a page whose CRC field is zero
+    pub fn crc32(bytes: &[u8]) -> u32 {
+        CrcOptions::crc32_ogg().build(bytes)
+    }
+
+    /// Encode the page into bytes, including its CRC-32
+    pub fn to_bytes(&self) -> Vec <u8> {
+        let mut bytes = Vec::with_capacity(HEADER_LENGTH + self.segments.len() + self.data.len());
+        bytes.extend_from_slice(b"OggS");
+        bytes.push(0);
+        bytes.push(self.flags);
+        bytes.extend_from_slice(&self.granule_position.to_le_bytes());
+        bytes.extend_from_slice(&self.serial.to_le_bytes());
+        bytes.extend_from_slice(&self.sequence.to_le_bytes());
+        bytes.extend_from_slice(&[0; 4]);
+        bytes.push(self.segments.len() as u8);
+        bytes.extend_from_slice(&self.segments);
+        bytes.extend_from_slice(&self.data);
+
+        let crc32 = OggPage::crc32(&bytes);
+        bytes[22..26].copy_from_slice(&crc32.to_le_bytes());
+        bytes
+    }
+
+    /// Parse a page at the start of `bytes`, returning it with its size in bytes
+    ///
+    /// # Errors
+    /// `CaptureError` is returned if the page does not start with `OggS`,
+    /// `VersionError` for a version other than 0, `UnexpectedEndError` if the
+    /// page runs past the end of `bytes`, and `CrcMismatchError` if its CRC-32
+    /// does not match.
+    pub fn parse(bytes: &[u8]) -> Result <(OggPage, usize), OggError> {
+        let header = bytes.get(..HEADER_LENGTH).ok_or(OggError::UnexpectedEndError)?;
+        if &header[0..4] != b"OggS" {
+            return Err(OggError::CaptureError);
+        }
+        if header[4] != 0 {
+            return Err(OggError::VersionError);
+        }
+        let n_segments = header[26] as usize;
+        let segments = bytes.get(HEADER_LENGTH..HEADER_LENGTH + n_segments).ok_or(OggError::UnexpectedEndError)?;
+        let data_start = HEADER_LENGTH + n_segments;
+        let data_len: usize = segments.iter().map(|&segment| segment as usize).sum();
+        let data = bytes.get(data_start..data_start + data_len).ok_or(OggError::UnexpectedEndError)?;
+
+        let mut page_bytes = bytes[..data_start + data_len].to_vec();
+        page_bytes[22..26].fill(0);
+        if OggPage::crc32(&page_bytes) != u32::from_le_bytes(header[22..26].try_into().unwrap()) {
+            return Err(OggError::CrcMismatchError);
+        }
+
+        let page = OggPage {
+            flags: header[5],
+            granule_position: u64::from_le_bytes(header[6..14].try_into().unwrap()),
+            serial: u32::from_le_bytes(header[14..18].try_into().unwrap()),
+            sequence: u32::from_le_bytes(header[18..22].try_into().unwrap()),
+            segments: segments.to_vec(),
+            data: data.to_vec(),
+        };
+        Ok((page, data_start + data_len))
+    }
+}
+
+impl OggWriter {
+    /// Create a writer of the logical stream with the given serial number
+    pub fn new(serial: u32) -> Self {
+        OggWriter {
+            serial,
+            sequence: 0,
+            page: OggWriter::empty_page(serial, BEGIN_OF_STREAM),
+            bytes: Vec::new(),
+        }
+    }
+
+    fn empty_page(serial: u32, flags: u8) -> OggPage {
+        OggPage { flags, granule_position: NO_GRANULE, serial, sequence: 0, segments: Vec::new(), data: Vec::new() }
+    }
+
+    /// Add a packet that ends at `granule_position`
+    ///
+    /// Full pages are written as the packet is added, but the page that holds
+    /// the end of the packet is kept open for the next packets.
+    pub fn write_packet(&mut self, packet: &[u8], granule_position: u64) {
+        let mut rest = packet;
+        let mut is_started = false;
+        loop {
+            if self.page.segments.len() == 255 || self.page.data.len() >= PAGE_DATA_TARGET {
+                self.write_page(false);
+                // Only a page that starts within the packet continues it
+                if is_started {
+                    self.page.flags |= CONTINUED;
+                }
+            }
+            is_started = true;
+            let len = rest.len().min(255);
+            self.page.segments.push(len as u8);
+            self.page.data.extend_from_slice(&rest[..len]);
+            rest = &rest[len..];
+
+            // A packet ends with a segment shorter than 255 bytes, which may be empty
+            if len < 255 {
+                break;
+            }
+        }
+        self.page.granule_position = granule_position;
+    }
+
+    /// Write the current page, if it holds any segment, and start a new page
+    pub fn flush(&mut self) {
+        if !self.page.segments.is_empty() {
+            self.write_page(false);
+        }
+    }
+
+    fn write_page(&mut self, is_last: bool) {
+        let next_page = OggWriter::empty_page(self.serial, 0);
+        let mut page = std::mem::replace(&mut self.page, next_page);
+        page.sequence = self.sequence;
+        if is_last {
+            page.flags |= END_OF_STREAM;
+        }
+        self.bytes.extend_from_slice(&page.to_bytes());
+        self.sequence += 1;
+    }
+
+    /// Write the last page, marked as the end of the stream, and get the stream
+    pub fn finish(mut self) -> Vec <u8> {
+        self.write_page(true);
+        self.bytes
+    }
+}
+
+impl <'a> OggReader <'a> {
+    /// Create a reader positioned at the first page of `data`
+    pub fn new(data: &'a [u8]) -> Self {
+        OggReader { data, pos: 0, serial: None, sequence: 0, segments: Vec::new(), page_data: &[], is_continued: false }
+    }
+
+    /// Read the next page and check that it follows the previous page
+    fn next_page(&mut self) -> Result <bool, OggError> {
+        if self.pos >= self.data.len() {
+            return Ok(false);
+        }
+        let (page, len) = OggPage::parse(&self.data[self.pos..])?;
+        let is_expected = match self.serial {
+            Some(serial) => page.serial == serial && page.sequence == self.sequence,
+            None => page.flags & BEGIN_OF_STREAM != 0,
+        };
+        // A continued page must follow an unfinished packet, and the other pages must not
+        if !is_expected || (page.flags & CONTINUED != 0) != self.is_continued {
+            return Err(OggError::SequenceError);
+        }
+
+        self.serial = Some(page.serial);
+        self.sequence = page.sequence.wrapping_add(1);
+        self.segments = page.segments.into_iter().rev().collect();
+        self.page_data = &self.data[self.pos + len - page.data.len()..self.pos + len];
+        self.pos += len;
+        Ok(true)
+    }
+
+    /// Read the next packet, which may be continued over several pages
+    ///
+    /// `None` is returned at the end of the stream.
+    ///
+    /// # Errors
+    /// Returns the errors of `OggPage::parse`, `SequenceError` if a page does
+    /// not follow the previous one, and `UnexpectedEndError` if the stream ends
+    /// within a packet.
+    pub fn read_packet(&mut self) -> Result <Option <Vec <u8>>, OggError> {
+        let mut packet = Vec::new();
+        loop {
+            while self.segments.is_empty() {
+                if !self.next_page()? {
+                    return match self.is_continued {
+                        true => Err(OggError::UnexpectedEndError),
+                        false => Ok(None),
+                    };
+                }
+            }
+            let len = self.segments.pop().unwrap() as usize;
+            packet.extend_from_slice(&self.page_data[..len]);
+            self.page_data = &self.page_data[len..];
+
+            self.is_continued = len == 255;
+            if !self.is_continued {
+                return Ok(Some(packet));
+            }
+        }
+    }
+}
+
+impl error::Error for OggError {}
+
+impl fmt::Display for OggError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", match self {
+            OggError::CaptureError => "Missing Ogg capture pattern",
+            OggError::VersionError => "Unsupported Ogg version",
+            OggError::CrcMismatchError => "Ogg page CRC mismatch",
+            OggError::UnexpectedEndError => "Unexpected end of Ogg stream",
+            OggError::SequenceError => "Ogg pages out of sequence",
+            OggError::NotFlacError => "Not an Ogg FLAC stream",
+        })
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn packets() -> Vec <Vec <u8>> {
+        vec![vec![1; 10], vec![2; 255], Vec::new(), vec![3; 70000], vec![4; 300]]
+    }
+
+    #[test]
+    fn test_page_round_trip() {
+        let page = OggPage { flags: BEGIN_OF_STREAM, granule_position: 1234, serial: 0xDEADBEEF, sequence: 0, segments: vec![255, 10], data: vec![5; 265] };
+        let bytes = page.to_bytes();
+
+        assert_eq!(&bytes[..6], b"OggS\x00\x02");
+        assert_eq!(bytes[26], 2);
+        assert_eq!(OggPage::parse(&bytes), Ok((page, bytes.len())));
+    }
+
+    #[test]
+    fn test_page_errors() {
+        let mut bytes = OggPage { flags: 0, granule_position: 0, serial: 1, sequence: 0, segments: vec![1], data: vec![9] }.to_bytes();
+
+        assert_eq!(OggPage::parse(&bytes[..28]), Err(OggError::UnexpectedEndError));
+        bytes[28] = 8;
+        assert_eq!(OggPage::parse(&bytes), Err(OggError::CrcMismatchError));
+        bytes[0] = b'X';
+        assert_eq!(OggPage::parse(&bytes), Err(OggError::CaptureError));
+    }
+
+    #[test]
+    fn test_packets_round_trip() {
+        let mut writer = OggWriter::new(7);
+        for (i, packet) in packets().iter().enumerate() {
+            writer.write_packet(packet, i as u64);
+            if i == 0 {
+                writer.flush();
+            }
+        }
+        let bytes = writer.finish();
+
+        let mut reader = OggReader::new(&bytes);
+        for packet in packets() {
+            assert_eq!(reader.read_packet(), Ok(Some(packet)));
+        }
+        assert_eq!(reader.read_packet(), Ok(None));
+    }
+
+    #[test]
+    fn test_page_layout() {
+        let mut writer = OggWriter::new(7);
+        for (i, packet) in packets().iter().enumerate() {
+            writer.write_packet(packet, i as u64);
+        }
+        let bytes = writer.finish();
+
+        let mut pages = Vec::new();
+        let mut pos = 0;
+        while pos < bytes.len() {
+            let (page, len) = OggPage::parse(&bytes[pos..]).unwrap();
+            pages.push(page);
+            pos += len;
+        }
+
+        assert_eq!(pages[0].flags, BEGIN_OF_STREAM);
+        // An empty segment ends the packet of 255 bytes
+        assert_eq!(&pages[0].segments[..4], &[10, 255, 0, 0]);
+        assert!(pages[1..].iter().all(|page| page.flags & CONTINUED != 0 || page.flags & END_OF_STREAM != 0));
+        // The large packet does not end on the first pages
+        assert_eq!(pages[1].granule_position, NO_GRANULE);
+        assert_eq!(pages.last().unwrap().flags & END_OF_STREAM, END_OF_STREAM);
+        assert_eq!(pages.last().unwrap().granule_position, 4);
+        assert!(pages.iter().enumerate().all(|(i, page)| page.sequence == i as u32));
+    }
+
+    #[test]
+    fn test_page_filled_at_packet_boundary() {
+        let mut writer = OggWriter::new(7);
+        writer.write_packet(&[1; PAGE_DATA_TARGET], 0);
+        writer.write_packet(&[2; 10], 1);
+        let bytes = writer.finish();
+        let (_, first_len) = OggPage::parse(&bytes).unwrap();
+        let (second, _) = OggPage::parse(&bytes[first_len..]).unwrap();
+
+        assert_eq!(second.flags, END_OF_STREAM);
+        let mut reader = OggReader::new(&bytes);
+        assert_eq!(reader.read_packet(), Ok(Some(vec![1; PAGE_DATA_TARGET])));
+        assert_eq!(reader.read_packet(), Ok(Some(vec![2; 10])));
+    }
+
+    #[test]
+    fn test_read_truncated_stream() {
+        let mut writer = OggWriter::new(7);
+        writer.write_packet(&[1; 70000], 0);
+        let bytes = writer.finish();
+        let (_, first_len) = OggPage::parse(&bytes).unwrap();
+
+        let mut reader = OggReader::new(&bytes[..first_len]);
+        assert_eq!(reader.read_packet(), Err(OggError::UnexpectedEndError));
+        let mut reader = OggReader::new(&bytes[first_len..]);
+        assert_eq!(reader.read_packet(), Err(OggError::SequenceError));
+    }
+}
@@ -2,10 +2,13 @@ use core::fmt;
 use std::fs::File;
 use std::path::Path;
 use std::error;
-use std::io::{self, Read, Seek, SeekFrom};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::marker::PhantomData;
 
 use byteorder::{ByteOrder, LittleEndian};
 
+use crate::flac::sample::Sample;
+
 // ------------------------------------------------------------------ //
 
 // Represents a PCM WAV file
@@ -13,6 +16,7 @@ pub struct PCMWaveInfo {
     pub riff_header: RiffChunk,
     pub fmt_header: PCMWaveFormatChunk,
     pub data_chunks: Vec <PCMWaveDataChunk>,
+    pub info_tags: Vec <(String, String)>,  // Four-character IDs and values of the LIST/INFO chunk
 }
 
 // Represents the RIFF chunk of a WAV file
@@ -30,23 +34,47 @@ pub struct PCMWaveFormatChunk {             // Holds the audio format details fo
 }
 
 // Represents a data chunk in a WAV file
-pub struct PCMWaveDataChunk {               // To manage and read the audio sample data
+// Samples are read as T, which must hold the bits per sample of the chunk
+pub struct PCMWaveDataChunk <T: Sample = i64> {             // To manage and read the audio sample data
     pub size_bytes: u32,
     pub format: PCMWaveFormatChunk,
     pub data_buf: io::BufReader<File>,
+    bytes_left: u64,
+    sample_type: PhantomData <T>,
+}
+
+// Represents a PCM WAV stream read in order, such as from a pipe
+// The stream is positioned at the samples of its data chunk
+pub struct PCMWaveStream <R: Read> {
+    pub fmt_header: PCMWaveFormatChunk,
+    pub data_size: Option <u32>,            // None if the header leaves the size unknown
+    pub info_tags: Vec <(String, String)>,  // Tags of the LIST/INFO chunks before the data chunk
+    reader: R,
+    bytes_left: Option <u64>,
 }
 
 // Represents an interator over a window of data chunks
 // Used to iterate over chunks of inter-channel samples
 // Facilitates batch processing of audio samples
-pub struct PCMWaveDataChunkWindow {
+pub struct PCMWaveDataChunkWindow <T: Sample = i64> {
     chunk_size: usize,
-    data_chunk: PCMWaveDataChunk
+    data_chunk: PCMWaveDataChunk <T>
 }
 
 /// Represents a WAV reader
 pub struct WaveReader;
 
+/// Represents a WAV writer
+pub struct WaveWriter;
+
+// Format tags of the format chunk
+const WAVE_FORMAT_PCM: u16 = 0x0001;
+const WAVE_FORMAT_EXTENSIBLE: u16 = 0xFFFE;
+// Subformat GUID of integer PCM in the extension of a WAVE_FORMAT_EXTENSIBLE format chunk
+const KSDATAFORMAT_SUBTYPE_PCM: [u8; 16] = [
+    0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x10, 0x00, 0x80, 0x00, 0x00, 0xAA, 0x00, 0x38, 0x9B, 0x71,
+];
+
 // Represents possible errors in the WAV Reader
 #[derive(Debug)]
 #[derive(PartialEq)]
@@ -54,6 +82,7 @@ pub enum WaveReaderError {
     NotRiffError,
     NotWaveError,
     NotPCMError,
+    UnsupportedFormatError,
     ChunkTypeError,
     DataAlignmentError,
     ReadError,
@@ -61,7 +90,8 @@ pub enum WaveReaderError {
 
 impl WaveReader {
     // Opens a PCM WAV file
-    // Reads RIFF, format, and data chunk in sequence
+    // Reads RIFF and format chunk in sequence, then the data and LIST chunks
+    // Other chunks are skipped
     pub fn open_pcm(file_path: &str) -> Result <PCMWaveInfo, WaveReaderError> {
         let mut file = File::open(Path::new(file_path))?;
 
@@ -75,16 +105,69 @@ impl WaveReader {
         // Read format chunk
         let fmt_chunk = WaveReader::read_fmt_chunk(&mut file)?;
 
-        // Read the data chunks
+        // Read the data and LIST chunks
         let mut data_chunks = Vec::new();
-        while let Ok(data_chunk) = WaveReader::read_data_chunk(file.seek(SeekFrom::Current(0))?, &fmt_chunk, file.try_clone()?) {
-            data_chunks.push(data_chunk);
+        let mut info_tags = Vec::new();
+        let mut chunk_header = [0u8; 8];
+        loop {
+            let start_pos = file.stream_position()?;
+            if file.read_exact(&mut chunk_header).is_err() {
+                break;
+            }
+            let size_bytes = LittleEndian::read_u32(&chunk_header[4..8]) as u64;
+
+            match &chunk_header[0..4] {
+                // Each data chunk has its own file handle so it can be read independently
+                b"data" => data_chunks.push(WaveReader::read_data_chunk(start_pos, &fmt_chunk, File::open(Path::new(file_path))?)?),
+                b"LIST" => info_tags.extend(WaveReader::read_list_chunk(&mut file, size_bytes)?),
+                _ => {}
+            }
+
+            // Chunks are padded to an even size
+            file.seek(SeekFrom::Start(start_pos + 8 + size_bytes + (size_bytes & 1)))?;
         }
  
-        Ok(PCMWaveInfo { riff_header: riff_chunk, fmt_header: fmt_chunk, data_chunks })
+        Ok(PCMWaveInfo { riff_header: riff_chunk, fmt_header: fmt_chunk, data_chunks, info_tags })
+    }
+    // Reads a PCM WAV stream up to the samples of its data chunk, without seeking
+    // Streaming tools write a data size of 0 or 0xFFFFFFFF when they cannot
+    // seek back to patch it, so those sizes are read as unknown
+    pub fn read_pcm_stream <R: Read> (mut reader: R) -> Result <PCMWaveStream <R>, WaveReaderError> {
+        let riff_chunk = WaveReader::read_riff_chunk(&mut reader)?;
+        if riff_chunk.is_big_endian {
+            return Err(WaveReaderError::NotWaveError);
+        }
+        let fmt_chunk = WaveReader::read_fmt_chunk(&mut reader)?;
+
+        let mut info_tags = Vec::new();
+        let mut chunk_header = [0u8; 8];
+        loop {
+            reader.read_exact(&mut chunk_header)?;
+            let size_bytes = LittleEndian::read_u32(&chunk_header[4..8]);
+
+            match &chunk_header[0..4] {
+                b"data" => break,
+                b"LIST" => {
+                    info_tags.extend(WaveReader::read_list_chunk(&mut reader, size_bytes as u64)?);
+                    WaveReader::skip(&mut reader, size_bytes as u64 & 1)?;
+                }
+                _ => WaveReader::skip(&mut reader, size_bytes as u64 + (size_bytes as u64 & 1))?,
+            }
+        }
+
+        let size_bytes = LittleEndian::read_u32(&chunk_header[4..8]);
+        let data_size = Some(size_bytes).filter(|&size| size != 0 && size != u32::MAX);
+        Ok(PCMWaveStream { fmt_header: fmt_chunk, data_size, info_tags, reader, bytes_left: data_size.map(|size| size as u64) })
+    }
+    // Skips bytes of a stream by reading them
+    fn skip(fh: &mut impl Read, n_bytes: u64) -> Result <(), WaveReaderError> {
+        if io::copy(&mut fh.take(n_bytes), &mut io::sink())? != n_bytes {
+            return Err(WaveReaderError::ReadError);
+        }
+        Ok(())
     }
     // Reads the RIFF chunk from the file
-    fn read_riff_chunk(fh: &mut File) -> Result <RiffChunk, WaveReaderError> {
+    fn read_riff_chunk(fh: &mut impl Read) -> Result <RiffChunk, WaveReaderError> {
         let mut riff_header = [0u8; 12];        // Buffer to read the first 12 Bytes
         fh.read_exact(&mut riff_header)?;
 
@@ -109,7 +192,7 @@ impl WaveReader {
         Ok(RiffChunk { file_size, is_big_endian })
     }
     // Reads the format chunk from the file
-    fn read_fmt_chunk(fh: &mut File) -> Result <PCMWaveFormatChunk, WaveReaderError> {
+    fn read_fmt_chunk(fh: &mut impl Read) -> Result <PCMWaveFormatChunk, WaveReaderError> {
         let mut fmt_header = [0u8; 24];         // Buffer to read the next 24 Bytes
         fh.read_exact(&mut fmt_header)?;        
 
@@ -119,6 +202,7 @@ impl WaveReader {
         }
 
         // Read the format details using LittleEndian
+        let format_tag = LittleEndian::read_u16(&fmt_header[8..10]);
         let num_channels = LittleEndian::read_u16(&fmt_header[10..12]);
         let samp_rate = LittleEndian::read_u32(&fmt_header[12..16]);
         let byte_rate = LittleEndian::read_u32(&fmt_header[16..20]);
@@ -127,6 +211,33 @@ impl WaveReader {
 
         let fmt_chunk = PCMWaveFormatChunk { num_channels, samp_rate, bps };
 
+        // Read the extension of the format chunk, if any
+        let fmt_size = LittleEndian::read_u32(&fmt_header[4..8]);
+        let mut extension = Vec::new();
+        if fmt_size > 16 {
+            let extension_size = (fmt_size - 16 + (fmt_size & 1)) as u64;
+            fh.take(extension_size).read_to_end(&mut extension)?;
+            if extension.len() as u64 != extension_size {
+                return Err(WaveReaderError::ReadError);
+            }
+        }
+
+        // Only integer PCM is supported, which extensible format chunks give as their subformat
+        // The extension holds its size, the valid bits, the channel mask and the subformat
+        let is_pcm = match format_tag {
+            WAVE_FORMAT_PCM => true,
+            WAVE_FORMAT_EXTENSIBLE => extension.get(8..24) == Some(&KSDATAFORMAT_SUBTYPE_PCM[..]),
+            _ => false,
+        };
+        if !is_pcm {
+            return Err(WaveReaderError::NotPCMError);
+        }
+
+        // FLAC holds 1 to 8 channels of 4 to 32 bits per sample
+        if !(1..=8).contains(&num_channels) || !(4..=32).contains(&bps) {
+            return Err(WaveReaderError::UnsupportedFormatError);
+        }
+
         // Validate byte rate and block alignment
         if byte_rate != fmt_chunk.byte_rate() {
             return Err(WaveReaderError::DataAlignmentError);
@@ -152,7 +263,90 @@ impl WaveReader {
         let size_bytes = LittleEndian::read_u32(&data_header[4..8]);        // DSubChunkSize
         let data_buf = io::BufReader::new(fh);
 
-        Ok(PCMWaveDataChunk { size_bytes, format: *fmt_info, data_buf })
+        Ok(PCMWaveDataChunk { size_bytes, format: *fmt_info, data_buf, bytes_left: size_bytes as u64, sample_type: PhantomData })
+    }
+    // Reads the tags of a LIST chunk, whose header has just been read
+    // Only LIST chunks of type 'INFO' hold tags, so other types are ignored
+    // The chunk is read as it arrives, so a size past the end of the file allocates nothing
+    fn read_list_chunk(fh: &mut impl Read, size_bytes: u64) -> Result <Vec <(String, String)>, WaveReaderError> {
+        let mut list_data = Vec::new();
+        fh.take(size_bytes).read_to_end(&mut list_data)?;
+        if list_data.len() as u64 != size_bytes {
+            return Err(WaveReaderError::ReadError);
+        }
+        if list_data.len() < 4 || &list_data[0..4] != b"INFO" {
+            return Ok(Vec::new());
+        }
+
+        let mut tags = Vec::new();
+        let mut pos = 4;
+        while pos + 8 <= list_data.len() {
+            let id = String::from_utf8_lossy(&list_data[pos..pos + 4]).to_string();
+            let len = LittleEndian::read_u32(&list_data[pos + 4..pos + 8]) as usize;
+            let value = list_data.get(pos + 8..pos + 8 + len).ok_or(WaveReaderError::ReadError)?;
+
+            // Values are null-terminated
+            let value = String::from_utf8_lossy(value).trim_end_matches('\0').to_string();
+            tags.push((id, value));
+            pos += 8 + len + (len & 1);
+        }
+        Ok(tags)
+    }
+}
+
+impl WaveWriter {
+    // Writes a canonical PCM WAV file: the RIFF header, the format chunk and the data chunk
+    // Fails with InvalidInput if the file would exceed the 4 GiB limit of the RIFF sizes
+    pub fn write_pcm <T: Sample> (writer: &mut impl Write, format: &PCMWaveFormatChunk, channels: &[Vec <T>]) -> io::Result <()> {
+        let n_samples = channels.first().map_or(0, |channel| channel.len());
+        let Some((data_size, riff_size)) = WaveWriter::pcm_sizes(format, n_samples) else {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "WAV data exceeds 4 GiB"));
+        };
+
+        writer.write_all(b"RIFF")?;
+        writer.write_all(&riff_size.to_le_bytes())?;
+        writer.write_all(b"WAVE")?;
+
+        writer.write_all(b"fmt ")?;
+        writer.write_all(&16u32.to_le_bytes())?;
+        writer.write_all(&1u16.to_le_bytes())?;         // PCM format
+        writer.write_all(&format.num_channels.to_le_bytes())?;
+        writer.write_all(&format.samp_rate.to_le_bytes())?;
+        writer.write_all(&format.byte_rate().to_le_bytes())?;
+        writer.write_all(&format.block_align().to_le_bytes())?;
+        writer.write_all(&format.bps.to_le_bytes())?;
+
+        writer.write_all(b"data")?;
+        writer.write_all(&data_size.to_le_bytes())?;
+        WaveWriter::write_samples(writer, format, channels)?;
+        if data_size & 1 == 1 {
+            writer.write_all(&[0])?;
+        }
+        Ok(())
+    }
+    // Gets the sizes of the data chunk and of the RIFF chunk holding n_samples inter-channel samples
+    // None is returned if either does not fit in 32 bits
+    fn pcm_sizes(format: &PCMWaveFormatChunk, n_samples: usize) -> Option <(u32, u32)> {
+        let data_size = u32::try_from(n_samples).ok()?.checked_mul(format.block_align() as u32)?;
+        let riff_size = (4 + 24 + 8 + (data_size & 1)).checked_add(data_size)?;
+        Some((data_size, riff_size))
+    }
+    // Writes interleaved samples as little-endian integers
+    // 8-bit samples are unsigned, and wider samples are signed
+    pub fn write_samples <T: Sample> (writer: &mut impl Write, format: &PCMWaveFormatChunk, channels: &[Vec <T>]) -> io::Result <()> {
+        let n_samples = channels.first().map_or(0, |channel| channel.len());
+        let n_bytes = (format.bps as usize).div_ceil(8);
+        let mut buf = Vec::with_capacity(n_bytes * channels.len());
+
+        for i in 0..n_samples {
+            buf.clear();
+            for channel in channels {
+                let sample = if n_bytes == 1 { channel[i].to_i64() + 128 } else { channel[i].to_i64() };
+                buf.extend_from_slice(&sample.to_le_bytes()[..n_bytes]);
+            }
+            writer.write_all(&buf)?;
+        }
+        Ok(())
     }
 }
 
@@ -164,6 +358,7 @@ impl fmt::Display for WaveReaderError {
             WaveReaderError::NotRiffError => "Not a valid RIFF header",
             WaveReaderError::NotWaveError => "Not a valid WAVE file",
             WaveReaderError::NotPCMError => "Not a PCM format",
+            WaveReaderError::UnsupportedFormatError => "Unsupported number of channels or bits per sample",
             WaveReaderError::ChunkTypeError => "Chunk type error",
             WaveReaderError::DataAlignmentError => "Data alignment error",
             WaveReaderError::ReadError => "Error reading from file",
@@ -192,34 +387,39 @@ impl fmt::Display for PCMWaveInfo {
 impl PCMWaveFormatChunk {
     // Calculates the byte rate of the WAV file
     pub fn byte_rate(&self) -> u32 {
-        self.samp_rate * (self.num_channels as u32) * (self.bps as u32) / 8
+        self.samp_rate * (self.block_align() as u32)
     }
     // Calculates the block alignment of the WAV file
+    // Samples are padded to whole bytes, so 20-bit samples take 3 bytes
     pub fn block_align(&self) -> u16 {
-        self.num_channels * self.bps / 8
+        self.num_channels * self.bps.div_ceil(8)
     }
 }
 
 // Defines how to iterate over inter-channel samples
-impl Iterator for PCMWaveDataChunk {
-    type Item = Vec <i64>;
+impl <T: Sample> Iterator for PCMWaveDataChunk <T> {
+    type Item = Vec <T>;
 
     fn next(&mut self) -> Option <Self::Item> {
-        let mut sample = vec![0; self.format.num_channels as usize];
-        for ch in &mut sample {
-            let mut buf = [0u8; 2];
-            if self.data_buf.read_exact(&mut buf).is_err() {
-                return None;
-            }
-            *ch = LittleEndian::read_i16(&buf) as i64;
+        // Stop at the end of the chunk, since other chunks may follow it
+        let block_align = self.format.block_align() as u64;
+        if self.bytes_left < block_align {
+            return None;
+        }
+        self.bytes_left -= block_align;
+
+        let mut buf = vec![0u8; block_align as usize];
+        if self.data_buf.read_exact(&mut buf).is_err() {
+            return None;
         }
-        Some(sample)
+        let n_bytes = (self.format.bps as usize).div_ceil(8);
+        Some(buf.chunks_exact(n_bytes).map(decode_sample).collect())
     }
 }
 
 // Defines how to iterate over chunks of inter-channel samples
-impl Iterator for PCMWaveDataChunkWindow {
-    type Item = Vec <Vec <i64>>;
+impl <T: Sample> Iterator for PCMWaveDataChunkWindow <T> {
+    type Item = Vec <Vec <T>>;
 
     fn next(&mut self) -> Option <Self::Item> {
         let mut batch = Vec::with_capacity(self.chunk_size);
@@ -238,10 +438,67 @@ impl Iterator for PCMWaveDataChunkWindow {
     }
 }
 
-impl PCMWaveDataChunk {
+impl <R: Read> PCMWaveStream <R> {
+    // Gets the number of inter-channel samples given by the data size, if known
+    pub fn total_samples(&self) -> Option <u64> {
+        self.data_size.map(|size| size as u64 / self.fmt_header.block_align() as u64)
+    }
+    // Reads up to n_samples inter-channel samples into one vector per channel
+    // Fewer samples are returned at the end of the data chunk or the stream
+    // 8-bit samples are unsigned, and wider samples are signed
+    // Samples are read as T, which must hold the bits per sample of the stream
+    pub fn read_samples <T: Sample> (&mut self, n_samples: usize) -> Result <Vec <Vec <T>>, WaveReaderError> {
+        let block_align = self.fmt_header.block_align() as usize;
+        let n_bytes = (self.fmt_header.bps as usize).div_ceil(8);
+        let mut n_wanted = (n_samples * block_align) as u64;
+        if let Some(bytes_left) = self.bytes_left {
+            n_wanted = n_wanted.min(bytes_left - bytes_left % block_align as u64);
+        }
+
+        let mut buf = Vec::with_capacity(n_wanted as usize);
+        (&mut self.reader).take(n_wanted).read_to_end(&mut buf)?;
+        // A stream of unknown size may end within an inter-channel sample
+        buf.truncate(buf.len() - buf.len() % block_align);
+        if let Some(bytes_left) = &mut self.bytes_left {
+            *bytes_left -= buf.len() as u64;
+        }
+
+        let mut channels = vec![Vec::with_capacity(buf.len() / block_align); self.fmt_header.num_channels as usize];
+        for sample in buf.chunks_exact(block_align) {
+            for (channel, bytes) in channels.iter_mut().zip(sample.chunks_exact(n_bytes)) {
+                channel.push(decode_sample(bytes));
+            }
+        }
+        Ok(channels)
+    }
+}
+
+// Decodes one little-endian sample of up to 8 bytes
+// 8-bit samples are unsigned, and wider samples are signed
+fn decode_sample <T: Sample> (bytes: &[u8]) -> T {
+    let n_bytes = bytes.len();
+    let mut value_bytes = [0u8; 8];
+    value_bytes[8 - n_bytes..].copy_from_slice(bytes);
+    // Shift down from the top to sign-extend the sample
+    let value = i64::from_le_bytes(value_bytes) >> (64 - 8 * n_bytes);
+    T::from_i64(if n_bytes == 1 { (value & 0xFF) - 128 } else { value })
+}
+
+impl <T: Sample> PCMWaveDataChunk <T> {
+    // Consumes a data chunk and reads its samples as another type instead
+    // The type must hold the bits per sample of the chunk, such as i16 for 16-bit samples
+    pub fn with_sample_type <U: Sample> (self) -> PCMWaveDataChunk <U> {
+        PCMWaveDataChunk {
+            size_bytes: self.size_bytes,
+            format: self.format,
+            data_buf: self.data_buf,
+            bytes_left: self.bytes_left,
+            sample_type: PhantomData,
+        }
+    }
     // Consumes a data chunk and returns an iterator for single inter-channel samples
     // Helps iterate over individual audio samples.
-    pub fn chunks_byte_rate(self) -> PCMWaveDataChunkWindow {
+    pub fn chunks_byte_rate(self) -> PCMWaveDataChunkWindow <T> {
         PCMWaveDataChunkWindow {
             chunk_size: self.format.byte_rate() as usize,
             data_chunk: self,
@@ -249,7 +506,7 @@ impl PCMWaveDataChunk {
     }
     // Consumes a data chunk and returns an iterator for a specified number of inter-channel samples
     // Enables batch processing of audio samples
-    pub fn chunks(self, chunk_size: usize) -> PCMWaveDataChunkWindow {
+    pub fn chunks(self, chunk_size: usize) -> PCMWaveDataChunkWindow <T> {
         PCMWaveDataChunkWindow {
             chunk_size,
             data_chunk: self,
@@ -484,6 +741,136 @@ mod tests {
                         bps: 16,
                     },
                 )),
+            it_valid_extensible_pcm: (
+                &[
+                    0x66, 0x6d, 0x74, 0x20,
+                    0x28, 0x0, 0x0, 0x0,
+                    0xfe, 0xff,
+                    0x02, 0x0,
+                    0x44, 0xac, 0x0, 0x0,
+                    0x98, 0x09, 0x04, 0x0,
+                    0x06, 0x00, 0x18, 0x0,
+                    0x16, 0x0, 0x18, 0x0, 0x03, 0x0, 0x0, 0x0,
+                    0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x10, 0x00, 0x80, 0x00, 0x00, 0xaa, 0x00, 0x38, 0x9b, 0x71,
+                ],
+                (
+                    false,
+                    PCMWaveFormatChunk {
+                        num_channels: 2,
+                        samp_rate: 44100,
+                        bps: 24,
+                    },
+                )),
+            it_valid_12_bit: (
+                &[
+                    0x66, 0x6d, 0x74, 0x20,
+                    0x10, 0x0, 0x0, 0x0,
+                    0x01, 0x0,
+                    0x01, 0x0,
+                    0x40, 0x1f, 0x0, 0x0,
+                    0x80, 0x3e, 0x0, 0x0,
+                    0x02, 0x00, 0x0c, 0x0,
+                ],
+                (
+                    false,
+                    PCMWaveFormatChunk {
+                        num_channels: 1,
+                        samp_rate: 8000,
+                        bps: 12,
+                    },
+                )),
+            it_invalid_12_bit_block_align: (
+                &[
+                    0x66, 0x6d, 0x74, 0x20,
+                    0x10, 0x0, 0x0, 0x0,
+                    0x01, 0x0,
+                    0x01, 0x0,
+                    0x40, 0x1f, 0x0, 0x0,
+                    0x40, 0x1f, 0x0, 0x0,
+                    0x01, 0x00, 0x0c, 0x0,
+                ],
+                (
+                    true,
+                    PCMWaveFormatChunk {
+                        num_channels: 1,
+                        samp_rate: 8000,
+                        bps: 12,
+                    },
+                )),
+            it_invalid_no_channels: (
+                &[
+                    0x66, 0x6d, 0x74, 0x20,
+                    0x10, 0x0, 0x0, 0x0,
+                    0x01, 0x0,
+                    0x00, 0x0,
+                    0x44, 0xac, 0x0, 0x0,
+                    0x0, 0x0, 0x0, 0x0,
+                    0x00, 0x00, 0x10, 0x0,
+                ],
+                (
+                    true,
+                    PCMWaveFormatChunk {
+                        num_channels: 0,
+                        samp_rate: 44100,
+                        bps: 16,
+                    },
+                )),
+            it_invalid_72_bit: (
+                &[
+                    0x66, 0x6d, 0x74, 0x20,
+                    0x10, 0x0, 0x0, 0x0,
+                    0x01, 0x0,
+                    0x01, 0x0,
+                    0x40, 0x1f, 0x0, 0x0,
+                    0x40, 0x19, 0x01, 0x0,
+                    0x09, 0x00, 0x48, 0x0,
+                ],
+                (
+                    true,
+                    PCMWaveFormatChunk {
+                        num_channels: 1,
+                        samp_rate: 8000,
+                        bps: 72,
+                    },
+                )),
+            it_invalid_float: (
+                &[
+                    0x66, 0x6d, 0x74, 0x20,
+                    0x10, 0x0, 0x0, 0x0,
+                    0x03, 0x0,
+                    0x01, 0x0,
+                    0x44, 0xac, 0x0, 0x0,
+                    0x10, 0xb1, 0x02, 0x0,
+                    0x04, 0x00, 0x20, 0x0,
+                ],
+                (
+                    true,
+                    PCMWaveFormatChunk {
+                        num_channels: 1,
+                        samp_rate: 44100,
+                        bps: 32,
+                    },
+                )),
+            it_invalid_extensible_float: (
+                &[
+                    0x66, 0x6d, 0x74, 0x20,
+                    0x28, 0x0, 0x0, 0x0,
+                    0xfe, 0xff,
+                    0x01, 0x0,
+                    0x44, 0xac, 0x0, 0x0,
+                    0x10, 0xb1, 0x02, 0x0,
+                    0x04, 0x00, 0x20, 0x0,
+                    0x16, 0x0, 0x20, 0x0, 0x04, 0x0, 0x0, 0x0,
+                    0x03, 0x00, 0x00, 0x00, 0x00, 0x00, 0x10, 0x00, 0x80, 0x00, 0x00, 0xaa, 0x00, 0x38, 0x9b, 0x71,
+                ],
+                (
+                    true,
+                    PCMWaveFormatChunk {
+                        num_channels: 1,
+                        samp_rate: 44100,
+                        bps: 32,
+                    },
+                )),
         }
     }
 
@@ -511,15 +898,15 @@ mod tests {
             let file_name = "data_chunk_test.wav";
             let result;
             {
-                create_temp_file(&file_name, input)?;
-                let input_fh = File::open(&file_name)?;
+                create_temp_file(file_name, input)?;
+                let input_fh = File::open(file_name)?;
                 result = WaveReader::read_data_chunk(0, &PCMWaveFormatChunk {
                     num_channels: 2,
                     samp_rate: 44100,
                     bps: 16,
                 }, input_fh);
             }
-            std::fs::remove_file(&file_name)?;
+            std::fs::remove_file(file_name)?;
     
             if let Ok(data_chunk) = result {
                 assert_eq!(data_chunk.size_bytes, 12);
@@ -545,15 +932,15 @@ mod tests {
             let file_name = "invalid_chunk_test.wav";
             let result;
             {
-                create_temp_file(&file_name, input)?;
-                let input_fh = File::open(&file_name)?;
+                create_temp_file(file_name, input)?;
+                let input_fh = File::open(file_name)?;
                 result = WaveReader::read_data_chunk(0, &PCMWaveFormatChunk {
                     num_channels: 2,
                     samp_rate: 44100,
                     bps: 16,
                 }, input_fh);
             }
-            std::fs::remove_file(&file_name)?;
+            std::fs::remove_file(file_name)?;
     
             assert!(result.is_err());
     
@@ -563,6 +950,196 @@ mod tests {
         // Add more test cases as needed
     }
     
+    #[cfg(test)]
+    mod open_pcm {
+        use super::*;
+        use std::io::Write;
+
+        fn create_temp_file(file_name: &str, content: &[u8]) -> Result <(), io::Error> {
+            let mut file = File::create(file_name)?;
+            file.write_all(content)?;
+
+            Ok(())
+        }
+
+        #[test]
+        fn it_reads_list_info_after_data() -> Result <(), WaveReaderError> {
+            let mut input = Vec::new();
+            input.extend_from_slice(b"RIFF");
+            input.extend_from_slice(&[0x4E, 0x00, 0x00, 0x00]);
+            input.extend_from_slice(b"WAVE");
+            // Format chunk: mono, 8000 Hz, 16-bit
+            input.extend_from_slice(b"fmt ");
+            input.extend_from_slice(&[0x10, 0x00, 0x00, 0x00, 0x01, 0x00, 0x01, 0x00]);
+            input.extend_from_slice(&[0x40, 0x1F, 0x00, 0x00, 0x80, 0x3E, 0x00, 0x00, 0x02, 0x00, 0x10, 0x00]);
+            // Data chunk with two samples
+            input.extend_from_slice(b"data");
+            input.extend_from_slice(&[0x04, 0x00, 0x00, 0x00, 0x01, 0x00, 0xFF, 0xFF]);
+            // LIST chunk with an odd-sized, null-terminated title and an artist
+            input.extend_from_slice(b"LIST");
+            input.extend_from_slice(&[0x20, 0x00, 0x00, 0x00]);
+            input.extend_from_slice(b"INFO");
+            input.extend_from_slice(b"INAM");
+            input.extend_from_slice(&[0x05, 0x00, 0x00, 0x00]);
+            input.extend_from_slice(b"Song\0\0");
+            input.extend_from_slice(b"IART");
+            input.extend_from_slice(&[0x06, 0x00, 0x00, 0x00]);
+            input.extend_from_slice(b"Alice\0");
+
+            let file_name = "midp_open_pcm_list_info.wav.part";
+            let result;
+            {
+                create_temp_file(file_name, &input)?;
+                result = WaveReader::open_pcm(file_name);
+            }
+            let wave_info = result?;
+            let samples: Vec <Vec <i64>> = wave_info.data_chunks.into_iter().flatten().collect();
+            std::fs::remove_file(file_name)?;
+
+            assert_eq!(samples, vec![vec![1], vec![-1]]);
+            assert_eq!(wave_info.info_tags, vec![
+                ("INAM".to_string(), "Song".to_string()),
+                ("IART".to_string(), "Alice".to_string()),
+            ]);
+
+            Ok(())
+        }
+
+        #[test]
+        fn it_reads_every_sample_size() -> Result <(), WaveReaderError> {
+            let cases = [
+                (8, vec![vec![-128, 127], vec![0, -1]]),
+                (24, vec![vec![-8388608, 8388607], vec![0, -1]]),
+                (32, vec![vec![i32::MIN as i64, i32::MAX as i64], vec![0, -1]]),
+            ];
+            for (bps, channels) in cases {
+                let format = PCMWaveFormatChunk { num_channels: 2, samp_rate: 8000, bps };
+                let mut input = Vec::new();
+                WaveWriter::write_pcm(&mut input, &format, &channels)?;
+
+                let file_name = format!("midp_open_pcm_{}_bit.wav.part", bps);
+                create_temp_file(&file_name, &input)?;
+                let result = WaveReader::open_pcm(&file_name);
+                std::fs::remove_file(&file_name)?;
+
+                let samples: Vec <Vec <i64>> = result?.data_chunks.into_iter().flatten().collect();
+                assert_eq!(samples, vec![vec![channels[0][0], channels[1][0]], vec![channels[0][1], channels[1][1]]]);
+            }
+
+            Ok(())
+        }
+    }
+
+    #[cfg(test)]
+    mod write_pcm {
+        use super::*;
+
+        #[test]
+        fn it_writes_canonical_header() -> Result <(), io::Error> {
+            let format = PCMWaveFormatChunk { num_channels: 2, samp_rate: 8000, bps: 16 };
+            let mut bytes = Vec::new();
+            WaveWriter::write_pcm(&mut bytes, &format, &[vec![1, -2], vec![-1, 2]])?;
+
+            assert_eq!(&bytes[0..12], b"RIFF\x2C\x00\x00\x00WAVE");
+            assert_eq!(&bytes[12..36], &[
+                0x66, 0x6D, 0x74, 0x20, 0x10, 0x00, 0x00, 0x00, 0x01, 0x00, 0x02, 0x00,
+                0x40, 0x1F, 0x00, 0x00, 0x00, 0x7D, 0x00, 0x00, 0x04, 0x00, 0x10, 0x00,
+            ]);
+            assert_eq!(&bytes[36..44], b"data\x08\x00\x00\x00");
+            assert_eq!(&bytes[44..], &[0x01, 0x00, 0xFF, 0xFF, 0xFE, 0xFF, 0x02, 0x00]);
+
+            Ok(())
+        }
+
+        #[test]
+        fn it_writes_unsigned_8_bit_samples() -> Result <(), io::Error> {
+            let format = PCMWaveFormatChunk { num_channels: 1, samp_rate: 8000, bps: 8 };
+            let mut bytes = Vec::new();
+            WaveWriter::write_samples(&mut bytes, &format, &[vec![-128, 0, 127]])?;
+
+            assert_eq!(bytes, vec![0x00, 0x80, 0xFF]);
+
+            Ok(())
+        }
+
+        #[test]
+        fn it_rejects_data_over_4_gib() {
+            let format = PCMWaveFormatChunk { num_channels: 2, samp_rate: 44100, bps: 16 };
+            assert_eq!(WaveWriter::pcm_sizes(&format, 3), Some((12, 48)));
+            assert_eq!(WaveWriter::pcm_sizes(&format, (u32::MAX / 4 - 9) as usize), Some((u32::MAX - 39, u32::MAX - 3)));
+            assert_eq!(WaveWriter::pcm_sizes(&format, (u32::MAX / 4) as usize), None);
+            assert_eq!(WaveWriter::pcm_sizes(&format, 1 << 32), None);
+        }
+    }
+
+    #[cfg(test)]
+    mod read_pcm_stream {
+        use super::*;
+
+        fn wav_bytes(format: &PCMWaveFormatChunk, channels: &[Vec <i64>]) -> Vec <u8> {
+            let mut bytes = Vec::new();
+            WaveWriter::write_pcm(&mut bytes, format, channels).unwrap();
+            bytes
+        }
+
+        #[test]
+        fn it_reads_samples_in_order() -> Result <(), WaveReaderError> {
+            let format = PCMWaveFormatChunk { num_channels: 2, samp_rate: 8000, bps: 24 };
+            let channels = vec![vec![1, -2, 8388607], vec![-8388608, 3, 0]];
+            let mut bytes = wav_bytes(&format, &channels);
+            // A LIST/INFO chunk before the samples and another chunk after them
+            bytes.splice(36..36, b"LIST\x0E\x00\x00\x00INFOINAM\x02\x00\x00\x00A\x00".iter().copied());
+            bytes.extend_from_slice(b"smpl\x02\x00\x00\x00\xAB\xCD");
+
+            let mut stream = WaveReader::read_pcm_stream(&bytes[..])?;
+            assert_eq!(stream.total_samples(), Some(3));
+            assert_eq!(stream.info_tags, vec![("INAM".to_string(), "A".to_string())]);
+            assert_eq!(stream.read_samples::<i32>(2)?, vec![vec![1, -2], vec![-8388608, 3]]);
+            assert_eq!(stream.read_samples::<i32>(2)?, vec![vec![8388607], vec![0]]);
+            assert_eq!(stream.read_samples::<i32>(2)?, vec![Vec::<i32>::new(), Vec::new()]);
+
+            Ok(())
+        }
+
+        #[test]
+        fn it_reads_unknown_size_to_end() -> Result <(), WaveReaderError> {
+            let format = PCMWaveFormatChunk { num_channels: 1, samp_rate: 8000, bps: 8 };
+            let mut bytes = wav_bytes(&format, &[vec![-128, 0, 127, 1]]);
+            bytes[40..44].copy_from_slice(&u32::MAX.to_le_bytes());
+
+            let mut stream = WaveReader::read_pcm_stream(&bytes[..])?;
+            assert_eq!(stream.data_size, None);
+            assert_eq!(stream.read_samples::<i8>(10)?, vec![vec![-128, 0, 127, 1]]);
+
+            Ok(())
+        }
+
+        #[test]
+        fn it_rejects_list_chunk_past_end() {
+            let format = PCMWaveFormatChunk { num_channels: 1, samp_rate: 8000, bps: 16 };
+            let mut bytes = wav_bytes(&format, &[vec![1]]);
+            bytes.splice(36..36, b"LIST\xF0\xFF\xFF\xFFINFO".iter().copied());
+            assert!(matches!(WaveReader::read_pcm_stream(&bytes[..]), Err(WaveReaderError::ReadError)));
+        }
+
+        #[test]
+        fn it_rejects_unsupported_format() {
+            let format = PCMWaveFormatChunk { num_channels: 1, samp_rate: 8000, bps: 16 };
+            let mut bytes = wav_bytes(&format, &[vec![1]]);
+            // No channels, which would make every inter-channel sample empty
+            bytes[22..24].copy_from_slice(&0u16.to_le_bytes());
+            bytes[32..34].copy_from_slice(&0u16.to_le_bytes());
+            assert!(matches!(WaveReader::read_pcm_stream(&bytes[..]), Err(WaveReaderError::UnsupportedFormatError)));
+        }
+
+        #[test]
+        fn it_rejects_missing_data_chunk() {
+            let format = PCMWaveFormatChunk { num_channels: 1, samp_rate: 8000, bps: 16 };
+            let bytes = wav_bytes(&format, &[vec![1]]);
+            assert!(matches!(WaveReader::read_pcm_stream(&bytes[..36]), Err(WaveReaderError::ReadError)));
+        }
+    }
+
     #[cfg(test)]
     mod pcm_wave_info_display {
         use super::*;
@@ -580,6 +1157,7 @@ mod tests {
                     bps: 16,
                 },
                 data_chunks: Vec::new(),
+                info_tags: Vec::new(),
             };
     
             assert_eq!(
@@ -614,6 +1192,18 @@ mod tests {
     
             assert_eq!(format_chunk.block_align(), 4);
         }
+
+        #[test]
+        fn test_padded_sample_size() {
+            let format_chunk = PCMWaveFormatChunk {
+                num_channels: 2,
+                samp_rate: 44100,
+                bps: 20,
+            };
+
+            assert_eq!(format_chunk.block_align(), 6);
+            assert_eq!(format_chunk.byte_rate(), 264600);
+        }
     }
 
     #[cfg(test)]
@@ -633,6 +1223,7 @@ mod tests {
                     bps: 16,
                 },
                 data_chunks: Vec::new(),
+                info_tags: Vec::new(),
             };
     
             let expected_endianess = false;
@@ -652,7 +1243,7 @@ mod tests {
     
         #[test]
         fn test_from_io_error() {
-            let io_error = io::Error::new(io::ErrorKind::Other, "Test error");
+            let io_error = io::Error::other("Test error");
             let wave_reader_error: WaveReaderError = io_error.into();
             assert_eq!(wave_reader_error, WaveReaderError::ReadError);
         }